@@ -0,0 +1,42 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use serde_json::{json, Value};
+use tangent_bench::synthesize::{Scope, Synth};
+
+const BATCH: usize = 1024;
+
+fn spec() -> Value {
+    json!({
+        "source": { "name": { "$const": "myservice" } },
+        "seen": { "$int": { "min": 0, "max": 500 } },
+        "duration": { "$float": { "min": 0.0, "max": 10.0 } },
+        "msg": { "$string": { "len": 16 } },
+        "level": { "$oneOf": ["info", "warn", "error"] }
+    })
+}
+
+fn bench_gen(c: &mut Criterion) {
+    let spec = spec();
+    let mut group = c.benchmark_group("synthesize");
+
+    group.bench_with_input(BenchmarkId::new("gen", BATCH), &spec, |b, spec| {
+        let mut synth = Synth::new(42);
+        b.iter(|| {
+            let mut out = Vec::new();
+            for _ in 0..BATCH {
+                let mut scope = Scope::new(spec);
+                out.push(synth.gen(spec, &mut scope).unwrap());
+            }
+            black_box(out)
+        })
+    });
+
+    group.bench_with_input(BenchmarkId::new("gen_batch", BATCH), &spec, |b, spec| {
+        let mut synth = Synth::new(42);
+        b.iter(|| black_box(synth.gen_batch(spec, BATCH).unwrap()))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_gen);
+criterion_main!(benches);
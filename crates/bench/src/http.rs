@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+use crate::synthesize::{Sequences, Synth};
+
+#[allow(clippy::too_many_arguments)]
+pub async fn run_bench(
+    name: Arc<str>,
+    addr: SocketAddr,
+    path: String,
+    bearer_token: Option<String>,
+    connections: u16,
+    payload: Vec<u8>,
+    max_bytes: usize,
+    seconds: u64,
+    synthesize_payload: bool,
+) -> Result<()> {
+    let url = format!("http://{addr}{path}");
+    info!("===Starting benchmark===");
+    info!("source={} url={} connections={}", name, url, connections);
+
+    let client = reqwest::Client::new();
+    let mut handles = Vec::with_capacity(connections as usize);
+
+    static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+    static RECS_SENT: AtomicU64 = AtomicU64::new(0);
+    let start = Instant::now();
+
+    let sequences = Sequences::default();
+    for _ in 0..connections {
+        let payload = payload.clone();
+        let client = client.clone();
+        let sequences = sequences.clone();
+        let url = url.clone();
+        let bearer_token = bearer_token.clone();
+
+        handles.push(tokio::spawn(async move {
+            let mut synth = Synth::with_sequences(rand::random::<u64>(), sequences);
+            let templates: Vec<Value> = payload
+                .split(|b| *b == b'\n')
+                .filter(|line| !line.is_empty())
+                .map(|line| serde_json::from_slice::<Value>(line))
+                .collect::<Result<_, _>>()?;
+
+            let deadline = Instant::now() + Duration::from_secs(seconds);
+            let mut buf = Vec::with_capacity(max_bytes.max(payload.len()));
+
+            let mut total_events: u64 = 0;
+            while Instant::now() < deadline {
+                buf.clear();
+                let mut events_per_buff: u64 = 0;
+                if synthesize_payload && !templates.is_empty() {
+                    'fill: loop {
+                        for template in templates.iter() {
+                            let start = buf.len();
+                            synth.append_ndjson(template, &mut buf)?;
+
+                            if max_bytes > 0 && buf.len() > max_bytes {
+                                buf.truncate(start);
+                                break 'fill;
+                            }
+
+                            events_per_buff += 1;
+
+                            if max_bytes == 0 {
+                                break 'fill;
+                            }
+                        }
+                    }
+                } else {
+                    while max_bytes == 0 || buf.len() + payload.len() <= max_bytes {
+                        buf.extend_from_slice(&payload);
+                        events_per_buff += 1;
+                        if max_bytes == 0 {
+                            break;
+                        }
+                    }
+                }
+
+                if buf.is_empty() {
+                    buf.extend_from_slice(&payload);
+                    events_per_buff = 1;
+                }
+
+                let buf_len = buf.len();
+                let mut req = client.post(&url).body(buf.clone());
+                if let Some(token) = &bearer_token {
+                    req = req.bearer_auth(token);
+                }
+                req.send()
+                    .await
+                    .with_context(|| format!("http source unreachable: {url}"))?
+                    .error_for_status()
+                    .with_context(|| format!("http source rejected payload: {url}"))?;
+
+                total_events += events_per_buff;
+                BYTES_SENT.fetch_add(buf_len as u64, Ordering::Relaxed);
+                RECS_SENT.fetch_add(events_per_buff, Ordering::Relaxed);
+            }
+
+            anyhow::Ok(total_events)
+        }));
+    }
+
+    futures::future::try_join_all(handles)
+        .await?
+        .into_iter()
+        .try_fold(0u64, |acc, res| res.map(|v| acc + v))?;
+
+    let bytes = BYTES_SENT.load(Ordering::Relaxed);
+    let records = RECS_SENT.load(Ordering::Relaxed);
+    let secs = start.elapsed().as_secs_f64();
+    println!(
+        "sent_bytes={} sent_records={} MB/s={:.2} MiB/s={:.2} recs/s={:.0}",
+        bytes,
+        records,
+        (bytes as f64) / (1_000_000.0 * secs),
+        (bytes as f64) / (1_048_576.0 * secs),
+        (records as f64) / secs,
+    );
+
+    Ok(())
+}
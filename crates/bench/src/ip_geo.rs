@@ -0,0 +1,118 @@
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::net::Ipv4Addr;
+
+/// Small embedded table of well-known IPv4 ranges used by `$ip_lookup`.
+/// Columns: cidr,country_code,asn_number,asn_org,latitude,longitude
+const IP_GEO_CSV: &str = include_str!("ip_geo.csv");
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoRecord {
+    pub country_code: String,
+    pub asn_number: u32,
+    pub asn_org: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl GeoRecord {
+    pub fn to_value(&self) -> Value {
+        json!({
+            "country_code": self.country_code,
+            "asn_number": self.asn_number,
+            "asn_org": self.asn_org,
+            "latitude": self.latitude,
+            "longitude": self.longitude,
+        })
+    }
+}
+
+struct Range {
+    network: u32,
+    mask: u32,
+    record: GeoRecord,
+}
+
+lazy_static! {
+    static ref RANGES: Vec<Range> = parse_table(IP_GEO_CSV);
+    static ref UNKNOWN: GeoRecord = GeoRecord {
+        country_code: "US".to_string(),
+        asn_number: 0,
+        asn_org: "UNKNOWN".to_string(),
+        latitude: 37.751,
+        longitude: -97.822,
+    };
+}
+
+fn parse_table(csv: &str) -> Vec<Range> {
+    let mut out = Vec::new();
+    for (i, line) in csv.lines().enumerate().skip(1) {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let cols: Vec<&str> = line.split(',').collect();
+        let parsed = (|| {
+            let [cidr, cc, asn, org, lat, lon] = cols.as_slice() else {
+                return None;
+            };
+            let (addr, bits) = cidr.split_once('/')?;
+            let bits: u32 = bits.parse().ok().filter(|b| *b <= 32)?;
+            let mask = if bits == 0 {
+                0
+            } else {
+                u32::MAX << (32 - bits)
+            };
+            Some(Range {
+                network: u32::from(addr.parse::<Ipv4Addr>().ok()?) & mask,
+                mask,
+                record: GeoRecord {
+                    country_code: cc.to_string(),
+                    asn_number: asn.parse().ok()?,
+                    asn_org: org.to_string(),
+                    latitude: lat.parse().ok()?,
+                    longitude: lon.parse().ok()?,
+                },
+            })
+        })();
+        match parsed {
+            Some(r) => out.push(r),
+            None => panic!("ip_geo.csv: malformed row {}: {line}", i + 1),
+        }
+    }
+    out
+}
+
+/// Resolve an IPv4 address to geo/ASN data. The most specific matching range
+/// wins; unparseable or unknown addresses get a fixed fallback record.
+pub fn lookup(ip: &str) -> &'static GeoRecord {
+    let Ok(addr) = ip.trim().parse::<Ipv4Addr>() else {
+        return &UNKNOWN;
+    };
+    let addr = u32::from(addr);
+    RANGES
+        .iter()
+        .filter(|r| addr & r.mask == r.network)
+        .max_by_key(|r| r.mask)
+        .map(|r| &r.record)
+        .unwrap_or(&UNKNOWN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_known_and_unknown() {
+        assert_eq!(RANGES.len(), 50);
+
+        let g = lookup("8.8.8.8");
+        assert_eq!(g.country_code, "US");
+        assert_eq!(g.asn_number, 15169);
+        assert_eq!(g.asn_org, "GOOGLE");
+
+        assert_eq!(lookup("1.1.1.1").asn_number, 13335);
+        assert_eq!(lookup("10.0.0.1"), &*UNKNOWN);
+        assert_eq!(lookup("not-an-ip"), &*UNKNOWN);
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use rdkafka::producer::FutureProducer;
+use rdkafka::ClientConfig;
+use secrecy::ExposeSecret;
+use std::sync::Arc;
+use tangent_shared::sources::kafka::KafkaSourceConfig;
+use tracing::info;
+
+use crate::msk::produce;
+
+/// Producer with the same brokers, SASL and TLS settings as the source.
+fn build_producer(kcfg: &KafkaSourceConfig) -> Result<FutureProducer> {
+    let mut cfg = ClientConfig::new();
+    cfg.set("bootstrap.servers", &kcfg.brokers)
+        .set("security.protocol", kcfg.security_protocol())
+        .set("compression.type", "snappy")
+        .set("linger.ms", "5")
+        .set("batch.num.messages", "10000")
+        .set("queue.buffering.max.kbytes", "1048576")
+        .set("message.timeout.ms", "60000")
+        .set("acks", "1");
+    if let Some(p) = &kcfg.ssl_ca_pem_path {
+        cfg.set("ssl.ca.location", p.to_string_lossy());
+    }
+    if let (Some(mechanism), Some(username), Some(password)) = (
+        &kcfg.sasl_mechanism,
+        &kcfg.sasl_username,
+        &kcfg.sasl_password,
+    ) {
+        cfg.set("sasl.mechanisms", mechanism)
+            .set("sasl.username", username)
+            .set("sasl.password", password.expose_secret());
+    }
+    cfg.create().context("creating kafka producer")
+}
+
+pub async fn run_bench(
+    name: Arc<str>,
+    kcfg: &KafkaSourceConfig,
+    connections: u16,
+    payload: Vec<u8>,
+    max_bytes: usize,
+    seconds: u64,
+) -> Result<()> {
+    info!("===Starting {name} benchmark===");
+    info!(
+        "brokers={} topic={} connections={}",
+        kcfg.brokers, kcfg.topic, connections,
+    );
+
+    let producer = build_producer(kcfg)?;
+    produce(
+        producer,
+        &kcfg.topic,
+        connections,
+        payload,
+        max_bytes,
+        seconds,
+    )
+    .await
+}
@@ -1,18 +1,30 @@
 use anyhow::{Context, Result};
-use serde_json::Value;
+use secrecy::ExposeSecret;
+use serde_json::{json, Value};
 use std::{fs, path::PathBuf, time::Instant};
 use tangent_shared::{sources::common::SourceConfig, Config};
 
-use crate::metrics::Stats;
+use crate::metrics::{HistogramSnapshot, Stats};
+use crate::rate::Pace;
 
+pub mod http;
+pub mod ip_geo;
+pub mod kafka;
 pub mod metrics;
 pub mod msk;
+pub mod pulsar;
+pub mod rate;
+pub mod redis_streams;
 pub mod socket;
 pub mod sqs;
 pub mod synthesize;
 pub mod tcp;
 
 const WARMUP_SECS: u64 = 5;
+/// Share of one payload per connection that must be consumed during warmup
+/// before the pipeline is considered to be moving.
+const WARMUP_MIN_FRACTION: f64 = 0.5;
+const GUEST_LATENCY_HISTOGRAM: &str = "tangent_guest_seconds";
 
 /// Options for running the benchmark.
 #[derive(Debug, Clone)]
@@ -37,6 +49,15 @@ pub struct BenchOptions {
     pub disable_metrics: bool,
     // Whether to use the payload as-is or synthesize new logs from the payload.
     pub synthesize: bool,
+    // Write the run settings and per-source results (throughput, guest
+    // latency percentiles) as JSON.
+    pub output: Option<PathBuf>,
+    /// Send at this many MB/s across all connections instead of as fast as
+    /// possible (socket and tcp sources).
+    pub rate_mb_s: Option<f64>,
+    /// Ramp linearly from 0 to `rate_mb_s` over this many seconds before
+    /// warmup starts.
+    pub ramp_seconds: u64,
 }
 
 impl Default for BenchOptions {
@@ -52,6 +73,9 @@ impl Default for BenchOptions {
             object_prefix: None,
             disable_metrics: false,
             synthesize: false,
+            output: None,
+            rate_mb_s: None,
+            ramp_seconds: 0,
         }
     }
 }
@@ -86,7 +110,14 @@ pub async fn run_with_config(cfg: Config, opts: BenchOptions) -> Result<()> {
         }
     }
 
-    run_one_payload(
+    let pace = match opts.rate_mb_s {
+        Some(r) if r > 0.0 => Some(Pace::from_mb_s(r, opts.ramp_seconds)),
+        Some(r) => anyhow::bail!("--rate-mb-s must be positive, got {r}"),
+        None if opts.ramp_seconds > 0 => anyhow::bail!("--ramp-seconds requires --rate-mb-s"),
+        None => None,
+    };
+
+    let results = run_one_payload(
         cfg,
         &opts.metrics_url,
         opts.connections,
@@ -97,9 +128,25 @@ pub async fn run_with_config(cfg: Config, opts: BenchOptions) -> Result<()> {
         opts.object_prefix.clone(),
         opts.disable_metrics,
         opts.synthesize,
+        pace,
     )
     .await?;
 
+    if let Some(path) = &opts.output {
+        let report = json!({
+            "tangent_version": env!("CARGO_PKG_VERSION"),
+            "payload_path": opts.payload.display().to_string(),
+            "connections": opts.connections,
+            "seconds": opts.seconds,
+            "rate_mb_s": opts.rate_mb_s,
+            "ramp_seconds": opts.ramp_seconds,
+            "results": results,
+        });
+        fs::write(path, serde_json::to_vec_pretty(&report)?)
+            .with_context(|| format!("failed to write report {}", path.display()))?;
+        println!("report written to {}", path.display());
+    }
+
     Ok(())
 }
 
@@ -114,17 +161,33 @@ pub async fn run_one_payload(
     obj_prefix: Option<String>,
     disable_metrics: bool,
     synthesize_payload: bool,
-) -> Result<()> {
+    pace: Option<Pace>,
+) -> Result<Vec<Value>> {
+    let mut results = Vec::new();
+
+    let warmup_min_bytes = connections as f64 * payload.len() as f64 * WARMUP_MIN_FRACTION;
+
+    let ramp_secs = pace.map_or(0, |p| p.ramp.as_secs());
+
     for (name, src) in &cfg.sources {
         let pd = payload.clone();
 
-        // Single-run with warmup: run bench for warmup_seconds + seconds.
+        if pace.is_some() && !matches!(src, SourceConfig::Socket(_) | SourceConfig::Tcp(_)) {
+            anyhow::bail!(
+                "source {name}: --rate-mb-s is only supported for socket and tcp sources"
+            );
+        }
+
+        // Single-run with ramp and warmup: run bench for ramp + warmup_seconds + seconds.
         // Capture baseline metrics exactly at warmup boundary (or immediately if warmup is 0).
-        let total_seconds = seconds.saturating_add(WARMUP_SECS);
+        let total_seconds = seconds
+            .saturating_add(WARMUP_SECS)
+            .saturating_add(ramp_secs);
 
         let (before_pair_res, bench_res) = {
             tracing::info!(
-                "warmup: {}s, then measuring {}s for source {}",
+                "ramp: {}s, warmup: {}s, then measuring {}s for source {}",
+                ramp_secs,
                 WARMUP_SECS,
                 seconds,
                 name
@@ -133,11 +196,30 @@ pub async fn run_one_payload(
             tokio::join!(
                 async {
                     if disable_metrics {
-                        return Ok::<Option<(Stats, Instant)>, anyhow::Error>(None);
+                        return Ok::<Option<(Stats, HistogramSnapshot, Instant)>, anyhow::Error>(
+                            None,
+                        );
                     }
+                    tokio::time::sleep(std::time::Duration::from_secs(ramp_secs)).await;
+                    let warmup_start = metrics::scrape_stats(metrics_url).await?;
                     tokio::time::sleep(std::time::Duration::from_secs(WARMUP_SECS)).await;
                     let stats = metrics::scrape_stats(metrics_url).await?;
-                    Ok::<Option<(Stats, Instant)>, anyhow::Error>(Some((stats, Instant::now())))
+                    let consumed = stats.consumer_bytes - warmup_start.consumer_bytes;
+                    if consumed < warmup_min_bytes {
+                        tracing::warn!(
+                            "pipeline appears stalled during warmup for source {}: consumed {:.0} bytes, expected at least {:.0}",
+                            name,
+                            consumed,
+                            warmup_min_bytes
+                        );
+                    }
+                    let hist =
+                        metrics::scrape_histogram(metrics_url, GUEST_LATENCY_HISTOGRAM).await?;
+                    Ok::<Option<(Stats, HistogramSnapshot, Instant)>, anyhow::Error>(Some((
+                        stats,
+                        hist,
+                        Instant::now(),
+                    )))
                 },
                 async {
                     match src {
@@ -150,6 +232,7 @@ pub async fn run_one_payload(
                                 max_bytes,
                                 total_seconds,
                                 synthesize_payload,
+                                pace,
                             )
                             .await
                         }
@@ -164,6 +247,17 @@ pub async fn run_one_payload(
                             )
                             .await
                         }
+                        SourceConfig::Kafka(kc) => {
+                            kafka::run_bench(
+                                name.clone(),
+                                kc,
+                                connections,
+                                pd,
+                                max_bytes,
+                                total_seconds,
+                            )
+                            .await
+                        }
                         SourceConfig::SQS(sq) => {
                             if let Some(ref b) = bucket {
                                 sqs::run_bench(
@@ -187,14 +281,49 @@ pub async fn run_one_payload(
                                 connections,
                                 pd,
                                 max_bytes,
-                                seconds,
+                                seconds.saturating_add(ramp_secs),
                                 synthesize_payload,
+                                pace,
                             )
                             .await
                         }
+                        SourceConfig::Http(hc) => {
+                            http::run_bench(
+                                name.clone(),
+                                hc.bind_address,
+                                hc.path.clone(),
+                                hc.bearer_token
+                                    .as_ref()
+                                    .map(|t| t.expose_secret().to_string()),
+                                connections,
+                                pd,
+                                max_bytes,
+                                total_seconds,
+                                synthesize_payload,
+                            )
+                            .await
+                        }
+                        SourceConfig::RedisStreams(rc) => {
+                            redis_streams::run_bench(
+                                name.clone(),
+                                rc,
+                                connections,
+                                pd,
+                                total_seconds,
+                            )
+                            .await
+                        }
+                        SourceConfig::Pulsar(pc) => {
+                            pulsar::run_bench(name.clone(), pc, connections, pd, total_seconds)
+                                .await
+                        }
                         SourceConfig::NPMRegistry(_) => unimplemented!("not implemented"),
                         SourceConfig::GithubWebhook(_) => unimplemented!("not implemented"),
                         SourceConfig::File(_) => unimplemented!("not implemented"),
+                        SourceConfig::HttpPolling(_) => unimplemented!("not implemented"),
+                        SourceConfig::Grpc(_) => unimplemented!("not implemented"),
+                        SourceConfig::CloudWatchLogs(_) => unimplemented!("not implemented"),
+                        SourceConfig::DockerEvents(_) => unimplemented!("not implemented"),
                     }
                 }
             )
@@ -204,8 +333,11 @@ pub async fn run_one_payload(
         let t1 = Instant::now();
 
         if !disable_metrics {
-            let (before, t0) = before_pair_res?.expect("metrics baseline missing");
+            let (before, before_hist, t0) = before_pair_res?.expect("metrics baseline missing");
             let drained = metrics::scrape_stats(metrics_url).await?;
+            let guest_hist = metrics::scrape_histogram(metrics_url, GUEST_LATENCY_HISTOGRAM)
+                .await?
+                .delta(&before_hist);
             let elapsed = t1.duration_since(t0).as_secs_f64();
 
             let in_bytes = (drained.consumer_bytes - before.consumer_bytes) as f64;
@@ -250,8 +382,42 @@ pub async fn run_one_payload(
                 guest_avg_ms,
                 guest_cnt_delta
             );
+
+            let p50_ms = guest_hist.quantile(0.50) * 1_000.0;
+            let p95_ms = guest_hist.quantile(0.95) * 1_000.0;
+            let p99_ms = guest_hist.quantile(0.99) * 1_000.0;
+            println!(
+                "guest latency: p50={:.3} ms, p95={:.3} ms, p99={:.3} ms",
+                p50_ms, p95_ms, p99_ms
+            );
+
+            // Uncompressed over on-the-wire sink bytes; 1.0 when sinks don't compress.
+            let compression_ratio = if out_bytes > 0.0 {
+                out_bytes_uncompressed / out_bytes
+            } else {
+                0.0
+            };
+
+            results.push(json!({
+                "source_name": name,
+                "elapsed_s": elapsed,
+                "in_bytes": in_bytes,
+                "out_bytes": out_bytes,
+                "out_bytes_uncompressed": out_bytes_uncompressed,
+                "in_mb_s": in_mbs_s,
+                "out_mb_s": out_mbs_s,
+                "out_mb_s_uncompressed": out_mbs_uncompressed_s,
+                "compression_ratio": compression_ratio,
+                "amplification": amp,
+                "guest_bytes_in": guest_bytes_delta,
+                "guest_avg_latency_ms": guest_avg_ms,
+                "guest_call_count": guest_cnt_delta,
+                "guest_p50_ms": p50_ms,
+                "guest_p95_ms": p95_ms,
+                "guest_p99_ms": p99_ms,
+            }));
         }
     }
 
-    Ok(())
+    Ok(results)
 }
@@ -5,15 +5,77 @@ pub struct Stats {
     pub sink_bytes_uncompressed: f64,
     pub inflight: f64,
     pub wal_pending: f64,
+    pub wal_pending_bytes: f64,
     pub consumer_bytes: f64,
     pub guest_bytes: f64,
     pub guest_seconds_sum: f64,
     pub guest_seconds_count: f64,
 }
 
+/// Cumulative histogram summed across all label sets. `buckets` holds
+/// `(upper_bound, cumulative_count)` sorted by bound, ending at `+Inf`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistogramSnapshot {
+    pub count: f64,
+    pub sum: f64,
+    pub buckets: Vec<(f64, f64)>,
+}
+
+impl HistogramSnapshot {
+    /// Observations recorded between `before` and `self`.
+    pub fn delta(&self, before: &HistogramSnapshot) -> HistogramSnapshot {
+        let buckets = self
+            .buckets
+            .iter()
+            .map(|(le, c)| {
+                let prev = before
+                    .buckets
+                    .iter()
+                    .find(|(ple, _)| ple == le)
+                    .map_or(0.0, |(_, pc)| *pc);
+                (*le, c - prev)
+            })
+            .collect();
+        HistogramSnapshot {
+            count: self.count - before.count,
+            sum: self.sum - before.sum,
+            buckets,
+        }
+    }
+
+    /// Estimate the `q` quantile (0..=1) by linear interpolation inside the
+    /// bucket that crosses the rank, like PromQL's `histogram_quantile`.
+    /// Returns 0 when nothing was observed.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let total = self.buckets.last().map_or(0.0, |(_, c)| *c);
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let rank = q.clamp(0.0, 1.0) * total;
+
+        let mut lower = 0.0;
+        let mut below = 0.0;
+        for (le, c) in &self.buckets {
+            if *c >= rank {
+                if le.is_infinite() {
+                    // Past the last finite bound; report that bound.
+                    return lower;
+                }
+                let in_bucket = c - below;
+                if in_bucket <= 0.0 {
+                    return *le;
+                }
+                return lower + (le - lower) * (rank - below) / in_bucket;
+            }
+            lower = *le;
+            below = *c;
+        }
+        lower
+    }
+}
+
 pub async fn scrape_stats(url: &str) -> anyhow::Result<Stats> {
-    let body = reqwest::get(url).await?.text().await?;
-    let scrape = prometheus_parse::Scrape::parse(body.lines().map(|s| Ok(s.to_string())))?;
+    let scrape = fetch(url).await?;
     let sum_exact = |name: &str| -> f64 {
         scrape
             .samples
@@ -33,9 +95,171 @@ pub async fn scrape_stats(url: &str) -> anyhow::Result<Stats> {
         sink_bytes_uncompressed: sum_exact("tangent_sink_bytes_uncompressed_total"),
         inflight: sum_exact("tangent_inflight"),
         wal_pending: sum_exact("tangent_wal_pending_files"),
+        wal_pending_bytes: sum_exact("tangent_wal_pending_bytes"),
         consumer_bytes: sum_exact("tangent_consumer_bytes_total"),
         guest_bytes: sum_exact("tangent_guest_bytes_total"),
         guest_seconds_sum: sum_exact("tangent_guest_seconds_sum"),
         guest_seconds_count: sum_exact("tangent_guest_seconds_count"),
     })
 }
+
+/// Scrape histogram `name` (e.g. `tangent_guest_seconds`), merging the
+/// buckets of every label set.
+pub async fn scrape_histogram(url: &str, name: &str) -> anyhow::Result<HistogramSnapshot> {
+    let scrape = fetch(url).await?;
+    Ok(histogram_from_scrape(&scrape, name))
+}
+
+/// Every series at `url` as a JSON object keyed by metric name, plus labels
+/// in exposition syntax when the series has any. Histograms map to an array
+/// of cumulative `{le, count}` buckets and summaries to `{quantile, value}`;
+/// their `_sum` and `_count` are separate series.
+pub async fn scrape_json(url: &str) -> anyhow::Result<serde_json::Value> {
+    let scrape = fetch(url).await?;
+    Ok(scrape_to_json(&scrape))
+}
+
+async fn fetch(url: &str) -> anyhow::Result<prometheus_parse::Scrape> {
+    let body = reqwest::get(url).await?.text().await?;
+    Ok(prometheus_parse::Scrape::parse(
+        body.lines().map(|s| Ok(s.to_string())),
+    )?)
+}
+
+fn scrape_to_json(scrape: &prometheus_parse::Scrape) -> serde_json::Value {
+    use serde_json::{json, Map, Value};
+
+    let mut out = Map::new();
+    for s in &scrape.samples {
+        let key = if s.labels.is_empty() {
+            s.metric.clone()
+        } else {
+            format!("{}{{{}}}", s.metric, s.labels)
+        };
+        let value = match &s.value {
+            prometheus_parse::Value::Counter(v)
+            | prometheus_parse::Value::Gauge(v)
+            | prometheus_parse::Value::Untyped(v) => json!(v),
+            prometheus_parse::Value::Histogram(hs) => hs
+                .iter()
+                .map(|h| {
+                    let le = if h.less_than.is_infinite() {
+                        "+Inf".to_string()
+                    } else {
+                        h.less_than.to_string()
+                    };
+                    json!({ "le": le, "count": h.count })
+                })
+                .collect(),
+            prometheus_parse::Value::Summary(ss) => ss
+                .iter()
+                .map(|q| json!({ "quantile": q.quantile, "value": q.count }))
+                .collect(),
+        };
+        out.insert(key, value);
+    }
+    Value::Object(out)
+}
+
+fn histogram_from_scrape(scrape: &prometheus_parse::Scrape, name: &str) -> HistogramSnapshot {
+    let sum_name = format!("{name}_sum");
+    let count_name = format!("{name}_count");
+
+    let mut snap = HistogramSnapshot::default();
+    for s in &scrape.samples {
+        match &s.value {
+            prometheus_parse::Value::Histogram(hs) if s.metric == name => {
+                for h in hs {
+                    match snap.buckets.iter_mut().find(|(le, _)| *le == h.less_than) {
+                        Some((_, c)) => *c += h.count,
+                        None => snap.buckets.push((h.less_than, h.count)),
+                    }
+                }
+            }
+            prometheus_parse::Value::Counter(v)
+            | prometheus_parse::Value::Gauge(v)
+            | prometheus_parse::Value::Untyped(v) => {
+                if s.metric == sum_name {
+                    snap.sum += v;
+                } else if s.metric == count_name {
+                    snap.count += v;
+                }
+            }
+            _ => {}
+        }
+    }
+    snap.buckets.sort_by(|a, b| a.0.total_cmp(&b.0));
+    snap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = r#"# HELP tangent_guest_seconds WASM guest call latency (sec)
+# TYPE tangent_guest_seconds histogram
+tangent_guest_seconds_bucket{worker="0",le="0.001"} 50
+tangent_guest_seconds_bucket{worker="0",le="0.002"} 90
+tangent_guest_seconds_bucket{worker="0",le="0.004"} 100
+tangent_guest_seconds_bucket{worker="0",le="+Inf"} 100
+tangent_guest_seconds_sum{worker="0"} 0.12
+tangent_guest_seconds_count{worker="0"} 100
+tangent_guest_seconds_bucket{worker="1",le="0.001"} 50
+tangent_guest_seconds_bucket{worker="1",le="0.002"} 90
+tangent_guest_seconds_bucket{worker="1",le="0.004"} 100
+tangent_guest_seconds_bucket{worker="1",le="+Inf"} 100
+tangent_guest_seconds_sum{worker="1"} 0.08
+tangent_guest_seconds_count{worker="1"} 100
+"#;
+
+    #[test]
+    fn histogram_merges_workers_and_interpolates_quantiles() {
+        let scrape =
+            prometheus_parse::Scrape::parse(BODY.lines().map(|s| Ok(s.to_string()))).unwrap();
+        let h = histogram_from_scrape(&scrape, "tangent_guest_seconds");
+
+        assert_eq!(h.count, 200.0);
+        assert!((h.sum - 0.2).abs() < 1e-9);
+        assert_eq!(h.buckets.len(), 4);
+        assert_eq!(h.buckets[3], (f64::INFINITY, 200.0));
+
+        assert!((h.quantile(0.5) - 0.001).abs() < 1e-9);
+        assert!((h.quantile(0.95) - 0.003).abs() < 1e-9);
+        assert!((h.quantile(0.99) - 0.0038).abs() < 1e-9);
+
+        let d = h.delta(&h);
+        assert_eq!(d.count, 0.0);
+        assert_eq!(d.quantile(0.99), 0.0);
+    }
+
+    #[test]
+    fn scrape_exports_every_series_as_json() {
+        let body = format!(
+            "{BODY}# TYPE tangent_inflight gauge\ntangent_inflight 3\n\
+             # TYPE tangent_consumer_bytes_total counter\n\
+             tangent_consumer_bytes_total{{source=\"kafka\",kind=\"msk\"}} 1024\n"
+        );
+        let scrape =
+            prometheus_parse::Scrape::parse(body.lines().map(|s| Ok(s.to_string()))).unwrap();
+        let out = scrape_to_json(&scrape);
+
+        assert_eq!(out["tangent_inflight"], serde_json::json!(3.0));
+        assert_eq!(
+            out[r#"tangent_consumer_bytes_total{kind="msk",source="kafka"}"#],
+            serde_json::json!(1024.0)
+        );
+        assert_eq!(
+            out[r#"tangent_guest_seconds{worker="1"}"#],
+            serde_json::json!([
+                { "le": "0.001", "count": 50.0 },
+                { "le": "0.002", "count": 90.0 },
+                { "le": "0.004", "count": 100.0 },
+                { "le": "+Inf", "count": 100.0 }
+            ])
+        );
+        assert_eq!(
+            out[r#"tangent_guest_seconds_count{worker="0"}"#],
+            serde_json::json!(100.0)
+        );
+    }
+}
@@ -48,14 +48,31 @@ pub async fn run_bench(
         } => build_scram_producer(&kcfg.bootstrap_servers, username, password.expose_secret()),
     };
 
-    let topic = kcfg.topic.clone();
-    let seconds = seconds;
-    let max_bytes = max_bytes;
+    produce(
+        producer,
+        &kcfg.topic,
+        connections,
+        payload,
+        max_bytes,
+        seconds,
+    )
+    .await
+}
 
+/// Send `payload`, repeated up to `max_bytes` per record, to `topic` from
+/// `connections` tasks for `seconds`.
+pub(crate) async fn produce(
+    producer: FutureProducer,
+    topic: &str,
+    connections: u16,
+    payload: Vec<u8>,
+    max_bytes: usize,
+    seconds: u64,
+) -> Result<()> {
     let mut tasks = Vec::with_capacity(connections as usize);
     for _ in 0..connections {
         let p = producer.clone();
-        let topic = topic.clone();
+        let topic = topic.to_string();
         let line_cl = payload.clone();
 
         tasks.push(task::spawn(async move {
@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+use pulsar::{Pulsar, TokioExecutor};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tangent_shared::sources::pulsar::PulsarSourceConfig;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+pub async fn run_bench(
+    name: Arc<str>,
+    cfg: &PulsarSourceConfig,
+    connections: u16,
+    payload: Vec<u8>,
+    seconds: u64,
+) -> Result<()> {
+    info!("===Starting benchmark===");
+    info!(
+        "source={} topic={} connections={} duration={}s",
+        name, cfg.topic, connections, seconds
+    );
+
+    let client: Pulsar<TokioExecutor> = Pulsar::builder(cfg.service_url.as_str(), TokioExecutor)
+        .build()
+        .await
+        .with_context(|| format!("pulsar unreachable: {}", cfg.service_url))?;
+    let deadline = Instant::now() + Duration::from_secs(seconds);
+
+    let mut handles: Vec<JoinHandle<Result<u64>>> = Vec::with_capacity(connections as usize);
+    for _ in 0..connections {
+        let mut producer = client
+            .producer()
+            .with_topic(&cfg.topic)
+            .build()
+            .await
+            .with_context(|| format!("creating producer for {}", cfg.topic))?;
+        let topic = cfg.topic.clone();
+        let bytes = payload.clone();
+
+        handles.push(tokio::spawn(async move {
+            let mut counter: u64 = 0;
+
+            while Instant::now() < deadline {
+                let sent = match producer.send_non_blocking(bytes.clone()).await {
+                    Ok(receipt) => receipt.await.map(|_| ()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = sent {
+                    tracing::warn!("send to {topic} failed: {e}");
+                    continue;
+                }
+                counter += 1;
+            }
+
+            Ok(counter)
+        }));
+    }
+
+    for h in handles {
+        h.await??;
+    }
+
+    Ok(())
+}
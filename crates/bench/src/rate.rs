@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant};
+
+/// Burst allowance: how far ahead of the target rate a producer may get
+/// after stalling, in seconds of traffic.
+const BURST_SECS: f64 = 0.1;
+
+/// Target send rate for a bench run, optionally reached by a linear ramp.
+#[derive(Debug, Clone, Copy)]
+pub struct Pace {
+    pub bytes_per_sec: f64,
+    pub ramp: Duration,
+}
+
+impl Pace {
+    pub fn from_mb_s(mb_s: f64, ramp_seconds: u64) -> Self {
+        Self {
+            bytes_per_sec: mb_s * 1_000_000.0,
+            ramp: Duration::from_secs(ramp_seconds),
+        }
+    }
+
+    /// The share of this pace each of `connections` producers should send at.
+    pub fn per_connection(self, connections: u16) -> Self {
+        Self {
+            bytes_per_sec: self.bytes_per_sec / f64::from(connections.max(1)),
+            ..self
+        }
+    }
+}
+
+/// Token bucket whose refill rate climbs linearly from 0 to the target over
+/// `Pace::ramp`, then holds.
+pub struct TokenBucket {
+    pace: Pace,
+    start: Instant,
+    /// Seconds since `start` at the last refill.
+    last: f64,
+    tokens: f64,
+}
+
+impl TokenBucket {
+    pub fn new(pace: Pace) -> Self {
+        Self {
+            pace,
+            start: Instant::now(),
+            last: 0.0,
+            tokens: 0.0,
+        }
+    }
+
+    /// Wait until `n` bytes may be sent, then take them from the bucket.
+    pub async fn acquire(&mut self, n: usize) {
+        let n = n as f64;
+        // A single write larger than the burst still has to fit.
+        let capacity = n.max(self.pace.bytes_per_sec * BURST_SECS);
+        loop {
+            let now = self.start.elapsed().as_secs_f64();
+            let earned = self.allowance(now) - self.allowance(self.last);
+            self.tokens = (self.tokens + earned).min(capacity);
+            self.last = now;
+            if self.tokens >= n {
+                self.tokens -= n;
+                return;
+            }
+            let ready_at = self.time_for(self.allowance(now) + n - self.tokens);
+            tokio::time::sleep(Duration::from_secs_f64((ready_at - now).max(0.0))).await;
+        }
+    }
+
+    /// Bytes the pace allows in the first `t` seconds.
+    fn allowance(&self, t: f64) -> f64 {
+        let rate = self.pace.bytes_per_sec;
+        let ramp = self.pace.ramp.as_secs_f64();
+        if t < ramp {
+            rate * t * t / (2.0 * ramp)
+        } else {
+            rate * (t - ramp / 2.0)
+        }
+    }
+
+    /// Inverse of `allowance`: when the first `bytes` bytes are allowed.
+    fn time_for(&self, bytes: f64) -> f64 {
+        let rate = self.pace.bytes_per_sec;
+        let ramp = self.pace.ramp.as_secs_f64();
+        if bytes < rate * ramp / 2.0 {
+            (2.0 * bytes * ramp / rate).sqrt()
+        } else {
+            bytes / rate + ramp / 2.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramp_allowance_is_continuous_and_invertible() {
+        let bucket = TokenBucket::new(Pace::from_mb_s(1.0, 10));
+        // Half the target on average over the ramp, then the full rate.
+        assert_eq!(bucket.allowance(10.0), 5_000_000.0);
+        assert_eq!(bucket.allowance(12.0), 7_000_000.0);
+        for t in [0.5, 3.0, 10.0, 25.0] {
+            let back = bucket.time_for(bucket.allowance(t));
+            assert!((back - t).abs() < 1e-9, "t={t} back={back}");
+        }
+
+        let flat = TokenBucket::new(Pace::from_mb_s(2.0, 0));
+        assert_eq!(flat.allowance(3.0), 6_000_000.0);
+        assert_eq!(flat.time_for(6_000_000.0), 3.0);
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{Context, Result};
+use redis::AsyncCommands;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tangent_shared::sources::redis_streams::RedisStreamsConfig;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+pub async fn run_bench(
+    name: Arc<str>,
+    cfg: &RedisStreamsConfig,
+    connections: u16,
+    payload: Vec<u8>,
+    seconds: u64,
+) -> Result<()> {
+    info!("===Starting benchmark===");
+    info!(
+        "source={} stream={} connections={} duration={}s",
+        name, cfg.stream, connections, seconds
+    );
+
+    let client = redis::Client::open(cfg.url.as_str()).context("invalid redis url")?;
+    let deadline = Instant::now() + Duration::from_secs(seconds);
+
+    let mut handles: Vec<JoinHandle<Result<u64>>> = Vec::with_capacity(connections as usize);
+    for _ in 0..connections {
+        let mut conn = client
+            .get_multiplexed_async_connection()
+            .await
+            .with_context(|| format!("redis unreachable: {}", cfg.url))?;
+        let stream = cfg.stream.clone();
+        let field = cfg.field.clone();
+        let bytes = payload.clone();
+
+        handles.push(tokio::spawn(async move {
+            let mut counter: u64 = 0;
+
+            while Instant::now() < deadline {
+                let res: redis::RedisResult<String> =
+                    conn.xadd(&stream, "*", &[(&field, &bytes)]).await;
+                if let Err(e) = res {
+                    tracing::warn!("XADD to {stream} failed: {e}");
+                    continue;
+                }
+                counter += 1;
+            }
+
+            Ok(counter)
+        }));
+    }
+
+    for h in handles {
+        h.await??;
+    }
+
+    Ok(())
+}
@@ -8,7 +8,8 @@ use std::{
 use tokio::{self, io::AsyncWriteExt, net::UnixStream};
 use tracing::info;
 
-use crate::synthesize::{Scope, Synth};
+use crate::rate::{Pace, TokenBucket};
+use crate::synthesize::{Sequences, Synth};
 
 pub async fn run_bench(
     name: Arc<str>,
@@ -18,6 +19,7 @@ pub async fn run_bench(
     max_bytes: usize,
     seconds: u64,
     synthesize_payload: bool,
+    pace: Option<Pace>,
 ) -> Result<()> {
     info!("===Starting benchmark===");
     info!(
@@ -27,9 +29,12 @@ pub async fn run_bench(
 
     let mut handles = Vec::with_capacity(connections as usize);
 
+    let sequences = Sequences::default();
     for _ in 0..connections {
         let payload = payload.clone();
         let uds = socket.clone();
+        let sequences = sequences.clone();
+        let mut bucket = pace.map(|p| TokenBucket::new(p.per_connection(connections)));
 
         handles.push(tokio::spawn(async move {
             let mut s = UnixStream::connect(&uds)
@@ -37,7 +42,7 @@ pub async fn run_bench(
                 .with_context(|| format!("socket does not exist: {}", uds.display()))?;
             let deadline = Instant::now() + Duration::from_secs(seconds);
 
-            let mut synth = Synth::new(rand::random::<u64>());
+            let mut synth = Synth::with_sequences(rand::random::<u64>(), sequences);
             let templates: Vec<Option<Value>> = payload
                 .clone()
                 .split(|b| *b == b'\n')
@@ -57,18 +62,14 @@ pub async fn run_bench(
                     'fill: loop {
                         for template in templates.iter() {
                             if let Some(tmpl) = template {
-                                let mut scope = Scope::new(&tmpl);
+                                let start = buf.len();
+                                synth.append_ndjson(tmpl, &mut buf)?;
 
-                                let v = synth.gen(&tmpl, &mut scope)?;
-
-                                let mut line = serde_json::to_vec(&v)?;
-                                line.push(b'\n');
-
-                                if max_bytes > 0 && buf.len() + line.len() > max_bytes {
+                                if max_bytes > 0 && buf.len() > max_bytes {
+                                    buf.truncate(start);
                                     break 'fill;
                                 }
 
-                                buf.extend_from_slice(&line);
                                 events_per_buff += 1;
 
                                 if max_bytes == 0 {
@@ -94,6 +95,13 @@ pub async fn run_bench(
                     events_per_buff = 1;
                 }
 
+                if let Some(bucket) = bucket.as_mut() {
+                    bucket.acquire(buf.len()).await;
+                    if Instant::now() >= deadline {
+                        break;
+                    }
+                }
+
                 match s.write_all(&buf).await {
                     Ok(()) => total_events += events_per_buff,
                     Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => break,
@@ -5,18 +5,47 @@ use anyhow::{bail, Context, Result};
 use rand_chacha::rand_core::SeedableRng;
 use rand_chacha::ChaCha8Rng;
 use std::collections::HashMap;
+use std::net::Ipv6Addr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// `$sequence` counters keyed by field path. Clones share the counters, so
+/// every `Synth` built from one handle draws from the same global sequence.
+#[derive(Clone, Default)]
+pub struct Sequences(Arc<Mutex<HashMap<String, Arc<AtomicI64>>>>);
+
+impl Sequences {
+    fn counter(&self, path: &str, start: i64) -> Arc<AtomicI64> {
+        let mut counters = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let c = counters
+            .entry(path.to_string())
+            .or_insert_with(|| Arc::new(AtomicI64::new(start)));
+        Arc::clone(c)
+    }
+}
 
 /// Per-thread generator with a seeded RNG and counters.
 pub struct Synth {
     rng: ChaCha8Rng,
     counters: HashMap<String, i64>,
+    sequences: Sequences,
+    /// Local handles to `sequences` entries, to skip the lock per value.
+    sequence_cache: HashMap<String, Arc<AtomicI64>>,
 }
 
 impl Synth {
     pub fn new(seed: u64) -> Self {
+        Self::with_sequences(seed, Sequences::default())
+    }
+
+    /// Like `new`, drawing `$sequence` values from counters shared with
+    /// other generators.
+    pub fn with_sequences(seed: u64, sequences: Sequences) -> Self {
         Self {
             rng: ChaCha8Rng::seed_from_u64(seed),
             counters: HashMap::new(),
+            sequences,
+            sequence_cache: HashMap::new(),
         }
     }
 
@@ -39,15 +68,91 @@ impl Synth {
                     }
                 }
                 let mut out = serde_json::Map::with_capacity(obj.len());
+                let mut deferred = Vec::new();
                 for (k, v) in obj {
+                    if is_correlated(v) {
+                        deferred.push((k, v));
+                        continue;
+                    }
                     let val = self.gen(v, scope.with_path(k))?;
                     out.insert(k.clone(), val);
                 }
+
+                // Correlated fields read their siblings, so they run last with
+                // the partially built object exposed through the scope.
+                if !deferred.is_empty() {
+                    let parent = std::mem::replace(&mut scope.current, out);
+                    for (k, v) in deferred {
+                        let val = self.gen(v, scope.with_path(k))?;
+                        scope.current.insert(k.clone(), val);
+                    }
+                    out = std::mem::replace(&mut scope.current, parent);
+                }
                 Ok(Value::Object(out))
             }
         }
     }
 
+    /// Generate `n` values from `spec`, each with a fresh root scope.
+    ///
+    /// Equivalent to calling `gen` in a loop; kept as its own entry point so
+    /// hot paths can be specialized per operator without touching callers.
+    pub fn gen_batch(&mut self, spec: &Value, n: usize) -> Result<Vec<Value>> {
+        let mut out = Vec::with_capacity(n);
+        for _ in 0..n {
+            let mut scope = Scope::new(spec);
+            out.push(self.gen(spec, &mut scope)?);
+        }
+        Ok(out)
+    }
+
+    /// Generate `count` values from `spec` as newline-delimited JSON.
+    ///
+    /// Records are serialized straight into one buffer, sized after the first
+    /// record, instead of allocating a `String` per line.
+    pub fn gen_ndjson_bytes(&mut self, spec: &Value, count: usize) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        for i in 0..count {
+            let n = self.append_ndjson(spec, &mut out)?;
+            if i == 0 {
+                out.reserve(n * (count - 1));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Generate one value from `spec` with a fresh root scope and append it
+    /// to `buf` as a JSON line. Returns the number of bytes written.
+    pub fn append_ndjson(&mut self, spec: &Value, buf: &mut Vec<u8>) -> Result<usize> {
+        let mut scope = Scope::new(spec);
+        let v = self.gen(spec, &mut scope)?;
+        let start = buf.len();
+        serde_json::to_writer(&mut *buf, &v)?;
+        buf.push(b'\n');
+        Ok(buf.len() - start)
+    }
+
+    /// Random non-zero W3C/B3 trace and span ids as lowercase hex.
+    fn trace_ids(&mut self) -> (String, String) {
+        let trace_id = self.rng.random_range(1..=u128::MAX);
+        let span_id = self.rng.random_range(1..=u64::MAX);
+        (format!("{trace_id:032x}"), format!("{span_id:016x}"))
+    }
+
+    /// Roll the `sampled` probability (0..=1, default 1) from `{sampled}`.
+    fn sample_flag(&mut self, arg: &Value, op: &str) -> Result<bool> {
+        let p = match arg.get("sampled") {
+            None => 1.0,
+            Some(v) => v
+                .as_f64()
+                .with_context(|| format!("{op}.sampled must be a number"))?,
+        };
+        if !(0.0..=1.0).contains(&p) {
+            bail!("{op}.sampled must be between 0 and 1, got {p}");
+        }
+        Ok(self.rng.random_bool(p))
+    }
+
     fn eval_op(&mut self, op: &str, arg: &Value, scope: &mut Scope) -> Result<Value> {
         match op {
             "$const" => Ok(arg.clone()),
@@ -82,6 +187,44 @@ impl Synth {
                 self.gen(weights.last().unwrap().0, scope)
             }
 
+            "$schema_union" | "$weighted_object_merge" => {
+                let o = arg
+                    .as_object()
+                    .context("$schema_union expects {variants:[{weight,schema}]}")?;
+                let variants = o
+                    .get("variants")
+                    .and_then(Value::as_array)
+                    .context("variants")?;
+                let mut total = 0.0f64;
+                let mut schemas = Vec::with_capacity(variants.len());
+                for v in variants {
+                    let w = v
+                        .get("weight")
+                        .and_then(Value::as_f64)
+                        .context("$schema_union variant missing weight")?;
+                    let schema = v
+                        .get("schema")
+                        .context("$schema_union variant missing schema")?;
+                    if w < 0.0 {
+                        bail!("$schema_union weight must be >= 0, got {w}");
+                    }
+                    schemas.push((schema, w));
+                    total += w;
+                }
+                if schemas.is_empty() || total <= 0.0 {
+                    bail!("$schema_union needs at least one variant with weight > 0");
+                }
+                let mut pick = self.rng.random::<f64>() * total;
+                for (schema, w) in schemas.iter() {
+                    if pick < *w {
+                        return self.gen(schema, scope);
+                    }
+                    pick -= w;
+                }
+                let (last, _) = schemas.iter().rev().find(|(_, w)| *w > 0.0).unwrap();
+                self.gen(last, scope)
+            }
+
             "$int" => {
                 let o = arg.as_object().context("$int expects object {min,max}")?;
                 let min = o.get("min").and_then(Value::as_i64).unwrap_or(0);
@@ -132,16 +275,48 @@ impl Synth {
 
             "$uuid" => Ok(Value::from(uuid::Uuid::new_v4().to_string())),
 
-            "$now" => {
-                let fmt = arg
-                    .get("format")
-                    .and_then(Value::as_str)
-                    .unwrap_or("rfc3339");
-                let now = chrono::Utc::now();
-                let v = match fmt {
-                    "unix_ms" => Value::from(now.timestamp_millis()),
-                    "unix" => Value::from(now.timestamp()),
-                    _ => Value::from(now.to_rfc3339()),
+            "$now" => Ok(format_time(chrono::Utc::now(), arg)),
+
+            "$timestamp" => {
+                let offset_ms = match arg.get("offset_ms") {
+                    None => 0,
+                    Some(spec) => self
+                        .gen(spec, scope)?
+                        .as_i64()
+                        .context("$timestamp.offset_ms must produce an integer")?,
+                };
+                let ts = chrono::Utc::now() + chrono::Duration::milliseconds(offset_ms);
+                Ok(format_time(ts, arg))
+            }
+
+            "$epoch_ms" | "$epoch_s" => {
+                let o = arg
+                    .as_object()
+                    .with_context(|| format!("{op} expects {{start_secs_ago,end_secs_ago}}"))?;
+                let start = o.get("start_secs_ago").and_then(Value::as_u64).unwrap_or(0);
+                let end = match o.get("end_secs_ago").and_then(Value::as_u64) {
+                    Some(end) => end,
+                    None => start
+                        .checked_add(3600)
+                        .with_context(|| format!("{op}: start_secs_ago is too large"))?,
+                };
+                if start > end {
+                    bail!("{op}: start_secs_ago must be <= end_secs_ago");
+                }
+                let now_ms = chrono::Utc::now().timestamp_millis();
+                let ago = |secs: u64| {
+                    secs.checked_mul(1000)
+                        .and_then(|ms| i64::try_from(ms).ok())
+                        .and_then(|ms| now_ms.checked_sub(ms))
+                        .with_context(|| format!("{op}: {secs} seconds ago is out of range"))
+                };
+                let lo = ago(end)?;
+                let hi = ago(start)?;
+                let ts = self.rng.random_range(lo..=hi);
+                let v = if op == "$epoch_s" {
+                    Value::from(ts / 1000)
+                } else {
+                    Value::from(ts)
                 };
                 Ok(v)
             }
@@ -151,6 +326,150 @@ impl Synth {
                 Ok(Value::from(format!("{}.{}.{}.{}", a[0], a[1], a[2], a[3])))
             }
 
+            "$ip6" => {
+                let a: [u16; 8] = self.rng.random();
+                Ok(Value::from(Ipv6Addr::from(a).to_string()))
+            }
+
+            "$mac" => {
+                let a: [u8; 6] = self.rng.random();
+                Ok(Value::from(format!(
+                    "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                    a[0], a[1], a[2], a[3], a[4], a[5]
+                )))
+            }
+
+            "$unix_path" => {
+                let o = arg
+                    .as_object()
+                    .context("$unix_path expects {depth,components}")?;
+                let depth = path_depth(o, "$unix_path")?;
+                let components = o
+                    .get("components")
+                    .context("$unix_path.components missing")?;
+                if let Value::Array(names) = components {
+                    for name in names {
+                        path_component(name, "$unix_path")?;
+                    }
+                }
+                let mut path = String::new();
+                for _ in 0..depth {
+                    let name = match components {
+                        Value::Array(names) if !names.is_empty() => {
+                            names[self.rng.random_range(0..names.len())].clone()
+                        }
+                        Value::Array(_) => bail!("$unix_path.components must not be empty"),
+                        spec => self.gen(spec, scope)?,
+                    };
+                    path.push('/');
+                    path.push_str(path_component(&name, "$unix_path")?);
+                }
+                Ok(Value::from(path))
+            }
+
+            "$windows_path" => {
+                let o = arg.as_object().context("$windows_path expects {depth}")?;
+                let depth = path_depth(o, "$windows_path")?;
+                let user = WINDOWS_USERS[self.rng.random_range(0..WINDOWS_USERS.len())];
+                let mut path = String::from("C:");
+                for i in 0..depth - 1 {
+                    let dir = match i {
+                        0 => "Users",
+                        1 => user,
+                        _ => WINDOWS_DIRS.get(i - 2).copied().unwrap_or_else(|| {
+                            WINDOWS_EXTRA_DIRS[self.rng.random_range(0..WINDOWS_EXTRA_DIRS.len())]
+                        }),
+                    };
+                    path.push('\\');
+                    path.push_str(dir);
+                }
+                let n: u32 = self.rng.random_range(0..100_000);
+                path.push_str(&format!("\\tmp{n:05}.log"));
+                Ok(Value::from(path))
+            }
+
+            // `tracestate` is one `vendor=value` member; `vendor` defaults to
+            // `tangent` and `value` (any spec) to 16 random hex digits.
+            "$trace_context" => {
+                let sampled = self.sample_flag(arg, "$trace_context")?;
+                let vendor = match arg.get("vendor") {
+                    None => "tangent",
+                    Some(v) => v
+                        .as_str()
+                        .context("$trace_context.vendor must be a string")?,
+                };
+                if !vendor.starts_with(|c: char| c.is_ascii_lowercase())
+                    || !vendor.chars().all(|c| {
+                        c.is_ascii_lowercase() || c.is_ascii_digit() || "_-*/@".contains(c)
+                    })
+                {
+                    bail!("$trace_context.vendor is not a valid tracestate key: {vendor:?}");
+                }
+                let value = match arg.get("value") {
+                    None => format!("{:016x}", self.rng.random::<u64>()),
+                    Some(spec) => match self.gen(spec, scope)? {
+                        Value::String(s) => s,
+                        v => v.to_string(),
+                    },
+                };
+                if value.is_empty()
+                    || !value
+                        .chars()
+                        .all(|c| matches!(c, '!'..='~') && c != ',' && c != '=')
+                {
+                    bail!("$trace_context.value is not a valid tracestate value: {value:?}");
+                }
+                let (trace_id, span_id) = self.trace_ids();
+                Ok(serde_json::json!({
+                    "traceparent": format!("00-{trace_id}-{span_id}-{:02x}", u8::from(sampled)),
+                    "tracestate": format!("{vendor}={value}"),
+                }))
+            }
+
+            "$b3_trace" => {
+                let sampled = self.sample_flag(arg, "$b3_trace")?;
+                let (trace_id, span_id) = self.trace_ids();
+                let flag = if sampled { "1" } else { "0" };
+                match arg
+                    .get("format")
+                    .and_then(Value::as_str)
+                    .unwrap_or("single")
+                {
+                    "single" => Ok(Value::from(format!("{trace_id}-{span_id}-{flag}"))),
+                    "multi" => Ok(serde_json::json!({
+                        "X-B3-TraceId": trace_id,
+                        "X-B3-SpanId": span_id,
+                        "X-B3-Sampled": flag,
+                    })),
+                    other => bail!("$b3_trace.format must be single or multi, got {other}"),
+                }
+            }
+
+            "$ip_lookup" => {
+                let o = arg.as_object().context("$ip_lookup expects {ip}")?;
+                let ip_spec = o.get("ip").context("$ip_lookup.ip missing")?;
+                let ip = self.gen(ip_spec, scope)?;
+                let ip = ip.as_str().context("$ip_lookup.ip must produce a string")?;
+                Ok(crate::ip_geo::lookup(ip).to_value())
+            }
+
+            "$env" => {
+                let o = arg.as_object().context("$env expects {var,default?}")?;
+                let var = o
+                    .get("var")
+                    .and_then(Value::as_str)
+                    .context("$env.var must be a string")?;
+                match std::env::var(var) {
+                    Ok(v) => Ok(Value::from(v)),
+                    Err(_) => match o.get("default") {
+                        Some(d) => Ok(d.clone()),
+                        None => bail!(
+                            "$env: environment variable {var} is not set and no default was given"
+                        ),
+                    },
+                }
+            }
+
             "$inc" => {
                 let o = arg.as_object().context("$inc expects {start,step}")?;
                 let name = scope.path.clone(); // counter per field path
@@ -164,6 +483,22 @@ impl Synth {
                 Ok(Value::from(out))
             }
 
+            "$sequence" => {
+                let o = arg.as_object().context("$sequence expects {start,step}")?;
+                let step = o.get("step").and_then(Value::as_i64).unwrap_or(1);
+                let counter = match self.sequence_cache.get(&scope.path) {
+                    Some(c) => Arc::clone(c),
+                    None => {
+                        let start = o.get("start").and_then(Value::as_i64).unwrap_or(0);
+                        let c = self.sequences.counter(&scope.path, start);
+                        self.sequence_cache
+                            .insert(scope.path.clone(), Arc::clone(&c));
+                        c
+                    }
+                };
+                Ok(Value::from(counter.fetch_add(step, Ordering::Relaxed)))
+            }
+
             "$array" => {
                 let o = arg.as_object().context("$array expects {of,len}")?;
                 let of = o.get("of").context("array.of missing")?;
@@ -182,6 +517,38 @@ impl Synth {
                 Ok(Value::from(out))
             }
 
+            "$repeat" => {
+                let o = arg
+                    .as_object()
+                    .context("$repeat expects {of,count,delimiter?}")?;
+                let of = o.get("of").context("$repeat.of missing")?;
+                let count_spec = o.get("count").context("$repeat.count missing")?;
+                let n = self
+                    .gen(count_spec, scope)?
+                    .as_u64()
+                    .context("$repeat.count must produce a non-negative integer")?
+                    as usize;
+
+                let mut out = Vec::with_capacity(n);
+                for _ in 0..n {
+                    out.push(self.gen(of, scope)?);
+                }
+                match o.get("delimiter") {
+                    None | Some(Value::Null) => Ok(Value::from(out)),
+                    Some(Value::String(delim)) => {
+                        let parts: Vec<String> = out
+                            .into_iter()
+                            .map(|v| match v {
+                                Value::String(s) => s,
+                                other => other.to_string(),
+                            })
+                            .collect();
+                        Ok(Value::from(parts.join(delim)))
+                    }
+                    Some(other) => bail!("$repeat.delimiter must be a string, got {other}"),
+                }
+            }
+
             "$map" => {
                 let o = arg.as_object().context("$map expects {of:{...}}")?;
                 let of = o
@@ -195,6 +562,45 @@ impl Synth {
                 Ok(Value::from(m))
             }
 
+            "$nested" => {
+                let o = arg.as_object().context("$nested expects {path,value}")?;
+                let path = o
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .context("$nested.path must be a string")?;
+                let value = self.gen(o.get("value").context("$nested.value missing")?, scope)?;
+                let mut out = serde_json::Map::new();
+                insert_path(&mut out, path, value, op)?;
+                Ok(Value::Object(out))
+            }
+
+            "$merge_nested" => {
+                let o = arg
+                    .as_object()
+                    .context("$merge_nested expects {base,nested:[{path,value}]}")?;
+                let base = o.get("base").context("$merge_nested.base missing")?;
+                let mut out = match self.gen(base, scope)? {
+                    Value::Object(m) => m,
+                    other => bail!("$merge_nested.base must produce an object, got {other}"),
+                };
+                let pairs = o
+                    .get("nested")
+                    .and_then(Value::as_array)
+                    .context("$merge_nested.nested must be a list of {path,value}")?;
+                for pair in pairs {
+                    let path = pair
+                        .get("path")
+                        .and_then(Value::as_str)
+                        .context("$merge_nested entry path must be a string")?;
+                    let spec = pair
+                        .get("value")
+                        .context("$merge_nested entry value missing")?;
+                    let value = self.gen(spec, scope)?;
+                    insert_path(&mut out, path, value, op)?;
+                }
+                Ok(Value::Object(out))
+            }
+
             "$rangeFrom" => {
                 let o = arg.as_object().context("$rangeFrom expects {base,pct}")?;
                 let base_v = o.get("base").context("base")?;
@@ -211,6 +617,39 @@ impl Synth {
                 Ok(Value::from(base))
             }
 
+            "$jitter" => {
+                let o = arg
+                    .as_object()
+                    .context("$jitter expects {base,pct,min?,max?}")?;
+                let base_v = o.get("base").context("$jitter.base missing")?;
+                let base = self
+                    .gen(base_v, scope)?
+                    .as_f64()
+                    .context("$jitter.base must produce a number")?;
+                let pct = o.get("pct").and_then(Value::as_f64).context("pct")?;
+                let mut x = base * (1.0 + gaussian(&mut self.rng) * pct);
+                if let Some(min) = o.get("min").and_then(Value::as_f64) {
+                    x = x.max(min);
+                }
+                if let Some(max) = o.get("max").and_then(Value::as_f64) {
+                    x = x.min(max);
+                }
+                Ok(Value::from(x))
+            }
+
+            "$abs" => {
+                let o = arg.as_object().context("$abs expects {of}")?;
+                let v = self.gen(o.get("of").context("$abs.of missing")?, scope)?;
+                if let Some(i) = v.as_i64() {
+                    Ok(Value::from(i.saturating_abs()))
+                } else if v.is_u64() {
+                    Ok(v)
+                } else {
+                    let f = v.as_f64().context("$abs.of must produce a number")?;
+                    Ok(Value::from(f.abs()))
+                }
+            }
+
             "$gte1" => {
                 let mut v = self.gen(arg, scope)?;
                 if v.as_i64().map(|x| x < 1).unwrap_or(false) {
@@ -219,6 +658,29 @@ impl Synth {
                 Ok(v)
             }
 
+            "$correlated" | "$multi_field_dependency" => {
+                let o = arg
+                    .as_object()
+                    .context("$correlated expects {base_field,transform}")?;
+                let base_field = o
+                    .get("base_field")
+                    .and_then(Value::as_str)
+                    .context("base_field")?;
+                let transform = o.get("transform").context("transform")?;
+
+                let mut base = scope.current.get(base_field.split('.').next().unwrap_or(""));
+                for seg in base_field.split('.').skip(1) {
+                    base = base.and_then(|v| v.get(seg));
+                }
+                let base = base
+                    .cloned()
+                    .with_context(|| format!("$correlated base_field not found: {base_field}"))?;
+
+                let mut inner = scope.child();
+                inner.bindings.insert("base".to_string(), base);
+                self.gen(transform, &mut inner)
+            }
+
             "$let" => {
                 let o = arg.as_object().context("$let expects {vars,in}")?;
                 let vars = o.get("vars").and_then(Value::as_object).context("vars")?;
@@ -268,6 +730,8 @@ pub struct Scope<'a> {
     root_template: &'a Value,
     path: String,
     bindings: HashMap<String, Value>,
+    /// Fields already generated for the object under construction.
+    current: serde_json::Map<String, Value>,
 }
 impl<'a> Scope<'a> {
     pub fn new(root: &'a Value) -> Self {
@@ -275,6 +739,7 @@ impl<'a> Scope<'a> {
             root_template: root,
             path: String::new(),
             bindings: HashMap::new(),
+            current: serde_json::Map::new(),
         }
     }
     pub fn with_path(&mut self, seg: &str) -> &mut Self {
@@ -291,6 +756,7 @@ impl<'a> Scope<'a> {
             root_template: self.root_template,
             path: String::new(),
             bindings: self.bindings.clone(),
+            current: serde_json::Map::new(),
         }
     }
     pub fn lookup_ref(&self, p: &str) -> Result<Value> {
@@ -313,6 +779,12 @@ impl<'a> Scope<'a> {
     }
 }
 
+fn is_correlated(v: &Value) -> bool {
+    v.as_object().is_some_and(|o| {
+        o.len() == 1 && (o.contains_key("$correlated") || o.contains_key("$multi_field_dependency"))
+    })
+}
+
 fn gaussian<R: Rng>(rng: &mut R) -> f64 {
     // Box–Muller
     let u1: f64 = rng.random::<f64>().max(f64::MIN_POSITIVE);
@@ -320,6 +792,71 @@ fn gaussian<R: Rng>(rng: &mut R) -> f64 {
     (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
 }
 
+const WINDOWS_USERS: &[&str] = &["Administrator", "alice", "bob", "svc_backup", "jdoe"];
+
+/// Directories under the user profile, in order, for `$windows_path`.
+const WINDOWS_DIRS: &[&str] = &["AppData", "Local", "Temp"];
+
+const WINDOWS_EXTRA_DIRS: &[&str] = &["cache", "logs", "data", "tmp"];
+
+/// `ts` in the `format` named by `arg` (`rfc3339` by default, `unix_ms` or
+/// `unix`), shared by `$now` and `$timestamp`.
+fn format_time(ts: chrono::DateTime<chrono::Utc>, arg: &Value) -> Value {
+    match arg.get("format").and_then(Value::as_str) {
+        Some("unix_ms") => Value::from(ts.timestamp_millis()),
+        Some("unix") => Value::from(ts.timestamp()),
+        _ => Value::from(ts.to_rfc3339()),
+    }
+}
+
+fn path_depth(o: &serde_json::Map<String, Value>, op: &str) -> Result<usize> {
+    let depth = o
+        .get("depth")
+        .and_then(Value::as_u64)
+        .with_context(|| format!("{op}.depth must be a positive integer"))?;
+    if depth < 1 {
+        bail!("{op}.depth must be >= 1, got {depth}");
+    }
+    Ok(depth as usize)
+}
+
+fn path_component<'a>(name: &'a Value, op: &str) -> Result<&'a str> {
+    let name = name
+        .as_str()
+        .with_context(|| format!("{op} component must be a string, got {name}"))?;
+    if name.is_empty() || name.contains(['/', '\\']) {
+        bail!("{op} component {name:?} must be non-empty and contain no path separators");
+    }
+    Ok(name)
+}
+
+/// Set `value` at the dot-separated `path` inside `obj`, creating objects for
+/// missing intermediate keys and keeping any fields already there.
+fn insert_path(
+    obj: &mut serde_json::Map<String, Value>,
+    path: &str,
+    value: Value,
+    op: &str,
+) -> Result<()> {
+    if path.split('.').any(str::is_empty) {
+        bail!("{op} path {path:?} must be dot-separated non-empty keys");
+    }
+    let (parents, leaf) = match path.rsplit_once('.') {
+        Some((parents, leaf)) => (Some(parents), leaf),
+        None => (None, path),
+    };
+    let mut cur = obj;
+    for seg in parents.into_iter().flat_map(|p| p.split('.')) {
+        cur = cur
+            .entry(seg)
+            .or_insert_with(|| Value::Object(serde_json::Map::new()))
+            .as_object_mut()
+            .with_context(|| format!("{op} path {path:?}: {seg:?} is not an object"))?;
+    }
+    cur.insert(leaf.to_string(), value);
+    Ok(())
+}
+
 fn interpolate(tpl: &str, vars: &HashMap<&str, Value>) -> String {
     let mut out = String::with_capacity(tpl.len() + 16);
     let mut i = 0;
@@ -335,3 +872,317 @@ fn interpolate(tpl: &str, vars: &HashMap<&str, Value>) -> String {
     out.push_str(&tpl[i..]);
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn schema_union_picks_variants_by_weight() {
+        let spec = json!({"$schema_union": {"variants": [
+            {"weight": 0.7, "schema": {"kind": "request", "method": {"$oneOf": ["GET", "POST"]}, "status": 200}},
+            {"weight": 0.3, "schema": {"kind": "error", "exception": "Boom", "severity": "high"}},
+            {"weight": 0.0, "schema": {"kind": "never"}},
+        ]}});
+
+        let n = 10_000;
+        let out = Synth::new(42).gen_batch(&spec, n).unwrap();
+
+        let requests = out.iter().filter(|v| v["kind"] == "request").count();
+        let errors = out.iter().filter(|v| v["kind"] == "error").count();
+        assert_eq!(requests + errors, n);
+
+        let share = requests as f64 / n as f64;
+        assert!((share - 0.7).abs() < 0.03, "request share {share}");
+
+        let req = out.iter().find(|v| v["kind"] == "request").unwrap();
+        assert!(req.get("method").is_some() && req.get("exception").is_none());
+        let err = out.iter().find(|v| v["kind"] == "error").unwrap();
+        assert!(err.get("exception").is_some() && err.get("method").is_none());
+    }
+
+    #[test]
+    fn schema_union_rejects_zero_total_weight() {
+        let spec = json!({"$schema_union": {"variants": [{"weight": 0, "schema": {}}]}});
+        let mut scope = Scope::new(&spec);
+        assert!(Synth::new(1).gen(&spec, &mut scope).is_err());
+    }
+
+    #[test]
+    fn env_reads_variable_or_falls_back_to_default() {
+        std::env::set_var("TANGENT_SYNTH_TEST_REGION", "eu-west-1");
+        let spec = json!({
+            "region": {"$env": {"var": "TANGENT_SYNTH_TEST_REGION"}},
+            "cluster": {"$env": {"var": "TANGENT_SYNTH_TEST_UNSET", "default": "dev"}},
+        });
+        let out = Synth::new(3).gen_batch(&spec, 1).unwrap();
+        assert_eq!(out[0]["region"], "eu-west-1");
+        assert_eq!(out[0]["cluster"], "dev");
+
+        let missing = json!({"$env": {"var": "TANGENT_SYNTH_TEST_UNSET"}});
+        let mut scope = Scope::new(&missing);
+        assert!(Synth::new(3).gen(&missing, &mut scope).is_err());
+    }
+
+    #[test]
+    fn jitter_clamps_and_abs_stays_non_negative() {
+        let spec = json!({
+            "latency": {"$jitter": {"base": {"$const": 100}, "pct": 0.5, "min": 80, "max": 120}},
+            "drift": {"$abs": {"of": {"$jitter": {"base": 1.0, "pct": 3.0}}}},
+            "neg": {"$abs": {"of": -7}},
+        });
+        let out = Synth::new(11).gen_batch(&spec, 500).unwrap();
+        for v in &out {
+            let latency = v["latency"].as_f64().unwrap();
+            assert!((80.0..=120.0).contains(&latency), "latency {latency}");
+            assert!(v["drift"].as_f64().unwrap() >= 0.0);
+            assert_eq!(v["neg"], 7);
+        }
+        let spread = out.iter().filter(|v| v["latency"] != 100.0).count();
+        assert!(spread > 400);
+    }
+
+    #[test]
+    fn path_operators_build_paths_and_validate() {
+        let spec = json!({
+            "unix": {"$unix_path": {"depth": 3, "components": ["var", "log", "nginx"]}},
+            "win": {"$windows_path": {"depth": 6}},
+        });
+        let out = Synth::new(5).gen_batch(&spec, 20).unwrap();
+        for v in &out {
+            let unix = v["unix"].as_str().unwrap();
+            assert_eq!(unix.split('/').count(), 4, "{unix}");
+            let win = v["win"].as_str().unwrap();
+            assert!(win.starts_with("C:\\Users\\"), "{win}");
+            assert!(win.contains("\\AppData\\Local\\Temp\\tmp"), "{win}");
+            assert!(win.ends_with(".log"));
+        }
+
+        for bad in [
+            json!({"$unix_path": {"depth": 0, "components": ["a"]}}),
+            json!({"$unix_path": {"depth": 2, "components": ["etc/passwd"]}}),
+            json!({"$windows_path": {"depth": 0}}),
+        ] {
+            let mut scope = Scope::new(&bad);
+            assert!(Synth::new(5).gen(&bad, &mut scope).is_err());
+        }
+    }
+
+    #[test]
+    fn gen_ndjson_bytes_writes_one_line_per_record() {
+        let spec = json!({"id": {"$inc": {"start": 1, "step": 1}}, "ok": true});
+        let buf = Synth::new(9).gen_ndjson_bytes(&spec, 3).unwrap();
+        let lines: Vec<Value> = buf
+            .strip_suffix(b"\n")
+            .unwrap()
+            .split(|b| *b == b'\n')
+            .map(|l| serde_json::from_slice(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|v| v["ok"] == true));
+        assert!(Synth::new(9).gen_ndjson_bytes(&spec, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn trace_context_and_b3_formats() {
+        let spec = json!({
+            "w3c": {"$trace_context": {"sampled": 1.0}},
+            "vendor": {"$trace_context": {"vendor": "vendor1", "value": "value1"}},
+            "b3": {"$b3_trace": {"sampled": 0.0}},
+            "b3m": {"$b3_trace": {"format": "multi"}},
+        });
+        let out = Synth::new(13).gen_batch(&spec, 10).unwrap();
+        for v in &out {
+            let parts: Vec<&str> = v["w3c"]["traceparent"]
+                .as_str()
+                .unwrap()
+                .split('-')
+                .collect();
+            assert_eq!(parts[0], "00");
+            assert_eq!(parts[1].len(), 32);
+            assert_eq!(parts[2].len(), 16);
+            assert_eq!(parts[3], "01");
+            let state = v["w3c"]["tracestate"].as_str().unwrap();
+            let hex = state.strip_prefix("tangent=").unwrap();
+            assert!(hex.len() == 16 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+            assert_eq!(v["vendor"]["tracestate"], "vendor1=value1");
+
+            let b3: Vec<&str> = v["b3"].as_str().unwrap().split('-').collect();
+            assert_eq!((b3[0].len(), b3[1].len(), b3[2]), (32, 16, "0"));
+            assert_eq!(v["b3m"]["X-B3-Sampled"], "1");
+        }
+
+        for bad in [
+            json!({"$trace_context": {"sampled": 2}}),
+            json!({"$trace_context": {"vendor": "Vendor 1"}}),
+            json!({"$trace_context": {"value": "a=b"}}),
+        ] {
+            let mut scope = Scope::new(&bad);
+            assert!(Synth::new(13).gen(&bad, &mut scope).is_err());
+        }
+    }
+
+    #[test]
+    fn epoch_values_stay_in_window_and_reject_overflow() {
+        let spec = json!({
+            "ms": {"$epoch_ms": {"start_secs_ago": 60, "end_secs_ago": 120}},
+            "s": {"$epoch_s": {"start_secs_ago": 60, "end_secs_ago": 120}},
+        });
+        let before = chrono::Utc::now().timestamp_millis();
+        let out = Synth::new(31).gen_batch(&spec, 100).unwrap();
+        let after = chrono::Utc::now().timestamp_millis();
+        for v in &out {
+            let ms = v["ms"].as_i64().unwrap();
+            assert!(ms >= before - 120_000 && ms <= after - 60_000, "ms {ms}");
+            let s = v["s"].as_i64().unwrap();
+            assert!(
+                s >= (before - 120_000) / 1000 && s <= (after - 60_000) / 1000,
+                "s {s}"
+            );
+        }
+
+        for bad in [
+            json!({"$epoch_ms": {"end_secs_ago": u64::MAX}}),
+            json!({"$epoch_s": {"start_secs_ago": u64::MAX}}),
+            json!({"$epoch_ms": {"start_secs_ago": 10, "end_secs_ago": 5}}),
+        ] {
+            let mut scope = Scope::new(&bad);
+            assert!(Synth::new(31).gen(&bad, &mut scope).is_err());
+        }
+    }
+
+    #[test]
+    fn correlated_reads_a_sibling_generated_in_the_same_object() {
+        let spec = json!({
+            "label": {"$correlated": {"base_field": "status", "transform": {"$fmt": {
+                "template": "HTTP {code}",
+                "vars": {"code": "$ref:base"},
+            }}}},
+            "status": {"$oneOf": [200, 404, 500]},
+        });
+        for v in Synth::new(19).gen_batch(&spec, 20).unwrap() {
+            assert_eq!(v["label"], format!("HTTP {}", v["status"]));
+        }
+    }
+
+    #[test]
+    fn correlated_follows_dotted_base_field() {
+        let spec = json!({
+            "copy": {"$correlated": {"base_field": "req.id", "transform": {"$ref": "base"}}},
+            "req": {"id": {"$int": {"min": 1, "max": 1000}}},
+        });
+        for v in Synth::new(23).gen_batch(&spec, 20).unwrap() {
+            assert!(v["copy"].is_i64());
+            assert_eq!(v["copy"], v["req"]["id"]);
+        }
+    }
+
+    #[test]
+    fn correlated_errors_on_missing_base_field() {
+        let spec = json!({
+            "copy": {"$correlated": {"base_field": "req.missing", "transform": {"$ref": "base"}}},
+            "req": {"id": 1},
+        });
+        let err = Synth::new(29).gen_batch(&spec, 1).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("base_field not found: req.missing"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn repeat_joins_or_collects() {
+        let spec = json!({
+            "plan": {"$repeat": {"of": {"$oneOf": ["scan", "join"]}, "count": 3, "delimiter": " -> "}},
+            "ids": {"$repeat": {"of": {"$int": {"min": 1, "max": 9}}, "count": {"$int": {"min": 1, "max": 4}}}},
+        });
+        let out = Synth::new(17).gen_batch(&spec, 20).unwrap();
+        for v in &out {
+            assert_eq!(v["plan"].as_str().unwrap().split(" -> ").count(), 3);
+            let ids = v["ids"].as_array().unwrap();
+            assert!((1..=4).contains(&ids.len()));
+        }
+
+        let nums = json!({"$repeat": {"of": 7, "count": 2, "delimiter": ","}});
+        let mut scope = Scope::new(&nums);
+        assert_eq!(Synth::new(1).gen(&nums, &mut scope).unwrap(), "7,7");
+    }
+
+    #[test]
+    fn ip_lookup_resolves_generated_ip() {
+        let spec = json!({"geo": {"$ip_lookup": {"ip": {"$oneOf": ["8.8.8.8", "192.168.1.1"]}}}});
+        let out = Synth::new(7).gen_batch(&spec, 50).unwrap();
+        for v in &out {
+            let geo = &v["geo"];
+            assert!(geo["asn_number"] == 15169 || geo["asn_org"] == "UNKNOWN");
+            assert!(geo["latitude"].is_f64() && geo["longitude"].is_f64());
+        }
+    }
+
+    #[test]
+    fn nested_builds_and_merges_dotted_paths() {
+        let mut synth = Synth::new(1);
+        let spec =
+            json!({"$nested": {"path": "kubernetes.pod.metadata.name", "value": {"$uuid": {}}}});
+        let v = synth.gen_batch(&spec, 1).unwrap().remove(0);
+        let name = v["kubernetes"]["pod"]["metadata"]["name"].as_str().unwrap();
+        assert_eq!(name.len(), 36);
+
+        let spec = json!({"$merge_nested": {
+            "base": {"kubernetes": {"namespace": {"name": "prod"}}, "msg": "hi"},
+            "nested": [
+                {"path": "kubernetes.namespace.labels.app", "value": {"$oneOf": ["api"]}},
+                {"path": "kubernetes.pod.name", "value": "api-0"},
+            ],
+        }});
+        let v = synth.gen_batch(&spec, 1).unwrap().remove(0);
+        assert_eq!(
+            v,
+            json!({
+                "kubernetes": {
+                    "namespace": {"name": "prod", "labels": {"app": "api"}},
+                    "pod": {"name": "api-0"},
+                },
+                "msg": "hi",
+            })
+        );
+
+        let bad =
+            json!({"$merge_nested": {"base": {"a": 1}, "nested": [{"path": "a.b", "value": 2}]}});
+        assert!(synth.gen_batch(&bad, 1).is_err());
+    }
+
+    #[test]
+    fn sequence_is_shared_and_timestamps_respect_offset() {
+        let spec = json!({"seq": {"$sequence": {"start": 10, "step": 2}}});
+        let sequences = Sequences::default();
+        let mut a = Synth::with_sequences(1, sequences.clone());
+        let mut b = Synth::with_sequences(2, sequences);
+        let seen: Vec<i64> = (0..3)
+            .flat_map(|_| {
+                [
+                    a.gen_batch(&spec, 1).unwrap(),
+                    b.gen_batch(&spec, 1).unwrap(),
+                ]
+            })
+            .map(|v| v[0]["seq"].as_i64().unwrap())
+            .collect();
+        assert_eq!(seen, vec![10, 12, 14, 16, 18, 20]);
+
+        let spec = json!({
+            "ts": {"$timestamp": {"offset_ms": {"$int": {"min": -3600000, "max": 0}}, "format": "unix_ms"}},
+            "ip": {"$ip6": {}},
+            "mac": {"$mac": {}},
+        });
+        let now = chrono::Utc::now().timestamp_millis();
+        for v in Synth::new(4).gen_batch(&spec, 50).unwrap() {
+            let ts = v["ts"].as_i64().unwrap();
+            assert!(ts <= now + 1000 && ts >= now - 3_600_000, "ts {ts}");
+            assert!(v["ip"].as_str().unwrap().parse::<Ipv6Addr>().is_ok());
+            let mac = v["mac"].as_str().unwrap();
+            assert_eq!(mac.len(), 17);
+            assert_eq!(mac.split(':').count(), 6);
+        }
+    }
+}
@@ -11,7 +11,8 @@ use std::{
 use tokio::{self, io::AsyncWriteExt, net::TcpStream};
 use tracing::info;
 
-use crate::synthesize::{Scope, Synth};
+use crate::rate::{Pace, TokenBucket};
+use crate::synthesize::{Sequences, Synth};
 
 pub async fn run_bench(
     name: Arc<str>,
@@ -21,6 +22,7 @@ pub async fn run_bench(
     max_bytes: usize,
     seconds: u64,
     synthesize_payload: bool,
+    pace: Option<Pace>,
 ) -> Result<()> {
     info!("===Starting benchmark===");
     info!("source={} tcp={} connections={}", name, addr, connections);
@@ -31,9 +33,12 @@ pub async fn run_bench(
     static RECS_SENT: AtomicU64 = AtomicU64::new(0);
     let start = Instant::now();
 
+    let sequences = Sequences::default();
     for _ in 0..connections {
         let payload = payload.clone();
         let addr = addr;
+        let sequences = sequences.clone();
+        let mut bucket = pace.map(|p| TokenBucket::new(p.per_connection(connections)));
 
         handles.push(tokio::spawn(async move {
             let mut stream = TcpStream::connect(addr)
@@ -43,7 +48,7 @@ pub async fn run_bench(
                 .set_nodelay(true)
                 .with_context(|| format!("failed to enable TCP_NODELAY for {addr}"))?;
 
-            let mut synth = Synth::new(rand::random::<u64>());
+            let mut synth = Synth::with_sequences(rand::random::<u64>(), sequences);
             let templates: Vec<Value> = payload
                 .split(|b| *b == b'\n')
                 .filter(|line| !line.is_empty())
@@ -60,18 +65,14 @@ pub async fn run_bench(
                 if synthesize_payload && templates.len() > 0 {
                     'fill: loop {
                         for template in templates.iter() {
-                            let mut scope = Scope::new(&template);
+                            let start = buf.len();
+                            synth.append_ndjson(template, &mut buf)?;
 
-                            let v = synth.gen(&template, &mut scope)?;
-
-                            let mut line = serde_json::to_vec(&v)?;
-                            line.push(b'\n');
-
-                            if max_bytes > 0 && buf.len() + line.len() > max_bytes {
+                            if max_bytes > 0 && buf.len() > max_bytes {
+                                buf.truncate(start);
                                 break 'fill;
                             }
 
-                            buf.extend_from_slice(&line);
                             events_per_buff += 1;
 
                             if max_bytes == 0 {
@@ -94,6 +95,13 @@ pub async fn run_bench(
                     events_per_buff = 1;
                 }
 
+                if let Some(bucket) = bucket.as_mut() {
+                    bucket.acquire(buf.len()).await;
+                    if Instant::now() >= deadline {
+                        break;
+                    }
+                }
+
                 let buf_len = buf.len();
                 match stream.write_all(&buf).await {
                     Ok(()) => {
@@ -0,0 +1,96 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use tangent_runtime::cache;
+use tangent_shared::{Config, ConfigFormat};
+
+/// Print cache entries as a table, optionally only those `plugin` wrote.
+pub fn list(config_path: &Path, plugin: Option<&str>, limit: Option<usize>) -> Result<()> {
+    let path = db_path(config_path)?;
+    let entries = cache::list_entries(&path, plugin, limit)?;
+    if entries.is_empty() {
+        println!("no cache entries in {}", path.display());
+        return Ok(());
+    }
+
+    let now = SystemTime::now();
+    let mut rows = vec![[
+        "key".to_string(),
+        "type".to_string(),
+        "size".to_string(),
+        "expires_at".to_string(),
+        "plugin".to_string(),
+    ]];
+    for e in entries {
+        let expires = UNIX_EPOCH + Duration::from_millis(e.expires_at);
+        let mut expires_at = DateTime::<Local>::from(expires)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        if expires <= now {
+            expires_at.push_str(" (expired)");
+        }
+        rows.push([
+            e.key,
+            e.kind,
+            e.size.to_string(),
+            expires_at,
+            e.owner.unwrap_or_else(|| "-".to_string()),
+        ]);
+    }
+
+    let mut widths = [0usize; 5];
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, w)| format!("{cell:<w$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+    Ok(())
+}
+
+/// Delete cache entries, optionally only those `plugin` wrote and only those
+/// last written more than `older_than` ago.
+pub fn clear(config_path: &Path, plugin: Option<&str>, older_than: Option<Duration>) -> Result<()> {
+    let path = db_path(config_path)?;
+    let deleted = cache::clear_entries(&path, plugin, older_than)?;
+    println!("🧹 deleted {deleted} cache entries from {}", path.display());
+    Ok(())
+}
+
+/// Parse durations like `90s`, `15m`, `12h` or `7d`.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n
+        .parse()
+        .with_context(|| format!("invalid duration {s:?}; expected e.g. 30m or 7d"))?;
+    let secs = match unit {
+        "ms" => return Ok(Duration::from_millis(n)),
+        "s" | "" => n,
+        "m" => n * 60,
+        "h" => n * 3600,
+        "d" => n * 86_400,
+        _ => bail!("invalid duration unit {unit:?} in {s:?}; use ms, s, m, h or d"),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+fn db_path(config_path: &Path) -> Result<std::path::PathBuf> {
+    let cfg = Config::from_file_with_format(config_path, ConfigFormat::from_path(config_path))?;
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    let path = cache::resolve_path(&cfg.runtime.cache, config_dir);
+    if !path.exists() {
+        bail!("cache db {} does not exist", path.display());
+    }
+    Ok(path)
+}
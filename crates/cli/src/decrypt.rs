@@ -0,0 +1,33 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use tangent_runtime::sinks::encryption::WalCipher;
+
+/// Decrypt an object written by a sink with `encryption` set. The output is
+/// the file as it was before encryption, so still compressed if the sink
+/// compresses.
+pub fn run(input: &Path, key_hex: &str, output: Option<PathBuf>) -> Result<()> {
+    let output = match output {
+        Some(p) => p,
+        None => match input.to_str().and_then(|s| s.strip_suffix(".enc")) {
+            Some(stripped) => PathBuf::from(stripped),
+            None => bail!(
+                "{} doesn't end in .enc; pass --output to name the decrypted file",
+                input.display()
+            ),
+        },
+    };
+
+    let cipher = WalCipher::from_hex(key_hex)?;
+    let src = File::open(input).with_context(|| format!("reading {}", input.display()))?;
+    let dst = File::create(&output).with_context(|| format!("writing {}", output.display()))?;
+    if let Err(e) = cipher.decrypt_stream(BufReader::new(src), BufWriter::new(dst)) {
+        let _ = std::fs::remove_file(&output);
+        return Err(e).with_context(|| format!("decrypting {}", input.display()));
+    }
+
+    println!("✅ decrypted {} -> {}", input.display(), output.display());
+    Ok(())
+}
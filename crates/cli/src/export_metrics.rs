@@ -0,0 +1,19 @@
+use anyhow::{Context, Result};
+use tangent_bench::metrics;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MetricsFormat {
+    /// One object keyed by series, pretty-printed.
+    Json,
+}
+
+/// Scrape the Prometheus endpoint at `url` once and print every series.
+pub async fn run(url: &str, format: MetricsFormat) -> Result<()> {
+    let scraped = metrics::scrape_json(url)
+        .await
+        .with_context(|| format!("scraping {url}"))?;
+    match format {
+        MetricsFormat::Json => println!("{}", serde_json::to_string_pretty(&scraped)?),
+    }
+    Ok(())
+}
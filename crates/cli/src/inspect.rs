@@ -0,0 +1,74 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+use tangent_runtime::cache::CacheHandle;
+use tangent_runtime::wasm::inspect::{self, Pred};
+use tangent_shared::runtime::CacheConfig;
+
+/// Print the metadata and selectors of a compiled plugin.
+pub async fn run(config_path: &Path, plugin: &str) -> Result<()> {
+    // Scratch cache so inspecting never touches the runtime's cache file.
+    let cache_dir = tempfile::tempdir()?;
+    let cache = Arc::new(CacheHandle::open(
+        &CacheConfig::default(),
+        cache_dir.path(),
+    )?);
+
+    let info = inspect::inspect(config_path, plugin, cache).await?;
+
+    println!("plugin:  {plugin}");
+    println!("name:    {}", info.name);
+    println!("version: {}", info.version);
+    println!();
+
+    if info.selectors.is_empty() {
+        println!("no selectors; the plugin receives no events");
+        return Ok(());
+    }
+
+    let mut rows = vec![[
+        "#".to_string(),
+        "all".to_string(),
+        "any".to_string(),
+        "none".to_string(),
+        "priority".to_string(),
+    ]];
+    for (i, (sel, priority)) in info.selectors.iter().zip(&info.priorities).enumerate() {
+        rows.push([
+            i.to_string(),
+            join_preds(&sel.all, " && "),
+            join_preds(&sel.any, " || "),
+            join_preds(&sel.none, " || "),
+            priority.map_or_else(|| "-".to_string(), |p| p.to_string()),
+        ]);
+    }
+
+    let mut widths = [0usize; 5];
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, w)| format!("{cell:<w$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+
+    Ok(())
+}
+
+fn join_preds(preds: &[Pred], sep: &str) -> String {
+    if preds.is_empty() {
+        return "-".to_string();
+    }
+    preds
+        .iter()
+        .map(inspect::describe_pred)
+        .collect::<Vec<_>>()
+        .join(sep)
+}
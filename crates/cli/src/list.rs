@@ -0,0 +1,108 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use tangent_runtime::cache::CacheHandle;
+use tangent_runtime::wasm::inspect;
+use tangent_shared::plugins::CompiledPluginMeta;
+use tangent_shared::runtime::CacheConfig;
+
+/// Print every compiled plugin in the config's plugins directory with its
+/// version, source language, size and build time. Fails if any of them
+/// can't be loaded.
+pub async fn run(config_path: &Path) -> Result<()> {
+    // Scratch cache so listing never touches the runtime's cache file.
+    let cache_dir = tempfile::tempdir()?;
+    let cache = Arc::new(CacheHandle::open(
+        &CacheConfig::default(),
+        cache_dir.path(),
+    )?);
+
+    let plugins = inspect::list(config_path, cache).await?;
+    if plugins.is_empty() {
+        println!("no compiled plugins; run `tangent plugin compile` first");
+        return Ok(());
+    }
+
+    let mut rows = vec![[
+        "name".to_string(),
+        "version".to_string(),
+        "language".to_string(),
+        "size".to_string(),
+        "modified".to_string(),
+    ]];
+    let mut failed = Vec::new();
+    for (path, info) in &plugins {
+        let info = match info {
+            Ok(info) => info,
+            Err(e) => {
+                failed.push(format!("{}: {e:#}", path.display()));
+                continue;
+            }
+        };
+        let language = fs::read(CompiledPluginMeta::path_for(path))
+            .ok()
+            .and_then(|b| serde_json::from_slice::<CompiledPluginMeta>(&b).ok())
+            .map_or_else(|| "-".to_string(), |m| m.language);
+        let md = fs::metadata(path)?;
+        let modified = md
+            .modified()
+            .map(|t| {
+                DateTime::<Local>::from(t)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|_| "-".to_string());
+        rows.push([
+            info.name.clone(),
+            info.version.clone(),
+            language,
+            human_size(md.len()),
+            modified,
+        ]);
+    }
+
+    let mut widths = [0usize; 5];
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, w)| format!("{cell:<w$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+
+    if !failed.is_empty() {
+        for f in &failed {
+            eprintln!("❌ {f}");
+        }
+        bail!(
+            "{} of {} plugins failed to load",
+            failed.len(),
+            plugins.len()
+        );
+    }
+    Ok(())
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
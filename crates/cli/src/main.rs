@@ -1,14 +1,30 @@
 use std::path::PathBuf;
+use std::process::ExitCode;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use tracing_subscriber::EnvFilter;
 
 use tangent_bench::BenchOptions;
-use tangent_runtime::RuntimeOptions;
+use tangent_runtime::{LogFormat, RuntimeOptions};
+use tangent_shared::error::{ConfigError, ConfigErrors};
+use tangent_shared::{ConfigFormat, STDIN_CONFIG};
 
+mod cache;
+mod decrypt;
+mod export_metrics;
+mod inspect;
+mod list;
+mod plugin_bench;
+mod probe;
+mod profile;
 mod scaffold;
+mod set_config;
+mod status;
 mod test;
+mod train_dict;
+mod validate;
+mod wal;
 mod wit_assets;
 
 #[global_allocator]
@@ -24,12 +40,33 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     Run {
-        /// Path to YAML config
+        /// Path to config (YAML or JSON), or `-` to read it from stdin
         #[arg(long, value_name = "FILE")]
         config: PathBuf,
         /// Exit after one drain cycle (for tests)
         #[arg(long, default_value_t = false)]
         once: bool,
+        /// Print sink output to stdout instead of writing it, and exit after
+        /// the first batch. Nothing is acked, so SQS messages, Kafka offsets
+        /// and the like are left for the next real run.
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Trap guest calls that run too long and log their wasm backtrace
+        #[arg(long, default_value_t = false)]
+        trace_wasm: bool,
+        /// Reload plugins when their compiled component changes
+        #[arg(long, default_value_t = false)]
+        watch_plugins: bool,
+        /// Log output format
+        #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+        log_format: LogFormat,
+        /// Write logs to this file (rotated daily) instead of stderr
+        #[arg(long, value_name = "PATH")]
+        log_file: Option<PathBuf>,
+        /// Sample the runtime's CPU until it exits and write an SVG
+        /// flamegraph to FILE. Needs the `profiling` feature.
+        #[arg(long, value_name = "FILE")]
+        profile: Option<PathBuf>,
     },
 
     Bench {
@@ -71,12 +108,119 @@ enum Commands {
         /// Synthesize logs. Used to generate payloads from the input payload.
         #[arg(long, default_value_t = false)]
         synthesize: bool,
+
+        /// Write the run settings and per-source results, including guest
+        /// latency percentiles, as JSON to FILE.
+        #[arg(long, value_name = "FILE", alias = "report-json")]
+        output: Option<PathBuf>,
+
+        /// Send at this many MB/s across all connections (token bucket)
+        /// instead of as fast as possible. Socket and tcp sources only.
+        #[arg(long, value_name = "MB_PER_SEC")]
+        rate_mb_s: Option<f64>,
+
+        /// Ramp linearly from 0 to --rate-mb-s over the first N seconds,
+        /// before warmup
+        #[arg(long, default_value_t = 0, requires = "rate_mb_s")]
+        ramp_seconds: u64,
+    },
+
+    /// Check a config without running it: DAG references, plugin paths, WAL
+    /// directories and zero-sized limits
+    Validate {
+        /// Path to config (YAML or JSON)
+        #[arg(long, value_name = "FILE")]
+        config: PathBuf,
+        /// Config format; detected from the file extension when omitted
+        #[arg(long, value_enum)]
+        format: Option<ConfigFormat>,
     },
 
     Plugin {
         #[command(subcommand)]
         command: PluginCommands,
     },
+
+    /// Inspect or purge the SQLite cache plugins keep state in
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    /// Check files left in a WAL directory
+    Wal {
+        #[command(subcommand)]
+        command: WalCommands,
+    },
+
+    /// Decrypt an object uploaded by a sink with `encryption` set
+    Decrypt {
+        /// Encrypted object, usually ending in `.enc`
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+        /// The sink's `encryption.key_hex`
+        #[arg(long, value_name = "HEX")]
+        key_hex: String,
+        /// Where to write the plaintext [default: FILE without `.enc`]
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Scrape a running instance's Prometheus metrics and print them
+    ExportMetrics {
+        /// Prometheus metrics endpoint
+        #[arg(long, default_value = "http://127.0.0.1:9184/metrics")]
+        url: String,
+        /// Output format
+        #[arg(long, value_enum, default_value = "json")]
+        format: export_metrics::MetricsFormat,
+    },
+
+    /// Live dashboard of a running instance's metrics, refreshed every second
+    Status {
+        /// Prometheus metrics endpoint
+        #[arg(long, default_value = "http://127.0.0.1:9184/metrics")]
+        url: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommands {
+    /// Print cache entries: key, type, size, expiry and owning plugin
+    List {
+        /// Runtime config
+        #[arg(long, value_name = "FILE")]
+        config: PathBuf,
+        /// Only entries written by this plugin
+        #[arg(long)]
+        plugin: Option<String>,
+        /// Print at most N entries
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+    },
+    /// Delete cache entries in one transaction
+    Clear {
+        /// Runtime config
+        #[arg(long, value_name = "FILE")]
+        config: PathBuf,
+        /// Only entries written by this plugin
+        #[arg(long)]
+        plugin: Option<String>,
+        /// Only entries last written longer ago than this, e.g. 30m or 7d
+        #[arg(long, value_name = "DURATION", value_parser = cache::parse_duration)]
+        older_than: Option<std::time::Duration>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum WalCommands {
+    /// Compare every sealed file with the checksum in its meta, without
+    /// uploading anything
+    Verify {
+        /// The sink's `wal_path`
+        #[arg(long, value_name = "PATH")]
+        dir: PathBuf,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -87,8 +231,14 @@ enum PluginCommands {
         #[arg(long)]
         name: String,
         /// Language: go|py|rust
-        #[arg(long)]
-        lang: String,
+        #[arg(long, required_unless_present = "from_example")]
+        lang: Option<String>,
+        /// Bootstrap from an example plugin: a git URL or `owner/repo/path/to/plugin`
+        #[arg(long, value_name = "URL", conflicts_with = "lang")]
+        from_example: Option<String>,
+        /// Plugin identifier in tangent.yaml and plugin metadata (defaults to --name)
+        #[arg(long, conflicts_with = "from_example")]
+        plugin_name: Option<String>,
     },
     /// Test a plugin with input/expected fixtures
     Test {
@@ -102,6 +252,100 @@ enum PluginCommands {
         /// Enable http calls in tests
         #[arg(long, default_value_t = false)]
         enable_http: bool,
+
+        /// Format of the test input files
+        #[arg(long, value_enum, default_value = "json_array")]
+        input_format: test::InputFormat,
+
+        /// Format of the expected output files
+        #[arg(long, value_enum, default_value = "json_array")]
+        expected_format: test::ExpectedFormat,
+
+        /// Fail a test whose run takes longer than this many seconds
+        #[arg(long, default_value_t = 30)]
+        timeout_secs: u64,
+
+        /// Stop after the first failing test
+        #[arg(long, default_value_t = false)]
+        fail_fast: bool,
+    },
+
+    /// Print a compiled plugin's metadata and the selectors it listens for
+    Inspect {
+        /// Plugin name in the config
+        #[arg(long)]
+        plugin: String,
+        /// Runtime config
+        #[arg(long, value_name = "FILE")]
+        config: PathBuf,
+    },
+
+    /// List every compiled plugin with its version, language, size and build time
+    List {
+        /// Runtime config
+        #[arg(long, value_name = "FILE")]
+        config: PathBuf,
+    },
+
+    /// Measure a plugin's raw guest throughput, without sources, routing or sinks
+    Benchmark {
+        /// Runtime config
+        #[arg(long, value_name = "FILE")]
+        config: PathBuf,
+        /// Plugin name in the config
+        #[arg(long)]
+        plugin: String,
+        /// Events to feed each call, as a JSON array or NDJSON
+        #[arg(long, value_name = "FILE")]
+        payload: PathBuf,
+        /// Duration (seconds)
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+    },
+
+    /// Report which plugins each test event would be routed to, by selector
+    Probe {
+        /// Runtime config
+        #[arg(long, value_name = "FILE")]
+        config: PathBuf,
+        /// Events to check, as a JSON array or NDJSON
+        #[arg(long, value_name = "FILE")]
+        input: PathBuf,
+    },
+
+    /// Change a plugin config value on running workers without a restart
+    SetConfig {
+        /// Plugin name in the config
+        #[arg(long)]
+        plugin: String,
+        /// Config key to set
+        #[arg(long)]
+        key: String,
+        /// New value, as JSON or a plain string
+        #[arg(long)]
+        value: String,
+        /// Runtime config
+        #[arg(long, value_name = "FILE")]
+        config: PathBuf,
+    },
+
+    /// Train a zstd dictionary from sample events for sinks' `zstd_dict`
+    TrainDict {
+        /// Runtime config
+        #[arg(long, value_name = "FILE")]
+        config: PathBuf,
+        /// Sample events as a JSON array or NDJSON
+        #[arg(long, value_name = "FILE")]
+        input: PathBuf,
+        /// Where to write the dictionary
+        #[arg(long, value_name = "FILE")]
+        output: PathBuf,
+        /// Maximum number of events to train on
+        #[arg(long, default_value_t = 10_000)]
+        samples: usize,
+        /// Maximum dictionary size in bytes
+        #[arg(long, default_value_t = 112_640)]
+        max_size: usize,
     },
 
     /// Compile a WASM component from a config (py via componentize-py; go via TinyGo)
@@ -116,21 +360,93 @@ enum PluginCommands {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    tracing_subscriber::fmt().with_env_filter(filter).init();
-
+async fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
 
+    // `run` owns its log output; every other command logs plainly to stderr.
+    let _log_guard = match &cli.command {
+        Commands::Run {
+            log_format,
+            log_file,
+            ..
+        } => Some(tangent_runtime::init_logging(&RuntimeOptions {
+            log_format: *log_format,
+            log_file: log_file.clone(),
+            ..Default::default()
+        })?),
+        _ => {
+            let filter =
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+            tracing_subscriber::fmt().with_env_filter(filter).init();
+            None
+        }
+    };
+
+    if let Err(e) = run(cli).await {
+        let errs = validate::config_errors(&e);
+        if errs.is_empty() {
+            return Err(e);
+        }
+        // Config problems are the user's to fix; list them by path rather
+        // than printing the error chain.
+        let context: Vec<String> = e
+            .chain()
+            .take_while(|c| !c.is::<ConfigError>() && !c.is::<ConfigErrors>())
+            .map(ToString::to_string)
+            .collect();
+        if context.is_empty() {
+            eprintln!("❌ invalid config");
+        } else {
+            eprintln!("❌ {}", context.join(": "));
+        }
+        for err in errs {
+            match err.path() {
+                "" => eprintln!("  {}", err.detail()),
+                path => eprintln!("  {path}\n    {}", err.detail()),
+            }
+        }
+        // Returning rather than exiting drops the log guard, so buffered
+        // log lines are flushed.
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn run(cli: Cli) -> Result<()> {
     match cli.command {
-        Commands::Run { config, once } => {
-            let cfg = config.canonicalize().unwrap_or(config);
+        Commands::Run {
+            config,
+            once,
+            dry_run,
+            trace_wasm,
+            watch_plugins,
+            log_format,
+            log_file,
+            profile,
+        } => {
+            let profiler = profile
+                .as_deref()
+                .map(profile::Profiler::start)
+                .transpose()?;
+            let cfg = if config.as_os_str() == STDIN_CONFIG {
+                config
+            } else {
+                config.canonicalize().unwrap_or(config)
+            };
             let opts = RuntimeOptions {
                 once,
+                dry_run,
+                trace_wasm,
+                watch_plugins,
+                log_format,
+                log_file,
                 ..Default::default()
             };
 
-            tangent_runtime::run(&cfg, opts).await?
+            tangent_runtime::run(&cfg, opts).await?;
+            if let Some(profiler) = profiler {
+                profiler.finish()?;
+            }
         }
         Commands::Bench {
             config,
@@ -143,6 +459,9 @@ async fn main() -> Result<()> {
             object_prefix,
             disable_metrics,
             synthesize,
+            output,
+            rate_mb_s,
+            ramp_seconds,
         } => {
             let opts = BenchOptions {
                 config_path: Some(config.clone()),
@@ -155,10 +474,45 @@ async fn main() -> Result<()> {
                 object_prefix,
                 disable_metrics,
                 synthesize,
+                output,
+                rate_mb_s,
+                ramp_seconds,
             };
             tangent_bench::run(&config, opts).await?;
         }
 
+        Commands::Validate { config, format } => {
+            let format = format.unwrap_or_else(|| ConfigFormat::from_path(&config));
+            validate::run(&config, format)?;
+        }
+
+        Commands::Cache { command } => match command {
+            CacheCommands::List {
+                config,
+                plugin,
+                limit,
+            } => cache::list(&config, plugin.as_deref(), limit)?,
+            CacheCommands::Clear {
+                config,
+                plugin,
+                older_than,
+            } => cache::clear(&config, plugin.as_deref(), older_than)?,
+        },
+
+        Commands::Wal { command } => match command {
+            WalCommands::Verify { dir } => wal::verify(&dir).await?,
+        },
+
+        Commands::Decrypt {
+            input,
+            key_hex,
+            output,
+        } => decrypt::run(&input, &key_hex, output)?,
+
+        Commands::ExportMetrics { url, format } => export_metrics::run(&url, format).await?,
+
+        Commands::Status { url } => status::run(&url).await?,
+
         Commands::Plugin { command } => match command {
             PluginCommands::Compile { config, wit } => {
                 // resolve to absolute paths to help downstream error messages
@@ -166,20 +520,71 @@ async fn main() -> Result<()> {
                 let wit = wit.canonicalize().unwrap_or(wit);
                 compile_wasm::compile_from_config(&cfg, &wit)?;
             }
-            PluginCommands::Scaffold { name, lang } => scaffold::scaffold(&name, &lang)?,
+            PluginCommands::Scaffold {
+                name,
+                lang,
+                from_example,
+                plugin_name,
+            } => match (from_example, lang) {
+                (Some(example), _) => scaffold::scaffold_from_example(&name, &example)?,
+                (None, Some(lang)) => scaffold::scaffold(&name, &lang, plugin_name.as_deref())?,
+                (None, None) => anyhow::bail!("--lang or --from-example is required"),
+            },
             PluginCommands::Test {
                 plugin,
                 config,
                 enable_http,
+                input_format,
+                expected_format,
+                timeout_secs,
+                fail_fast,
             } => {
                 let config = config.canonicalize().unwrap_or(config);
                 test::run(test::TestOptions {
                     plugin,
                     config_path: config,
                     enable_http: enable_http,
+                    input_format,
+                    expected_format,
+                    timeout_secs,
+                    fail_fast,
                 })
                 .await?;
             }
+            PluginCommands::Inspect { plugin, config } => {
+                let config = config.canonicalize().unwrap_or(config);
+                inspect::run(&config, &plugin).await?;
+            }
+            PluginCommands::List { config } => {
+                let config = config.canonicalize().unwrap_or(config);
+                list::run(&config).await?;
+            }
+            PluginCommands::Benchmark {
+                config,
+                plugin,
+                payload,
+                seconds,
+            } => {
+                let config = config.canonicalize().unwrap_or(config);
+                plugin_bench::run(&config, &plugin, &payload, seconds).await?;
+            }
+            PluginCommands::Probe { config, input } => {
+                let config = config.canonicalize().unwrap_or(config);
+                probe::run(&config, &input).await?;
+            }
+            PluginCommands::SetConfig {
+                plugin,
+                key,
+                value,
+                config,
+            } => set_config::run(&config, &plugin, &key, &value)?,
+            PluginCommands::TrainDict {
+                config,
+                input,
+                output,
+                samples,
+                max_size,
+            } => train_dict::run(&config, &input, &output, samples, max_size)?,
         },
     }
 
@@ -0,0 +1,211 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use bytes::BytesMut;
+use prometheus::core::Metric;
+use serde_json::Value;
+use tangent_bench::metrics::HistogramSnapshot;
+use tangent_runtime::cache::CacheHandle;
+use tangent_runtime::wasm::engine::WasmEngine;
+use tangent_runtime::wasm::host::JsonLogView;
+use tangent_runtime::wasm::mapper::MapperCtx;
+use tangent_runtime::GUEST_LATENCY;
+use tangent_shared::runtime::CacheConfig;
+use tangent_shared::Config;
+
+/// `worker` label the benchmark's guest calls are recorded under.
+const BENCH_WORKER: &str = "plugin-bench";
+
+/// Call plugin `plugin`'s `process_logs` on the events in `payload` back to
+/// back for `seconds`, with no sources, routing or sinks, and report guest
+/// latency, throughput and memory. Remote calls are disabled.
+pub async fn run(config_path: &Path, plugin: &str, payload: &Path, seconds: u64) -> Result<()> {
+    let cfg = Config::from_file(config_path)?;
+    let (name, plugin_cfg) = cfg
+        .plugins
+        .get_key_value(plugin)
+        .with_context(|| format!("plugin {plugin} not found in {}", config_path.display()))?;
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    let component_path = config_dir
+        .join(&cfg.runtime.plugins_path)
+        .join(format!("{name}.cwasm"));
+
+    let events = read_events(payload)?;
+    if events.is_empty() {
+        bail!("{} has no events", payload.display());
+    }
+    let batch_bytes: usize = events.iter().map(BytesMut::len).sum();
+
+    // Scratch cache so benchmarking never touches the runtime's cache file.
+    let cache_dir = tempfile::tempdir()?;
+    let cache = Arc::new(CacheHandle::open(
+        &CacheConfig::default(),
+        cache_dir.path(),
+    )?);
+    let mut engine = WasmEngine::new(cache, true)?;
+    let component = engine
+        .load_precompiled(Arc::clone(name), &component_path, plugin_cfg)
+        .with_context(|| format!("loading {}", component_path.display()))?;
+    let mut mapper = MapperCtx::load(&engine, name, &component).await?;
+
+    println!(
+        "benchmarking {} {} for {seconds}s with {} events ({}) per call",
+        mapper.name,
+        mapper.version,
+        events.len(),
+        human_bytes(batch_bytes as f64)
+    );
+
+    let latency = GUEST_LATENCY.with_label_values(&[BENCH_WORKER]);
+    let rss_before = rss_kib("VmRSS");
+    let (mut calls, mut out_events) = (0u64, 0usize);
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(seconds);
+    while Instant::now() < deadline {
+        let mut input = Vec::with_capacity(events.len());
+        for ev in &events {
+            let lv = JsonLogView::from_bytes(ev.clone())?;
+            input.push(mapper.store.data_mut().table.push(lv)?);
+        }
+
+        let start = Instant::now();
+        let res = mapper.process_logs(input).await?;
+        latency.observe(start.elapsed().as_secs_f64());
+
+        match res {
+            Ok(out) => out_events += out.len(),
+            Err(e) => bail!("plugin returned an error after {calls} calls: {e}"),
+        }
+        calls += 1;
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let hist = snapshot(&latency);
+    let in_events = calls as f64 * events.len() as f64;
+    let in_bytes = calls as f64 * batch_bytes as f64;
+
+    println!("calls:       {calls} ({out_events} output events)");
+    println!(
+        "latency:     p50 {}  p95 {}  p99 {}  mean {}",
+        human_secs(hist.quantile(0.50)),
+        human_secs(hist.quantile(0.95)),
+        human_secs(hist.quantile(0.99)),
+        human_secs(hist.sum / hist.count.max(1.0)),
+    );
+    println!(
+        "throughput:  {:.0} events/s  {}/s",
+        in_events / elapsed,
+        human_bytes(in_bytes / elapsed)
+    );
+    match (rss_before, rss_kib("VmRSS"), rss_kib("VmHWM")) {
+        (Some(before), Some(after), Some(peak)) => println!(
+            "memory:      rss {} (+{} during run), peak {}",
+            human_bytes(after as f64 * 1024.0),
+            human_bytes(after.saturating_sub(before) as f64 * 1024.0),
+            human_bytes(peak as f64 * 1024.0)
+        ),
+        _ => println!("memory:      unavailable on this platform"),
+    }
+    Ok(())
+}
+
+/// Events from a JSON array, a single JSON object, or NDJSON.
+pub(crate) fn read_events(path: &Path) -> Result<Vec<BytesMut>> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let values: Vec<Value> = match serde_json::from_str::<Value>(&text) {
+        Ok(Value::Array(items)) => items,
+        Ok(v) => vec![v],
+        Err(_) => text
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("{} is not JSON or NDJSON", path.display()))?,
+    };
+    values
+        .iter()
+        .map(|v| Ok(BytesMut::from(serde_json::to_vec(v)?.as_slice())))
+        .collect()
+}
+
+/// Cumulative buckets of one `GUEST_LATENCY` series, in the shape the bench
+/// report computes quantiles from.
+fn snapshot(h: &prometheus::Histogram) -> HistogramSnapshot {
+    let m = h.metric();
+    let proto = m.get_histogram();
+    let count = proto.get_sample_count() as f64;
+    let mut buckets: Vec<(f64, f64)> = proto
+        .get_bucket()
+        .iter()
+        .map(|b| (b.get_upper_bound(), b.get_cumulative_count() as f64))
+        .collect();
+    buckets.push((f64::INFINITY, count));
+    HistogramSnapshot {
+        count,
+        sum: proto.get_sample_sum(),
+        buckets,
+    }
+}
+
+/// A `/proc/self/status` field in KiB, e.g. `VmRSS` or `VmHWM`. Linux only.
+fn rss_kib(field: &str) -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix(field)?.strip_prefix(':'))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
+
+pub(crate) fn human_secs(secs: f64) -> String {
+    if secs < 1e-3 {
+        format!("{:.0}µs", secs * 1e6)
+    } else if secs < 1.0 {
+        format!("{:.2}ms", secs * 1e3)
+    } else {
+        format!("{secs:.2}s")
+    }
+}
+
+pub(crate) fn human_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size:.0} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_from_array_object_or_ndjson() {
+        let path = std::env::temp_dir().join(format!("tangent-events-{}", std::process::id()));
+        let read = |text: &str| {
+            fs::write(&path, text).unwrap();
+            read_events(&path).unwrap()
+        };
+
+        assert_eq!(read("[{\"a\": 1}, {\"a\": 2}]"), ["{\"a\":1}", "{\"a\":2}"]);
+        assert_eq!(read("{\n  \"a\": 1\n}\n"), ["{\"a\":1}"]);
+        assert_eq!(read("{\"a\":1}\n\n{\"a\":2}\n"), ["{\"a\":1}", "{\"a\":2}"]);
+
+        fs::write(&path, "{\"a\":1}\nnot json\n").unwrap();
+        assert!(read_events(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
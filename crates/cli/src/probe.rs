@@ -0,0 +1,86 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use tangent_runtime::cache::CacheHandle;
+use tangent_runtime::wasm::host::JsonLogView;
+use tangent_runtime::wasm::inspect;
+use tangent_shared::runtime::CacheConfig;
+
+use crate::plugin_bench::read_events;
+
+/// Dry-run the events in `input` against the selectors of every compiled
+/// plugin in the config and print, per event and plugin, whether the plugin
+/// would receive it and through which selector. A plugin whose selector
+/// matched but lost to a higher-priority one is marked `outranked`. No
+/// plugin code beyond `metadata()` and `probe()` runs.
+pub async fn run(config_path: &Path, input: &Path) -> Result<()> {
+    let events = read_events(input)?;
+    if events.is_empty() {
+        bail!("{} has no events", input.display());
+    }
+
+    // Scratch cache so probing never touches the runtime's cache file.
+    let cache_dir = tempfile::tempdir()?;
+    let cache = Arc::new(CacheHandle::open(
+        &CacheConfig::default(),
+        cache_dir.path(),
+    )?);
+
+    let plugins = inspect::probe_all(config_path, cache).await?;
+    if plugins.is_empty() {
+        println!("no plugins in {}", config_path.display());
+        return Ok(());
+    }
+
+    let mut rows = vec![[
+        "event_index".to_string(),
+        "plugin_name".to_string(),
+        "matched".to_string(),
+        "matching_selector".to_string(),
+    ]];
+    for (i, ev) in events.into_iter().enumerate() {
+        let view = JsonLogView::from_bytes(ev)?;
+        let receivers = inspect::receivers(&plugins, &view);
+        for (pi, p) in plugins.iter().enumerate() {
+            let hit = p.first_match(&view);
+            let received = receivers.contains(&pi);
+            rows.push([
+                i.to_string(),
+                p.plugin.to_string(),
+                received.to_string(),
+                hit.map_or_else(
+                    || "-".to_string(),
+                    |idx| {
+                        let mut sel = inspect::describe_selector(&p.selectors[idx]);
+                        if let Some(priority) = p.priorities[idx] {
+                            sel.push_str(&format!(" [priority {priority}]"));
+                        }
+                        if received {
+                            format!("#{idx} {sel}")
+                        } else {
+                            format!("#{idx} {sel} (outranked)")
+                        }
+                    },
+                ),
+            ]);
+        }
+    }
+
+    let mut widths = [0usize; 4];
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, w)| format!("{cell:<w$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+
+    Ok(())
+}
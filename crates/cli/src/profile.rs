@@ -0,0 +1,59 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+/// CPU profile of this process for `tangent run --profile`, written as an SVG
+/// flamegraph by `finish`. Needs the `profiling` feature.
+#[cfg(feature = "profiling")]
+pub struct Profiler {
+    guard: pprof::ProfilerGuard<'static>,
+    path: std::path::PathBuf,
+}
+
+#[cfg(feature = "profiling")]
+impl Profiler {
+    pub fn start(path: &Path) -> Result<Self> {
+        use anyhow::Context;
+
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(999)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .context("starting CPU profiler")?;
+        Ok(Self {
+            guard,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn finish(self) -> Result<()> {
+        use anyhow::Context;
+
+        let report = self
+            .guard
+            .report()
+            .build()
+            .context("building CPU profile")?;
+        let file = std::fs::File::create(&self.path)
+            .with_context(|| format!("creating {}", self.path.display()))?;
+        report
+            .flamegraph(file)
+            .with_context(|| format!("writing flamegraph to {}", self.path.display()))?;
+        println!("wrote flamegraph to {}", self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "profiling"))]
+pub struct Profiler(std::convert::Infallible);
+
+#[cfg(not(feature = "profiling"))]
+impl Profiler {
+    pub fn start(_path: &Path) -> Result<Self> {
+        anyhow::bail!("--profile needs tangent built with `--features profiling`")
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.0 {}
+    }
+}
@@ -1,6 +1,11 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use std::os::unix::fs::PermissionsExt;
-use std::{fs, path::Path, process::Command};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tangent_shared::Config;
 
 use crate::wit_assets;
 
@@ -34,9 +39,13 @@ const DOCKERFILE: &str = include_str!(concat!(
     "/../../assets/Dockerfile"
 ));
 
-pub fn scaffold(name: &str, lang: &str) -> Result<()> {
+/// Scaffold a new plugin project in `name/`. The plugin is registered in
+/// `tangent.yaml` as `plugin_name`, defaulting to the project name.
+pub fn scaffold(name: &str, lang: &str, plugin_name: Option<&str>) -> Result<()> {
     let renamed = name.replace("-", "");
     let name = renamed.as_str();
+    let plugin = plugin_name.unwrap_or(name);
+    check_plugin_name(plugin)?;
 
     let proj_dir = Path::new(name);
     if proj_dir.exists() {
@@ -60,9 +69,9 @@ pub fn scaffold(name: &str, lang: &str) -> Result<()> {
     fs::write(proj_dir.join("tests/bench.json"), TEST_BENCH)?;
 
     match lang {
-        "go" => scaffold_go(name, &proj_dir)?,
-        "python" => scaffold_py(name, &proj_dir)?,
-        "rust" => scaffold_rust(name, &proj_dir)?,
+        "go" => scaffold_go(name, plugin, &proj_dir)?,
+        "python" => scaffold_py(name, plugin, &proj_dir)?,
+        "rust" => scaffold_rust(name, plugin, &proj_dir)?,
         other => bail!("unsupported --lang {other} (options: go, python, rust)"),
     }
 
@@ -75,6 +84,171 @@ pub fn scaffold(name: &str, lang: &str) -> Result<()> {
     Ok(())
 }
 
+/// Scaffold a plugin by copying an example out of a git repository.
+///
+/// `source` is either a clonable URL or GitHub shorthand
+/// `owner/repo[/path/to/plugin]`.
+pub fn scaffold_from_example(name: &str, source: &str) -> Result<()> {
+    let renamed = name.replace("-", "");
+    let name = renamed.as_str();
+
+    let proj_dir = Path::new(name);
+    if proj_dir.exists() {
+        bail!("destination already exists: {}", proj_dir.display());
+    }
+
+    let (url, subdir) = resolve_example_source(source)?;
+
+    println!("📥 Cloning {url}");
+    let checkout = tempfile::tempdir().context("creating temp dir for clone")?;
+    git2::Repository::clone(&url, checkout.path()).map_err(|e| clone_error(&url, e))?;
+
+    let example_dir = match &subdir {
+        Some(p) => checkout.path().join(p),
+        None => checkout.path().to_path_buf(),
+    };
+    if !example_dir.is_dir() {
+        bail!(
+            "example path {} not found in {url}",
+            subdir.as_deref().unwrap_or(".")
+        );
+    }
+
+    let example_cfg_path = example_dir.join("tangent.yaml");
+    if !example_cfg_path.exists() {
+        bail!(
+            "no tangent.yaml in {url}{}; not a tangent plugin",
+            subdir.map(|p| format!(" at {p}")).unwrap_or_default()
+        );
+    }
+    let example_cfg = Config::from_file(&example_cfg_path)
+        .with_context(|| format!("invalid tangent.yaml in example {source}"))?;
+    let (example_name, plugin) = example_cfg
+        .plugins
+        .iter()
+        .next()
+        .ok_or_else(|| anyhow!("example tangent.yaml does not define any plugins"))?;
+    let lang = plugin.module_type.clone();
+    let example_name = example_name.to_string();
+
+    println!("🔧 Creating new plugin at {}/", proj_dir.display());
+    copy_example_dir(&example_dir, proj_dir)?;
+    write_embedded_wit(&proj_dir.join(".tangent/wit"))?;
+
+    for file in ["tangent.yaml", "go.mod", "Cargo.toml", "pyproject.toml"] {
+        rename_in_file(&proj_dir.join(file), &example_name, name)?;
+    }
+
+    let setup_path = proj_dir.join("setup.sh");
+    if setup_path.exists() {
+        let mut permissions = fs::metadata(&setup_path)?.permissions();
+        permissions.set_mode(permissions.mode() | 0o111);
+        fs::set_permissions(&setup_path, permissions)?;
+        run_setup(proj_dir)?;
+    }
+
+    println!(
+        "✅ Scaffolded {} ({}) from {} at {}/",
+        name,
+        lang,
+        source,
+        proj_dir.display()
+    );
+    Ok(())
+}
+
+fn resolve_example_source(source: &str) -> Result<(String, Option<String>)> {
+    let source = source.trim().trim_end_matches('/');
+    if source.contains("://") || source.starts_with("git@") {
+        return Ok((source.to_string(), None));
+    }
+
+    let mut parts = source.splitn(3, '/');
+    let (Some(owner), Some(repo)) = (parts.next(), parts.next()) else {
+        bail!("invalid example {source}: expected a git URL or owner/repo[/path]");
+    };
+    if owner.is_empty() || repo.is_empty() {
+        bail!("invalid example {source}: expected a git URL or owner/repo[/path]");
+    }
+    let repo = repo.trim_end_matches(".git");
+
+    Ok((
+        format!("https://github.com/{owner}/{repo}.git"),
+        parts.next().map(str::to_string),
+    ))
+}
+
+fn clone_error(url: &str, e: git2::Error) -> anyhow::Error {
+    match (e.code(), e.class()) {
+        (git2::ErrorCode::Auth, _) | (_, git2::ErrorClass::Ssh) => anyhow!(
+            "authentication failed cloning {url}: {}. Only public repositories are supported; use an https URL",
+            e.message()
+        ),
+        (git2::ErrorCode::NotFound, _) => anyhow!("repository not found: {url}"),
+        (_, git2::ErrorClass::Net) | (_, git2::ErrorClass::Http) => {
+            anyhow!("network error cloning {url}: {}", e.message())
+        }
+        _ => anyhow!("failed to clone {url}: {}", e.message()),
+    }
+}
+
+fn copy_example_dir(src: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src).with_context(|| format!("reading {}", src.display()))? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if file_name == ".git" {
+            continue;
+        }
+        let from: PathBuf = entry.path();
+        let to = dst.join(&file_name);
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            // A cloned repo's links can point anywhere on this machine.
+            println!("⚠️  skipping symlink {}", from.display());
+            continue;
+        }
+        if file_type.is_dir() {
+            copy_example_dir(&from, &to)?;
+        } else {
+            fs::copy(&from, &to).with_context(|| format!("copying {}", from.display()))?;
+        }
+    }
+    Ok(())
+}
+
+fn rename_in_file(path: &Path, from: &str, to: &str) -> Result<()> {
+    if from == to || !path.exists() {
+        return Ok(());
+    }
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    fs::write(path, replace_identifier(&contents, from, to))
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
+
+/// Replace whole-word occurrences of `from`, so renaming `log` leaves
+/// `logs` and `log_level` alone.
+fn replace_identifier(text: &str, from: &str, to: &str) -> String {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(ix) = rest.find(from) {
+        let (before, after) = (&rest[..ix], &rest[ix + from.len()..]);
+        let prev = before
+            .chars()
+            .next_back()
+            .or_else(|| out.chars().next_back());
+        let whole = !prev.is_some_and(is_ident) && !after.chars().next().is_some_and(is_ident);
+        out.push_str(before);
+        out.push_str(if whole { to } else { from });
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
 pub fn write_embedded_wit(dest: &Path) -> Result<()> {
     fs::create_dir_all(dest)?;
     for entry in wit_assets::WIT_DIR.find("**/*").unwrap() {
@@ -98,10 +272,10 @@ pub fn write_embedded_wit(dest: &Path) -> Result<()> {
     Ok(())
 }
 
-fn scaffold_go(name: &str, dir: &Path) -> Result<()> {
+fn scaffold_go(name: &str, plugin: &str, dir: &Path) -> Result<()> {
     fs::write(dir.join("go.mod"), go_mod_for(name))?;
-    fs::write(dir.join("main.go"), go_main_for(name))?;
-    fs::write(dir.join("tangent.yaml"), tangent_config_for("go", name))?;
+    fs::write(dir.join("main.go"), go_main_for(plugin))?;
+    fs::write(dir.join("tangent.yaml"), tangent_config_for("go", plugin))?;
     fs::write(dir.join("Agents.md"), GO_AGENTS_MD)?;
 
     let setup_path = dir.join("setup.sh");
@@ -116,10 +290,13 @@ fn scaffold_go(name: &str, dir: &Path) -> Result<()> {
     Ok(())
 }
 
-fn scaffold_py(name: &str, dir: &Path) -> Result<()> {
+fn scaffold_py(name: &str, plugin: &str, dir: &Path) -> Result<()> {
     fs::write(dir.join("pyproject.toml"), py_project_for(name))?;
-    fs::write(dir.join("mapper.py"), py_mapper_for(name))?;
-    fs::write(dir.join("tangent.yaml"), tangent_config_for("python", name))?;
+    fs::write(dir.join("mapper.py"), py_mapper_for(plugin))?;
+    fs::write(
+        dir.join("tangent.yaml"),
+        tangent_config_for("python", plugin),
+    )?;
     fs::write(dir.join("Agents.md"), PY_AGENTS_MD)?;
     fs::write(dir.join("requirements.txt"), PYTHON_REQUIREMENTS)?;
 
@@ -134,11 +311,11 @@ fn scaffold_py(name: &str, dir: &Path) -> Result<()> {
     Ok(())
 }
 
-fn scaffold_rust(name: &str, dir: &Path) -> Result<()> {
+fn scaffold_rust(name: &str, plugin: &str, dir: &Path) -> Result<()> {
     fs::create_dir(dir.join("src"))?;
     fs::write(dir.join("Cargo.toml"), rust_cargo_toml_for(name))?;
-    fs::write(dir.join("src/lib.rs"), rust_lib_for(name))?;
-    fs::write(dir.join("tangent.yaml"), tangent_config_for("rust", name))?;
+    fs::write(dir.join("src/lib.rs"), rust_lib_for(plugin))?;
+    fs::write(dir.join("tangent.yaml"), tangent_config_for("rust", plugin))?;
     fs::write(dir.join("Agents.md"), RUST_AGENTS_MD)?;
 
     let setup_path = dir.join("setup.sh");
@@ -405,6 +582,17 @@ tool go.bytecodealliance.org/cmd/wit-bindgen-go
     )
 }
 
+/// Plugin names land unquoted in `tangent.yaml` and inside string literals
+/// in the generated sources, so keep them to characters none of those
+/// need escaped.
+fn check_plugin_name(name: &str) -> Result<()> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if name.is_empty() || !name.chars().all(plain) {
+        bail!("plugin name {name:?} may only use letters, digits, `_` and `-` (see --plugin-name)");
+    }
+    Ok(())
+}
+
 fn tangent_config_for(language: &str, name: &str) -> String {
     let path = if language == "python" {
         "mapper.py"
@@ -468,10 +656,15 @@ var Metadata = tangent_sdk.Metadata{
 	Version:	"0.1.0",
 }
 
+// Match logs from any of these services.
+var services = []string{"myservice", "checkout", "payments"}
+
+// When another plugin's selectors overlap these, set `selector_priority` on
+// this plugin in tangent.yaml: only the lowest priority gets a shared event.
 var selectors = []tangent_sdk.Selector{
 	{
 		All: []tangent_sdk.Predicate{
-			tangent_sdk.EqString("source.name", "myservice"),
+			tangent_sdk.InString("source.name", services...),
 		},
 	},
 }
@@ -624,12 +817,15 @@ impl Guest for Component {
     }
 
     fn probe() -> Vec<Selector> {
+        // Match logs from any of these services.
+        let services = ["myservice", "checkout", "payments"]
+            .into_iter()
+            .map(|s| Scalar::Str(s.to_string()))
+            .collect();
+
         vec![Selector {
             any: Vec::new(),
-            all: vec![Pred::Eq((
-                "source.name".to_string(),
-                Scalar::Str("myservice".to_string()),
-            ))],
+            all: vec![Pred::In(("source.name".to_string(), services))],
             none: Vec::new(),
         }]
     }
@@ -822,13 +1018,14 @@ class Mapper(wit_world.WitWorld):
         return mapper.Meta(name="{module}", version="0.1.0")
 
     def probe(self) -> List[mapper.Selector]:
-        # Match logs where source.name == "myservice"
+        # Match logs where source.name is any of these services
+        services = ["myservice", "checkout", "payments"]
         return [
             mapper.Selector(
                 any=[],
                 all=[
-                    mapper.Pred_Eq(
-                        ("source.name", log.Scalar_Str("myservice"))
+                    mapper.Pred_In(
+                        ("source.name", [log.Scalar_Str(s) for s in services])
                     )
                 ],
                 none=[],
@@ -892,3 +1089,56 @@ class Mapper(wit_world.WitWorld):
 
     tpl.replace("{module}", module)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_copies_are_renamed_by_identifier() {
+        let root = tempfile::tempdir().unwrap();
+        let example = root.path().join("example");
+        fs::create_dir_all(example.join("tests")).unwrap();
+        fs::write(
+            example.join("tangent.yaml"),
+            tangent_config_for("go", "logs"),
+        )
+        .unwrap();
+        fs::write(example.join("go.mod"), go_mod_for("logs")).unwrap();
+        fs::write(example.join("tests/input.json"), TEST_INPUT).unwrap();
+        std::os::unix::fs::symlink("/etc/passwd", example.join("passwd")).unwrap();
+
+        let dst = root.path().join("copy");
+        copy_example_dir(&example, &dst).unwrap();
+        for file in ["tangent.yaml", "go.mod"] {
+            rename_in_file(&dst.join(file), "logs", "audit").unwrap();
+        }
+
+        assert!(dst.join("tests/input.json").exists());
+        assert!(fs::symlink_metadata(dst.join("passwd")).is_err());
+        assert!(fs::read_to_string(dst.join("go.mod"))
+            .unwrap()
+            .starts_with("module audit\n"));
+
+        let cfg = Config::from_file(&dst.join("tangent.yaml")).unwrap();
+        assert!(cfg.plugins.contains_key("audit"));
+        assert!(!cfg.plugins.contains_key("logs"));
+        assert_eq!(cfg.dag.len(), 2);
+    }
+
+    #[test]
+    fn plugin_names_must_be_plain_identifiers() {
+        assert!(check_plugin_name("audit-logs_2").is_ok());
+        for bad in ["", "audit: x", "a\"b", "a\nb"] {
+            assert!(check_plugin_name(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn only_whole_identifiers_are_replaced() {
+        assert_eq!(
+            replace_identifier("log: logs log_level \"log\" my-log", "log", "audit"),
+            "audit: logs log_level \"audit\" my-log"
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+use tangent_shared::plugins::PluginConfigOverrides;
+use tangent_shared::{Config, STDIN_CONFIG};
+
+/// Set `key` in plugin `plugin`'s config through the sidecar that running
+/// workers poll. `value` is parsed as JSON and falls back to a plain string.
+pub fn run(config_path: &Path, plugin: &str, key: &str, value: &str) -> Result<()> {
+    if config_path.as_os_str() == STDIN_CONFIG {
+        bail!("set-config needs a config file; a config read from stdin has no sidecar");
+    }
+    let cfg = Config::from_file(config_path)?;
+    if !cfg.plugins.contains_key(plugin) {
+        bail!("plugin {plugin} is not in {}", config_path.display());
+    }
+
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+
+    let path = PluginConfigOverrides::path_for(config_path);
+    let mut overrides = PluginConfigOverrides::load(&path)?;
+    overrides.set(plugin, key, value);
+    overrides.save(&path)?;
+
+    if cfg.config_reload_interval().is_some() {
+        println!(
+            "set {plugin}.{key} in {}; workers pick it up within runtime.config_reload_interval_ms",
+            path.display()
+        );
+    } else {
+        println!(
+            "set {plugin}.{key} in {}; workers only read it once runtime.config_reload_interval_ms is set",
+            path.display()
+        );
+    }
+    Ok(())
+}
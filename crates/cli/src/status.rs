@@ -0,0 +1,106 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use crossterm::{cursor, execute, queue, style::Print, terminal};
+use tangent_bench::metrics::{self, Stats};
+
+use crate::plugin_bench::{human_bytes, human_secs};
+
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// Scrape the Prometheus endpoint at `url` every second and redraw a
+/// dashboard of the pipeline's health until Ctrl-C. Rates are computed from
+/// the change since the previous scrape.
+pub async fn run(url: &str) -> Result<()> {
+    let _screen = Screen::enter()?;
+    let mut prev: Option<(Stats, Instant)> = None;
+
+    loop {
+        let lines = match metrics::scrape_stats(url).await {
+            Ok(cur) => {
+                let now = Instant::now();
+                let lines = render(&cur, prev.as_ref().map(|(s, at)| (s, now - *at)));
+                prev = Some((cur, now));
+                lines
+            }
+            Err(e) => vec![format!("scraping failed: {e:#}")],
+        };
+        draw(url, &lines)?;
+
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => {
+                res?;
+                return Ok(());
+            }
+            () = tokio::time::sleep(REFRESH) => {}
+        }
+    }
+}
+
+/// Dashboard rows for `cur`. `prev` is the previous scrape and how long ago
+/// it was taken; rates read `-` until there is one.
+fn render(cur: &Stats, prev: Option<(&Stats, Duration)>) -> Vec<String> {
+    let rate = |f: fn(&Stats) -> f64| {
+        prev.map(|(p, elapsed)| (f(cur) - f(p)).max(0.0) / elapsed.as_secs_f64().max(1e-3))
+    };
+    let sink = rate(|s| s.sink_bytes).map_or("-".to_string(), |r| format!("{}/s", human_bytes(r)));
+    let consumer =
+        rate(|s| s.consumer_bytes).map_or("-".to_string(), |r| format!("{:.2} MB/s", r / 1e6));
+    let latency = match prev {
+        Some((p, _)) if cur.guest_seconds_count > p.guest_seconds_count => human_secs(
+            (cur.guest_seconds_sum - p.guest_seconds_sum)
+                / (cur.guest_seconds_count - p.guest_seconds_count),
+        ),
+        Some(_) => "- (idle)".to_string(),
+        None => "-".to_string(),
+    };
+
+    vec![
+        format!("in-flight batches    {:.0}", cur.inflight),
+        format!(
+            "wal pending          {:.0} files ({})",
+            cur.wal_pending,
+            human_bytes(cur.wal_pending_bytes)
+        ),
+        format!("sink throughput      {sink}"),
+        format!("consumer throughput  {consumer}"),
+        format!("guest latency (avg)  {latency}"),
+    ]
+}
+
+fn draw(url: &str, lines: &[String]) -> Result<()> {
+    let mut out = io::stdout();
+    queue!(
+        out,
+        cursor::MoveTo(0, 0),
+        terminal::Clear(terminal::ClearType::All),
+        Print(format!(
+            "tangent status  {url}  {}  (Ctrl-C to quit)",
+            chrono::Local::now().format("%H:%M:%S")
+        )),
+        cursor::MoveToNextLine(2),
+    )?;
+    for line in lines {
+        queue!(out, Print(line), cursor::MoveToNextLine(1))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The alternate screen, left again when dropped so the shell comes back
+/// as it was.
+struct Screen;
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+    }
+}
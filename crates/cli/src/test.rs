@@ -5,13 +5,15 @@ use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
 use tangent_shared::dag::{Edge, NodeRef};
-use tangent_shared::plugins::PluginConfig;
+use tangent_shared::plugins::{PluginConfig, PluginTests};
 use tangent_shared::runtime::{CacheConfig, RuntimeConfig};
 use tangent_shared::sinks::common::{CommonSinkOptions, Compression, Encoding};
 use tangent_shared::Config;
+use tokio_util::sync::CancellationToken;
 use tracing::{info, warn};
 
 use serde_json::{Map, Value};
@@ -20,14 +22,38 @@ use tangent_shared::sinks::{
     common::{SinkConfig, SinkKind},
     file as fileSink,
 };
-use tangent_shared::sources::common::{DecodeCompression, DecodeFormat, Decoding, SourceConfig};
+use tangent_shared::sources::common::{
+    default_max_restart_delay_secs, DecodeCompression, DecodeFormat, Decoding, SourceConfig,
+};
 use tangent_shared::sources::file;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InputFormat {
+    #[value(name = "json_array")]
+    JsonArray,
+    Ndjson,
+    /// First row is the header; every row becomes an object of string fields.
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExpectedFormat {
+    #[value(name = "json_array")]
+    JsonArray,
+    Ndjson,
+}
+
 #[derive(Debug)]
 pub struct TestOptions {
     pub plugin: Option<String>,
     pub config_path: PathBuf,
     pub enable_http: bool,
+    pub input_format: InputFormat,
+    pub expected_format: ExpectedFormat,
+    /// Upper bound on a single test run, so a looping plugin can't hang the suite.
+    pub timeout_secs: u64,
+    /// Stop at the first failing test instead of running the rest.
+    pub fail_fast: bool,
 }
 
 pub async fn run(opts: TestOptions) -> Result<()> {
@@ -43,8 +69,7 @@ pub async fn run(opts: TestOptions) -> Result<()> {
 
     let mut plugins_to_test = Vec::<(Arc<str>, PluginConfig)>::new();
 
-    if opts.plugin.is_some() {
-        let plugin_name = opts.plugin.unwrap();
+    if let Some(plugin_name) = &opts.plugin {
         let mut found = false;
         for (name, plugin_cfg) in cfg.plugins {
             if name.as_ref() == plugin_name.as_str() {
@@ -63,130 +88,220 @@ pub async fn run(opts: TestOptions) -> Result<()> {
         }
     }
 
-    for (name, plugin_cfg) in plugins_to_test {
-        for test in plugin_cfg.tests {
-            let input = config_root
-                .join(test.input)
-                .canonicalize()
-                .context("test input file")?;
-            let expected = config_root
-                .join(test.expected)
-                .canonicalize()
-                .context("test expected file")?;
-
-            let plugins_path = config_root
-                .join(plugin_cfg.path.clone())
-                .canonicalize()
-                .context("plugins path")?;
-
-            let input_source = SourceConfig::File(file::FileConfig {
-                path: input,
-                decoding: Decoding {
-                    compression: DecodeCompression::None,
-                    format: DecodeFormat::JsonArray,
-                },
-            });
-
-            let out_file = PathBuf::from_str("test_out.ndjson")?;
-            if out_file.exists() {
-                fs::remove_file(out_file.clone())?;
+    let mut failed = 0usize;
+    let mut total = 0usize;
+    for (name, plugin_cfg) in &plugins_to_test {
+        for test in &plugin_cfg.tests {
+            total += 1;
+            let res = run_test(
+                &opts,
+                &cfg.runtime,
+                config_root,
+                &rt,
+                name,
+                plugin_cfg,
+                test,
+            )
+            .await;
+            if let Err(e) = res {
+                if opts.fail_fast {
+                    return Err(e);
+                }
+                warn!("{name}: {} failed: {e:#}", test.input.display());
+                failed += 1;
             }
+        }
+    }
 
-            let file_sink = SinkConfig {
-                kind: SinkKind::File(fileSink::FileConfig {
-                    path: out_file.clone(),
-                }),
-                common: CommonSinkOptions {
-                    compression: Compression::None,
-                    encoding: Encoding::NDJSON,
-                    object_max_bytes: tangent_shared::sinks::common::object_max_bytes(),
-                    in_flight_limit: tangent_shared::sinks::common::in_flight_limit(),
-                    default: true,
-                },
-            };
-
-            let runtime = RuntimeConfig {
-                plugins_path: cfg.runtime.plugins_path.clone(),
-                batch_size: 1,
-                batch_age: 1,
-                workers: 1,
-                cache: CacheConfig::default(),
-                disable_remote_calls: !opts.enable_http,
-            };
-
-            let entry = Edge {
-                from: NodeRef::Source {
-                    name: "input".into(),
-                },
-                to: vec![NodeRef::Plugin { name: name.clone() }],
-            };
-
-            let exit = Edge {
-                from: NodeRef::Plugin { name: name.clone() },
-                to: vec![NodeRef::Sink {
-                    name: "out".into(),
-                    key_prefix: None,
-                }],
-            };
-
-            let mut sinks = BTreeMap::new();
-            sinks.insert(Arc::<str>::from("out"), file_sink);
-
-            let mut sources = BTreeMap::new();
-            sources.insert(Arc::<str>::from("input"), input_source);
-
-            let plugin_config = PluginConfig {
-                module_type: "".to_string(), // not used
-                path: plugins_path,
-                tests: vec![],
-                config: plugin_cfg.config.clone(),
-            };
-
-            let mut plugins = BTreeMap::new();
-            plugins.insert(name.clone(), plugin_config);
-
-            let test_config = tangent_shared::Config {
-                runtime,
-                sources,
-                sinks,
-                plugins,
-                dag: vec![entry, exit],
-            };
-
-            let yaml = serde_yaml::to_string(&test_config)?;
-
-            let test_file = PathBuf::from(".test.yaml");
-            let test_config_file = config_root.join(&test_file);
-            fs::write(&test_config_file, yaml)?;
-
-            {
-                let sqlite_cache =
-                    cache::CacheHandle::open(&test_config.runtime.cache, config_root)?;
-                sqlite_cache.reset()?;
-            }
+    if failed > 0 {
+        bail!("{failed} of {total} plugin tests failed");
+    }
+    Ok(())
+}
+
+async fn run_test(
+    opts: &TestOptions,
+    base: &RuntimeConfig,
+    config_root: &Path,
+    rt: &RuntimeOptions,
+    name: &Arc<str>,
+    plugin_cfg: &PluginConfig,
+    test: &PluginTests,
+) -> Result<()> {
+    let input = config_root
+        .join(&test.input)
+        .canonicalize()
+        .context("test input file")?;
+    let expected = config_root
+        .join(&test.expected)
+        .canonicalize()
+        .context("test expected file")?;
+
+    let plugins_path = config_root
+        .join(plugin_cfg.path.clone())
+        .canonicalize()
+        .context("plugins path")?;
+
+    // CSV input is converted into a temp file that lives until the test
+    // returns and is removed when dropped.
+    let mut converted = None;
+    let (input, format) = match opts.input_format {
+        InputFormat::JsonArray => (input, DecodeFormat::JsonArray),
+        InputFormat::Ndjson => (input, DecodeFormat::Ndjson),
+        InputFormat::Csv => {
+            let tmp = converted.insert(tempfile::Builder::new().suffix(".ndjson").tempfile()?);
+            csv_to_ndjson(&input, tmp.path())?;
+            (tmp.path().to_path_buf(), DecodeFormat::Ndjson)
+        }
+    };
+
+    let input_source = SourceConfig::File(file::FileConfig {
+        path: input,
+        glob: None,
+        decoding: Decoding {
+            compression: DecodeCompression::None,
+            format,
+        },
+        tail: false,
+        max_line_bytes: file::default_max_line_bytes(),
+        max_restart_delay_secs: default_max_restart_delay_secs(),
+    });
+
+    let out_file = PathBuf::from_str("test_out.ndjson")?;
+    if out_file.exists() {
+        fs::remove_file(out_file.clone())?;
+    }
 
-            tangent_runtime::run(&test_config_file, rt.clone()).await?;
+    let file_sink = SinkConfig {
+        kind: SinkKind::File(fileSink::FileConfig {
+            path: out_file.clone(),
+        }),
+        common: CommonSinkOptions {
+            compression: Compression::None,
+            encoding: Encoding::NDJSON,
+            object_max_bytes: tangent_shared::sinks::common::object_max_bytes(),
+            in_flight_limit: tangent_shared::sinks::common::in_flight_limit(),
+            parquet_row_group_size: tangent_shared::sinks::common::parquet_row_group_size(),
+            default: true,
+            zstd_dict: None,
+            key_prefix_field: None,
+            key_prefix_fallback: None,
+            key_prefix_max_values: tangent_shared::sinks::common::key_prefix_max_values(),
+            encryption: None,
+            circuit_breaker: None,
+            max_wal_bytes: None,
+        },
+    };
+
+    let runtime = RuntimeConfig {
+        plugins_path: base.plugins_path.clone(),
+        batch_size: 1,
+        batch_age: 1,
+        workers: 1,
+        cache: CacheConfig::default(),
+        disable_remote_calls: !opts.enable_http,
+        shard_strategy: base.shard_strategy,
+        config_reload_interval_ms: 0,
+        backpressure_timeout_ms: None,
+    };
+
+    let entry = Edge {
+        from: NodeRef::Source {
+            name: "input".into(),
+        },
+        to: vec![NodeRef::Plugin { name: name.clone() }],
+        filter: None,
+        route_by: None,
+        flush_interval_ms: None,
+    };
+
+    let exit = Edge {
+        from: NodeRef::Plugin { name: name.clone() },
+        to: vec![NodeRef::Sink {
+            name: "out".into(),
+            key_prefix: None,
+        }],
+        filter: None,
+        route_by: None,
+        flush_interval_ms: None,
+    };
+
+    let mut sinks = BTreeMap::new();
+    sinks.insert(Arc::<str>::from("out"), file_sink);
+
+    let mut sources = BTreeMap::new();
+    sources.insert(Arc::<str>::from("input"), input_source);
+
+    let plugin_config = PluginConfig {
+        module_type: "".to_string(), // not used
+        path: plugins_path,
+        tests: vec![],
+        config: plugin_cfg.config.clone(),
+        remote_call_concurrency: plugin_cfg.remote_call_concurrency,
+        dead_letter: None,
+        timeout_ms: plugin_cfg.timeout_ms,
+        max_memory_mb: plugin_cfg.max_memory_mb,
+        encoding: plugin_cfg.encoding,
+        worker_affinity_key: plugin_cfg.worker_affinity_key.clone(),
+        selector_priority: plugin_cfg.selector_priority,
+        capabilities: plugin_cfg.capabilities.clone(),
+    };
+
+    let mut plugins = BTreeMap::new();
+    plugins.insert(name.clone(), plugin_config);
+
+    let test_config = tangent_shared::Config {
+        runtime,
+        sources,
+        sinks,
+        plugins,
+        dag: vec![entry, exit],
+    };
+
+    let yaml = serde_yaml::to_string(&test_config)?;
+
+    let test_file = PathBuf::from(".test.yaml");
+    let test_config_file = config_root.join(&test_file);
+    fs::write(&test_config_file, yaml)?;
+
+    {
+        let sqlite_cache = cache::CacheHandle::open(&test_config.runtime.cache, config_root)?;
+        sqlite_cache.reset()?;
+    }
 
-            let produced = read_ndjson(&out_file).context("reading produced NDJSON")?;
-            let expected = read_json(&expected)?;
+    let limit = Duration::from_secs(opts.timeout_secs);
+    let stop = CancellationToken::new();
+    let run = tangent_runtime::run_until(&test_config_file, rt.clone(), stop.clone());
+    tokio::pin!(run);
+    if tokio::time::timeout(limit, run.as_mut()).await.is_err() {
+        warn!("❌ test TIMEOUT after {}s", opts.timeout_secs);
+        // Stop the runtime so nothing is left writing to the output file.
+        stop.cancel();
+        let _ = run.await;
+        bail!("test timed out after {}s", opts.timeout_secs);
+    }
 
-            if produced.is_array() != expected.is_array() {
-                warn!("❌ test failed: output differs from expected\n");
-                bail!(
-                    "output is array: {}, expected is array: {}",
-                    produced.is_array(),
-                    expected.is_array()
-                );
-            }
-            let diffs = diff_lines(&expected, &produced);
+    let produced = read_ndjson(&out_file).context("reading produced NDJSON")?;
+    let expected = match opts.expected_format {
+        ExpectedFormat::JsonArray => read_json(&expected)?,
+        ExpectedFormat::Ndjson => read_ndjson(&expected)?,
+    };
+
+    if produced.is_array() != expected.is_array() {
+        warn!("❌ test failed: output differs from expected\n");
+        bail!(
+            "output is array: {}, expected is array: {}",
+            produced.is_array(),
+            expected.is_array()
+        );
+    }
+    let diffs = diff_lines(&expected, &produced);
 
-            if diffs.is_empty() {
-                info!("✅ test passed: output matches expected");
-            } else {
-                warn!("❌ test failed: output differs from expected\n{}", diffs);
-                bail!("output differs from expected");
-            }
-        }
+    if diffs.is_empty() {
+        info!("✅ test passed: output matches expected");
+    } else {
+        warn!("❌ test failed: output differs from expected\n{}", diffs);
+        bail!("output differs from expected");
     }
     Ok(())
 }
@@ -198,6 +313,25 @@ fn read_json(path: &Path) -> Result<Value> {
     Ok(stabilize(v))
 }
 
+fn csv_to_ndjson(src: &Path, dst: &Path) -> Result<()> {
+    let mut rdr = csv::Reader::from_path(src).with_context(|| format!("read {}", src.display()))?;
+    let headers = rdr.headers()?.clone();
+
+    let mut out = String::new();
+    for (i, record) in rdr.records().enumerate() {
+        let record = record.with_context(|| format!("parse CSV row {}", i + 2))?;
+        let row: Map<String, Value> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+            .collect();
+        out.push_str(&serde_json::to_string(&row)?);
+        out.push('\n');
+    }
+
+    fs::write(dst, out).with_context(|| format!("write {}", dst.display()))
+}
+
 fn read_ndjson(path: &Path) -> Result<Value> {
     let file = File::open(path).with_context(|| format!("read {}", path.display()))?;
 
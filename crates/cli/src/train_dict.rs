@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use tangent_shared::sinks::common::Compression;
+use tangent_shared::{Config, ConfigFormat};
+
+use crate::plugin_bench::read_events;
+
+/// Train a zstd dictionary from up to `samples` events in `input` (a JSON
+/// array, a single JSON object, or NDJSON) and write it to `output`. Each event is one sample,
+/// serialized the way sinks write NDJSON lines.
+pub fn run(
+    config_path: &Path,
+    input: &Path,
+    output: &Path,
+    samples: usize,
+    max_size: usize,
+) -> Result<()> {
+    let cfg = Config::from_file_with_format(config_path, ConfigFormat::from_path(config_path))?;
+
+    let lines: Vec<Vec<u8>> = read_events(input)?
+        .into_iter()
+        .take(samples)
+        .map(|ev| {
+            let mut line = ev.to_vec();
+            line.push(b'\n');
+            line
+        })
+        .collect();
+    if lines.is_empty() {
+        bail!("{} contains no events", input.display());
+    }
+
+    let dict = zstd::dict::from_samples(&lines, max_size)
+        .with_context(|| format!("training on {} samples", lines.len()))?;
+    fs::write(output, &dict).with_context(|| format!("write {}", output.display()))?;
+    println!(
+        "✅ wrote {} byte dictionary trained on {} samples to {}",
+        dict.len(),
+        lines.len(),
+        output.display()
+    );
+
+    let zstd_sinks: Vec<&str> = cfg
+        .sinks
+        .iter()
+        .filter(|(_, s)| matches!(s.common.compression, Compression::Zstd { .. }))
+        .map(|(name, _)| name.as_ref())
+        .collect();
+    if zstd_sinks.is_empty() {
+        println!("no sink in {} uses zstd compression", config_path.display());
+    } else {
+        println!(
+            "set `zstd_dict: {}` on sink(s): {}",
+            output.display(),
+            zstd_sinks.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use tangent_shared::dag::NodeRef;
+use tangent_shared::error::{ConfigError, ConfigErrors};
+use tangent_shared::sinks::common::SinkKind;
+use tangent_shared::{Config, ConfigFormat};
+
+/// Problems found in a config. Errors stop `tangent run` from working;
+/// warnings are legal but probably not what was intended.
+#[derive(Default)]
+struct Report {
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl Report {
+    fn error(&mut self, msg: impl Into<String>) {
+        self.errors.push(msg.into());
+    }
+
+    fn warn(&mut self, msg: impl Into<String>) {
+        self.warnings.push(msg.into());
+    }
+}
+
+/// Check `config_path` without starting the runtime or loading any WASM.
+/// Prints every error and warning, and fails when there is at least one error.
+pub fn run(config_path: &Path, format: ConfigFormat) -> Result<()> {
+    let cfg = match Config::from_file_with_format(config_path, format) {
+        Ok(cfg) => cfg,
+        Err(e) => match config_errors(&e).as_slice() {
+            [] => bail!("❌ {} does not parse: {e:#}", config_path.display()),
+            errs => {
+                for err in errs {
+                    println!("error: {err}");
+                }
+                bail!("❌ {} does not parse", config_path.display())
+            }
+        },
+    };
+    let config_dir = config_path.parent().unwrap_or(Path::new("."));
+
+    let mut report = Report::default();
+    if let Err(e) = cfg.validate() {
+        match config_errors(&e).as_slice() {
+            [] => report.error(format!("{e:#}")),
+            errs => errs.iter().for_each(|err| report.error(err.to_string())),
+        }
+    }
+    check_runtime(&cfg, &mut report);
+    check_plugins(&cfg, config_dir, &mut report);
+    check_sinks(&cfg, &mut report);
+    check_unused_nodes(&cfg, &mut report);
+
+    for e in &report.errors {
+        println!("error: {e}");
+    }
+    for w in &report.warnings {
+        println!("warning: {w}");
+    }
+
+    if !report.errors.is_empty() {
+        bail!(
+            "❌ {} has {} error(s) and {} warning(s)",
+            config_path.display(),
+            report.errors.len(),
+            report.warnings.len()
+        );
+    }
+    println!(
+        "✅ {} is valid ({} warning(s))",
+        config_path.display(),
+        report.warnings.len()
+    );
+    Ok(())
+}
+
+/// The located config errors anywhere in `err`'s chain.
+pub fn config_errors(err: &anyhow::Error) -> Vec<&ConfigError> {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<ConfigError>() {
+            return vec![e];
+        }
+        if let Some(ConfigErrors(errs)) = cause.downcast_ref::<ConfigErrors>() {
+            return errs.iter().collect();
+        }
+    }
+    Vec::new()
+}
+
+fn check_runtime(cfg: &Config, report: &mut Report) {
+    if cfg.runtime.batch_size == 0 {
+        report.error("runtime.batch_size must be greater than 0");
+    }
+    if cfg.runtime.batch_age == 0 {
+        report.error("runtime.batch_age must be greater than 0");
+    }
+}
+
+fn check_plugins(cfg: &Config, config_dir: &Path, report: &mut Report) {
+    let plugin_root = config_dir.join(&cfg.runtime.plugins_path);
+    for (name, plugin) in &cfg.plugins {
+        let src = config_dir.join(&plugin.path);
+        if !src.exists() {
+            report.error(format!(
+                "plugin {name}: path {} does not exist",
+                src.display()
+            ));
+        }
+        let compiled = plugin_root.join(format!("{name}.cwasm"));
+        if !compiled.exists() {
+            report.warn(format!(
+                "plugin {name}: {} not found; run `tangent plugin compile` first",
+                compiled.display()
+            ));
+        }
+    }
+}
+
+fn check_sinks(cfg: &Config, report: &mut Report) {
+    for (name, sink) in &cfg.sinks {
+        if sink.common.object_max_bytes == 0 {
+            report.error(format!(
+                "sink {name}: object_max_bytes must be greater than 0"
+            ));
+        }
+        if sink.common.in_flight_limit == 0 {
+            report.error(format!(
+                "sink {name}: in_flight_limit must be greater than 0"
+            ));
+        }
+
+        if let Some(dict) = &sink.common.zstd_dict {
+            if !dict.exists() {
+                report.error(format!(
+                    "sink {name}: zstd_dict {} does not exist",
+                    dict.display()
+                ));
+            }
+        }
+
+        let wal_path = match &sink.kind {
+            SinkKind::S3(c) => &c.wal_path,
+            SinkKind::Gcs(c) => &c.wal_path,
+            SinkKind::AzureBlob(c) => &c.wal_path,
+            SinkKind::File(_)
+            | SinkKind::Blackhole(_)
+            | SinkKind::PrometheusRemoteWrite(_)
+            | SinkKind::Loki(_)
+            | SinkKind::Elasticsearch(_)
+            | SinkKind::ClickHouse(_) => continue,
+        };
+        if let Err(e) = check_writable(wal_path) {
+            report.error(format!(
+                "sink {name}: wal_path {} is not writable: {e}",
+                wal_path.display()
+            ));
+        }
+    }
+}
+
+/// Whether files can be created in `dir`, or in its nearest existing
+/// ancestor when the runtime would still have to create it.
+fn check_writable(dir: &Path) -> std::io::Result<()> {
+    let mut existing: PathBuf = dir.to_path_buf();
+    while !existing.exists() {
+        match existing.parent() {
+            Some(p) if !p.as_os_str().is_empty() => existing = p.to_path_buf(),
+            _ => existing = PathBuf::from("."),
+        }
+    }
+    if !existing.is_dir() {
+        return Err(std::io::Error::other(format!(
+            "{} is not a directory",
+            existing.display()
+        )));
+    }
+    let probe = existing.join(format!(".tangent-validate-{}", std::process::id()));
+    fs::File::create(&probe)?;
+    fs::remove_file(&probe)
+}
+
+fn check_unused_nodes(cfg: &Config, report: &mut Report) {
+    let feeds = |node: &NodeRef| cfg.dag.iter().any(|e| &e.from == node);
+    let fed = |node: &NodeRef| {
+        cfg.dag
+            .iter()
+            .any(|e| e.to.iter().any(|t| same_node(t, node)))
+    };
+
+    for name in cfg.sources.keys() {
+        let node = NodeRef::Source { name: name.clone() };
+        if !feeds(&node) {
+            report.warn(format!("source {name} has no outgoing edges"));
+        }
+    }
+    for name in cfg.plugins.keys() {
+        let node = NodeRef::Plugin { name: name.clone() };
+        if !fed(&node) {
+            report.warn(format!("plugin {name} receives no events"));
+        } else if !feeds(&node) {
+            report.warn(format!("plugin {name} has no outgoing edges"));
+        }
+    }
+    for (name, sink) in &cfg.sinks {
+        let node = NodeRef::Sink {
+            name: name.clone(),
+            key_prefix: None,
+        };
+        let dead_letter = cfg
+            .plugins
+            .values()
+            .any(|p| p.dead_letter.as_deref() == Some(name.as_ref()))
+            || cfg.sinks.values().any(|s| {
+                s.common
+                    .circuit_breaker
+                    .as_ref()
+                    .and_then(|cb| cb.dead_letter.as_deref())
+                    == Some(name.as_ref())
+            });
+        if !fed(&node) && !dead_letter && !sink.common.default {
+            report.warn(format!("sink {name} receives no events"));
+        }
+    }
+}
+
+/// Node equality ignoring a sink edge's `key_prefix`.
+fn same_node(a: &NodeRef, b: &NodeRef) -> bool {
+    match (a, b) {
+        (NodeRef::Sink { name: x, .. }, NodeRef::Sink { name: y, .. }) => x == y,
+        _ => a == b,
+    }
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use tangent_runtime::sinks::wal::{self, Verified};
+
+/// Report every sealed file in `dir` whose contents no longer match the
+/// checksum recorded in its meta. Fails if any do.
+pub async fn verify(dir: &Path) -> Result<()> {
+    let results = wal::verify_dir(dir).await?;
+    if results.is_empty() {
+        println!("no sealed files in {}", dir.display());
+        return Ok(());
+    }
+
+    let (mut ok, mut unchecked, mut failed) = (0, 0, 0);
+    for (path, verified) in &results {
+        match verified {
+            Verified::Ok => ok += 1,
+            Verified::Unchecked => unchecked += 1,
+            Verified::Corrupt { expected, actual } => {
+                failed += 1;
+                println!(
+                    "❌ {}: checksum {actual:08x}, expected {expected:08x}",
+                    path.display()
+                );
+            }
+            Verified::Unreadable(e) => {
+                failed += 1;
+                println!("❌ {}: {e}", path.display());
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!(
+            "{failed} of {} sealed file(s) failed verification",
+            results.len()
+        );
+    }
+    println!("✅ {ok} sealed file(s) ok, {unchecked} without a checksum");
+    Ok(())
+}
@@ -4,11 +4,14 @@ use std::{
     path::{Path, PathBuf},
     process::{Command, Stdio},
 };
+use tangent_shared::plugins::CompiledPluginMeta;
 use tangent_shared::Config;
 use toml::Value;
 use wasmtime::component::Component;
 use which::which;
 
+pub mod wit;
+
 const WORLD: &str = "processor";
 
 pub fn compile_from_config(cfg_path: &PathBuf, wit_path: &PathBuf) -> Result<()> {
@@ -22,6 +25,8 @@ pub fn compile_from_config(cfg_path: &PathBuf, wit_path: &PathBuf) -> Result<()>
         .canonicalize()
         .with_context(|| "configured plugins path")?;
 
+    wit::validate(wit_path, WORLD)?;
+
     for (name, plugin) in cfg.plugins {
         let entry_point_path = config_dir
             .join(&plugin.path)
@@ -49,6 +54,15 @@ pub fn compile_from_config(cfg_path: &PathBuf, wit_path: &PathBuf) -> Result<()>
         let cwasm_out = &out.join(format!("{name}.cwasm"));
         std::fs::write(cwasm_out, bytes)?;
 
+        let meta = CompiledPluginMeta {
+            language: plugin.module_type.clone(),
+            tangent_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        std::fs::write(
+            CompiledPluginMeta::path_for(cwasm_out),
+            serde_json::to_vec_pretty(&meta)?,
+        )?;
+
         println!(
             "✅ Compiled {} → {}",
             entry_point_path.display(),
@@ -0,0 +1,274 @@
+use anyhow::{Context, Result};
+use std::{fmt, path::Path};
+use wit_parser::{Function, Handle, InterfaceId, Resolve, Type, TypeDefKind, WorldItem};
+
+/// Package the processor world and its interfaces must come from.
+const PACKAGE: (&str, &str) = ("tangent", "logs");
+
+/// Functions every plugin must export through the `mapper` interface, with
+/// the signature the runtime binds against.
+const EXPORTS: &[(&str, &str)] = &[
+    ("metadata", "func() -> meta"),
+    ("probe", "func() -> list<selector>"),
+    (
+        "process-logs",
+        "func(input: list<logview>) -> result<list<u8>, string>",
+    ),
+];
+
+/// Interfaces the host links into every plugin.
+const IMPORTS: &[&str] = &["log", "remote"];
+
+/// Everything wrong with a WIT directory, reported together rather than
+/// failing on the first problem.
+#[derive(Debug, Default)]
+pub struct WitValidationError {
+    pub world: String,
+    pub missing_exports: Vec<String>,
+    pub missing_imports: Vec<String>,
+    pub mismatched: Vec<String>,
+}
+
+impl WitValidationError {
+    fn is_empty(&self) -> bool {
+        self.missing_exports.is_empty()
+            && self.missing_imports.is_empty()
+            && self.mismatched.is_empty()
+    }
+}
+
+impl fmt::Display for WitValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "WIT world `{}` does not match the tangent runtime:",
+            self.world
+        )?;
+        for e in &self.missing_exports {
+            writeln!(f, "  missing export: {e}")?;
+        }
+        for i in &self.missing_imports {
+            writeln!(f, "  missing import: {i}")?;
+        }
+        for m in &self.mismatched {
+            writeln!(f, "  mismatched: {m}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for WitValidationError {}
+
+/// Parse the WIT package at `wit_path` and check that `world` exports the
+/// mapper functions and imports the host interfaces the runtime expects.
+pub fn validate(wit_path: &Path, world: &str) -> Result<()> {
+    let mut resolve = Resolve::default();
+    let (pkg, _) = resolve
+        .push_dir(wit_path)
+        .with_context(|| format!("parsing WIT in {}", wit_path.display()))?;
+
+    let world_id = *resolve.packages[pkg].worlds.get(world).with_context(|| {
+        format!(
+            "WIT package in {} has no `{world}` world",
+            wit_path.display()
+        )
+    })?;
+    let w = &resolve.worlds[world_id];
+
+    let mut err = WitValidationError {
+        world: world.to_string(),
+        ..Default::default()
+    };
+
+    let exported = w
+        .exports
+        .values()
+        .filter_map(|item| match item {
+            WorldItem::Interface { id, .. } => Some(*id),
+            _ => None,
+        })
+        .find(|id| is_tangent_interface(&resolve, *id, "mapper"));
+
+    match exported {
+        Some(id) => {
+            let iface = &resolve.interfaces[id];
+            for (name, want) in EXPORTS {
+                match iface.functions.get(*name) {
+                    Some(func) => {
+                        let got = signature(&resolve, func);
+                        if got != *want {
+                            err.mismatched
+                                .push(format!("mapper.{name}: expected `{want}`, found `{got}`"));
+                        }
+                    }
+                    None => err.missing_exports.push(format!("mapper.{name}")),
+                }
+            }
+        }
+        None => err
+            .missing_exports
+            .extend(EXPORTS.iter().map(|(name, _)| format!("mapper.{name}"))),
+    }
+
+    for want in IMPORTS {
+        let found = w.imports.values().any(|item| match item {
+            WorldItem::Interface { id, .. } => is_tangent_interface(&resolve, *id, want),
+            _ => false,
+        });
+        if !found {
+            err.missing_imports
+                .push(format!("{}:{}/{want}", PACKAGE.0, PACKAGE.1));
+        }
+    }
+
+    if err.is_empty() {
+        Ok(())
+    } else {
+        Err(err.into())
+    }
+}
+
+fn is_tangent_interface(resolve: &Resolve, id: InterfaceId, name: &str) -> bool {
+    let iface = &resolve.interfaces[id];
+    let Some(pkg) = iface.package else {
+        return false;
+    };
+    let pkg = &resolve.packages[pkg].name;
+    iface.name.as_deref() == Some(name) && pkg.namespace == PACKAGE.0 && pkg.name == PACKAGE.1
+}
+
+fn signature(resolve: &Resolve, func: &Function) -> String {
+    let params = func
+        .params
+        .iter()
+        .map(|(name, ty)| format!("{name}: {}", type_name(resolve, ty)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    match &func.result {
+        Some(ty) => format!("func({params}) -> {}", type_name(resolve, ty)),
+        None => format!("func({params})"),
+    }
+}
+
+fn type_name(resolve: &Resolve, ty: &Type) -> String {
+    let id = match ty {
+        Type::Bool => return "bool".into(),
+        Type::U8 => return "u8".into(),
+        Type::U16 => return "u16".into(),
+        Type::U32 => return "u32".into(),
+        Type::U64 => return "u64".into(),
+        Type::S8 => return "s8".into(),
+        Type::S16 => return "s16".into(),
+        Type::S32 => return "s32".into(),
+        Type::S64 => return "s64".into(),
+        Type::F32 => return "f32".into(),
+        Type::F64 => return "f64".into(),
+        Type::Char => return "char".into(),
+        Type::String => return "string".into(),
+        Type::ErrorContext => return "error-context".into(),
+        Type::Id(id) => *id,
+    };
+
+    let def = &resolve.types[id];
+    if let Some(name) = &def.name {
+        return name.clone();
+    }
+    let opt = |t: &Option<Type>| {
+        t.as_ref()
+            .map_or("_".to_string(), |t| type_name(resolve, t))
+    };
+    match &def.kind {
+        TypeDefKind::List(t) => format!("list<{}>", type_name(resolve, t)),
+        TypeDefKind::Option(t) => format!("option<{}>", type_name(resolve, t)),
+        TypeDefKind::Result(r) => format!("result<{}, {}>", opt(&r.ok), opt(&r.err)),
+        TypeDefKind::Tuple(t) => format!(
+            "tuple<{}>",
+            t.types
+                .iter()
+                .map(|t| type_name(resolve, t))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        // `list<logview>` resolves to a list of owned handles; print the
+        // resource name so it reads like the source.
+        TypeDefKind::Handle(Handle::Own(r)) => type_name(resolve, &Type::Id(*r)),
+        TypeDefKind::Handle(Handle::Borrow(r)) => {
+            format!("borrow<{}>", type_name(resolve, &Type::Id(*r)))
+        }
+        TypeDefKind::Type(t) => type_name(resolve, t),
+        other => format!("{other:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_wit(body: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("processor.wit"), body).unwrap();
+        dir
+    }
+
+    const INTERFACES: &str = r#"
+package tangent:logs@0.1.0;
+
+interface remote {
+  call: func(url: string) -> string;
+}
+
+interface log {
+  resource logview {
+    log: func() -> string;
+  }
+}
+"#;
+
+    #[test]
+    fn accepts_expected_world() {
+        let dir = write_wit(&format!(
+            "{INTERFACES}
+interface mapper {{
+  use log.{{logview}};
+  record meta {{ name: string, version: string }}
+  record selector {{ all: list<string> }}
+  metadata: func() -> meta;
+  probe: func() -> list<selector>;
+  process-logs: func(input: list<logview>) -> result<list<u8>, string>;
+}}
+
+world processor {{
+  import remote;
+  import log;
+  export mapper;
+}}
+"
+        ));
+        validate(dir.path(), "processor").unwrap();
+    }
+
+    #[test]
+    fn reports_missing_and_mismatched_items() {
+        let dir = write_wit(&format!(
+            "{INTERFACES}
+interface mapper {{
+  use log.{{logview}};
+  record meta {{ name: string }}
+  metadata: func() -> meta;
+  process-logs: func(input: list<logview>) -> list<u8>;
+}}
+
+world processor {{
+  import log;
+  export mapper;
+}}
+"
+        ));
+        let err = validate(dir.path(), "processor").unwrap_err();
+        let err = err.downcast_ref::<WitValidationError>().unwrap();
+        assert_eq!(err.missing_exports, vec!["mapper.probe"]);
+        assert_eq!(err.missing_imports, vec!["tangent:logs/remote"]);
+        assert_eq!(err.mismatched.len(), 1);
+        assert!(err.mismatched[0].starts_with("mapper.process-logs"));
+    }
+}
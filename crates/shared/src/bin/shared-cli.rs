@@ -24,7 +24,7 @@ fn main() -> Result<()> {
     let batch_kb = cfg.batch_size_kb();
     let batch_bytes = batch_kb << 10;
     let batch_age_ms = cfg.batch_age_ms();
-    let workers = cfg.runtime.workers;
+    let workers = cfg.effective_workers();
 
     if args.json {
         println!(
@@ -1,21 +1,28 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::dag::{Edge, NodeRef};
-use crate::sinks::common::SinkConfig;
-use crate::sources::common::SourceConfig;
+use crate::dag::{Edge, EdgeFilter, NodeRef};
+use crate::error::{ConfigError, ConfigErrors};
+use crate::sinks::common::{SinkConfig, SinkKind};
+use crate::sinks::loki;
+use crate::sources::common::{DecodeFormat, SourceConfig};
 
 pub mod dag;
+pub mod error;
 pub mod plugins;
 pub mod runtime;
 pub mod sinks;
 pub mod sources;
 pub mod wasm_engine;
 
+/// `--config` value that reads the config from stdin instead of a file.
+pub const STDIN_CONFIG: &str = "-";
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
     pub runtime: runtime::RuntimeConfig,
@@ -33,25 +40,120 @@ pub struct Config {
     pub dag: Vec<Edge>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// `.json` files are JSON; everything else is treated as YAML.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Yaml,
+        }
+    }
+}
+
 impl Config {
     pub fn from_file(path: &PathBuf) -> Result<Self> {
+        Self::from_file_with_format(path, ConfigFormat::from_path(path))
+    }
+
+    pub fn from_file_with_format(path: &Path, format: ConfigFormat) -> Result<Self> {
         let contents =
             fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
-        let expanded = Self::expand_env(&contents);
-        let cfg = serde_yaml::from_str(&expanded)
-            .with_context(|| format!("parsing YAML {}", path.display()))?;
+        let cfg = match format {
+            ConfigFormat::Yaml => Self::from_yaml_str(&contents)
+                .with_context(|| format!("parsing YAML {}", path.display()))?,
+            ConfigFormat::Json => Self::from_json_str(&contents)
+                .with_context(|| format!("parsing JSON {}", path.display()))?,
+        };
 
         Ok(cfg)
     }
 
+    /// Read a config from `r`, e.g. stdin. It is parsed as YAML, which also
+    /// accepts JSON.
+    pub fn from_reader(mut r: impl Read) -> Result<Self> {
+        let mut contents = String::new();
+        r.read_to_string(&mut contents).context("reading config")?;
+        Self::from_yaml_str(&contents).context("parsing config")
+    }
+
+    /// Parse a YAML config. Failures are a `ConfigError` naming the path of
+    /// the offending field.
+    pub fn from_yaml_str(s: &str) -> Result<Self> {
+        let expanded = Self::expand_env(s);
+        serde_yaml::from_str(&expanded).map_err(|e| {
+            let doc = serde_yaml::from_str::<serde_yaml::Value>(&expanded).ok();
+            Self::parse_error(doc, e.to_string()).into()
+        })
+    }
+
+    /// Parse a JSON config. Failures are a `ConfigError` naming the path of
+    /// the offending field.
+    pub fn from_json_str(s: &str) -> Result<Self> {
+        let expanded = Self::expand_env(s);
+        serde_json::from_slice(expanded.as_bytes()).map_err(|e| {
+            let doc = serde_json::from_str::<serde_yaml::Value>(&expanded).ok();
+            Self::parse_error(doc, e.to_string()).into()
+        })
+    }
+
+    /// Only called once parsing failed: re-check `doc` piece by piece to find
+    /// where. `doc` is `None` when the text isn't YAML / JSON at all.
+    fn parse_error(doc: Option<serde_yaml::Value>, message: String) -> ConfigError {
+        match doc {
+            None => ConfigError::Syntax { message },
+            Some(doc) => error::locate(&doc).unwrap_or(ConfigError::InvalidValue {
+                path: String::new(),
+                message,
+            }),
+        }
+    }
+
     pub const fn batch_age_ms(&self) -> Duration {
         Duration::from_millis(self.runtime.batch_age)
     }
 
+    /// `None` when plugin config polling is turned off.
+    pub const fn config_reload_interval(&self) -> Option<Duration> {
+        match self.runtime.config_reload_interval_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
     pub const fn batch_size_kb(&self) -> usize {
         self.runtime.batch_size << 10
     }
 
+    /// The field workers are picked by, from whichever plugins set
+    /// `worker_affinity_key` (`validate` checks they agree).
+    pub fn worker_affinity_key(&self) -> Option<&str> {
+        self.plugins
+            .values()
+            .find_map(|p| p.worker_affinity_key.as_deref())
+    }
+
+    /// Number of WASM workers to run, in order of precedence:
+    /// 1. the `WASM_WORKERS` env var, kept for older deployments that set it;
+    /// 2. `runtime.workers` (which itself defaults to the CPU count);
+    /// 3. `num_cpus::get()`.
+    ///
+    /// Values that are zero or don't parse are skipped.
+    pub fn effective_workers(&self) -> usize {
+        std::env::var("WASM_WORKERS")
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .or(Some(self.runtime.workers).filter(|n| *n > 0))
+            .unwrap_or_else(num_cpus::get)
+    }
+
     fn expand_env(s: &str) -> String {
         let mut out = String::new();
         let mut rest = s;
@@ -73,36 +175,878 @@ impl Config {
         out
     }
 
+    /// Sources whose logs can reach `sink`, following DAG edges back
+    /// through any plugins. Sorted and deduplicated.
+    pub fn upstream_sources(&self, sink: &str) -> Vec<Arc<str>> {
+        let mut stack: Vec<&NodeRef> = self
+            .dag
+            .iter()
+            .flat_map(|e| &e.to)
+            .filter(|n| matches!(n, NodeRef::Sink { name, .. } if name.as_ref() == sink))
+            .collect();
+        let mut seen = std::collections::BTreeSet::new();
+        let mut out = std::collections::BTreeSet::new();
+
+        while let Some(node) = stack.pop() {
+            if !seen.insert(node) {
+                continue;
+            }
+            for e in self.dag.iter().filter(|e| e.to.contains(node)) {
+                match &e.from {
+                    NodeRef::Source { name } => {
+                        out.insert(Arc::clone(name));
+                    }
+                    from => stack.push(from),
+                }
+            }
+        }
+        out.into_iter().collect()
+    }
+
+    /// Check references between nodes and values serde can't. Every problem
+    /// is reported together as `ConfigErrors`.
     pub fn validate(&self) -> Result<()> {
-        let mut missing: Vec<String> = Vec::new();
-        let exists = |n: &NodeRef, this: &Config| -> bool {
-            match n {
-                NodeRef::Source { name } => this.sources.contains_key(name),
-                NodeRef::Plugin { name } => this.plugins.contains_key(name),
+        let mut errors: Vec<ConfigError> = Vec::new();
+        let mut check_ref = |n: &NodeRef, path: String, this: &Config| {
+            let (exists, kind, name) = match n {
+                NodeRef::Source { name } => (this.sources.contains_key(name), "source", name),
+                NodeRef::Plugin { name } => (this.plugins.contains_key(name), "plugin", name),
                 NodeRef::Sink {
                     name,
                     key_prefix: _,
-                } => this.sinks.contains_key(name),
+                } => (this.sinks.contains_key(name), "sink", name),
+            };
+            if !exists {
+                errors.push(ConfigError::MissingReference {
+                    path,
+                    target: format!("{kind} `{name}`"),
+                });
             }
         };
 
-        for e in &self.dag {
-            if !exists(&e.from, self) {
-                missing.push(format!("from {:?} does not exist", e.from));
+        for (i, e) in self.dag.iter().enumerate() {
+            check_ref(&e.from, format!("dag[{i}].from"), self);
+            for (j, t) in e.to.iter().enumerate() {
+                check_ref(t, format!("dag[{i}].to[{j}]"), self);
+            }
+        }
+        for (name, plugin) in &self.plugins {
+            if let Some(sink) = &plugin.dead_letter {
+                if !self.sinks.contains_key(sink) {
+                    errors.push(ConfigError::MissingReference {
+                        path: format!("plugins.{name}.dead_letter"),
+                        target: format!("sink `{sink}`"),
+                    });
+                }
+            }
+        }
+
+        for (name, sink) in &self.sinks {
+            let breaker_dead_letter = sink
+                .common
+                .circuit_breaker
+                .as_ref()
+                .and_then(|cb| cb.dead_letter.as_ref())
+                .map(|dl| (dl, "circuit_breaker.dead_letter"));
+            let rejects_dead_letter = match &sink.kind {
+                SinkKind::Elasticsearch(es) => es.dead_letter.as_ref(),
+                SinkKind::ClickHouse(ch) => ch.dead_letter.as_ref(),
+                _ => None,
+            }
+            .map(|dl| (dl, "dead_letter"));
+            if let SinkKind::Loki(l) = &sink.kind {
+                for (i, field) in l.labels.iter().enumerate() {
+                    if !loki::is_valid_label_name(&loki::label_name(field)) {
+                        errors.push(ConfigError::InvalidValue {
+                            path: format!("sinks.{name}.labels[{i}]"),
+                            message: format!(
+                                "`{field}` doesn't make a valid Loki label name \
+                                 ([a-zA-Z_][a-zA-Z0-9_]* once dots become `_`)"
+                            ),
+                        });
+                    }
+                }
+            }
+            for (dead_letter, field) in breaker_dead_letter.into_iter().chain(rejects_dead_letter) {
+                let path = format!("sinks.{name}.{field}");
+                if dead_letter == name {
+                    errors.push(ConfigError::InvalidValue {
+                        path,
+                        message: "a sink can't be its own dead_letter".into(),
+                    });
+                } else if !self.sinks.contains_key(dead_letter) {
+                    errors.push(ConfigError::MissingReference {
+                        path,
+                        target: format!("sink `{dead_letter}`"),
+                    });
+                }
+            }
+        }
+
+        for (i, e) in self.dag.iter().enumerate() {
+            if let Some(EdgeFilter::Eq { path, value }) = &e.filter {
+                if value.is_array() || value.is_object() || value.is_null() {
+                    errors.push(ConfigError::InvalidValue {
+                        path: format!("dag[{i}].filter.value"),
+                        message: format!("eq {path} needs a string, number or boolean"),
+                    });
+                }
+            }
+            if let Some(route) = &e.route_by {
+                for (value, target) in &route.map {
+                    if !e.to.iter().any(|t| t.name() == target) {
+                        errors.push(ConfigError::InvalidValue {
+                            path: format!("dag[{i}].route_by.map.{value}"),
+                            message: format!("`{target}` is not one of this edge's `to` nodes"),
+                        });
+                    }
+                }
+            }
+            if e.flush_interval_ms.is_some()
+                && !e.to.iter().any(|t| matches!(t, NodeRef::Sink { .. }))
+            {
+                errors.push(ConfigError::InvalidValue {
+                    path: format!("dag[{i}].flush_interval_ms"),
+                    message: "only applies to edges into sinks".into(),
+                });
+            }
+        }
+
+        for (name, source) in &self.sources {
+            if source.max_restart_delay().is_zero() {
+                errors.push(ConfigError::InvalidValue {
+                    path: format!("sources.{name}.max_restart_delay_secs"),
+                    message: "must be at least 1, or a failing source restarts in a hot loop"
+                        .into(),
+                });
             }
-            for t in &e.to {
-                if !exists(&t, self) {
-                    missing.push(format!("to {:?} does not exist", t));
+            match source {
+                SourceConfig::Http(h)
+                    if h.proto_schema.is_some() && h.proto_message_type.is_none() =>
+                {
+                    errors.push(ConfigError::InvalidValue {
+                        path: format!("sources.{name}.proto_message_type"),
+                        message: "required when proto_schema is set".into(),
+                    });
+                }
+                SourceConfig::File(f) if f.glob.is_some() != f.path.as_os_str().is_empty() => {
+                    errors.push(ConfigError::InvalidValue {
+                        path: format!("sources.{name}.path"),
+                        message: "set exactly one of path and glob".into(),
+                    });
+                }
+                SourceConfig::File(f)
+                    if (f.tail || f.glob.is_some())
+                        && matches!(f.decoding.format, DecodeFormat::Protobuf { .. }) =>
+                {
+                    errors.push(ConfigError::InvalidValue {
+                        path: format!("sources.{name}.decoding.format"),
+                        message: "tail and glob read line-delimited files, not protobuf".into(),
+                    });
+                }
+                SourceConfig::SQS(q) if q.visibility_timeout_secs == 0 => {
+                    errors.push(ConfigError::InvalidValue {
+                        path: format!("sources.{name}.visibility_timeout_secs"),
+                        message: "must be at least 1; 0 makes every message visible again \
+                                  straight away"
+                            .into(),
+                    });
                 }
+                SourceConfig::Kafka(k) => {
+                    let set = [
+                        k.sasl_mechanism.is_some(),
+                        k.sasl_username.is_some(),
+                        k.sasl_password.is_some(),
+                    ];
+                    if set.contains(&true) && set.contains(&false) {
+                        errors.push(ConfigError::InvalidValue {
+                            path: format!("sources.{name}.sasl_mechanism"),
+                            message: "sasl_mechanism, sasl_username and sasl_password go together"
+                                .into(),
+                        });
+                    }
+                }
+                _ => {}
             }
         }
-        if !missing.is_empty() {
-            anyhow::bail!(
-                "DAG references missing nodes:\n  - {}",
-                missing.join("\n  - ")
+
+        if let Some(key) = self.worker_affinity_key() {
+            for (name, plugin) in &self.plugins {
+                if plugin
+                    .worker_affinity_key
+                    .as_deref()
+                    .is_some_and(|k| k != key)
+                {
+                    errors.push(ConfigError::InvalidValue {
+                        path: format!("plugins.{name}.worker_affinity_key"),
+                        message: format!("workers are shared, so every plugin must use `{key}`"),
+                    });
+                }
+            }
+        }
+
+        for (name, plugin) in &self.plugins {
+            for (field, value) in [
+                ("timeout_ms", plugin.timeout_ms),
+                ("max_memory_mb", plugin.max_memory_mb.map(|mb| mb as u64)),
+            ] {
+                if value == Some(0) {
+                    errors.push(ConfigError::InvalidValue {
+                        path: format!("plugins.{name}.{field}"),
+                        message: "must be greater than 0".into(),
+                    });
+                }
+            }
+        }
+
+        for (name, sink) in &self.sinks {
+            let object_store = matches!(
+                sink.kind,
+                SinkKind::S3(_) | SinkKind::Gcs(_) | SinkKind::AzureBlob(_)
             );
+            if sink.common.key_prefix_field.is_some() && !object_store {
+                errors.push(ConfigError::invalid(
+                    format!("sinks.{name}.key_prefix_field"),
+                    "only s3, gcs and azure_blob sinks write under key prefixes",
+                ));
+            }
+            if sink.common.key_prefix_max_values == 0 {
+                errors.push(ConfigError::invalid(
+                    format!("sinks.{name}.key_prefix_max_values"),
+                    "must be greater than 0",
+                ));
+            }
+            if let Some(enc) = &sink.common.encryption {
+                if !object_store {
+                    errors.push(ConfigError::invalid(
+                        format!("sinks.{name}.encryption"),
+                        "only s3, gcs and azure_blob sinks encrypt their WAL files",
+                    ));
+                } else if !enc.key_is_valid() {
+                    errors.push(ConfigError::invalid(
+                        format!("sinks.{name}.encryption.key_hex"),
+                        "must be 64 hex characters (a 256-bit key)",
+                    ));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors(errors).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::PluginConfigOverrides;
+    use crate::sinks::common::{Encoding, SinkKind};
+    use crate::sources::pulsar::PulsarSubscriptionType;
+
+    const FULL_JSON: &str = r#"{
+      "runtime": { "batch_size": 128, "workers": 2 },
+      "sources": {
+        "kafka": {
+          "type": "msk",
+          "bootstrap_servers": "b-1:9096",
+          "topic": "logs",
+          "auth": { "mode": "scram", "username": "u", "password": "p" },
+          "decoding": { "format": { "type": "ndjson" } }
+        },
+        "files": {
+          "type": "file",
+          "path": "/var/log/app.log",
+          "decoding": { "format": { "type": "json" }, "compression": { "type": "gzip" } }
+        },
+        "sock": { "type": "socket", "socket_path": "/tmp/tangent.sock" },
+        "tcp": { "type": "tcp", "bind_address": "0.0.0.0:9000" },
+        "queue": {
+          "type": "sqs",
+          "queue_url": "https://sqs.us-east-1.amazonaws.com/1/q",
+          "decoding": { "format": { "type": "msgpack" } }
+        },
+        "gh": { "type": "github_webhook", "path": "/hook", "secret": null, "token": "t" },
+        "npm": { "type": "npm_registry", "packages": ["left-pad"], "orgs": null, "token": null },
+        "poll": {
+          "type": "http_polling",
+          "url": "https://example.com/events",
+          "auth": { "mode": "bearer", "token": "t" },
+          "decoding": { "format": { "type": "json-array" } }
+        },
+        "ingest": {
+          "type": "http",
+          "path": "/ingest",
+          "bearer_token": "t",
+          "max_body_bytes": 1048576,
+          "decoding": { "format": { "type": "ndjson" } },
+          "proto_schema": "schemas/events.proto",
+          "proto_message_type": "acme.logs.v1.Event"
+        },
+        "events": {
+          "type": "redis_streams",
+          "url": "redis://localhost:6379",
+          "stream": "logs",
+          "group": "tangent",
+          "decoding": { "format": { "type": "ndjson" } }
+        },
+        "rpc": { "type": "grpc", "bind_address": "127.0.0.1:50051", "reflection": true },
+        "bus": {
+          "type": "pulsar",
+          "service_url": "pulsar://localhost:6650",
+          "topic": "persistent://public/default/logs",
+          "subscription": "tangent",
+          "subscription_type": "shared",
+          "decoding": { "format": { "type": "ndjson" } }
+        },
+        "cw": {
+          "type": "cloudwatch_logs",
+          "log_group_name": "/aws/lambda/api",
+          "log_stream_prefix": "2024/"
+        },
+        "docker": {
+          "type": "docker_events",
+          "filters": { "type": ["container"], "event": ["die", "oom"] }
+        },
+        "clicks": {
+          "type": "kafka",
+          "brokers": "kafka-1:9093,kafka-2:9093",
+          "topic": "clicks",
+          "sasl_mechanism": "SCRAM-SHA-256",
+          "sasl_username": "u",
+          "sasl_password": "p",
+          "ssl_ca_pem_path": "/etc/ssl/kafka-ca.pem"
         }
+      },
+      "sinks": {
+        "lake": {
+          "type": "s3",
+          "bucket_name": "bucket",
+          "region": "us-east-1",
+          "encoding": { "type": "parquet", "schema": "s.json" },
+          "compression": { "type": "gzip", "level": 9 }
+        },
+        "local": { "type": "file", "path": "/tmp/out.ndjson", "default": true },
+        "devnull": { "type": "blackhole" },
+        "metrics": {
+          "type": "prometheus_remote_write",
+          "endpoint": "http://prometheus:9090/api/v1/write",
+          "bearer_token": "t0ken"
+        },
+        "logs": {
+          "type": "loki",
+          "endpoint": "http://loki:3100",
+          "tenant_id": "acme",
+          "basic_auth": { "username": "u", "password": "p" },
+          "labels": ["service", "env", "level"]
+        },
+        "archive": { "type": "gcs", "bucket_name": "archive", "key_prefix": "tangent" },
+        "blobs": {
+          "type": "azure_blob",
+          "account_name": "acct",
+          "container_name": "logs",
+          "credentials": { "mode": "managed_identity" }
+        },
+        "search": {
+          "type": "elasticsearch",
+          "endpoint": "https://es:9200",
+          "index_pattern": "logs-{YYYY-MM-DD}",
+          "api_key": "a2V5",
+          "dead_letter": "local"
+        },
+        "warehouse": {
+          "type": "clickhouse",
+          "endpoint": "http://clickhouse:8123",
+          "table": "logs",
+          "password": "p",
+          "auto_create_table": true
+        }
+      },
+      "plugins": {
+        "mapper": { "module_type": "rust", "path": "mapper.wasm", "config": { "k": 1 } }
+      },
+      "dag": [
+        {
+          "from": { "kind": "source", "name": "kafka" },
+          "to": [{ "kind": "plugin", "name": "mapper" }]
+        },
+        {
+          "from": { "kind": "plugin", "name": "mapper" },
+          "to": [
+            { "kind": "sink", "name": "lake", "key_prefix": "logs/" },
+            { "kind": "sink", "name": "local" },
+            { "kind": "sink", "name": "devnull" },
+            { "kind": "sink", "name": "archive" }
+          ]
+        }
+      ]
+    }"#;
+
+    #[test]
+    fn json_config_parses_all_node_types() {
+        let cfg = Config::from_json_str(FULL_JSON).unwrap();
+        cfg.validate().unwrap();
+
+        assert_eq!(cfg.runtime.batch_size, 128);
+        assert_eq!(cfg.sources.len(), 15);
+        assert!(matches!(cfg.sources["kafka"], SourceConfig::MSK(_)));
+        assert!(matches!(cfg.sources["files"], SourceConfig::File(_)));
+        assert!(matches!(cfg.sources["sock"], SourceConfig::Socket(_)));
+        assert!(matches!(cfg.sources["tcp"], SourceConfig::Tcp(_)));
+        assert!(matches!(cfg.sources["queue"], SourceConfig::SQS(_)));
+        assert!(matches!(cfg.sources["gh"], SourceConfig::GithubWebhook(_)));
+        assert!(matches!(cfg.sources["npm"], SourceConfig::NPMRegistry(_)));
+        assert!(matches!(cfg.sources["poll"], SourceConfig::HttpPolling(_)));
+        assert!(matches!(
+            &cfg.sources["ingest"],
+            SourceConfig::Http(h) if matches!(h.proto_format(), Some(DecodeFormat::Protobuf { .. }))
+        ));
+        assert!(matches!(
+            cfg.sources["events"],
+            SourceConfig::RedisStreams(_)
+        ));
+        assert!(matches!(cfg.sources["rpc"], SourceConfig::Grpc(_)));
+        assert!(matches!(
+            &cfg.sources["bus"],
+            SourceConfig::Pulsar(p) if p.subscription_type == PulsarSubscriptionType::Shared
+        ));
+        assert!(matches!(
+            &cfg.sources["cw"],
+            SourceConfig::CloudWatchLogs(c) if c.poll_interval_secs == 10 && c.filter_pattern.is_none()
+        ));
+        assert!(matches!(
+            &cfg.sources["docker"],
+            SourceConfig::DockerEvents(d)
+                if d.socket_path == Path::new("/var/run/docker.sock") && d.filters["event"] == ["die", "oom"]
+        ));
+        assert!(matches!(
+            &cfg.sources["clicks"],
+            SourceConfig::Kafka(k)
+                if k.security_protocol() == "SASL_SSL" && matches!(k.decoding.format, DecodeFormat::Auto)
+        ));
+
+        assert!(matches!(cfg.sinks["lake"].kind, SinkKind::S3(_)));
+        assert!(matches!(cfg.sinks["local"].kind, SinkKind::File(_)));
+        assert!(matches!(cfg.sinks["devnull"].kind, SinkKind::Blackhole(_)));
+        assert!(matches!(cfg.sinks["archive"].kind, SinkKind::Gcs(_)));
+        assert!(matches!(cfg.sinks["blobs"].kind, SinkKind::AzureBlob(_)));
+        assert!(matches!(
+            &cfg.sinks["search"].kind,
+            SinkKind::Elasticsearch(e) if e.batch_max_docs == 1000 && e.dead_letter.as_deref() == Some("local")
+        ));
+        assert!(matches!(
+            &cfg.sinks["warehouse"].kind,
+            SinkKind::ClickHouse(c) if c.database == "default" && c.batch_rows == 10_000 && c.auto_create_table
+        ));
+        assert!(matches!(
+            &cfg.sinks["metrics"].kind,
+            SinkKind::PrometheusRemoteWrite(p) if p.batch_max_samples == 2000 && p.bearer_token.is_some()
+        ));
+        assert!(matches!(
+            &cfg.sinks["logs"].kind,
+            SinkKind::Loki(l) if l.labels == ["service", "env", "level"] && l.basic_auth.is_some()
+        ));
+        assert!(cfg.sinks["local"].common.default);
+        assert!(matches!(
+            &cfg.sinks["lake"].common.encoding,
+            Encoding::Parquet { schema: Some(s) } if s == "s.json"
+        ));
+        assert_eq!(cfg.sinks["lake"].common.parquet_row_group_size, 65536);
+
+        assert_eq!(cfg.plugins["mapper"].module_type, "rust");
+        assert_eq!(cfg.dag.len(), 2);
+    }
+
+    #[test]
+    fn json_and_yaml_configs_are_equivalent() {
+        let yaml = r#"
+runtime:
+  batch_size: 128
+sources:
+  tcp:
+    type: tcp
+sinks:
+  devnull:
+    type: blackhole
+dag:
+  - from: { kind: source, name: tcp }
+    to: [{ kind: sink, name: devnull }]
+"#;
+        let json = r#"{
+          "runtime": { "batch_size": 128 },
+          "sources": { "tcp": { "type": "tcp" } },
+          "sinks": { "devnull": { "type": "blackhole" } },
+          "dag": [
+            { "from": { "kind": "source", "name": "tcp" }, "to": [{ "kind": "sink", "name": "devnull" }] }
+          ]
+        }"#;
+
+        let from_yaml = Config::from_yaml_str(yaml).unwrap();
+        let from_json = Config::from_json_str(json).unwrap();
+        assert_eq!(
+            serde_json::to_value(&from_yaml).unwrap(),
+            serde_json::to_value(&from_json).unwrap()
+        );
+    }
+
+    #[test]
+    fn config_reads_from_any_reader() {
+        let cfg = Config::from_reader(FULL_JSON.as_bytes()).unwrap();
+        assert_eq!(cfg.sources.len(), 15);
+
+        let cfg = Config::from_reader("runtime: { batch_size: 64 }\n".as_bytes()).unwrap();
+        assert_eq!(cfg.runtime.batch_size, 64);
+        assert!(Config::from_reader("runtime: [".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn format_detected_from_extension() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("tangent.json")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("tangent.yaml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("tangent.yml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("tangent")),
+            ConfigFormat::Yaml
+        );
+    }
+
+    #[test]
+    fn edge_filters_parse_from_yaml() {
+        let yaml = r#"
+runtime: {}
+dag:
+  - from: { kind: source, name: kafka }
+    to: [{ kind: sink, name: payments }]
+    filter: { op: eq, path: source.name, value: payments }
+  - from: { kind: source, name: kafka }
+    to: [{ kind: sink, name: errors }]
+    filter: { op: contains, path: msg, value: error }
+  - from: { kind: source, name: kafka }
+    to: [{ kind: sink, name: all }]
+"#;
+        let cfg = Config::from_yaml_str(yaml).unwrap();
+        assert_eq!(
+            cfg.dag[0].filter,
+            Some(EdgeFilter::Eq {
+                path: "source.name".into(),
+                value: serde_json::json!("payments"),
+            })
+        );
+        assert!(matches!(
+            cfg.dag[1].filter,
+            Some(EdgeFilter::Contains { .. })
+        ));
+        assert!(cfg.dag[2].filter.is_none());
+    }
+
+    #[test]
+    fn route_by_targets_must_be_edge_outputs() {
+        let yaml = r#"
+runtime: {}
+dag:
+  - from: { kind: source, name: kafka }
+    to: [{ kind: sink, name: errors }, { kind: sink, name: metrics }]
+    route_by:
+      field: output_type
+      map: { errors: errors, metrics: metrics, default: logs }
+"#;
+        let cfg = Config::from_yaml_str(yaml).unwrap();
+        let route = cfg.dag[0].route_by.as_ref().unwrap();
+        assert_eq!(route.target(Some("metrics")).map(|t| &**t), Some("metrics"));
+        assert_eq!(route.target(Some("traces")).map(|t| &**t), Some("logs"));
+        assert_eq!(route.target(None).map(|t| &**t), Some("logs"));
+
+        let errs = cfg
+            .validate()
+            .unwrap_err()
+            .downcast::<ConfigErrors>()
+            .unwrap();
+        let routes: Vec<&str> = errs
+            .0
+            .iter()
+            .map(ConfigError::path)
+            .filter(|p| p.contains("route_by"))
+            .collect();
+        assert_eq!(routes, vec!["dag[0].route_by.map.default"]);
+    }
+
+    #[test]
+    fn flush_interval_only_applies_to_sink_edges() {
+        let yaml = r#"
+runtime: {}
+dag:
+  - from: { kind: source, name: kafka }
+    to: [{ kind: plugin, name: mapper }]
+    flush_interval_ms: 100
+  - from: { kind: plugin, name: mapper }
+    to: [{ kind: sink, name: errors }]
+    flush_interval_ms: 0
+"#;
+        let cfg = Config::from_yaml_str(yaml).unwrap();
+        assert_eq!(cfg.dag[1].flush_interval_ms, Some(0));
+
+        let errs = cfg
+            .validate()
+            .unwrap_err()
+            .downcast::<ConfigErrors>()
+            .unwrap();
+        let flush: Vec<&str> = errs
+            .0
+            .iter()
+            .map(ConfigError::path)
+            .filter(|p| p.contains("flush_interval_ms"))
+            .collect();
+        assert_eq!(flush, vec!["dag[0].flush_interval_ms"]);
+    }
+
+    #[test]
+    fn upstream_sources_follow_plugins() {
+        let yaml = r#"
+runtime: {}
+dag:
+  - from: { kind: source, name: kafka }
+    to: [{ kind: plugin, name: mapper }]
+  - from: { kind: source, name: files }
+    to: [{ kind: plugin, name: mapper }, { kind: sink, name: raw }]
+  - from: { kind: plugin, name: mapper }
+    to: [{ kind: sink, name: lake, key_prefix: mapped }]
+"#;
+        let cfg = Config::from_yaml_str(yaml).unwrap();
+        let names = |sink| {
+            cfg.upstream_sources(sink)
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("lake"), vec!["files", "kafka"]);
+        assert_eq!(names("raw"), vec!["files"]);
+        assert!(names("missing").is_empty());
+    }
+
+    fn parse_error(yaml: &str) -> ConfigError {
+        Config::from_yaml_str(yaml)
+            .unwrap_err()
+            .downcast::<ConfigError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_errors_name_the_offending_field() {
+        let err = parse_error(
+            "runtime: {}\nsinks:\n  lake:\n    type: s3\n    bucket_name: b\n    compression: { type: zstdx }\n",
+        );
+        assert_eq!(err.path(), "sinks.lake.compression.type");
+        assert!(err.detail().contains("unknown variant `zstdx`"));
+
+        let err = parse_error("runtime: {}\nsources:\n  kafka:\n    type: msk\n    topic: t\n");
+        assert_eq!(
+            err,
+            ConfigError::MissingField {
+                path: "sources.kafka".into(),
+                field: "bootstrap_servers".into(),
+            }
+        );
+
+        let err = Config::from_json_str(r#"{ "runtime": { "batch_size": "big" } }"#)
+            .unwrap_err()
+            .downcast::<ConfigError>()
+            .unwrap();
+        assert_eq!(err.path(), "runtime.batch_size");
+
+        assert!(matches!(
+            parse_error("runtime: [unclosed"),
+            ConfigError::Syntax { .. }
+        ));
+    }
+
+    #[test]
+    fn validate_reports_every_missing_reference() {
+        let cfg = Config::from_yaml_str(
+            r#"
+runtime: {}
+plugins:
+  mapper: { module_type: rust, path: m.wasm, dead_letter: nowhere }
+dag:
+  - from: { kind: source, name: kafka }
+    to: [{ kind: plugin, name: mapper }, { kind: sink, name: lake }]
+"#,
+        )
+        .unwrap();
+        let errs = cfg
+            .validate()
+            .unwrap_err()
+            .downcast::<ConfigErrors>()
+            .unwrap();
+        let paths: Vec<&str> = errs.0.iter().map(ConfigError::path).collect();
+        assert_eq!(
+            paths,
+            vec!["dag[0].from", "dag[0].to[1]", "plugins.mapper.dead_letter"]
+        );
+        assert_eq!(
+            errs.0[0].to_string(),
+            "dag[0].from: source `kafka` does not exist"
+        );
+    }
+
+    #[test]
+    fn loki_labels_must_make_valid_label_names() {
+        let cfg = Config::from_yaml_str(
+            r#"
+runtime: {}
+sinks:
+  logs:
+    type: loki
+    endpoint: http://loki:3100
+    labels: [service, k8s.namespace, http-status, 2xx]
+"#,
+        )
+        .unwrap();
+        let errs = cfg
+            .validate()
+            .unwrap_err()
+            .downcast::<ConfigErrors>()
+            .unwrap();
+        let paths: Vec<&str> = errs.0.iter().map(ConfigError::path).collect();
+        assert_eq!(paths, vec!["sinks.logs.labels[2]", "sinks.logs.labels[3]"]);
+    }
+
+    #[test]
+    fn followed_files_reject_protobuf() {
+        let cfg = Config::from_yaml_str(
+            r#"
+runtime: {}
+sources:
+  whole:
+    type: file
+    path: /data/events.pb
+    decoding: { format: { type: protobuf, descriptor_path: e.desc, message_type: acme.Event } }
+  tailed:
+    type: file
+    path: /data/events.pb
+    tail: true
+    decoding: { format: { type: protobuf, descriptor_path: e.desc, message_type: acme.Event } }
+"#,
+        )
+        .unwrap();
+        let errs = cfg
+            .validate()
+            .unwrap_err()
+            .downcast::<ConfigErrors>()
+            .unwrap();
+        let paths: Vec<&str> = errs.0.iter().map(ConfigError::path).collect();
+        assert_eq!(paths, vec!["sources.tailed.decoding.format"]);
+    }
+
+    #[test]
+    fn max_restart_delay_must_be_positive() {
+        let cfg = Config::from_yaml_str(
+            "runtime: {}\nsources:\n  events:\n    type: file\n    path: /data/events.ndjson\n    decoding: { format: { type: ndjson } }\n    max_restart_delay_secs: 0",
+        )
+        .unwrap();
+        let errs = cfg
+            .validate()
+            .unwrap_err()
+            .downcast::<ConfigErrors>()
+            .unwrap();
+        let paths: Vec<&str> = errs.0.iter().map(ConfigError::path).collect();
+        assert_eq!(paths, vec!["sources.events.max_restart_delay_secs"]);
+    }
+
+    #[test]
+    fn sqs_visibility_timeout_must_be_positive() {
+        let cfg = Config::from_yaml_str(
+            "runtime: {}\nsources:\n  queue:\n    type: sqs\n    queue_url: https://sqs/q\n    visibility_timeout: 0\n    decoding: { format: { type: ndjson } }",
+        )
+        .unwrap();
+        let errs = cfg
+            .validate()
+            .unwrap_err()
+            .downcast::<ConfigErrors>()
+            .unwrap();
+        let paths: Vec<&str> = errs.0.iter().map(ConfigError::path).collect();
+        assert_eq!(paths, vec!["sources.queue.visibility_timeout_secs"]);
+    }
+
+    #[test]
+    fn effective_workers_prefers_env_then_config() {
+        let mut cfg = Config::from_yaml_str("runtime: { workers: 3 }").unwrap();
+
+        std::env::remove_var("WASM_WORKERS");
+        assert_eq!(cfg.effective_workers(), 3);
+
+        std::env::set_var("WASM_WORKERS", "7");
+        assert_eq!(cfg.effective_workers(), 7);
+
+        std::env::set_var("WASM_WORKERS", "zero");
+        assert_eq!(cfg.effective_workers(), 3);
+
+        std::env::remove_var("WASM_WORKERS");
+        cfg.runtime.workers = 0;
+        assert_eq!(cfg.effective_workers(), num_cpus::get());
+    }
+
+    #[test]
+    fn plugin_config_overrides_layer_over_yaml() {
+        let cfg = Config::from_yaml_str(
+            "runtime: {}\nplugins:\n  mapper:\n    module_type: go\n    path: m\n    config: { threshold: 5, channel: ops }",
+        )
+        .unwrap();
+        let mut overrides = PluginConfigOverrides::from_slice(br#"{"other": {"x": 1}}"#).unwrap();
+        overrides.set("mapper", "threshold", serde_json::json!(10));
+
+        let merged = overrides.apply("mapper", &cfg.plugins["mapper"].config);
+        assert_eq!(merged["threshold"], serde_json::json!(10));
+        assert_eq!(merged["channel"], serde_json::json!("ops"));
+        assert_eq!(
+            PluginConfigOverrides::path_for(Path::new("/etc/tangent/tangent.yaml")),
+            Path::new("/etc/tangent/tangent.plugin-config.json")
+        );
+        assert_eq!(cfg.config_reload_interval(), None);
+    }
+
+    #[test]
+    fn plugin_config_sidecar_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!(
+            "tangent-sidecar-{}.plugin-config.json",
+            std::process::id()
+        ));
+        let mut overrides = PluginConfigOverrides::default();
+        overrides.set("mapper", "token", serde_json::json!("secret"));
+        overrides.save(&path).unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    #[test]
+    fn plugin_capabilities_default_to_everything() {
+        use crate::plugins::PluginCapability;
+
+        let cfg = Config::from_yaml_str(
+            "runtime: {}\nplugins:\n  open:\n    module_type: go\n    path: m\n  sandboxed:\n    module_type: go\n    path: m\n    capabilities: [cache]",
+        )
+        .unwrap();
+        assert!(cfg.plugins["open"].allows(PluginCapability::RemoteCalls));
+        assert!(cfg.plugins["sandboxed"].allows(PluginCapability::Cache));
+        assert!(!cfg.plugins["sandboxed"].allows(PluginCapability::RemoteCalls));
+        assert!(!cfg.plugins["sandboxed"].allows(PluginCapability::EnvVars));
 
-        Ok(())
+        assert!(Config::from_yaml_str(
+            "runtime: {}\nplugins:\n  p:\n    module_type: go\n    path: m\n    capabilities: [filesystem]",
+        )
+        .is_err());
     }
 }
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
@@ -18,8 +19,70 @@ pub enum NodeRef {
     },
 }
 
+impl NodeRef {
+    pub fn name(&self) -> &Arc<str> {
+        match self {
+            Self::Source { name } | Self::Plugin { name } | Self::Sink { name, .. } => name,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Edge {
     pub from: NodeRef,
     pub to: Vec<NodeRef>,
+    /// Only events matching this take the edge; the rest are dropped from it
+    /// but still follow other edges out of `from`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<EdgeFilter>,
+    /// Send each event to only the `to` node its field value maps to,
+    /// instead of to all of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_by: Option<RouteBy>,
+    /// Seal WAL files holding events from this edge at most this long after
+    /// they were opened, when it is shorter than the sink's
+    /// `max_file_age_seconds`. `0` seals them as soon as they are written.
+    /// Only applies to `to` nodes that are sinks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flush_interval_ms: Option<u64>,
+}
+
+/// Content-based routing for an edge. Events whose `field` is missing or
+/// not listed go to the `default` entry, or are dropped from the edge if
+/// there is none.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RouteBy {
+    /// Dotted lookup, as in `EdgeFilter`.
+    pub field: String,
+    /// Field value (numbers and booleans as written in JSON) -> name of a
+    /// node in the edge's `to`.
+    pub map: BTreeMap<String, Arc<str>>,
+}
+
+impl RouteBy {
+    /// Catch-all key in `map`.
+    pub const DEFAULT: &'static str = "default";
+
+    /// The target for an event whose field is `value`.
+    pub fn target(&self, value: Option<&str>) -> Option<&Arc<str>> {
+        value
+            .and_then(|v| self.map.get(v))
+            .or_else(|| self.map.get(Self::DEFAULT))
+    }
+}
+
+/// Predicate on a single event. `path` is a dotted lookup such as
+/// `source.name` or `tags[0]`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum EdgeFilter {
+    /// Field equals a string, number or boolean.
+    Eq {
+        path: String,
+        value: serde_json::Value,
+    },
+    /// String field contains `value`.
+    Contains { path: String, value: String },
+    /// Field is present.
+    Exists { path: String },
 }
@@ -0,0 +1,202 @@
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+use std::fmt;
+
+use crate::dag::Edge;
+use crate::plugins::PluginConfig;
+use crate::runtime::RuntimeConfig;
+use crate::sinks::common::SinkConfig;
+use crate::sources::common::SourceConfig;
+
+/// A problem with a config, located by its path in the document, e.g.
+/// `sinks.lake.compression.type` or `dag[0].to[1]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The document isn't valid YAML / JSON at all.
+    Syntax { message: String },
+    /// A required field is absent from the mapping at `path`.
+    MissingField { path: String, field: String },
+    /// The value at `path` has the wrong type or is out of range.
+    InvalidValue { path: String, message: String },
+    /// The node or sink named at `path` isn't defined.
+    MissingReference { path: String, target: String },
+}
+
+impl ConfigError {
+    /// Location of the problem; empty for syntax errors and the document root.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Syntax { .. } => "",
+            Self::MissingField { path, .. }
+            | Self::InvalidValue { path, .. }
+            | Self::MissingReference { path, .. } => path,
+        }
+    }
+
+    /// The problem without its location.
+    pub fn detail(&self) -> String {
+        match self {
+            Self::Syntax { message } | Self::InvalidValue { message, .. } => message.clone(),
+            Self::MissingField { field, .. } => format!("missing field `{field}`"),
+            Self::MissingReference { target, .. } => format!("{target} does not exist"),
+        }
+    }
+
+    pub(crate) fn invalid(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::InvalidValue {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path() {
+            "" => write!(f, "{}", self.detail()),
+            path => write!(f, "{path}: {}", self.detail()),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Every problem `Config::validate` found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [one] => write!(f, "{one}"),
+            all => {
+                write!(f, "{} config errors:", all.len())?;
+                for e in all {
+                    write!(f, "\n  - {e}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Find why `doc` doesn't deserialize into a `Config` by deserializing each
+/// section and named entry on its own, then pointing at the offending value
+/// inside the first one that fails.
+///
+/// serde loses the path inside internally tagged and flattened types (every
+/// source and sink), so this recovers it from the document instead.
+pub(crate) fn locate(doc: &Value) -> Option<ConfigError> {
+    let Value::Mapping(root) = doc else {
+        return Some(ConfigError::invalid("", "config must be a mapping"));
+    };
+
+    match root.get("runtime") {
+        Some(v) => {
+            if let Some(e) = check::<RuntimeConfig>(v, "runtime") {
+                return Some(e);
+            }
+        }
+        None => {
+            return Some(ConfigError::MissingField {
+                path: String::new(),
+                field: "runtime".into(),
+            })
+        }
+    }
+    if let Some(e) = check_entries::<SourceConfig>(root.get("sources"), "sources") {
+        return Some(e);
+    }
+    if let Some(e) = check_entries::<SinkConfig>(root.get("sinks"), "sinks") {
+        return Some(e);
+    }
+    if let Some(e) = check_entries::<PluginConfig>(root.get("plugins"), "plugins") {
+        return Some(e);
+    }
+    if let Some(Value::Sequence(edges)) = root.get("dag") {
+        for (i, edge) in edges.iter().enumerate() {
+            if let Some(e) = check::<Edge>(edge, &format!("dag[{i}]")) {
+                return Some(e);
+            }
+        }
+    }
+    None
+}
+
+fn check_entries<T: DeserializeOwned>(section: Option<&Value>, path: &str) -> Option<ConfigError> {
+    match section {
+        None | Some(Value::Null) => None,
+        Some(Value::Mapping(entries)) => entries.iter().find_map(|(name, v)| {
+            let name = scalar_text(name).unwrap_or_default();
+            check::<T>(v, &format!("{path}.{name}"))
+        }),
+        Some(_) => Some(ConfigError::invalid(path, "expected a mapping of names")),
+    }
+}
+
+fn check<T: DeserializeOwned>(v: &Value, path: &str) -> Option<ConfigError> {
+    let err = serde_yaml::from_value::<T>(v.clone()).err()?;
+    Some(from_serde(path, v, &err.to_string()))
+}
+
+/// Turn a serde message about `v` into a located error. Where the message
+/// quotes the offending value or field, the path is narrowed to it.
+fn from_serde(path: &str, v: &Value, message: &str) -> ConfigError {
+    if let Some(field) = quoted(message, "missing field `", '`') {
+        return ConfigError::MissingField {
+            path: path.to_string(),
+            field: field.to_string(),
+        };
+    }
+    if let Some(field) = quoted(message, "unknown field `", '`') {
+        return ConfigError::invalid(join(path, field), "unknown field");
+    }
+
+    let offending = quoted(message, "`", '`').or_else(|| quoted(message, "\"", '"'));
+    let located = offending.and_then(|needle| find(v, needle, path.to_string()));
+    ConfigError::invalid(located.unwrap_or_else(|| path.to_string()), message)
+}
+
+/// The text between the first `open` and the following `close`.
+fn quoted<'a>(message: &'a str, open: &str, close: char) -> Option<&'a str> {
+    let start = message.find(open)? + open.len();
+    let len = message[start..].find(close)?;
+    Some(&message[start..start + len])
+}
+
+/// Path of the first scalar (or mapping key) under `v` whose text is `needle`.
+fn find(v: &Value, needle: &str, path: String) -> Option<String> {
+    match v {
+        Value::Mapping(m) => m.iter().find_map(|(k, child)| {
+            let key = scalar_text(k)?;
+            let child_path = join(&path, &key);
+            find(child, needle, child_path.clone())
+                .or_else(|| (key == needle).then_some(child_path))
+        }),
+        Value::Sequence(items) => items
+            .iter()
+            .enumerate()
+            .find_map(|(i, child)| find(child, needle, format!("{path}[{i}]"))),
+        Value::Tagged(t) => find(&t.value, needle, path),
+        scalar => (scalar_text(scalar)? == needle).then_some(path),
+    }
+}
+
+fn scalar_text(v: &Value) -> Option<String> {
+    match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
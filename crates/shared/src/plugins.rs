@@ -1,7 +1,12 @@
 use ahash::HashMap;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PluginConfig {
@@ -13,6 +18,167 @@ pub struct PluginConfig {
 
     #[serde(default)]
     pub config: HashMap<String, Value>,
+
+    /// Max concurrent HTTP requests per worker from `remote::call-batch`.
+    #[serde(default = "default_remote_call_concurrency")]
+    pub remote_call_concurrency: usize,
+
+    /// Sink that receives events the plugin returned an error for, tagged
+    /// with `__tangent_error`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter: Option<Arc<str>>,
+
+    /// Wall-clock limit for one guest call. A batch that runs over goes to
+    /// `dead_letter` and the plugin instance is restarted. Setting it on any
+    /// plugin makes `tangent run` compile plugins from source with epoch
+    /// interruption, so busy guests yield to the deadline every 100ms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+
+    /// Cap on the plugin's linear memory. Growing past it traps the guest;
+    /// the batch is handled like a timeout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_mb: Option<usize>,
+
+    /// Format of the plugin's output. `msgpack` output is a sequence of
+    /// MessagePack records and `arrow_ipc` an Arrow IPC stream; the host
+    /// re-encodes both as NDJSON.
+    #[serde(default)]
+    pub encoding: PluginEncoding,
+
+    /// Dotted JSON field whose value picks the worker an event goes to, so
+    /// events sharing a value are always processed by the same worker (e.g.
+    /// for per-key state in the cache). Events without the field are spread
+    /// round-robin. Workers are shared by every plugin, so all plugins that
+    /// set this must name the same field. A hot key can overload its worker
+    /// while the others sit idle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_affinity_key: Option<String>,
+
+    /// Dispatch priority for the plugin's selectors that don't report one
+    /// through the `ranked-mapper` export. When an event matches several
+    /// plugins, only those whose matching selector has the lowest priority
+    /// get it; ties all do, and unset ranks after every set priority.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selector_priority: Option<u8>,
+
+    /// Host access the plugin is granted. Left unset, it gets everything;
+    /// set, anything not listed is denied: remote and cache calls return
+    /// an error, and the guest sees no environment variables.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<PluginCapability>>,
+}
+
+impl PluginConfig {
+    /// Whether the plugin's `capabilities` allow `cap`.
+    pub fn allows(&self, cap: PluginCapability) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_none_or(|caps| caps.contains(&cap))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginCapability {
+    /// `remote::call-batch`.
+    RemoteCalls,
+    /// The `cache` interface, including transactions.
+    Cache,
+    /// The host's environment variables, through WASI.
+    EnvVars,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginEncoding {
+    #[default]
+    Ndjson,
+    Msgpack,
+    /// Needs the runtime's `arrow-ipc` feature.
+    #[serde(rename = "arrow_ipc")]
+    ArrowIpc,
+}
+
+const fn default_remote_call_concurrency() -> usize {
+    16
+}
+
+/// Sidecar that `tangent plugin compile` writes next to each `.cwasm`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompiledPluginMeta {
+    /// The `module_type` the component was built from.
+    pub language: String,
+    /// Version of tangent that compiled it.
+    pub tangent_version: String,
+}
+
+impl CompiledPluginMeta {
+    /// `<plugins_path>/<name>.meta.json` for `<plugins_path>/<name>.cwasm`.
+    pub fn path_for(cwasm: &Path) -> PathBuf {
+        cwasm.with_extension("meta.json")
+    }
+}
+
+/// Plugin `config` values set with `tangent plugin set-config`, keyed by
+/// plugin name. They are layered over `plugins.<name>.config` and re-read
+/// by running workers, so changing them needs no restart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PluginConfigOverrides(pub BTreeMap<String, BTreeMap<String, Value>>);
+
+impl PluginConfigOverrides {
+    /// `<dir>/<stem>.plugin-config.json` for the config at `<dir>/<stem>.yaml`.
+    pub fn path_for(config_path: &Path) -> PathBuf {
+        config_path.with_extension("plugin-config.json")
+    }
+
+    /// Parse the sidecar at `path`; a missing file has no overrides.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => Self::from_slice(&bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).context("parsing plugin config overrides")
+    }
+
+    /// Write to `path` through a temp file, so a worker polling it never
+    /// sees a partial write. Plugin config can hold secrets, so the file is
+    /// only readable by its owner.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        // `mode` only applies on create, so a stale temp file must go first.
+        let _ = std::fs::remove_file(&tmp);
+        let body = serde_json::to_vec_pretty(self)?;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp)
+            .and_then(|mut f| f.write_all(&body))
+            .with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("replacing {}", path.display()))
+    }
+
+    pub fn set(&mut self, plugin: &str, key: &str, value: Value) {
+        self.0
+            .entry(plugin.to_string())
+            .or_default()
+            .insert(key.to_string(), value);
+    }
+
+    /// `base` with this file's values for `plugin` on top.
+    pub fn apply(&self, plugin: &str, base: &HashMap<String, Value>) -> HashMap<String, Value> {
+        let mut merged = base.clone();
+        if let Some(values) = self.0.get(plugin) {
+            merged.extend(values.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        merged
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +23,34 @@ pub struct RuntimeConfig {
     /// Useful for `tangent plugin test` or benchmarking to avoid external calls.
     #[serde(default)]
     pub disable_remote_calls: bool,
+
+    /// How sink writes are spread across the sink manager's shards.
+    #[serde(default)]
+    pub shard_strategy: ShardStrategy,
+
+    /// How often workers re-read the `tangent plugin set-config` sidecar and
+    /// apply changed plugin config between batches. `0`, the default, turns
+    /// polling off.
+    #[serde(default)]
+    pub config_reload_interval_ms: u64,
+
+    /// How long a source waits for room in a worker's queue once every
+    /// worker is full. Past it the batch is dropped without being acked, so
+    /// sources that redeliver will retry it. Unset waits indefinitely.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backpressure_timeout_ms: Option<u64>,
+}
+
+/// `hash_by_key_prefix` pins each `(sink, key_prefix)` to one shard, so writes
+/// for a prefix land in the order they were enqueued, at the cost of hot
+/// shards when busy prefixes collide. `round_robin` spreads writes evenly but
+/// gives no ordering guarantee across writes to the same prefix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShardStrategy {
+    #[default]
+    HashByKeyPrefix,
+    RoundRobin,
 }
 
 #[must_use]
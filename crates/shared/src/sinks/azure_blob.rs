@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+
+use crate::sinks::s3::{max_file_age_seconds, wal_path};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AzureBlobConfig {
+    pub account_name: String,
+    pub container_name: String,
+
+    /// Prepended to every blob name, ahead of any per-route `key_prefix`.
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+
+    pub credentials: AzureCredentials,
+
+    #[serde(default = "wal_path")]
+    pub wal_path: PathBuf,
+
+    #[serde(default = "max_file_age_seconds")]
+    pub max_file_age_seconds: u64,
+
+    /// Log an error once the oldest sealed WAL file is older than this many
+    /// seconds. Pair with `tangent_wal_oldest_sealed_file_age_seconds`.
+    #[serde(default)]
+    pub wal_alert_age_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum AzureCredentials {
+    /// Shared key for the storage account.
+    AccountKey {
+        #[serde(skip_serializing)]
+        account_key: SecretString,
+    },
+    /// Token from the instance metadata service of the Azure host.
+    ManagedIdentity,
+}
@@ -0,0 +1,49 @@
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Inserts events into a ClickHouse table over the HTTP interface, one row
+/// per NDJSON line, with `INSERT ... FORMAT JSONEachRow`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClickHouseConfig {
+    /// HTTP interface URL, e.g. `http://clickhouse:8123`.
+    pub endpoint: String,
+
+    #[serde(default = "default_database")]
+    pub database: String,
+
+    pub table: String,
+
+    /// Sent as `X-ClickHouse-User`; ClickHouse uses `default` without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    /// Sent as `X-ClickHouse-Key`.
+    #[serde(default, skip_serializing)]
+    pub password: Option<SecretString>,
+
+    /// Most rows sent in one insert.
+    #[serde(default = "default_batch_rows")]
+    pub batch_rows: usize,
+
+    /// Create the table if it doesn't exist, with a nullable column for each
+    /// top-level field of the first batch written. Types are inferred from
+    /// the values: `Bool`, `Int64`, `UInt64`, `Float64`, or `String` for
+    /// anything else, including fields whose type varies.
+    #[serde(default)]
+    pub auto_create_table: bool,
+
+    /// Sink that receives rows ClickHouse can't insert, such as ones that
+    /// don't fit the table's schema, tagged with `__tangent_error`. Without
+    /// one they are logged and dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter: Option<Arc<str>>,
+}
+
+fn default_database() -> String {
+    "default".to_string()
+}
+
+const fn default_batch_rows() -> usize {
+    10_000
+}
@@ -1,6 +1,11 @@
+use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use crate::sinks::{blackhole, file, s3};
+use crate::sinks::{
+    azure_blob, blackhole, clickhouse, elasticsearch, file, gcs, loki, prometheus_remote_write, s3,
+};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SinkConfig {
@@ -16,10 +21,22 @@ pub struct SinkConfig {
 pub enum SinkKind {
     #[serde(rename = "s3")]
     S3(s3::S3Config),
+    #[serde(rename = "gcs")]
+    Gcs(gcs::GcsConfig),
+    #[serde(rename = "azure_blob")]
+    AzureBlob(azure_blob::AzureBlobConfig),
     #[serde(rename = "file")]
     File(file::FileConfig),
     #[serde(rename = "blackhole")]
     Blackhole(blackhole::BlackholeConfig),
+    #[serde(rename = "prometheus_remote_write")]
+    PrometheusRemoteWrite(prometheus_remote_write::PrometheusRemoteWriteConfig),
+    #[serde(rename = "loki")]
+    Loki(loki::LokiConfig),
+    #[serde(rename = "elasticsearch")]
+    Elasticsearch(elasticsearch::ElasticsearchConfig),
+    #[serde(rename = "clickhouse")]
+    ClickHouse(clickhouse::ClickHouseConfig),
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -36,8 +53,88 @@ pub struct CommonSinkOptions {
     #[serde(default = "in_flight_limit")]
     pub in_flight_limit: usize,
 
+    /// Rows per Parquet row group when `encoding` is `parquet`.
+    #[serde(default = "parquet_row_group_size")]
+    pub parquet_row_group_size: usize,
+
     #[serde(default = "default_sink")]
     pub default: bool,
+
+    /// Pre-trained dictionary (see `tangent plugin train-dict`) used by
+    /// WAL-backed sinks when `compression` is `zstd`. Uploaded objects can
+    /// only be decompressed with the same dictionary.
+    #[serde(default)]
+    pub zstd_dict: Option<PathBuf>,
+
+    /// JSON field (dotted path) whose value is appended to each event's key
+    /// prefix, so a batch is split into one object per value, e.g. per-tenant
+    /// prefixes with `tenant_id`. Object-store sinks only.
+    #[serde(default)]
+    pub key_prefix_field: Option<String>,
+
+    /// Used in place of the field's value for events that don't have
+    /// `key_prefix_field`. Without it those events keep the edge's prefix.
+    #[serde(default)]
+    pub key_prefix_fallback: Option<String>,
+
+    /// Distinct `key_prefix_field` values a sink writes under. Each one keeps
+    /// a WAL file open, so once this many have been seen, events with new
+    /// values are written as if they lacked the field.
+    #[serde(default = "key_prefix_max_values")]
+    pub key_prefix_max_values: usize,
+
+    /// Encrypt sealed WAL files before upload. WAL-backed sinks only.
+    #[serde(default)]
+    pub encryption: Option<EncryptionConfig>,
+
+    /// Stop calling the sink after repeated write failures.
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+
+    /// Cap on the bytes of open and sealed files in the WAL directory. Once
+    /// reached, writes wait until uploads bring it back under 80% of the cap.
+    /// WAL-backed sinks only.
+    #[serde(default)]
+    pub max_wal_bytes: Option<u64>,
+}
+
+/// A sink's circuit opens after `failure_threshold` consecutive failed
+/// writes. While open, writes wait and new batches go to `dead_letter`.
+/// Every `probe_interval_secs` the circuit half-opens and lets up to
+/// `half_open_max_calls` writes through; a success closes it again.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+
+    #[serde(default = "default_probe_interval_secs")]
+    pub probe_interval_secs: u64,
+
+    #[serde(default = "default_half_open_max_calls")]
+    pub half_open_max_calls: u32,
+
+    /// Sink that receives new batches while the circuit is open. Without
+    /// one they queue until the sink recovers.
+    #[serde(default)]
+    pub dead_letter: Option<Arc<str>>,
+}
+
+/// AES-256-GCM encryption of uploaded objects, in 64 KiB chunks so files are
+/// never held in memory whole. Encrypted keys end in `.enc`; `tangent
+/// decrypt` reverses it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EncryptionConfig {
+    /// 32-byte key as 64 hex characters.
+    #[serde(skip_serializing)]
+    pub key_hex: SecretString,
+}
+
+impl EncryptionConfig {
+    /// Whether `key_hex` is a well-formed 256-bit key.
+    pub fn key_is_valid(&self) -> bool {
+        let key = self.key_hex.expose_secret();
+        key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
@@ -49,8 +146,18 @@ pub enum Encoding {
     Avro {
         schema: String,
     },
+    /// Arrow schema as JSON. Inferred from the data when omitted.
     Parquet {
-        schema: String,
+        #[serde(default)]
+        schema: Option<String>,
+    },
+    /// Arrow IPC stream, for DuckDB and Polars. Arrow schema as JSON,
+    /// inferred from the first lines when omitted. Needs the runtime's
+    /// `arrow-ipc` feature.
+    #[serde(rename = "arrow_ipc")]
+    ArrowIpc {
+        #[serde(default)]
+        schema: Option<String>,
     },
 }
 
@@ -61,6 +168,7 @@ impl Encoding {
             Self::JSON => "application/json",
             Self::Avro { .. } => "application/avro",
             Self::Parquet { .. } => "application/vnd.apache.parquet",
+            Self::ArrowIpc { .. } => "application/vnd.apache.arrow.stream",
         }
     }
 
@@ -70,6 +178,7 @@ impl Encoding {
             Self::JSON => "json",
             Self::Avro { .. } => "avro",
             Self::Parquet { .. } => "parquet",
+            Self::ArrowIpc { .. } => "arrows",
         }
     }
 }
@@ -90,9 +199,11 @@ pub enum Compression {
         #[serde(default = "default_zstd_level")]
         level: i32,
     },
+    /// zlib-wrapped DEFLATE, as HTTP `Content-Encoding: deflate` expects.
+    /// Applies to NDJSON and JSON output; Avro uses its own deflate codec.
     Deflate {
-        #[serde(default = "default_zstd_level")]
-        level: i32,
+        #[serde(default = "default_gzip_level")]
+        level: u32,
     },
 }
 
@@ -103,7 +214,7 @@ impl Compression {
             Self::Gzip { .. } => ".gz",
             Self::Zstd { .. } => ".zst",
             Self::Snappy { .. } => "",
-            Self::Deflate { .. } => "",
+            Self::Deflate { .. } => ".deflate",
         }
     }
 }
@@ -131,6 +242,26 @@ pub const fn in_flight_limit() -> usize {
     16
 }
 
+pub const fn key_prefix_max_values() -> usize {
+    1000
+}
+
+pub const fn parquet_row_group_size() -> usize {
+    65536
+}
+
+const fn default_failure_threshold() -> u32 {
+    5
+}
+
+const fn default_probe_interval_secs() -> u64 {
+    30
+}
+
+const fn default_half_open_max_calls() -> u32 {
+    1
+}
+
 const fn default_sink() -> bool {
     false
 }
@@ -0,0 +1,34 @@
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Indexes events into Elasticsearch (or OpenSearch) with the `_bulk` API,
+/// one document per NDJSON line.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ElasticsearchConfig {
+    /// Cluster URL, e.g. `https://es:9200`; batches are posted to `/_bulk`
+    /// under it.
+    pub endpoint: String,
+
+    /// Index documents are written to. `{YYYY-MM-DD}` is replaced with the
+    /// current UTC date, e.g. `logs-{YYYY-MM-DD}`.
+    pub index_pattern: String,
+
+    /// Sent as `Authorization: ApiKey <api_key>`, the base64 `id:key` pair
+    /// Elasticsearch returns as `encoded`.
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<SecretString>,
+
+    /// Most documents sent in one bulk request.
+    #[serde(default = "default_batch_max_docs")]
+    pub batch_max_docs: usize,
+
+    /// Sink that receives documents Elasticsearch rejects, tagged with
+    /// `__tangent_error`. Without one they are logged and dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter: Option<Arc<str>>,
+}
+
+const fn default_batch_max_docs() -> usize {
+    1000
+}
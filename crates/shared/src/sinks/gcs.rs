@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sinks::s3::{max_file_age_seconds, wal_path};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GcsConfig {
+    pub bucket_name: String,
+
+    /// Prepended to every object name, ahead of any per-route `key_prefix`.
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+
+    #[serde(default = "wal_path")]
+    pub wal_path: PathBuf,
+
+    #[serde(default = "max_file_age_seconds")]
+    pub max_file_age_seconds: u64,
+
+    /// Log an error once the oldest sealed WAL file is older than this many
+    /// seconds. Pair with `tangent_wal_oldest_sealed_file_age_seconds`.
+    #[serde(default)]
+    pub wal_alert_age_secs: Option<u64>,
+}
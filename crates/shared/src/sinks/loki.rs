@@ -0,0 +1,47 @@
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+
+/// Pushes events to Grafana Loki. Each NDJSON line becomes one log line,
+/// in a stream labelled by the values of `labels`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LokiConfig {
+    /// Loki's base URL, e.g. `http://loki:3100`; batches are posted to
+    /// `/loki/api/v1/push` under it.
+    pub endpoint: String,
+
+    /// Sent as `X-Scope-OrgID` for multi-tenant Loki.
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+
+    #[serde(default)]
+    pub basic_auth: Option<LokiBasicAuth>,
+
+    /// JSON fields (dotted paths) whose values label each event's stream,
+    /// e.g. `[service, env, level]`. Dots in a path become `_` in the label
+    /// name; events without a field get no label for it. Every stream also
+    /// carries `tangent_sink` set to the sink's name.
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// The Loki label name for the JSON field at dotted path `field`.
+pub fn label_name(field: &str) -> String {
+    field.replace('.', "_")
+}
+
+/// Whether Loki accepts `name` as a label name: `[a-zA-Z_][a-zA-Z0-9_]*`.
+pub fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LokiBasicAuth {
+    pub username: String,
+
+    #[serde(skip_serializing)]
+    pub password: SecretString,
+}
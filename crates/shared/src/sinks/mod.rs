@@ -1,4 +1,10 @@
+pub mod azure_blob;
 pub mod blackhole;
+pub mod clickhouse;
 pub mod common;
+pub mod elasticsearch;
 pub mod file;
+pub mod gcs;
+pub mod loki;
+pub mod prometheus_remote_write;
 pub mod s3;
@@ -0,0 +1,23 @@
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+
+/// Sends samples to a Prometheus remote_write endpoint. Each NDJSON line is
+/// one sample: `__name__`, `__value__`, optional `__timestamp_ms__`, and any
+/// other top-level fields as labels.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PrometheusRemoteWriteConfig {
+    /// e.g. `http://prometheus:9090/api/v1/write`
+    pub endpoint: String,
+
+    /// Most samples sent in one `WriteRequest`.
+    #[serde(default = "default_batch_max_samples")]
+    pub batch_max_samples: usize,
+
+    /// Sent as `Authorization: Bearer <token>`.
+    #[serde(default, skip_serializing)]
+    pub bearer_token: Option<SecretString>,
+}
+
+const fn default_batch_max_samples() -> usize {
+    2000
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
@@ -12,12 +13,22 @@ pub struct S3Config {
 
     #[serde(default = "max_file_age_seconds")]
     pub max_file_age_seconds: u64,
+
+    /// Log an error once the oldest sealed WAL file is older than this many
+    /// seconds. Pair with `tangent_wal_oldest_sealed_file_age_seconds`.
+    #[serde(default)]
+    pub wal_alert_age_secs: Option<u64>,
+
+    /// Object tags applied to every upload. Values may use `{date}`,
+    /// `{sink_name}` and `{source_name}`.
+    #[serde(default)]
+    pub tags: Option<HashMap<String, String>>,
 }
 
-fn wal_path() -> PathBuf {
+pub(crate) fn wal_path() -> PathBuf {
     "/tmp/wal".into()
 }
 
-const fn max_file_age_seconds() -> u64 {
+pub(crate) const fn max_file_age_seconds() -> u64 {
     60
 }
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sources::common::default_max_restart_delay_secs;
+
+/// Polls a CloudWatch Logs group with `FilterLogEvents`. Credentials and
+/// region come from the default AWS provider chain.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CloudWatchLogsConfig {
+    pub log_group_name: String,
+
+    /// Only read streams whose name starts with this.
+    #[serde(default)]
+    pub log_stream_prefix: Option<String>,
+
+    /// CloudWatch filter pattern, e.g. `ERROR` or `{ $.level = "error" }`.
+    #[serde(default)]
+    pub filter_pattern: Option<String>,
+
+    /// Seconds between polls once the previous one has caught up.
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+
+    /// How far behind the newest event each query starts again, so events
+    /// ingested up to this long after their timestamp are still read.
+    /// Events read twice are dropped by `eventId`.
+    #[serde(default = "default_lookback_secs")]
+    pub lookback_secs: u64,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+}
+
+const fn default_poll_interval_secs() -> u64 {
+    10
+}
+
+const fn default_lookback_secs() -> u64 {
+    300
+}
@@ -1,9 +1,19 @@
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
 
+use crate::sources::cloudwatch_logs::CloudWatchLogsConfig;
+use crate::sources::docker_events::DockerEventsConfig;
 use crate::sources::file::FileConfig;
 use crate::sources::github_webhook::GithubWebhookConfig;
+use crate::sources::grpc::GrpcSourceConfig;
+use crate::sources::http::HttpSourceConfig;
+use crate::sources::http_polling::HttpPollingConfig;
+use crate::sources::kafka::KafkaSourceConfig;
 use crate::sources::msk::MSKConfig;
 use crate::sources::npm_registry::NpmRegistryConfig;
+use crate::sources::pulsar::PulsarSourceConfig;
+use crate::sources::redis_streams::RedisStreamsConfig;
 use crate::sources::socket::SocketConfig;
 use crate::sources::sqs::SQSConfig;
 use crate::sources::tcp::TcpConfig;
@@ -25,14 +35,60 @@ pub enum SourceConfig {
     GithubWebhook(GithubWebhookConfig),
     #[serde(rename = "npm_registry")]
     NPMRegistry(NpmRegistryConfig),
+    #[serde(rename = "http_polling")]
+    HttpPolling(HttpPollingConfig),
+    #[serde(rename = "http")]
+    Http(HttpSourceConfig),
+    #[serde(rename = "redis_streams")]
+    RedisStreams(RedisStreamsConfig),
+    #[serde(rename = "grpc")]
+    Grpc(GrpcSourceConfig),
+    #[serde(rename = "pulsar")]
+    Pulsar(PulsarSourceConfig),
+    #[serde(rename = "cloudwatch_logs")]
+    CloudWatchLogs(CloudWatchLogsConfig),
+    #[serde(rename = "docker_events")]
+    DockerEvents(DockerEventsConfig),
+    #[serde(rename = "kafka")]
+    Kafka(KafkaSourceConfig),
+}
+
+impl SourceConfig {
+    /// Cap on the exponential backoff used when restarting a failed consumer.
+    #[must_use]
+    pub fn max_restart_delay(&self) -> Duration {
+        let secs = match self {
+            SourceConfig::MSK(c) => c.max_restart_delay_secs,
+            SourceConfig::File(c) => c.max_restart_delay_secs,
+            SourceConfig::Socket(c) => c.max_restart_delay_secs,
+            SourceConfig::Tcp(c) => c.max_restart_delay_secs,
+            SourceConfig::SQS(c) => c.max_restart_delay_secs,
+            SourceConfig::GithubWebhook(c) => c.max_restart_delay_secs,
+            SourceConfig::NPMRegistry(c) => c.max_restart_delay_secs,
+            SourceConfig::HttpPolling(c) => c.max_restart_delay_secs,
+            SourceConfig::Http(c) => c.max_restart_delay_secs,
+            SourceConfig::RedisStreams(c) => c.max_restart_delay_secs,
+            SourceConfig::Grpc(c) => c.max_restart_delay_secs,
+            SourceConfig::Pulsar(c) => c.max_restart_delay_secs,
+            SourceConfig::CloudWatchLogs(c) => c.max_restart_delay_secs,
+            SourceConfig::DockerEvents(c) => c.max_restart_delay_secs,
+            SourceConfig::Kafka(c) => c.max_restart_delay_secs,
+        };
+        Duration::from_secs(secs)
+    }
+}
+
+#[must_use]
+pub const fn default_max_restart_delay_secs() -> u64 {
+    60
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Decoding {
-    pub format: DecodeFormat, // ndjson | json | json-array | text | msgpack
+    pub format: DecodeFormat, // ndjson | json | json-array | text | msgpack | protobuf | auto
 
     #[serde(default)]
-    pub compression: DecodeCompression, // auto | none | gzip | zstd
+    pub compression: DecodeCompression, // auto | none | gzip | zstd | lz4
 }
 
 impl Decoding {
@@ -55,6 +111,9 @@ impl Decoding {
             if enc.contains("zstd") || enc.contains("zst") {
                 return DecodeCompression::Zstd;
             }
+            if enc.contains("lz4") {
+                return DecodeCompression::Lz4;
+            }
             if enc.contains("identity") || enc.contains("none") {
                 return DecodeCompression::None;
             }
@@ -68,6 +127,9 @@ impl Decoding {
             if n.ends_with(".zst") || n.ends_with(".zstd") {
                 return DecodeCompression::Zstd;
             }
+            if n.ends_with(".lz4") {
+                return DecodeCompression::Lz4;
+            }
         }
 
         if is_gzip(sniff) {
@@ -76,6 +138,9 @@ impl Decoding {
         if is_zstd(sniff) {
             return DecodeCompression::Zstd;
         }
+        if is_lz4(sniff) {
+            return DecodeCompression::Lz4;
+        }
 
         DecodeCompression::None
     }
@@ -89,6 +154,10 @@ fn is_zstd(b: &[u8]) -> bool {
     b.len() >= 4 && b[0] == 0x28 && b[1] == 0xB5 && b[2] == 0x2F && b[3] == 0xFD
 }
 
+fn is_lz4(b: &[u8]) -> bool {
+    b.len() >= 4 && b[0] == 0x04 && b[1] == 0x22 && b[2] == 0x4D && b[3] == 0x18
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum DecodeFormat {
@@ -97,6 +166,17 @@ pub enum DecodeFormat {
     JsonArray,
     Msgpack,
     Text,
+    /// One protobuf message per payload, decoded against a descriptor set
+    /// produced by `protoc --descriptor_set_out`.
+    Protobuf {
+        descriptor_path: PathBuf,
+        /// Fully-qualified message name, e.g. `acme.logs.v1.Event`.
+        message_type: String,
+    },
+    /// Avro object container files (`Obj\x01` magic) are decoded with the
+    /// schema they embed; anything else is treated as `json`. Schema-registry
+    /// framed Avro has no embedded schema and is not recognised.
+    Auto,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -106,9 +186,44 @@ pub enum DecodeCompression {
     None,
     Gzip,
     Zstd,
+    Lz4,
 }
 impl Default for DecodeCompression {
     fn default() -> Self {
         Self::Auto
     }
 }
+
+/// Join consecutive lines into one event, for stacktraces and other
+/// multi-line messages sent over line-oriented sources.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MultilineConfig {
+    /// A line matching this regex starts a new event; any other line is
+    /// appended to the current one.
+    pub start_pattern: String,
+
+    /// Emit the current event once it has this many lines.
+    #[serde(default = "default_multiline_max_lines")]
+    pub max_lines: usize,
+
+    /// Emit the current event when no line has arrived for this long.
+    #[serde(default = "default_multiline_timeout_ms")]
+    pub timeout_ms: u64,
+
+    /// Emit the current event once it reaches this many bytes; longer lines
+    /// are truncated.
+    #[serde(default = "default_multiline_max_event_bytes")]
+    pub max_event_bytes: usize,
+}
+
+const fn default_multiline_max_lines() -> usize {
+    500
+}
+
+const fn default_multiline_timeout_ms() -> u64 {
+    1000
+}
+
+const fn default_multiline_max_event_bytes() -> usize {
+    1 << 20
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::sources::common::default_max_restart_delay_secs;
+
+/// Streams the Docker Engine's `GET /events` over its Unix socket.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DockerEventsConfig {
+    #[serde(default = "default_socket_path")]
+    pub socket_path: PathBuf,
+
+    /// Docker event filters, e.g. `{type: [container], event: [die, oom]}`.
+    /// Values for the same key are or'ed, keys are and'ed.
+    #[serde(default)]
+    pub filters: BTreeMap<String, Vec<String>>,
+
+    /// Upper bound for the backoff between reconnects after a socket error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+}
+
+fn default_socket_path() -> PathBuf {
+    PathBuf::from("/var/run/docker.sock")
+}
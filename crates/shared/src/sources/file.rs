@@ -1,11 +1,39 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-use crate::sources::common::Decoding;
+use crate::sources::common::{default_max_restart_delay_secs, Decoding};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileConfig {
+    /// File to read. Leave unset when `glob` is given.
+    #[serde(default)]
     pub path: PathBuf,
 
+    /// Follow every file matching this pattern (e.g. `logs/**/*.log`) as in
+    /// `tail` mode, starting on new matches as they are created. How far
+    /// each file has been read is checkpointed in the runtime cache, so a
+    /// restart resumes where it left off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glob: Option<String>,
+
     pub decoding: Decoding,
+
+    /// Keep following the file like `tail -F` instead of stopping at EOF,
+    /// reopening it when it is rotated or truncated. Reads from the start.
+    #[serde(default)]
+    pub tail: bool,
+
+    /// In tail mode, lines longer than this are dropped rather than
+    /// forwarded.
+    #[serde(default = "default_max_line_bytes")]
+    pub max_line_bytes: usize,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+}
+
+#[must_use]
+pub const fn default_max_line_bytes() -> usize {
+    1 << 20
 }
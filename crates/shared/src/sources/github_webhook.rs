@@ -2,6 +2,8 @@ use std::net::SocketAddr;
 
 use serde::{Deserialize, Serialize};
 
+use crate::sources::common::default_max_restart_delay_secs;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GithubWebhookConfig {
     #[serde(default = "default_bind_address")]
@@ -9,6 +11,23 @@ pub struct GithubWebhookConfig {
     pub path: String,
     pub secret: Option<String>,
     pub token: String,
+
+    /// Require a GitHub Actions OIDC token in `X-GitHub-OIDC-Token` and verify
+    /// it against GitHub's JWKS before accepting the webhook.
+    #[serde(default)]
+    pub verify_oidc: bool,
+
+    /// Expected `aud` claim of the OIDC token. Required when `verify_oidc` is set.
+    #[serde(default)]
+    pub oidc_audience: Option<String>,
+
+    /// Allowed `repository` claims (e.g. "telophasehq/tangent"). Empty allows any repository.
+    #[serde(default)]
+    pub oidc_repositories: Vec<String>,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
 }
 
 fn default_bind_address() -> SocketAddr {
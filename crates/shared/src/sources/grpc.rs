@@ -0,0 +1,39 @@
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sources::common::{default_max_restart_delay_secs, DecodeFormat, Decoding};
+
+/// gRPC server implementing `tangent.ingest.v1.Ingest` (see
+/// `assets/proto/ingest.proto`). Each `IngestRequest.payload` is decoded
+/// with `decoding`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GrpcSourceConfig {
+    #[serde(default = "default_bind_address")]
+    pub bind_address: SocketAddr,
+
+    /// Serve gRPC server reflection so tools like `grpcurl` can discover
+    /// the service without the `.proto`.
+    #[serde(default)]
+    pub reflection: bool,
+
+    #[serde(default = "default_decoding")]
+    pub decoding: Decoding,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+}
+
+fn default_bind_address() -> SocketAddr {
+    "0.0.0.0:50051"
+        .parse()
+        .expect("default gRPC bind address should be valid")
+}
+
+fn default_decoding() -> Decoding {
+    Decoding {
+        format: DecodeFormat::Ndjson,
+        compression: Default::default(),
+    }
+}
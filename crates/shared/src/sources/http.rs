@@ -0,0 +1,75 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+
+use crate::sources::common::{default_max_restart_delay_secs, DecodeFormat, Decoding};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HttpSourceConfig {
+    #[serde(default = "default_bind_address")]
+    pub bind_address: SocketAddr,
+
+    /// Route that accepts POSTed payloads.
+    #[serde(default = "default_path")]
+    pub path: String,
+
+    /// When set, requests must carry `Authorization: Bearer <token>`.
+    #[serde(default, skip_serializing)]
+    pub bearer_token: Option<SecretString>,
+
+    /// Reject request bodies larger than this with `413`.
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+
+    /// Reject compressed bodies that inflate to more than this with `413`,
+    /// without decompressing the rest.
+    #[serde(default = "default_max_decompressed_bytes")]
+    pub max_decompressed_bytes: usize,
+
+    pub decoding: Decoding,
+
+    /// Schema for `Content-Type: application/x-protobuf` bodies: a `.proto`
+    /// file, or a descriptor set from `protoc --descriptor_set_out`.
+    #[serde(default)]
+    pub proto_schema: Option<PathBuf>,
+
+    /// Fully-qualified message type of protobuf bodies, e.g.
+    /// `acme.logs.v1.Event`. Required with `proto_schema`.
+    #[serde(default)]
+    pub proto_message_type: Option<String>,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+}
+
+impl HttpSourceConfig {
+    /// How `application/x-protobuf` bodies are decoded, when configured.
+    #[must_use]
+    pub fn proto_format(&self) -> Option<DecodeFormat> {
+        Some(DecodeFormat::Protobuf {
+            descriptor_path: self.proto_schema.clone()?,
+            message_type: self.proto_message_type.clone()?,
+        })
+    }
+}
+
+fn default_bind_address() -> SocketAddr {
+    "0.0.0.0:8080"
+        .parse()
+        .expect("default HTTP bind address should be valid")
+}
+
+fn default_path() -> String {
+    "/".into()
+}
+
+const fn default_max_body_bytes() -> usize {
+    10 << 20
+}
+
+const fn default_max_decompressed_bytes() -> usize {
+    100 << 20
+}
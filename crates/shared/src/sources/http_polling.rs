@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+
+use crate::sources::common::{default_max_restart_delay_secs, Decoding};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HttpPollingConfig {
+    pub url: String,
+
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+
+    /// HTTP method, e.g. "GET" or "POST".
+    #[serde(default = "default_method")]
+    pub method: String,
+
+    /// Extra request headers sent on every poll.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub auth: Option<HttpPollingAuth>,
+
+    pub decoding: Decoding,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum HttpPollingAuth {
+    Bearer {
+        #[serde(skip_serializing)]
+        token: SecretString,
+    },
+    Basic {
+        username: String,
+
+        #[serde(skip_serializing)]
+        password: SecretString,
+    },
+}
+
+const fn default_poll_interval_secs() -> u64 {
+    60
+}
+
+fn default_method() -> String {
+    "GET".into()
+}
@@ -0,0 +1,67 @@
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::sources::common::{
+    default_max_restart_delay_secs, DecodeCompression, DecodeFormat, Decoding,
+};
+
+/// A plain Kafka cluster (self-hosted, Confluent, Redpanda, ...). Use `msk`
+/// for Amazon MSK.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KafkaSourceConfig {
+    /// Comma-separated `host:port` bootstrap brokers.
+    pub brokers: String,
+    pub topic: String,
+    #[serde(default = "default_group_id")]
+    pub group_id: String,
+
+    /// SASL mechanism, e.g. `PLAIN` or `SCRAM-SHA-512`. Set together with
+    /// `sasl_username` and `sasl_password`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sasl_mechanism: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sasl_username: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub sasl_password: Option<SecretString>,
+
+    /// PEM bundle of CAs to verify the brokers with. Setting it turns on TLS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssl_ca_pem_path: Option<PathBuf>,
+
+    /// Defaults to `auto`, which tells Avro object container files from JSON
+    /// per message.
+    #[serde(default = "default_decoding")]
+    pub decoding: Decoding,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+}
+
+impl KafkaSourceConfig {
+    /// librdkafka `security.protocol` implied by the SASL and TLS settings.
+    #[must_use]
+    pub fn security_protocol(&self) -> &'static str {
+        match (
+            self.sasl_mechanism.is_some(),
+            self.ssl_ca_pem_path.is_some(),
+        ) {
+            (true, true) => "SASL_SSL",
+            (true, false) => "SASL_PLAINTEXT",
+            (false, true) => "SSL",
+            (false, false) => "PLAINTEXT",
+        }
+    }
+}
+
+fn default_group_id() -> String {
+    "tangent-node".into()
+}
+
+fn default_decoding() -> Decoding {
+    Decoding {
+        format: DecodeFormat::Auto,
+        compression: DecodeCompression::Auto,
+    }
+}
@@ -1,8 +1,16 @@
+pub mod cloudwatch_logs;
 pub mod common;
+pub mod docker_events;
 pub mod file;
 pub mod github_webhook;
+pub mod grpc;
+pub mod http;
+pub mod http_polling;
+pub mod kafka;
 pub mod msk;
 pub mod npm_registry;
+pub mod pulsar;
+pub mod redis_streams;
 pub mod socket;
 pub mod sqs;
 pub mod tcp;
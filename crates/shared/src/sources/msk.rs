@@ -1,9 +1,9 @@
 use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
 
-use crate::sources::common::Decoding;
+use crate::sources::common::{default_max_restart_delay_secs, Decoding};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MSKConfig {
     pub bootstrap_servers: String,
     pub topic: String,
@@ -22,10 +22,19 @@ pub struct MSKConfig {
 
     pub auth: MSKAuth,
 
+    /// Mirror acked offsets into the runtime cache, once a second, and resume
+    /// from them when the broker has no committed offset for a partition.
+    #[serde(default)]
+    pub checkpoint_offsets: bool,
+
     pub decoding: Decoding,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "mode", rename_all = "lowercase")]
 pub enum MSKAuth {
     Scram {
@@ -1,19 +1,32 @@
 use serde::{Deserialize, Serialize};
 
+use crate::sources::common::default_max_restart_delay_secs;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NpmRegistryConfig {
-    /// List of npm package names to poll, e.g. ["tangent-home-js", "@telophasehq/foo"].
-    /// Leave empty to check all packages registered to your org.  Must specify this or orgs.
+    /// List of npm package names to watch, e.g. ["tangent-home-js", "@telophasehq/foo"].
+    /// At least one of this and `orgs` must be set.
     pub packages: Option<Vec<String>>,
 
-    /// List of orgs to poll for packages, e.g. ["telophasehq", "ethanblackburn"]. Must specify this or packages.
+    /// List of orgs whose packages are watched, e.g. ["telophasehq", "ethanblackburn"].
     pub orgs: Option<Vec<String>>,
 
-    /// Poll interval in seconds
-    #[serde(default = "default_interval_secs")]
-    pub interval_secs: u64,
+    /// Seconds between polls of the registry's changes feed.
+    #[serde(default = "default_interval_secs", alias = "interval_secs")]
+    pub poll_interval_secs: u64,
+
+    /// Changes-feed sequence to start from when no checkpoint is cached.
+    /// Unset starts at the end of the feed, so only versions published after
+    /// the first start are emitted. Every version of a package whose change
+    /// is replayed from here is emitted.
+    #[serde(default)]
+    pub since_sequence: Option<u64>,
 
     pub token: Option<String>,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
 }
 
 fn default_interval_secs() -> u64 {
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sources::common::{default_max_restart_delay_secs, Decoding};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PulsarSourceConfig {
+    /// `pulsar://` or `pulsar+ssl://` broker URL.
+    pub service_url: String,
+
+    /// Topic to consume, e.g. `persistent://public/default/logs`.
+    pub topic: String,
+
+    /// Subscription name, created on first connect.
+    pub subscription: String,
+
+    #[serde(default)]
+    pub subscription_type: PulsarSubscriptionType,
+
+    pub decoding: Decoding,
+
+    /// Topic that messages are moved to once they have been redelivered
+    /// `max_redeliveries` times, e.g. because they can't be decoded. Unset,
+    /// such messages are nacked and redelivered indefinitely.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter_topic: Option<String>,
+
+    /// Redeliveries before a message goes to `dead_letter_topic`.
+    #[serde(default = "default_max_redeliveries")]
+    pub max_redeliveries: usize,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+}
+
+const fn default_max_redeliveries() -> usize {
+    3
+}
+
+/// `exclusive` allows one consumer per subscription; `shared` spreads
+/// messages across every consumer; `failover` delivers to one consumer at a
+/// time and switches to another if it disconnects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PulsarSubscriptionType {
+    #[default]
+    Exclusive,
+    Shared,
+    Failover,
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sources::common::{default_max_restart_delay_secs, Decoding};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RedisStreamsConfig {
+    /// `redis://` or `rediss://` connection URL.
+    pub url: String,
+
+    /// Key of the stream to consume.
+    pub stream: String,
+
+    /// Consumer group, created (along with the stream) if it does not exist.
+    pub group: String,
+
+    /// Name of this consumer within `group`.
+    #[serde(default = "default_consumer")]
+    pub consumer: String,
+
+    /// Entry field holding the payload. Entries without it are acked and
+    /// skipped.
+    #[serde(default = "default_field")]
+    pub field: String,
+
+    /// How long each `XREADGROUP` blocks waiting for new entries.
+    #[serde(default = "default_block_ms")]
+    pub block_ms: u64,
+
+    /// Maximum entries fetched per `XREADGROUP` / `XAUTOCLAIM`.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    /// Entries left unacked by any consumer for this long are claimed by
+    /// this one and delivered again.
+    #[serde(default = "default_pending_timeout_ms")]
+    pub pending_timeout_ms: u64,
+
+    pub decoding: Decoding,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+}
+
+fn default_consumer() -> String {
+    "tangent".to_string()
+}
+
+fn default_field() -> String {
+    "data".to_string()
+}
+
+const fn default_block_ms() -> u64 {
+    5_000
+}
+
+const fn default_batch_size() -> usize {
+    100
+}
+
+const fn default_pending_timeout_ms() -> u64 {
+    60_000
+}
@@ -1,10 +1,25 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Debug, Deserialize, Serialize)]
+use crate::sources::common::{default_max_restart_delay_secs, MultilineConfig};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SocketConfig {
     #[serde(default = "default_socket_path")]
     pub socket_path: PathBuf,
+
+    /// Prepend `_connection_id` to every log. Unix sockets have no peer
+    /// address, so only the id is added.
+    #[serde(default)]
+    pub inject_connection_metadata: bool,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+    /// Join stacktraces and other multi-line messages into single
+    /// `{"_raw": ...}` events instead of reading NDJSON.
+    #[serde(default)]
+    pub multiline: Option<MultilineConfig>,
 }
 
 fn default_socket_path() -> PathBuf {
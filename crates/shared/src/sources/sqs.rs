@@ -1,22 +1,40 @@
 use serde::{Deserialize, Serialize};
 
-use crate::sources::common::Decoding;
+use crate::sources::common::{default_max_restart_delay_secs, Decoding};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SQSConfig {
     pub queue_url: String,
     #[serde(default = "default_wait_time_seconds")]
     pub wait_time_seconds: i64,
-    #[serde(default = "default_visibility_timeout")]
-    pub visibility_timeout: i64,
+
+    /// Visibility timeout requested when receiving messages.
+    #[serde(
+        default = "default_visibility_timeout_secs",
+        alias = "visibility_timeout"
+    )]
+    pub visibility_timeout_secs: u32,
+
+    /// Keep extending the visibility timeout every `visibility_timeout_secs / 2`
+    /// until the message is acked, so slow S3 fetches aren't redelivered.
+    #[serde(default = "default_extend_visibility_timeout")]
+    pub extend_visibility_timeout: bool,
 
     pub decoding: Decoding,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
 }
 
 const fn default_wait_time_seconds() -> i64 {
     20
 }
 
-const fn default_visibility_timeout() -> i64 {
-    60
+const fn default_visibility_timeout_secs() -> u32 {
+    30
+}
+
+const fn default_extend_visibility_timeout() -> bool {
+    true
 }
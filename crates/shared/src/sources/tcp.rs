@@ -1,13 +1,46 @@
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
-#[derive(Debug, Deserialize, Serialize)]
+use crate::sources::common::{default_max_restart_delay_secs, MultilineConfig};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TcpConfig {
     #[serde(default = "default_bind_address")]
     pub bind_address: SocketAddr,
 
     #[serde(default = "default_read_buffer_size")]
     pub read_buffer_size: usize,
+
+    /// Prepend `_src_ip`, `_src_port` and `_connection_id` to every log.
+    #[serde(default)]
+    pub inject_connection_metadata: bool,
+
+    /// Upper bound for the backoff between consumer restarts after an error.
+    #[serde(default = "default_max_restart_delay_secs")]
+    pub max_restart_delay_secs: u64,
+
+    /// Terminate TLS on accepted connections.
+    #[serde(default)]
+    pub tls: Option<TcpTlsConfig>,
+    /// Join stacktraces and other multi-line messages into single
+    /// `{"_raw": ...}` events instead of reading NDJSON.
+    #[serde(default)]
+    pub multiline: Option<MultilineConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TcpTlsConfig {
+    /// PEM certificate chain presented to clients.
+    pub cert_pem_path: PathBuf,
+
+    /// PEM private key for `cert_pem_path`.
+    pub key_pem_path: PathBuf,
+
+    /// When set, clients must present a certificate signed by one of these
+    /// PEM CA certificates.
+    #[serde(default)]
+    pub ca_pem_path: Option<PathBuf>,
 }
 
 fn default_bind_address() -> SocketAddr {
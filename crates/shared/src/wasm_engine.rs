@@ -3,6 +3,30 @@ use anyhow::Result;
 use wasmtime::{Config, Engine};
 
 pub fn build() -> Result<Engine> {
+    Engine::new(&base_config())
+}
+
+/// Engine whose guest calls can be interrupted via epochs, for plugins with a
+/// `timeout_ms`. Like `build_traced()`, it can't load `.cwasm` files built by
+/// `build()`.
+pub fn build_interruptible() -> Result<Engine> {
+    let mut cfg = base_config();
+    cfg.epoch_interruption(true);
+
+    Engine::new(&cfg)
+}
+
+/// Engine for `tangent run --trace-wasm`: guest calls can be interrupted via
+/// epochs and traps carry a wasm backtrace. Components must be compiled with
+/// this engine; `.cwasm` files built by `build()` won't load.
+pub fn build_traced() -> Result<Engine> {
+    let mut cfg = base_config();
+    cfg.epoch_interruption(true).wasm_backtrace(true);
+
+    Engine::new(&cfg)
+}
+
+fn base_config() -> Config {
     let mut cfg = Config::new();
     cfg.wasm_component_model(true)
         .async_support(true)
@@ -14,6 +38,5 @@ pub fn build() -> Result<Engine> {
         .parallel_compilation(false)
         .async_support(true)
         .allocation_strategy(wasmtime::InstanceAllocationStrategy::pooling());
-
-    Engine::new(&cfg)
+    cfg
 }
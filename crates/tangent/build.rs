@@ -0,0 +1,23 @@
+// Generates the `tangent.ingest.v1.Ingest` server for the grpc source. The
+// messages are plain prost structs in `src/sources/grpc.rs`, so no `protoc`
+// is needed; keep both in sync with `assets/proto/ingest.proto`.
+fn main() {
+    let ingest = tonic_build::manual::Service::builder()
+        .name("Ingest")
+        .package("tangent.ingest.v1")
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("stream")
+                .route_name("Stream")
+                .input_type("crate::sources::grpc::IngestRequest")
+                .output_type("crate::sources::grpc::IngestResponse")
+                .codec_path("tonic_prost::ProstCodec")
+                .client_streaming()
+                .build(),
+        )
+        .build();
+
+    tonic_build::manual::Builder::new()
+        .build_client(false)
+        .compile(&[ingest]);
+}
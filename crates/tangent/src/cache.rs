@@ -1,5 +1,5 @@
 use std::fs::{create_dir_all, OpenOptions};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context, Result};
@@ -13,11 +13,20 @@ use tracing::info;
 
 use crate::wasm::host::tangent::logs::log::Scalar;
 
+/// Keys under this prefix hold runtime state such as source checkpoints,
+/// written as `tangent:<source kind>:<source name>:...`. Plugins can't read or
+/// write them.
+pub const RUNTIME_KEY_PREFIX: &str = "tangent:";
+
 static CACHE_OPEN_GUARD: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
 #[derive(Clone)]
 pub struct CacheHandle {
     conn: std::sync::Arc<Mutex<Connection>>,
+    /// Idle connections for transactions, so an open transaction never holds
+    /// `conn` and plain cache calls keep working meanwhile.
+    tx_conns: std::sync::Arc<Mutex<Vec<Connection>>>,
+    path: std::sync::Arc<Path>,
     _lock: std::sync::Arc<std::fs::File>,
     default_ttl_ms: u64,
     max_ttl_ms: u64,
@@ -27,11 +36,7 @@ impl CacheHandle {
     pub fn open(cfg: &CacheConfig, base_dir: &Path) -> Result<Self> {
         let _open_guard = CACHE_OPEN_GUARD.lock();
 
-        let path = if cfg.path.is_absolute() {
-            cfg.path.clone()
-        } else {
-            base_dir.join(&cfg.path)
-        };
+        let path = resolve_path(cfg, base_dir);
 
         if let Some(parent) = path.parent() {
             create_dir_all(parent)
@@ -40,15 +45,7 @@ impl CacheHandle {
 
         let lock = acquire_lock(&path, Duration::from_millis(cfg.lock_timeout_ms))?;
 
-        let conn = Connection::open_with_flags(
-            &path,
-            OpenFlags::SQLITE_OPEN_READ_WRITE
-                | OpenFlags::SQLITE_OPEN_CREATE
-                | OpenFlags::SQLITE_OPEN_FULL_MUTEX,
-        )
-        .with_context(|| format!("opening cache db at {}", path.display()))?;
-
-        conn.busy_timeout(Duration::from_secs(5))?;
+        let conn = open_conn(&path, OpenFlags::SQLITE_OPEN_CREATE)?;
         conn.pragma_update(None, "journal_mode", &"WAL")?;
         conn.pragma_update(None, "synchronous", &"NORMAL")?;
         conn.pragma_update(None, "wal_autocheckpoint", &1000i64)?;
@@ -60,12 +57,14 @@ impl CacheHandle {
                 kind TEXT,
                 value BLOB NOT NULL,
                 expires_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
+                updated_at INTEGER NOT NULL,
+                owner TEXT
             );
             CREATE INDEX IF NOT EXISTS cache_expires_idx ON cache(expires_at);
             "#,
         )
         .context("creating schema")?;
+        add_owner_column(&conn)?;
 
         let guard = std::sync::Arc::new(lock);
 
@@ -73,6 +72,8 @@ impl CacheHandle {
 
         Ok(Self {
             conn: std::sync::Arc::new(Mutex::new(conn)),
+            tx_conns: Default::default(),
+            path: path.into(),
             _lock: guard,
             default_ttl_ms: cfg.default_ttl_ms,
             max_ttl_ms: cfg.max_ttl_ms,
@@ -80,50 +81,68 @@ impl CacheHandle {
     }
 
     pub fn get(&self, key: &str) -> Result<Option<Scalar>> {
-        let now = now_ms();
-        let conn = self.conn.lock();
-        let mut stmt =
-            conn.prepare_cached("SELECT kind, value, expires_at FROM cache WHERE key = ?1")?;
-        let mut rows = stmt.query(params![key])?;
-
-        if let Some(row) = rows.next()? {
-            let expires_at: i64 = row.get(2)?;
-            if expires_at <= now as i64 {
-                drop(rows);
-                conn.execute("DELETE FROM cache WHERE key = ?1", params![key])?;
-                return Ok(None);
-            }
-            let kind: String = row.get(0)?;
-            let val: Value = row.get(1)?;
-            return Ok(Some(Scalar::from_sqlite(&kind, val)?));
-        }
-
-        Ok(None)
+        get_in(&self.conn.lock(), key)
     }
 
     pub fn set(&self, key: &str, v: &Scalar, ttl_ms: Option<u64>) -> Result<()> {
-        let (kind, val) = v.to_sqlite();
-
-        let ttl = ttl_ms.unwrap_or(self.default_ttl_ms).min(self.max_ttl_ms);
-        let expires_at = now_ms()
-            .checked_add(ttl)
-            .ok_or_else(|| anyhow!("ttl overflow"))?;
-        let updated_at = now_ms();
+        let expires_at = self.expires_at(ttl_ms)?;
+        set_in(&self.conn.lock(), None, key, v, expires_at)
+    }
 
-        let conn = self.conn.lock();
-        conn.execute(
-            "INSERT INTO cache(key, kind, value, expires_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)
-             ON CONFLICT(key) DO UPDATE SET kind=excluded.kind, value=excluded.value, expires_at=excluded.expires_at, updated_at=excluded.updated_at",
-            rusqlite::params![key, kind, val, expires_at as i64, updated_at as i64],
-        )?;
-        Ok(())
+    /// Like `set`, recording `owner` (the plugin name) so the entry shows up
+    /// under `tangent cache list --plugin`.
+    pub fn set_as(&self, owner: &str, key: &str, v: &Scalar, ttl_ms: Option<u64>) -> Result<()> {
+        let expires_at = self.expires_at(ttl_ms)?;
+        set_in(&self.conn.lock(), Some(owner), key, v, expires_at)
     }
 
     pub fn del(&self, key: &str) -> Result<bool> {
-        let conn = self.conn.lock();
-        let rows = conn.execute("DELETE FROM cache WHERE key = ?1", params![key])?;
-        Ok(rows > 0)
+        del_in(&self.conn.lock(), key)
+    }
+
+    /// Start a `BEGIN IMMEDIATE` transaction on a connection of its own.
+    /// Reads elsewhere see the last committed state meanwhile; other writers,
+    /// including non-transactional `set`s, wait for the commit (up to the
+    /// busy timeout) rather than deadlocking.
+    pub fn begin(&self) -> Result<CacheTx> {
+        let idle = self.tx_conns.lock().pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => open_conn(&self.path, OpenFlags::empty())?,
+        };
+        conn.execute_batch("BEGIN IMMEDIATE")
+            .context("beginning cache transaction")?;
+        Ok(CacheTx {
+            conn: Some(conn),
+            pool: self.tx_conns.clone(),
+            default_ttl_ms: self.default_ttl_ms,
+            max_ttl_ms: self.max_ttl_ms,
+            open: true,
+        })
+    }
+
+    /// Run `f` inside a transaction: commit if it returns `Ok`, roll back otherwise.
+    pub fn transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut CacheTx) -> Result<T>,
+    {
+        let mut tx = self.begin()?;
+        match f(&mut tx) {
+            Ok(v) => {
+                tx.commit()?;
+                Ok(v)
+            }
+            Err(e) => {
+                if let Err(rb) = tx.rollback() {
+                    tracing::warn!(target = "cache", error = %rb, "rollback failed");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn expires_at(&self, ttl_ms: Option<u64>) -> Result<u64> {
+        expires_at(ttl_ms, self.default_ttl_ms, self.max_ttl_ms)
     }
 
     pub fn reset(&self) -> Result<()> {
@@ -136,6 +155,239 @@ impl CacheHandle {
     }
 }
 
+/// An open cache transaction; see [`CacheHandle::begin`]. Dropping it without
+/// committing rolls back.
+pub struct CacheTx {
+    /// `None` once the connection went back to `pool`.
+    conn: Option<Connection>,
+    pool: std::sync::Arc<Mutex<Vec<Connection>>>,
+    default_ttl_ms: u64,
+    max_ttl_ms: u64,
+    open: bool,
+}
+
+/// Transaction connections kept around for reuse.
+const MAX_IDLE_TX_CONNS: usize = 4;
+
+impl CacheTx {
+    fn conn(&self) -> &Connection {
+        self.conn.as_ref().expect("transaction connection in use")
+    }
+
+    /// End the transaction with `stmt` and hand the connection back.
+    fn finish(&mut self, stmt: &str) -> rusqlite::Result<()> {
+        self.open = false;
+        let Some(conn) = self.conn.take() else {
+            return Ok(());
+        };
+        conn.execute_batch(stmt)?;
+        let mut pool = self.pool.lock();
+        if pool.len() < MAX_IDLE_TX_CONNS {
+            pool.push(conn);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Scalar>> {
+        get_in(self.conn(), key)
+    }
+
+    pub fn set(&self, key: &str, v: &Scalar, ttl_ms: Option<u64>) -> Result<()> {
+        let expires_at = expires_at(ttl_ms, self.default_ttl_ms, self.max_ttl_ms)?;
+        set_in(self.conn(), None, key, v, expires_at)
+    }
+
+    pub fn set_as(&self, owner: &str, key: &str, v: &Scalar, ttl_ms: Option<u64>) -> Result<()> {
+        let expires_at = expires_at(ttl_ms, self.default_ttl_ms, self.max_ttl_ms)?;
+        set_in(self.conn(), Some(owner), key, v, expires_at)
+    }
+
+    pub fn del(&self, key: &str) -> Result<bool> {
+        del_in(self.conn(), key)
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        self.finish("COMMIT")
+            .context("committing cache transaction")
+    }
+
+    pub fn rollback(mut self) -> Result<()> {
+        self.finish("ROLLBACK")
+            .context("rolling back cache transaction")
+    }
+}
+
+impl Drop for CacheTx {
+    fn drop(&mut self) {
+        if self.open {
+            if let Err(e) = self.finish("ROLLBACK") {
+                tracing::warn!(target = "cache", error = %e, "rollback of dropped transaction failed");
+            }
+        }
+    }
+}
+
+/// Open a connection to the cache database at `path`; `extra` adds to the
+/// read-write flags.
+fn open_conn(path: &Path, extra: OpenFlags) -> Result<Connection> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_FULL_MUTEX | extra,
+    )
+    .with_context(|| format!("opening cache db at {}", path.display()))?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    Ok(conn)
+}
+
+fn get_in(conn: &Connection, key: &str) -> Result<Option<Scalar>> {
+    let now = now_ms();
+    let mut stmt =
+        conn.prepare_cached("SELECT kind, value, expires_at FROM cache WHERE key = ?1")?;
+    let mut rows = stmt.query(params![key])?;
+
+    if let Some(row) = rows.next()? {
+        let expires_at: i64 = row.get(2)?;
+        if expires_at <= now as i64 {
+            drop(rows);
+            conn.execute("DELETE FROM cache WHERE key = ?1", params![key])?;
+            return Ok(None);
+        }
+        let kind: String = row.get(0)?;
+        let val: Value = row.get(1)?;
+        return Ok(Some(Scalar::from_sqlite(&kind, val)?));
+    }
+
+    Ok(None)
+}
+
+fn set_in(
+    conn: &Connection,
+    owner: Option<&str>,
+    key: &str,
+    v: &Scalar,
+    expires_at: u64,
+) -> Result<()> {
+    let (kind, val) = v.to_sqlite();
+    let updated_at = now_ms();
+
+    conn.execute(
+        "INSERT INTO cache(key, kind, value, expires_at, updated_at, owner)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(key) DO UPDATE SET kind=excluded.kind, value=excluded.value, expires_at=excluded.expires_at, updated_at=excluded.updated_at, owner=excluded.owner",
+        rusqlite::params![key, kind, val, expires_at as i64, updated_at as i64, owner],
+    )?;
+    Ok(())
+}
+
+fn del_in(conn: &Connection, key: &str) -> Result<bool> {
+    let rows = conn.execute("DELETE FROM cache WHERE key = ?1", params![key])?;
+    Ok(rows > 0)
+}
+
+fn expires_at(ttl_ms: Option<u64>, default_ttl_ms: u64, max_ttl_ms: u64) -> Result<u64> {
+    let ttl = ttl_ms.unwrap_or(default_ttl_ms).min(max_ttl_ms);
+    now_ms()
+        .checked_add(ttl)
+        .ok_or_else(|| anyhow!("ttl overflow"))
+}
+
+/// Databases created before entries recorded their owner lack the column.
+fn add_owner_column(conn: &Connection) -> Result<()> {
+    if !has_owner_column(conn)? {
+        conn.execute_batch("ALTER TABLE cache ADD COLUMN owner TEXT")
+            .context("adding cache.owner column")?;
+    }
+    Ok(())
+}
+
+/// Where the cache database for `cfg` lives; relative paths are resolved
+/// against `base_dir` (the config's directory).
+pub fn resolve_path(cfg: &CacheConfig, base_dir: &Path) -> PathBuf {
+    if cfg.path.is_absolute() {
+        cfg.path.clone()
+    } else {
+        base_dir.join(&cfg.path)
+    }
+}
+
+/// One row of the cache, as shown by `tangent cache list`.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub key: String,
+    pub kind: String,
+    /// Stored value size in bytes.
+    pub size: u64,
+    /// Unix milliseconds.
+    pub expires_at: u64,
+    /// Plugin that last wrote the entry; `None` for runtime state such as
+    /// source offsets and for entries written before owners were recorded.
+    pub owner: Option<String>,
+}
+
+/// List entries in the cache database at `path` without taking the runtime's
+/// lock. The file is opened read-only, so this is safe while tangent runs.
+pub fn list_entries(
+    path: &Path,
+    plugin: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Vec<CacheEntry>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("opening cache db at {}", path.display()))?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+
+    let owner_col = if has_owner_column(&conn)? {
+        "owner"
+    } else {
+        "NULL"
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT key, COALESCE(kind, ''), length(value), expires_at, {owner_col} FROM cache
+         WHERE ?1 IS NULL OR {owner_col} = ?1
+         ORDER BY key LIMIT ?2"
+    ))?;
+    let limit = limit.map_or(-1, |n| n as i64);
+    let rows = stmt.query_map(params![plugin, limit], |row| {
+        Ok(CacheEntry {
+            key: row.get(0)?,
+            kind: row.get(1)?,
+            size: row.get::<_, i64>(2)? as u64,
+            expires_at: row.get::<_, i64>(3)? as u64,
+            owner: row.get(4)?,
+        })
+    })?;
+    rows.collect::<rusqlite::Result<_>>().map_err(Into::into)
+}
+
+/// Delete entries from the cache database at `path` in one transaction:
+/// those owned by `plugin` if given, and only those last written more than
+/// `older_than` ago if given. Returns how many were deleted.
+pub fn clear_entries(
+    path: &Path,
+    plugin: Option<&str>,
+    older_than: Option<Duration>,
+) -> Result<usize> {
+    let mut conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .with_context(|| format!("opening cache db at {}", path.display()))?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    add_owner_column(&conn)?;
+
+    let cutoff = older_than.map(|d| now_ms().saturating_sub(d.as_millis() as u64) as i64);
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    let deleted = tx.execute(
+        "DELETE FROM cache
+         WHERE (?1 IS NULL OR owner = ?1) AND (?2 IS NULL OR updated_at < ?2)",
+        params![plugin, cutoff],
+    )?;
+    tx.commit().context("committing cache clear")?;
+    Ok(deleted)
+}
+
+fn has_owner_column(conn: &Connection) -> Result<bool> {
+    Ok(conn
+        .prepare("SELECT 1 FROM pragma_table_info('cache') WHERE name = 'owner'")?
+        .exists([])?)
+}
+
 fn acquire_lock(path: &Path, timeout: Duration) -> Result<std::fs::File> {
     let mut lock_path = path.to_path_buf();
     lock_path.set_extension("sqlite.lock");
@@ -167,3 +419,110 @@ fn now_ms() -> u64 {
         .unwrap_or_default()
         .as_millis() as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_temp() -> CacheHandle {
+        let dir = std::env::temp_dir().join(format!("tangent-cache-{}", ulid::Ulid::new()));
+        let cfg = CacheConfig {
+            path: dir.join("cache.sqlite"),
+            ..CacheConfig::default()
+        };
+        CacheHandle::open(&cfg, &dir).unwrap()
+    }
+
+    #[test]
+    fn transaction_commits_on_ok_and_rolls_back_on_err() {
+        let cache = open_temp();
+        cache.set("n", &Scalar::Int(1), None).unwrap();
+
+        let n = cache
+            .transaction(|tx| {
+                let Some(Scalar::Int(n)) = tx.get("n")? else {
+                    anyhow::bail!("missing counter");
+                };
+                tx.set("n", &Scalar::Int(n + 1), None)?;
+                tx.set("seen", &Scalar::Boolean(true), None)?;
+                Ok(n + 1)
+            })
+            .unwrap();
+        assert_eq!(n, 2);
+        assert!(matches!(cache.get("n").unwrap(), Some(Scalar::Int(2))));
+
+        let res: Result<()> = cache.transaction(|tx| {
+            tx.set("n", &Scalar::Int(100), None)?;
+            tx.del("seen")?;
+            anyhow::bail!("abort")
+        });
+        assert!(res.is_err());
+        assert!(matches!(cache.get("n").unwrap(), Some(Scalar::Int(2))));
+        assert!(cache.get("seen").unwrap().is_some());
+
+        let tx = cache.begin().unwrap();
+        tx.set("n", &Scalar::Int(7), None).unwrap();
+        drop(tx);
+        assert!(matches!(cache.get("n").unwrap(), Some(Scalar::Int(2))));
+    }
+
+    #[test]
+    fn plain_reads_go_through_while_a_transaction_is_open() {
+        let cache = open_temp();
+        cache.set("n", &Scalar::Int(1), None).unwrap();
+
+        let tx = cache.begin().unwrap();
+        tx.set("n", &Scalar::Int(2), None).unwrap();
+        // Would deadlock if the transaction held the handle's connection.
+        assert!(matches!(cache.get("n").unwrap(), Some(Scalar::Int(1))));
+        tx.commit().unwrap();
+        assert!(matches!(cache.get("n").unwrap(), Some(Scalar::Int(2))));
+
+        // The connection is reused for the next transaction.
+        cache
+            .transaction(|tx| tx.set("n", &Scalar::Int(3), None))
+            .unwrap();
+        assert!(matches!(cache.get("n").unwrap(), Some(Scalar::Int(3))));
+    }
+
+    #[test]
+    fn list_and_clear_filter_by_owner() {
+        let dir = std::env::temp_dir().join(format!("tangent-cache-{}", ulid::Ulid::new()));
+        let path = dir.join("cache.sqlite");
+        let cfg = CacheConfig {
+            path: path.clone(),
+            ..CacheConfig::default()
+        };
+        let cache = CacheHandle::open(&cfg, &dir).unwrap();
+        cache
+            .set_as("geo", "ip:1.2.3.4", &Scalar::Str("US".into()), None)
+            .unwrap();
+        cache
+            .set_as("geo", "ip:5.6.7.8", &Scalar::Int(1), None)
+            .unwrap();
+        cache
+            .set_as("dedupe", "seen:a", &Scalar::Boolean(true), None)
+            .unwrap();
+        cache
+            .set("tangent:msk:offset", &Scalar::Int(42), None)
+            .unwrap();
+
+        let all = list_entries(&path, None, None).unwrap();
+        assert_eq!(all.len(), 4);
+        let geo = list_entries(&path, Some("geo"), Some(1)).unwrap();
+        assert_eq!(geo.len(), 1);
+        assert_eq!(geo[0].key, "ip:1.2.3.4");
+        assert_eq!(geo[0].kind, "str");
+        assert_eq!(geo[0].size, 2);
+
+        // Nothing has been around for an hour yet.
+        assert_eq!(
+            clear_entries(&path, None, Some(Duration::from_secs(3600))).unwrap(),
+            0
+        );
+        assert_eq!(clear_entries(&path, Some("geo"), None).unwrap(), 2);
+        assert!(cache.get("ip:1.2.3.4").unwrap().is_none());
+        assert!(cache.get("seen:a").unwrap().is_some());
+        assert!(cache.get("tangent:msk:offset").unwrap().is_some());
+    }
+}
@@ -1,51 +1,112 @@
-use ahash::AHashMap as HashMap;
-
 use anyhow::{anyhow, Context, Result};
+use bytes::BytesMut;
+use futures::FutureExt;
 use std::{
     collections::BTreeMap,
+    future::Future,
+    panic::AssertUnwindSafe,
     path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
-use tangent_shared::{dag::NodeRef, sources::common::SourceConfig, Config};
+use tangent_shared::plugins::PluginEncoding;
+use tangent_shared::sinks::common::Encoding;
+use tangent_shared::{dag::NodeRef, sources::common::SourceConfig, Config, STDIN_CONFIG};
+use tokio::sync::broadcast;
 use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
 use wasmtime::component::Component;
+use wasmtime::Engine;
 
 use crate::{
-    cache::CacheHandle, router::Router, sinks::manager::SinkManager, sources,
-    wasm::engine::WasmEngine, worker::WorkerPool,
+    cache::CacheHandle,
+    router::Router,
+    sinks::{encoding, manager::SinkManager},
+    sources,
+    wasm::{
+        self,
+        config_reload::PluginConfigSource,
+        engine::{WasmEngine, EPOCH_TICK},
+        watch::PluginReload,
+    },
+    worker::{Ack, WorkerPool},
+    RuntimeOptions, CONSUMER_RESTARTS_TOTAL,
 };
 
+/// First backoff after a consumer fails; doubled on each further failure.
+const INITIAL_RESTART_DELAY: Duration = Duration::from_secs(1);
+
 pub struct DagRuntime {
     pub router: Arc<Router>,
     pool: Arc<WorkerPool>,
     sink_manager: Arc<SinkManager>,
     consumer_handles: Vec<tokio::task::JoinHandle<()>>,
+    epoch_ticker: Option<tokio::task::JoinHandle<()>>,
+    plugin_watcher: Option<notify::RecommendedWatcher>,
 }
 
 impl DagRuntime {
     pub async fn build(
         cfg: Config,
         cfg_path: &PathBuf,
+        opts: &RuntimeOptions,
         shutdown: CancellationToken,
     ) -> anyhow::Result<Self> {
-        let sink_manager = Arc::new(SinkManager::new(&cfg.sinks).await?);
-        let config_dir = cfg_path.parent().unwrap_or_else(|| Path::new("."));
+        for (name, plugin) in &cfg.plugins {
+            if plugin.encoding == PluginEncoding::ArrowIpc {
+                encoding::ensure_supported(&Encoding::ArrowIpc { schema: None })
+                    .with_context(|| format!("plugin {name}"))?;
+            }
+        }
+        let sink_manager = Arc::new(SinkManager::new(&cfg, opts.dry_run, &shutdown).await?);
+        // A config read from stdin (`-`) has no directory of its own; its
+        // relative paths resolve against the working directory.
+        let config_dir = cfg_path
+            .parent()
+            .filter(|d| !d.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
         let plugin_root = config_dir.join(&cfg.runtime.plugins_path).canonicalize()?;
 
-        let workers = cfg.runtime.workers;
+        let workers = cfg.effective_workers();
 
         let cache = Arc::new(CacheHandle::open(&cfg.runtime.cache.clone(), config_dir)?);
 
+        // Plugin timeouts need guest calls to yield, which `.cwasm` files
+        // built without epoch interruption never do.
+        let timeouts = cfg.plugins.values().any(|p| p.timeout_ms.is_some());
+        let interruptible = opts.trace_wasm || timeouts;
         let mut engines: Vec<WasmEngine> = (0..workers)
-            .map(|_| WasmEngine::new(cache.clone(), cfg.runtime.disable_remote_calls))
+            .map(|_| {
+                if opts.trace_wasm {
+                    WasmEngine::new_traced(cache.clone(), cfg.runtime.disable_remote_calls)
+                } else if timeouts {
+                    WasmEngine::new_interruptible(cache.clone(), cfg.runtime.disable_remote_calls)
+                } else {
+                    WasmEngine::new(cache.clone(), cfg.runtime.disable_remote_calls)
+                }
+            })
             .collect::<Result<_, _>>()?;
+        if opts.trace_wasm {
+            tracing::warn!(
+                "--trace-wasm enabled; compiling plugins from source with epoch interruption"
+            );
+        } else if timeouts {
+            tracing::info!(
+                "plugin timeout_ms set; compiling plugins from source with epoch interruption"
+            );
+        }
         let mut components: Vec<Vec<(Arc<str>, Component)>> = Vec::with_capacity(workers);
+        let mut plugin_paths: Vec<(Arc<str>, PathBuf)> = Vec::new();
         for i in 0..workers {
             components.push(Vec::<(Arc<str>, Component)>::new());
             for (name, plugin_cfg) in &cfg.plugins {
-                let component_file = format!("{name}.cwasm");
+                // Interruptible engines can't load .cwasm built by the default
+                // engine.
+                let component_file = if interruptible {
+                    format!("{name}.component.wasm")
+                } else {
+                    format!("{name}.cwasm")
+                };
                 let plugin_path = plugin_root
                     .join(&component_file)
                     .canonicalize()
@@ -57,26 +118,71 @@ impl DagRuntime {
                         )
                     })?;
 
-                components[i].push((
-                    Arc::clone(name),
-                    engines[i]
-                        .load_precompiled(Arc::clone(name), &plugin_path, plugin_cfg.config.clone())
-                        .with_context(|| format!("loading {}", &component_file))?,
-                ));
+                let component = if interruptible {
+                    engines[i].load_source(Arc::clone(name), &plugin_path, plugin_cfg)
+                } else {
+                    engines[i].load_precompiled(Arc::clone(name), &plugin_path, plugin_cfg)
+                }
+                .with_context(|| format!("loading {}", &component_file))?;
+                components[i].push((Arc::clone(name), component));
+                if i == 0 {
+                    plugin_paths.push((Arc::clone(name), plugin_path));
+                }
             }
         }
 
-        let mut outs: HashMap<NodeRef, Vec<NodeRef>> = HashMap::default();
-        for e in &cfg.dag {
-            outs.entry(e.from.clone()).or_default().extend(e.to.clone());
-        }
-
-        let router = Arc::new(Router::new(outs, Arc::clone(&sink_manager)));
+        let dead_letters = cfg
+            .plugins
+            .iter()
+            .filter_map(|(name, p)| Some((Arc::clone(name), p.dead_letter.clone()?)))
+            .collect();
+        let router = Arc::new(
+            Router::from_edges(&cfg.dag, Arc::clone(&sink_manager))?
+                .with_dead_letters(dead_letters),
+        );
 
+        let epoch_ticker = interruptible.then(|| {
+            let engines: Vec<Engine> = engines.iter().map(|e| e.engine().clone()).collect();
+            tokio::spawn(async move {
+                let mut tick = tokio::time::interval(EPOCH_TICK);
+                loop {
+                    tick.tick().await;
+                    for e in &engines {
+                        e.increment_epoch();
+                    }
+                }
+            })
+        });
+
+        // A stdin config has no file for `plugin set-config` to sit beside.
+        let plugin_config = cfg
+            .config_reload_interval()
+            .filter(|_| cfg_path.as_os_str() != STDIN_CONFIG)
+            .map(|every| PluginConfigSource::new(cfg_path, every, &cfg.plugins));
+
+        let affinity_key = cfg.worker_affinity_key().map(str::to_string);
+        let affinity_plugins = cfg
+            .plugins
+            .iter()
+            .filter(|(_, p)| p.worker_affinity_key.is_some())
+            .map(|(name, _)| Arc::clone(name))
+            .collect();
         let batch_size = cfg.batch_size_kb();
         let batch_age = cfg.batch_age_ms();
+        let backpressure_timeout = cfg
+            .runtime
+            .backpressure_timeout_ms
+            .map(Duration::from_millis);
         let sources = cfg.sources;
 
+        let reloads = opts
+            .watch_plugins
+            .then(|| broadcast::channel::<PluginReload>(16).0);
+        let plugin_watcher = reloads
+            .as_ref()
+            .map(|tx| wasm::watch::watch(plugin_paths, tx.clone()))
+            .transpose()?;
+
         let pool = Arc::new(
             WorkerPool::new(
                 workers,
@@ -85,31 +191,70 @@ impl DagRuntime {
                 batch_size,
                 batch_age,
                 Arc::clone(&router),
+                reloads.as_ref(),
+                plugin_config.as_ref(),
+                affinity_key.as_deref(),
+                affinity_plugins,
+                backpressure_timeout,
             )
             .await?,
         );
 
-        router.set_pool(&pool);
+        router.set_pool_weak(&pool);
 
         let consumer_handles =
-            spawn_consumers(sources, batch_size, router.clone(), shutdown.clone());
+            spawn_consumers(sources, batch_size, router.clone(), cache, shutdown.clone());
 
         Ok(Self {
             router,
             pool,
             sink_manager,
             consumer_handles,
+            epoch_ticker,
+            plugin_watcher,
         })
     }
 
-    pub async fn shutdown(self, worker_timeout: Duration, sink_timeout: Duration) -> Result<()> {
+    /// Route NDJSON frames into the DAG as if they were emitted by the source
+    /// named `source_name`. Lets embedding applications feed data without a
+    /// configured consumer; `acks` fire once every downstream delivery lands.
+    pub async fn push_from_source(
+        &self,
+        source_name: &str,
+        frames: Vec<BytesMut>,
+        acks: Vec<Arc<dyn Ack>>,
+    ) -> Result<()> {
+        let from = NodeRef::Source {
+            name: Arc::from(source_name),
+        };
+        self.router.forward(&from, frames, acks).await
+    }
+
+    /// Resolves once a `--dry-run` sink has printed its first batch.
+    pub async fn dry_run_written(&self) {
+        self.sink_manager.dry_run_written().await;
+    }
+
+    /// Stop consumers, then workers, then drain the sinks. Cancelling `abort`
+    /// cuts this short: remaining consumers are aborted and sinks are left
+    /// undrained.
+    pub async fn shutdown(
+        self,
+        worker_timeout: Duration,
+        sink_timeout: Duration,
+        abort: &CancellationToken,
+    ) -> Result<()> {
         let Self {
             router,
             pool,
             sink_manager,
             consumer_handles,
+            epoch_ticker,
+            plugin_watcher,
         } = self;
 
+        drop(plugin_watcher);
+
         tracing::info!("waiting on consumers to shutdown...");
         for mut h in consumer_handles {
             let sleep = tokio::time::sleep(Duration::from_secs(30));
@@ -121,6 +266,10 @@ impl DagRuntime {
                     h.abort();
                     let _ = h.await;
                 }
+                () = abort.cancelled() => {
+                    h.abort();
+                    let _ = h.await;
+                }
                 res = &mut h => {
                     if let Err(e) = res {
                         tracing::warn!("consumer task panicked or was cancelled: {e}");
@@ -134,24 +283,41 @@ impl DagRuntime {
         drop(router);
 
         if let Ok(pool_owned) = Arc::try_unwrap(pool) {
-            if let Err(e) = timeout(worker_timeout, pool_owned.join()).await {
-                tracing::warn!(?e, "pool shutdown timeout exceeded. Logs may be dropped.");
+            tokio::select! {
+                res = timeout(worker_timeout, pool_owned.join()) => {
+                    if let Err(e) = res {
+                        tracing::warn!(?e, "pool shutdown timeout exceeded. Logs may be dropped.");
+                    }
+                }
+                () = abort.cancelled() => tracing::warn!("shutdown aborted while workers were busy"),
             }
         } else {
             tracing::warn!("WorkerPool still has refs; cannot consume for join()");
         }
+        if let Some(h) = epoch_ticker {
+            h.abort();
+        }
 
         tracing::info!("waiting on sink manager to shutdown...");
         let sink_owned = Arc::try_unwrap(sink_manager)
             .map_err(|_| anyhow!("SinkManager still has refs; drop all clones before shutdown"))?;
-        match timeout(sink_timeout, sink_owned.join()).await {
-            Err(e) => {
-                tracing::warn!(
-                    ?e,
-                    "sink manager shutdown timeout exceeded. Logs may be dropped."
-                )
+        let drained = tokio::select! {
+            res = sink_owned.drain_timeout(sink_timeout) => Some(res),
+            () = abort.cancelled() => None,
+        };
+        match drained {
+            Some(Ok(stats)) => {
+                tracing::info!(
+                    flushed_bytes = stats.flushed_bytes,
+                    pending_files = stats.pending_files.len(),
+                    "sinks drained"
+                );
+                sink_owned.close().await;
+            }
+            Some(Err(e)) => {
+                tracing::warn!("{e:#}. Logs may be dropped.");
             }
-            Ok(_) => (),
+            None => tracing::warn!("shutdown aborted before sinks drained. Logs may be dropped."),
         }
 
         Ok(())
@@ -162,101 +328,305 @@ fn spawn_consumers(
     sources: BTreeMap<Arc<str>, SourceConfig>,
     batch_size: usize,
     router: Arc<Router>,
+    cache: Arc<CacheHandle>,
     shutdown: CancellationToken,
 ) -> Vec<tokio::task::JoinHandle<()>> {
     let mut handles = Vec::new();
-    for src in sources {
+    for (name, src) in sources {
         let shutdown = shutdown.clone();
-        match src {
-            (name, SourceConfig::MSK(kc)) => {
-                let router = router.clone();
-                handles.push(tokio::spawn(async move {
-                    if let Err(e) =
-                        sources::msk::run_consumer(name, kc, batch_size, router, shutdown.clone())
-                            .await
-                    {
-                        tracing::error!("msk consumer error: {e}");
-                    }
-                }));
+        let max_delay = src.max_restart_delay();
+        let router = router.clone();
+        let restart_name = name.clone();
+        let restart_shutdown = shutdown.clone();
+        let h = match src {
+            SourceConfig::MSK(kc) => {
+                let cache = cache.clone();
+                tokio::spawn(run_with_restart(
+                    restart_name,
+                    max_delay,
+                    restart_shutdown,
+                    move || {
+                        sources::msk::run_consumer(
+                            name.clone(),
+                            kc.clone(),
+                            batch_size,
+                            router.clone(),
+                            cache.clone(),
+                            shutdown.clone(),
+                        )
+                    },
+                ))
             }
-            (name, SourceConfig::File(fc)) => {
-                let router = router.clone();
-                handles.push(tokio::spawn(async move {
-                    if let Err(e) =
-                        sources::file::run_consumer(name, fc, batch_size, router, shutdown.clone())
-                            .await
-                    {
-                        tracing::error!("file consumer error: {e}");
-                    }
-                }));
+            SourceConfig::File(fc) => {
+                let cache = cache.clone();
+                tokio::spawn(run_with_restart(
+                    restart_name,
+                    max_delay,
+                    restart_shutdown,
+                    move || {
+                        sources::file::run_consumer(
+                            name.clone(),
+                            fc.clone(),
+                            batch_size,
+                            router.clone(),
+                            cache.clone(),
+                            shutdown.clone(),
+                        )
+                    },
+                ))
             }
-            (name, SourceConfig::Socket(sc)) => {
-                let router = router.clone();
-                handles.push(tokio::spawn(async move {
-                    if let Err(e) =
-                        sources::socket::run_consumer(name, sc, router, shutdown.clone()).await
-                    {
-                        tracing::error!("socket listener error: {e}");
-                    }
-                }));
+            SourceConfig::Socket(sc) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::socket::run_consumer(
+                        name.clone(),
+                        sc.clone(),
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::Tcp(tc) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::tcp::run_consumer(
+                        name.clone(),
+                        tc.clone(),
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::SQS(sq) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::sqs::run_consumer(
+                        name.clone(),
+                        sq.clone(),
+                        batch_size,
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::GithubWebhook(gw) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::github_webhook::run_consumer(
+                        name.clone(),
+                        gw.clone(),
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::NPMRegistry(np) => {
+                let cache = cache.clone();
+                tokio::spawn(run_with_restart(
+                    restart_name,
+                    max_delay,
+                    restart_shutdown,
+                    move || {
+                        sources::npm_registry::run_consumer(
+                            name.clone(),
+                            np.clone(),
+                            router.clone(),
+                            cache.clone(),
+                            shutdown.clone(),
+                        )
+                    },
+                ))
             }
-            (name, SourceConfig::Tcp(tc)) => {
-                let router = router.clone();
-                handles.push(tokio::spawn(async move {
-                    if let Err(e) =
-                        sources::tcp::run_consumer(name, tc, router, shutdown.clone()).await
-                    {
-                        tracing::error!("tcp listener error: {e}");
-                    }
-                }));
+            SourceConfig::Http(hc) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::http::run_consumer(
+                        name.clone(),
+                        hc.clone(),
+                        batch_size,
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::RedisStreams(rc) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::redis_streams::run_consumer(
+                        name.clone(),
+                        rc.clone(),
+                        batch_size,
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::Grpc(gc) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::grpc::run_consumer(
+                        name.clone(),
+                        gc.clone(),
+                        batch_size,
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::Pulsar(pc) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::pulsar::run_consumer(
+                        name.clone(),
+                        pc.clone(),
+                        batch_size,
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::Kafka(kc) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::kafka::run_consumer(
+                        name.clone(),
+                        kc.clone(),
+                        batch_size,
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::DockerEvents(dc) => tokio::spawn(run_with_restart(
+                restart_name,
+                max_delay,
+                restart_shutdown,
+                move || {
+                    sources::docker_events::run_consumer(
+                        name.clone(),
+                        dc.clone(),
+                        router.clone(),
+                        shutdown.clone(),
+                    )
+                },
+            )),
+            SourceConfig::CloudWatchLogs(cw) => {
+                let cache = cache.clone();
+                tokio::spawn(run_with_restart(
+                    restart_name,
+                    max_delay,
+                    restart_shutdown,
+                    move || {
+                        sources::cloudwatch_logs::run_consumer(
+                            name.clone(),
+                            cw.clone(),
+                            router.clone(),
+                            cache.clone(),
+                            shutdown.clone(),
+                        )
+                    },
+                ))
             }
-            (name, SourceConfig::SQS(sq)) => {
-                let router = router.clone();
-                handles.push(tokio::spawn(async move {
-                    if let Err(e) =
-                        sources::sqs::run_consumer(name, sq, batch_size, router, shutdown.clone())
-                            .await
-                    {
-                        tracing::error!("SQS consumer error: {e}");
-                    }
-                }));
+            SourceConfig::HttpPolling(hc) => {
+                let cache = cache.clone();
+                tokio::spawn(run_with_restart(
+                    restart_name,
+                    max_delay,
+                    restart_shutdown,
+                    move || {
+                        sources::http_polling::run_consumer(
+                            name.clone(),
+                            hc.clone(),
+                            batch_size,
+                            router.clone(),
+                            cache.clone(),
+                            shutdown.clone(),
+                        )
+                    },
+                ))
             }
-            (name, SourceConfig::GithubWebhook(gw)) => {
-                let router = router.clone();
-                handles.push(tokio::spawn(async move {
-                    if let Err(e) =
-                        sources::github_webhook::run_consumer(name, gw, router, shutdown.clone())
-                            .await
-                    {
-                        tracing::error!("Github Webhook consumer error: {e}");
-                    }
-                }));
+        };
+        handles.push(h);
+    }
+
+    handles
+}
+
+/// Run a consumer until it returns `Ok` or shutdown is requested, restarting
+/// it with exponential backoff (capped at `max_delay`) after errors and panics.
+async fn run_with_restart<F, Fut>(
+    name: Arc<str>,
+    max_delay: Duration,
+    shutdown: CancellationToken,
+    mut run: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut delay = INITIAL_RESTART_DELAY.min(max_delay);
+    loop {
+        let reason = match AssertUnwindSafe(run()).catch_unwind().await {
+            Ok(Ok(())) => break,
+            Ok(Err(e)) => {
+                if shutdown.is_cancelled() {
+                    tracing::warn!("consumer {name} error during shutdown: {e}");
+                    break;
+                }
+                tracing::warn!(
+                    "consumer {name} error: {e}, restarting in {}s",
+                    delay.as_secs_f64()
+                );
+                "error"
             }
-            (name, SourceConfig::NPMRegistry(np)) => {
-                let router = router.clone();
-                handles.push(tokio::spawn(async move {
-                    if let Err(e) =
-                        sources::npm_registry::run_consumer(name, np, router, shutdown.clone())
-                            .await
-                    {
-                        tracing::error!("NPM Registry consumer error: {e}");
-                    }
-                }));
+            Err(_) => {
+                if shutdown.is_cancelled() {
+                    break;
+                }
+                tracing::error!(
+                    "consumer {name} panicked, restarting in {}s",
+                    delay.as_secs_f64()
+                );
+                "panic"
             }
+        };
+        CONSUMER_RESTARTS_TOTAL
+            .with_label_values(&[&*name, reason])
+            .inc();
+
+        tokio::select! {
+            () = tokio::time::sleep(delay) => {}
+            () = shutdown.cancelled() => break,
         }
+        delay = (delay * 2).min(max_delay);
     }
-
-    handles
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::sinks::manager::{Sink, SinkManager, SinkWrite};
-    use crate::worker::Ack;
+    use crate::worker::Record;
+    use ahash::AHashMap as HashMap;
     use anyhow::Result;
     use async_trait::async_trait;
-    use bytes::BytesMut;
     use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
     use tokio::sync::{Mutex, Notify};
 
@@ -339,6 +709,8 @@ mod tests {
             pool: worker_pool,
             sink_manager: Arc::clone(&sink_manager),
             consumer_handles: vec![],
+            epoch_ticker: None,
+            plugin_watcher: None,
         };
 
         let ack = Arc::new(CountingAck::default());
@@ -355,7 +727,12 @@ mod tests {
 
         drop(sink_manager);
 
-        let shutdown = runtime.shutdown(Duration::from_millis(200), Duration::from_millis(200));
+        let abort = CancellationToken::new();
+        let shutdown = runtime.shutdown(
+            Duration::from_millis(200),
+            Duration::from_millis(200),
+            &abort,
+        );
         tokio::pin!(shutdown);
 
         assert!(
@@ -371,4 +748,240 @@ mod tests {
         assert!(blocking_sink.flush_completed());
         assert_eq!(ack.count(), 1);
     }
+
+    #[tokio::test]
+    async fn edge_filters_route_matching_events_only() {
+        let payments_sink = BlockingSink::new();
+        let all_sink = BlockingSink::new();
+        let sink_manager = Arc::new(SinkManager::for_test(
+            vec![
+                (
+                    Arc::from("payments"),
+                    payments_sink.clone() as Arc<dyn Sink>,
+                ),
+                (Arc::from("all"), all_sink.clone() as Arc<dyn Sink>),
+            ],
+            2,
+        ));
+
+        let dag: Vec<tangent_shared::dag::Edge> = serde_yaml::from_str(
+            r#"
+- from: { kind: source, name: input }
+  to: [{ kind: sink, name: payments }]
+  filter: { op: eq, path: source.name, value: payments }
+- from: { kind: source, name: input }
+  to: [{ kind: sink, name: all }]
+- from: { kind: source, name: input }
+  to: [{ kind: sink, name: payments }]
+  filter: { op: exists, path: never_set }
+"#,
+        )
+        .unwrap();
+        let router = Router::from_edges(&dag, Arc::clone(&sink_manager)).unwrap();
+
+        let ack = Arc::new(CountingAck::default());
+        let frame = BytesMut::from(
+            "{\"source\":{\"name\":\"payments\"},\"n\":1}\n{\"source\":{\"name\":\"auth\"},\"n\":2}\n",
+        );
+        let from = NodeRef::Source {
+            name: Arc::from("input"),
+        };
+        router
+            .forward(&from, vec![frame], vec![ack.clone() as Arc<dyn Ack>])
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while ack.count() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("every delivery, filtered or not, acks");
+
+        let payments = payments_sink.writes.lock().await.concat();
+        assert_eq!(payments, b"{\"source\":{\"name\":\"payments\"},\"n\":1}\n");
+        assert_eq!(all_sink.write_count().await, 1);
+        assert_eq!(ack.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn route_by_sends_each_event_to_its_mapped_sink() {
+        let errors_sink = BlockingSink::new();
+        let other_sink = BlockingSink::new();
+        let sink_manager = Arc::new(SinkManager::for_test(
+            vec![
+                (Arc::from("errors"), errors_sink.clone() as Arc<dyn Sink>),
+                (Arc::from("other"), other_sink.clone() as Arc<dyn Sink>),
+            ],
+            2,
+        ));
+
+        let dag: Vec<tangent_shared::dag::Edge> = serde_yaml::from_str(
+            r#"
+- from: { kind: source, name: input }
+  to: [{ kind: sink, name: errors }, { kind: sink, name: other }]
+  route_by: { field: output_type, map: { errors: errors, default: other } }
+"#,
+        )
+        .unwrap();
+        let router = Router::from_edges(&dag, Arc::clone(&sink_manager)).unwrap();
+
+        let ack = Arc::new(CountingAck::default());
+        let frame = BytesMut::from(
+            "{\"output_type\":\"errors\",\"n\":1}\n{\"output_type\":\"metrics\",\"n\":2}\n{\"n\":3}\n",
+        );
+        let from = NodeRef::Source {
+            name: Arc::from("input"),
+        };
+        router
+            .forward(&from, vec![frame], vec![ack.clone() as Arc<dyn Ack>])
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while ack.count() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("both branches ack");
+
+        let errors = errors_sink.writes.lock().await.concat();
+        assert_eq!(errors, b"{\"output_type\":\"errors\",\"n\":1}\n");
+        let other = other_sink.writes.lock().await.concat();
+        assert_eq!(other, b"{\"output_type\":\"metrics\",\"n\":2}\n{\"n\":3}\n");
+    }
+
+    #[tokio::test]
+    async fn fanout_to_two_plugins_acks_upstream_once() {
+        let dag: Vec<tangent_shared::dag::Edge> = serde_yaml::from_str(
+            r#"
+- from: { kind: source, name: input }
+  to: [{ kind: plugin, name: first }, { kind: plugin, name: second }]
+"#,
+        )
+        .unwrap();
+        let sink_manager = Arc::new(SinkManager::for_test(Vec::new(), 1));
+        let router = Router::from_edges(&dag, sink_manager).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let pool = Arc::new(WorkerPool::with_senders_for_test(vec![tx]));
+        router.set_pool(&pool);
+
+        let ack = Arc::new(CountingAck::default());
+        let from = NodeRef::Source {
+            name: Arc::from("input"),
+        };
+        let frames = vec![BytesMut::from("{\"n\":1}\n"), BytesMut::from("{\"n\":2}\n")];
+        router
+            .forward(&from, frames, vec![ack.clone() as Arc<dyn Ack>])
+            .await
+            .unwrap();
+
+        let mut branch_acks = Vec::new();
+        while let Ok(Record::Inline { ack, .. }) = rx.try_recv() {
+            branch_acks.push(ack.expect("fanout records carry an ack"));
+        }
+        assert_eq!(branch_acks.len(), 4, "every frame reaches both plugins");
+
+        let last = branch_acks.pop().unwrap();
+        for a in &branch_acks {
+            a.ack().await.unwrap();
+        }
+        assert_eq!(ack.count(), 0, "upstream waits for every branch");
+        last.ack().await.unwrap();
+        assert_eq!(ack.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn router_counts_forwarded_and_dropped_events() {
+        use crate::{ROUTER_EVENTS_DROPPED_TOTAL, ROUTER_EVENTS_FORWARDED_TOTAL};
+
+        let errors_sink = BlockingSink::new();
+        let sink_manager = Arc::new(SinkManager::for_test(
+            vec![(
+                Arc::from("counted-errors"),
+                errors_sink.clone() as Arc<dyn Sink>,
+            )],
+            1,
+        ));
+        let dag: Vec<tangent_shared::dag::Edge> = serde_yaml::from_str(
+            r#"
+- from: { kind: source, name: counted }
+  to: [{ kind: sink, name: counted-errors }]
+  filter: { op: eq, path: level, value: error }
+"#,
+        )
+        .unwrap();
+        let router = Router::from_edges(&dag, Arc::clone(&sink_manager)).unwrap();
+
+        let ack = Arc::new(CountingAck::default());
+        let frame = BytesMut::from(
+            "{\"level\":\"error\",\"n\":1}\n{\"level\":\"info\",\"n\":2}\n{\"level\":\"error\",\"n\":3}\n",
+        );
+        let counted = NodeRef::Source {
+            name: Arc::from("counted"),
+        };
+        router
+            .forward(&counted, vec![frame], vec![ack.clone() as Arc<dyn Ack>])
+            .await
+            .unwrap();
+
+        // A node with no outgoing edges, e.g. a misspelled name in the DAG.
+        let orphan = NodeRef::Source {
+            name: Arc::from("counted-orphan"),
+        };
+        router
+            .forward(&orphan, vec![BytesMut::from("{\"n\":4}\n")], Vec::new())
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while ack.count() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("sink write acks");
+
+        let forwarded = ROUTER_EVENTS_FORWARDED_TOTAL
+            .with_label_values(&["counted", "counted-errors"])
+            .get();
+        assert_eq!(forwarded, 2);
+        let dropped = |from| ROUTER_EVENTS_DROPPED_TOTAL.with_label_values(&[from]).get();
+        assert_eq!(dropped("counted"), 1);
+        assert_eq!(dropped("counted-orphan"), 1);
+    }
+
+    #[tokio::test]
+    async fn consumer_is_restarted_until_it_succeeds() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = attempts.clone();
+        run_with_restart(
+            Arc::from("restart-test"),
+            Duration::ZERO,
+            CancellationToken::new(),
+            move || {
+                let n = counted.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match n {
+                        0 => Err(anyhow!("transient")),
+                        1 => panic!("boom"),
+                        _ => Ok(()),
+                    }
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        let restarts = |reason| {
+            CONSUMER_RESTARTS_TOTAL
+                .with_label_values(&["restart-test", reason])
+                .get()
+        };
+        assert_eq!(restarts("error"), 1);
+        assert_eq!(restarts("panic"), 1);
+    }
 }
@@ -1,15 +1,18 @@
-use anyhow::{bail, Result};
-use std::{net::SocketAddr, path::PathBuf};
+use anyhow::{bail, Context, Result};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
 use tokio::time::Duration;
 use tokio_util::sync::CancellationToken;
 use tracing::info;
 
 use prometheus::{
-    register_histogram_vec, register_int_counter, register_int_gauge, HistogramVec, IntCounter,
-    IntGauge,
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 
-use tangent_shared::Config;
+use tangent_shared::{Config, STDIN_CONFIG};
 
 use crate::dag::DagRuntime;
 
@@ -26,6 +29,18 @@ pub mod worker;
 pub struct RuntimeOptions {
     pub prometheus_bind: Option<SocketAddr>,
     pub once: bool,
+    /// Print sink batches to stdout instead of writing them, and exit after
+    /// the first one.
+    pub dry_run: bool,
+    /// Compile plugins with epoch interruption so hung guest calls trap and
+    /// log a wasm backtrace. Slower; for debugging only.
+    pub trace_wasm: bool,
+    /// Reload a plugin on every worker when its compiled component changes
+    /// on disk.
+    pub watch_plugins: bool,
+    pub log_format: LogFormat,
+    /// Write logs to this file, rotated daily, instead of stderr.
+    pub log_file: Option<PathBuf>,
 }
 
 impl Default for RuntimeOptions {
@@ -33,8 +48,60 @@ impl Default for RuntimeOptions {
         Self {
             prometheus_bind: Some("0.0.0.0:9184".parse().unwrap()),
             once: false,
+            dry_run: false,
+            trace_wasm: false,
+            watch_plugins: false,
+            log_format: LogFormat::Text,
+            log_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line with timestamp, level, target, spans and
+    /// event fields.
+    Json,
+}
+
+/// Install the global tracing subscriber described by `opts`. Logs are
+/// written on a background thread; keep the guard alive until exit so
+/// buffered lines are flushed.
+pub fn init_logging(opts: &RuntimeOptions) -> Result<tracing_appender::non_blocking::WorkerGuard> {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    let (writer, guard) = match &opts.log_file {
+        Some(path) => {
+            let prefix = path
+                .file_name()
+                .with_context(|| format!("--log-file {} has no file name", path.display()))?;
+            let dir = path
+                .parent()
+                .filter(|d| !d.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("creating log directory {}", dir.display()))?;
+            tracing_appender::non_blocking(tracing_appender::rolling::daily(dir, prefix))
         }
+        None => tracing_appender::non_blocking(std::io::stderr()),
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_env_filter(filter)
+        .with_ansi(opts.log_file.is_none());
+    match opts.log_format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
     }
+    Ok(guard)
 }
 
 lazy_static::lazy_static! {
@@ -54,6 +121,12 @@ lazy_static::lazy_static! {
     pub static ref CONSUMER_OBJECTS_TOTAL: IntCounter =
         register_int_counter!("tangent_consumer_objects_total", "Objects consumed (raw input)").unwrap();
 
+    pub static ref WORKER_BACKPRESSURE_TOTAL: IntCounter =
+        register_int_counter!("tangent_worker_backpressure_total", "Dispatches that found every worker queue full").unwrap();
+
+    pub static ref WORKER_DROPS_TOTAL: IntCounter =
+        register_int_counter!("tangent_worker_drops_total", "Batches dropped after waiting backpressure_timeout_ms for a worker").unwrap();
+
     pub static ref WAL_SEALED_BYTES_TOTAL: IntCounter =
         register_int_counter!("tangent_wal_sealed_bytes_total", "Bytes sealed to WAL files").unwrap();
 
@@ -77,10 +150,135 @@ lazy_static::lazy_static! {
 
     pub static ref WAL_PENDING_BYTES: IntGauge =
         register_int_gauge!("tangent_wal_pending_bytes", "Approx bytes pending in sealed WAL files").unwrap();
+
+    pub static ref WAL_OLDEST_SEALED_AGE_SECONDS: IntGauge =
+        register_int_gauge!("tangent_wal_oldest_sealed_file_age_seconds", "Age of the oldest sealed WAL file awaiting upload (sec)").unwrap();
+
+    pub static ref WAL_OLDEST_FILE_AGE_SECONDS: IntGauge =
+        register_int_gauge!("tangent_wal_oldest_file_age_seconds", "Age of the oldest open or sealed WAL file (sec)").unwrap();
+
+    pub static ref WAL_QUOTA_WAITS_TOTAL: IntCounter =
+        register_int_counter!("tangent_wal_quota_waits_total", "Writes held back because the WAL directory reached max_wal_bytes").unwrap();
+
+    pub static ref WAL_RECOVERED_FILES_TOTAL: IntCounter =
+        register_int_counter!("tangent_wal_recovered_files_total", "Sealed WAL files left by a previous run and retried at startup").unwrap();
+
+    pub static ref WAL_CORRUPT_FILES_TOTAL: IntCounter =
+        register_int_counter!("tangent_wal_corrupt_files_total", "Sealed WAL files discarded because they no longer matched their checksum").unwrap();
+
+    pub static ref WAL_RECOVERED_BYTES_TOTAL: IntCounter =
+        register_int_counter!("tangent_wal_recovered_bytes_total", "Bytes in sealed WAL files retried at startup").unwrap();
+
+    pub static ref PLUGIN_REMOTE_CALLS_INFLIGHT: IntGaugeVec = register_int_gauge_vec!(
+        "tangent_plugin_remote_calls_inflight",
+        "Plugin remote calls currently in flight",
+        &["plugin"]
+    ).unwrap();
+
+    pub static ref KAFKA_CONSUMER_LAG: IntGaugeVec = register_int_gauge_vec!(
+        "tangent_kafka_consumer_lag",
+        "Messages between the committed offset and the high watermark",
+        &["topic", "partition"]
+    ).unwrap();
+
+    pub static ref CONSUMER_RESTARTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_consumer_restarts_total",
+        "Source consumer restarts after an error or panic",
+        &["source", "reason"]
+    ).unwrap();
+
+    pub static ref HTTP_POLLING_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_http_polling_requests_total",
+        "HTTP polling source requests by response status",
+        &["source", "status"]
+    ).unwrap();
+
+    pub static ref CLOUDWATCH_LOGS_EVENTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_cloudwatch_logs_events_total",
+        "Log events read from CloudWatch Logs",
+        &["source"]
+    ).unwrap();
+
+    pub static ref KAFKA_REBALANCE_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_kafka_rebalance_total",
+        "Kafka partition assignment changes",
+        &["source"]
+    ).unwrap();
+
+    pub static ref PLUGIN_RELOADS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_plugin_reloads_total",
+        "Plugin hot reloads applied, counted per worker",
+        &["plugin"]
+    ).unwrap();
+
+    pub static ref PLUGIN_CONFIG_RELOAD_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_plugin_config_reload_total",
+        "Plugin config changes applied from the set-config sidecar, counted per worker",
+        &["plugin"]
+    ).unwrap();
+
+    pub static ref PLUGIN_RELOAD_ERRORS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_plugin_reload_errors_total",
+        "Plugin hot reloads that failed and kept the previous component, counted per worker",
+        &["plugin"]
+    ).unwrap();
+
+    pub static ref PLUGIN_TIMEOUT_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_plugin_timeout_total",
+        "Guest calls abandoned after exceeding the plugin's timeout_ms",
+        &["plugin"]
+    ).unwrap();
+
+    pub static ref ROUTER_EVENTS_FORWARDED_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_router_events_forwarded_total",
+        "Events the router delivered along a DAG edge",
+        &["from", "to"]
+    ).unwrap();
+
+    pub static ref ROUTER_EVENTS_DROPPED_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_router_events_dropped_total",
+        "Events the router delivered nowhere: no outgoing edges, or no edge filter matched",
+        &["from"]
+    ).unwrap();
+
+    pub static ref SINK_CIRCUIT_STATE: IntGaugeVec = register_int_gauge_vec!(
+        "tangent_sink_circuit_state",
+        "Sink circuit breaker state (0=closed, 1=half-open, 2=open)",
+        &["sink"]
+    ).unwrap();
+
+    pub static ref DEAD_LETTER_BYTES_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_dead_letter_bytes_total",
+        "Bytes of failed events sent to a plugin's dead_letter sink",
+        &["plugin"]
+    ).unwrap();
+
+    pub static ref DEAD_LETTER_OBJECTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "tangent_dead_letter_objects_total",
+        "Failed events sent to a plugin's dead_letter sink",
+        &["plugin"]
+    ).unwrap();
 }
 
+/// Run the pipeline described by the config at `config_path`, or on stdin
+/// when it is `-`.
 pub async fn run(config_path: &PathBuf, opts: RuntimeOptions) -> Result<()> {
-    let cfg = Config::from_file(config_path)?;
+    run_until(config_path, opts, CancellationToken::new()).await
+}
+
+/// `run`, which also shuts down when `stop` is cancelled. Cancelling it
+/// during shutdown aborts what is still running; either way every task has
+/// stopped by the time this returns.
+pub async fn run_until(
+    config_path: &PathBuf,
+    opts: RuntimeOptions,
+    stop: CancellationToken,
+) -> Result<()> {
+    let cfg = if config_path.as_os_str() == STDIN_CONFIG {
+        Config::from_reader(std::io::stdin().lock())?
+    } else {
+        Config::from_file(config_path)?
+    };
 
     let _exporter_guard = opts
         .prometheus_bind
@@ -109,13 +307,22 @@ pub async fn run(config_path: &PathBuf, opts: RuntimeOptions) -> Result<()> {
         cfg.batch_age_ms()
     );
 
-    let dag_runtime = DagRuntime::build(cfg, &config_path, ingest_shutdown.clone()).await?;
+    let dag_runtime = DagRuntime::build(cfg, &config_path, &opts, ingest_shutdown.clone()).await?;
 
     #[cfg(feature = "alloc-prof")]
     jemalloc_dump("warm");
 
-    if !opts.once {
-        wait_for_shutdown_signal().await?;
+    if opts.dry_run {
+        tokio::select! {
+            () = dag_runtime.dry_run_written() => info!("dry run: first batch printed"),
+            res = wait_for_shutdown_signal() => res?,
+            () = stop.cancelled() => {}
+        }
+    } else if !opts.once {
+        tokio::select! {
+            res = wait_for_shutdown_signal() => res?,
+            () = stop.cancelled() => {}
+        }
     }
 
     #[cfg(feature = "alloc-prof")]
@@ -125,7 +332,7 @@ pub async fn run(config_path: &PathBuf, opts: RuntimeOptions) -> Result<()> {
     ingest_shutdown.cancel();
 
     dag_runtime
-        .shutdown(Duration::from_secs(120), Duration::from_secs(120))
+        .shutdown(Duration::from_secs(120), Duration::from_secs(120), &stop)
         .await?;
 
     Ok(())
@@ -1,8 +1,7 @@
 use anyhow::Result;
 use clap::Parser;
 use std::path::PathBuf;
-use tangent_runtime::{run, RuntimeOptions};
-use tracing_subscriber::EnvFilter;
+use tangent_runtime::{init_logging, run, LogFormat, RuntimeOptions};
 
 #[derive(Parser, Debug)]
 #[command(version, about = "Run log processor")]
@@ -10,18 +9,24 @@ struct Args {
     /// Path to YAML config
     #[arg(long)]
     config: PathBuf,
+    /// Log output format
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Write logs to this file (rotated daily) instead of stderr
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
 }
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    let (nb, _guard) = tracing_appender::non_blocking(std::io::stderr());
-    tracing_subscriber::fmt()
-        .with_writer(nb)
-        .with_env_filter(filter)
-        .init();
-
     let args = Args::parse();
 
-    run(&args.config, RuntimeOptions::default()).await
+    let opts = RuntimeOptions {
+        log_format: args.log_format,
+        log_file: args.log_file,
+        ..Default::default()
+    };
+    let _guard = init_logging(&opts)?;
+
+    run(&args.config, opts).await
 }
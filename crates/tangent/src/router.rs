@@ -1,27 +1,39 @@
 use ahash::AHashMap as HashMap;
 use anyhow::Result;
 use async_trait::async_trait;
-use bytes::BytesMut;
+use bytes::{BufMut, Bytes, BytesMut};
+use prometheus::IntCounter;
+use std::pin::Pin;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc, Weak,
 };
-use tangent_shared::dag::NodeRef;
+use std::time::Duration;
+use tangent_shared::dag::{Edge, NodeRef, RouteBy};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::sync::OnceCell;
 
 use crate::{
     sinks::manager::SinkManager,
-    worker::{Ack, Record, WorkerPool},
+    wasm::{
+        host::{tangent::logs::log::Scalar, JsonLogView},
+        probe::{compile_edge_filter, eval_edge_filter, CompiledEdgeFilter},
+    },
+    worker::{Ack, Record, StreamAck, WorkerPool},
+    DEAD_LETTER_BYTES_TOTAL, DEAD_LETTER_OBJECTS_TOTAL, ROUTER_EVENTS_DROPPED_TOTAL,
+    ROUTER_EVENTS_FORWARDED_TOTAL,
 };
 
+/// Fires every upstream ack once all `n` downstream deliveries have acked,
+/// so a batch fanned out to several branches is acked exactly once.
 #[derive(Clone)]
-struct RefCountAck {
+pub(crate) struct FanoutAck {
     remaining: Arc<AtomicUsize>,
     inners: Arc<Vec<Arc<dyn Ack>>>,
 }
 
-impl RefCountAck {
-    fn new(inner: Vec<Arc<dyn Ack>>, n: usize) -> Self {
+impl FanoutAck {
+    pub(crate) fn new(inner: Vec<Arc<dyn Ack>>, n: usize) -> Self {
         Self {
             remaining: Arc::new(AtomicUsize::new(n)),
             inners: Arc::new(inner),
@@ -30,7 +42,7 @@ impl RefCountAck {
 }
 
 #[async_trait]
-impl Ack for RefCountAck {
+impl Ack for FanoutAck {
     async fn ack(&self) -> Result<()> {
         if self.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
             for a in self.inners.iter() {
@@ -41,37 +53,246 @@ impl Ack for RefCountAck {
     }
 }
 
+/// A downstream node, with the filter and routing of the edge that leads
+/// to it.
+#[derive(Clone)]
+struct Out {
+    to: NodeRef,
+    filter: Option<Arc<CompiledEdgeFilter>>,
+    route: Option<Arc<RouteBy>>,
+    /// The edge's `flush_interval_ms`, passed on to sinks.
+    flush_interval: Option<Duration>,
+    /// `tangent_router_events_forwarded_total` for this edge.
+    forwarded: IntCounter,
+}
+
+impl Out {
+    fn new(
+        from: &NodeRef,
+        to: NodeRef,
+        filter: Option<Arc<CompiledEdgeFilter>>,
+        route: Option<Arc<RouteBy>>,
+        flush_interval: Option<Duration>,
+    ) -> Self {
+        let forwarded = ROUTER_EVENTS_FORWARDED_TOTAL
+            .with_label_values(&[from.name().as_ref(), to.name().as_ref()]);
+        Self {
+            to,
+            filter,
+            route,
+            flush_interval,
+            forwarded,
+        }
+    }
+
+    /// Whether every event takes this edge.
+    fn takes_all(&self) -> bool {
+        self.filter.is_none() && self.route.is_none()
+    }
+}
+
 pub struct Router {
-    outs: HashMap<NodeRef, Vec<NodeRef>>,
+    outs: HashMap<NodeRef, Vec<Out>>,
+    /// Plugin name -> sink for events the plugin failed on.
+    dead_letters: HashMap<Arc<str>, Arc<str>>,
     pool: OnceCell<Weak<WorkerPool>>,
     sink_manager: Arc<SinkManager>,
 }
 
 impl Router {
     pub fn new(outs: HashMap<NodeRef, Vec<NodeRef>>, sink_manager: Arc<SinkManager>) -> Self {
+        let outs = outs
+            .into_iter()
+            .map(|(from, tos)| {
+                let tos = tos
+                    .into_iter()
+                    .map(|to| Out::new(&from, to, None, None, None))
+                    .collect();
+                (from, tos)
+            })
+            .collect();
         Self {
             outs,
+            dead_letters: HashMap::default(),
             pool: OnceCell::new(),
             sink_manager,
         }
     }
 
-    pub fn set_pool(&self, pool: &Arc<WorkerPool>) {
+    /// Router for the DAG `edges`, compiling each edge's `filter` and
+    /// `route_by`.
+    pub fn from_edges(edges: &[Edge], sink_manager: Arc<SinkManager>) -> Result<Self> {
+        let mut outs: HashMap<NodeRef, Vec<Out>> = HashMap::default();
+        for e in edges {
+            let filter = e
+                .filter
+                .as_ref()
+                .map(compile_edge_filter)
+                .transpose()
+                .map_err(|err| anyhow::anyhow!("filter on edge from {:?}: {err}", e.from))?
+                .map(Arc::new);
+            let route = e.route_by.clone().map(Arc::new);
+            let flush_interval = e.flush_interval_ms.map(Duration::from_millis);
+            outs.entry(e.from.clone())
+                .or_default()
+                .extend(e.to.iter().map(|to| {
+                    Out::new(
+                        &e.from,
+                        to.clone(),
+                        filter.clone(),
+                        route.clone(),
+                        flush_interval,
+                    )
+                }));
+        }
+        Ok(Self {
+            outs,
+            dead_letters: HashMap::default(),
+            pool: OnceCell::new(),
+            sink_manager,
+        })
+    }
+
+    /// Route events each plugin fails on to the named sink, in addition to
+    /// the DAG edges.
+    #[must_use]
+    pub fn with_dead_letters(mut self, dead_letters: HashMap<Arc<str>, Arc<str>>) -> Self {
+        self.dead_letters = dead_letters;
+        self
+    }
+
+    /// Whether any plugin has a dead-letter sink, so callers know to keep
+    /// the original event bytes around.
+    pub fn has_dead_letters(&self) -> bool {
+        !self.dead_letters.is_empty()
+    }
+
+    /// Send the original `events` that `plugin` returned `error` for to its
+    /// dead-letter sink, each with an added `__tangent_error` field. A no-op
+    /// for plugins without one.
+    pub async fn dead_letter(&self, plugin: &str, events: Vec<Bytes>, error: &str) -> Result<()> {
+        let Some(sink) = self.dead_letters.get(plugin) else {
+            return Ok(());
+        };
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut frame = BytesMut::new();
+        for ev in &events {
+            tag_with_error(&mut frame, ev, error);
+        }
+        DEAD_LETTER_OBJECTS_TOTAL
+            .with_label_values(&[plugin])
+            .inc_by(events.len() as u64);
+        DEAD_LETTER_BYTES_TOTAL
+            .with_label_values(&[plugin])
+            .inc_by(frame.len() as u64);
+
+        self.sink_manager
+            .enqueue(Arc::clone(sink), None, frame, Vec::new())
+            .await
+    }
+
+    /// Register the worker pool for plugin edges. Only a `Weak` is kept so the
+    /// router never keeps the pool alive past `DagRuntime::shutdown`.
+    pub fn set_pool_weak(&self, pool: &Arc<WorkerPool>) {
         let _ = self.pool.set(Arc::downgrade(pool));
     }
+
+    pub fn set_pool(&self, pool: &Arc<WorkerPool>) {
+        self.set_pool_weak(pool);
+    }
+
     #[inline]
     fn pool(&self) -> Option<Arc<WorkerPool>> {
         self.pool.get().and_then(|w| w.upgrade())
     }
 
+    /// True once a pool was registered and has since been dropped.
+    #[inline]
+    fn pool_shut_down(&self) -> bool {
+        self.pool.get().is_some_and(|w| w.strong_count() == 0)
+    }
+
     pub async fn forward(
         &self,
         from: &NodeRef,
-        mut frames: Vec<BytesMut>,
+        frames: Vec<BytesMut>,
+        acks: Vec<Arc<dyn Ack>>,
+    ) -> Result<()> {
+        self.route(from, frames.into_iter().map(|f| (None, f)), acks)
+            .await
+    }
+
+    /// Like `forward`, but each frame may carry its own key prefix. A `Some`
+    /// prefix replaces the sink edge's `key_prefix` for that frame; plugin
+    /// edges ignore it.
+    pub async fn forward_with_key_prefix(
+        &self,
+        from: &NodeRef,
+        frames: Vec<(Option<Arc<str>>, BytesMut)>,
         acks: Vec<Arc<dyn Ack>>,
     ) -> Result<()> {
+        self.route(from, frames, acks).await
+    }
+
+    /// Forward the NDJSON read from `reader` without holding all of it in
+    /// memory. A lone unfiltered plugin edge gets the reader itself as a
+    /// `Record::Streaming`, split into lines by the worker pool; any other
+    /// edges get it in frames of about `frame_bytes`. `ack` fires once every
+    /// line has been delivered, and never if reading fails.
+    pub async fn forward_stream(
+        &self,
+        from: &NodeRef,
+        reader: Pin<Box<dyn AsyncRead + Send>>,
+        ack: Arc<dyn Ack>,
+        frame_bytes: usize,
+    ) -> Result<()> {
+        if let (Some([out]), Some(pool)) = (self.outs.get(from).map(Vec::as_slice), self.pool()) {
+            if let (NodeRef::Plugin { name }, true) = (&out.to, out.takes_all()) {
+                let rec = Record::Streaming {
+                    reader,
+                    ack: Some(ack),
+                    source_name: Arc::clone(from.name()),
+                };
+                return pool.dispatch(name, rec).await;
+            }
+        }
+
+        let stream_ack = StreamAck::new(ack);
+        let mut reader = BufReader::new(reader);
+        let mut frame = BytesMut::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let n = reader.read_until(b'\n', &mut line).await?;
+            if !line.iter().all(u8::is_ascii_whitespace) {
+                frame.extend_from_slice(&line);
+                if !line.ends_with(b"\n") {
+                    frame.put_u8(b'\n');
+                }
+            }
+            if !frame.is_empty() && (n == 0 || frame.len() >= frame_bytes) {
+                self.forward(from, vec![frame.split()], vec![stream_ack.part()])
+                    .await?;
+            }
+            if n == 0 {
+                break;
+            }
+        }
+        stream_ack.ack().await
+    }
+
+    async fn route<I>(&self, from: &NodeRef, frames: I, acks: Vec<Arc<dyn Ack>>) -> Result<()>
+    where
+        I: IntoIterator<Item = (Option<Arc<str>>, BytesMut)>,
+        I::IntoIter: ExactSizeIterator + Send,
+    {
+        let frames = frames.into_iter();
         let Some(tos) = self.outs.get(from) else {
             tracing::warn!("no output from node: {:?}", from);
+            count_dropped(from, frames.map(|(_, f)| event_count(&f)).sum());
             for a in acks {
                 let _ = a.ack().await;
             }
@@ -87,36 +308,60 @@ impl Router {
         }
 
         let pool = self.pool();
-        if tos.iter().any(|to| matches!(to, NodeRef::Plugin { .. })) && pool.is_none() {
+        if tos.iter().any(|o| matches!(o.to, NodeRef::Plugin { .. })) && pool.is_none() {
+            if self.pool_shut_down() {
+                tracing::debug!(
+                    "worker pool shut down; dropping {} frames from {:?}",
+                    frames.len(),
+                    from
+                );
+                count_dropped(from, frames.map(|(_, f)| event_count(&f)).sum());
+                return Ok(());
+            }
             anyhow::bail!(
                 "router called before pool set (from={:?}, tos={:?}, frames={})",
                 from,
-                tos,
+                tos.iter().map(|o| &o.to).collect::<Vec<_>>(),
                 frames.len(),
             );
         }
 
-        let shared = Arc::new(RefCountAck::new(acks, deliveries));
+        let shared = Arc::new(FanoutAck::new(acks, deliveries));
 
         if tos.len() == 1 {
-            let to = &tos[0];
-            for frame in frames.drain(..) {
-                match to {
-                    NodeRef::Plugin { .. } => {
+            let out = &tos[0];
+            for (prefix, frame) in frames {
+                let frame = if out.takes_all() {
+                    frame
+                } else {
+                    let (mut kept, n) = select_frames(tos, &frame);
+                    count_dropped(from, n);
+                    match kept.pop().flatten() {
+                        Some(kept) => kept,
+                        None => {
+                            let _ = shared.ack().await;
+                            continue;
+                        }
+                    }
+                };
+                out.forwarded.inc_by(event_count(&frame));
+                match &out.to {
+                    NodeRef::Plugin { name } => {
                         let pool = pool.as_ref().expect("pool must be set for plugin edges");
-                        let rec = Record {
+                        let rec = Record::Inline {
                             payload: frame,
                             ack: Some(shared.clone()),
                         };
-                        pool.dispatch(rec).await?;
+                        pool.dispatch(name, rec).await?;
                     }
                     NodeRef::Sink { name, key_prefix } => {
                         self.sink_manager
-                            .enqueue(
+                            .enqueue_with_flush_interval(
                                 name.clone(),
-                                key_prefix.clone(),
+                                prefix.or_else(|| key_prefix.clone()),
                                 frame,
                                 vec![shared.clone()],
+                                out.flush_interval,
                             )
                             .await?;
                     }
@@ -128,36 +373,299 @@ impl Router {
             return Ok(());
         }
 
-        for frame in frames.drain(..) {
-            for to in tos {
-                match to {
-                    NodeRef::Plugin { .. } => {
-                        if let Some(ref pool) = pool {
-                            let rec = Record {
-                                payload: frame.clone(),
-                                ack: Some(shared.clone()),
-                            };
-                            pool.dispatch(rec).await?;
-                        } else {
-                            let _ = shared.ack().await;
-                        }
-                    }
-                    NodeRef::Sink { name, key_prefix } => {
-                        self.sink_manager
-                            .enqueue(
-                                name.clone(),
-                                key_prefix.clone(),
-                                frame.clone(),
-                                vec![shared.clone()],
-                            )
-                            .await?;
-                    }
-                    NodeRef::Source { .. } => {
-                        let _ = shared.ack().await;
-                    }
+        // Split every frame between the branches up front, so each event is
+        // parsed once however many edges filter it.
+        let mut per_out: Vec<Vec<(Option<Arc<str>>, Option<BytesMut>)>> = tos
+            .iter()
+            .map(|_| Vec::with_capacity(frames.len()))
+            .collect();
+        for (prefix, frame) in frames {
+            let (kept, n) = select_frames(tos, &frame);
+            count_dropped(from, n);
+            for (branch, part) in per_out.iter_mut().zip(kept) {
+                branch.push((prefix.clone(), part));
+            }
+        }
+
+        // Several branches: each gets its own task so a slow or backed-up
+        // branch doesn't hold up delivery to the others.
+        let branches: Vec<_> = tos
+            .iter()
+            .zip(per_out)
+            .map(|(out, frames)| {
+                let out = out.clone();
+                let pool = pool.clone();
+                let sink_manager = Arc::clone(&self.sink_manager);
+                let shared = Arc::clone(&shared);
+                tokio::spawn(async move {
+                    send_branch(&out, frames, pool.as_ref(), &sink_manager, &shared).await
+                })
+            })
+            .collect();
+
+        let mut result = Ok(());
+        for branch in branches {
+            let res = branch
+                .await
+                .map_err(|e| anyhow::anyhow!("fanout branch panicked: {e}"))
+                .and_then(|r| r);
+            if result.is_ok() {
+                result = res;
+            }
+        }
+        result
+    }
+}
+
+/// Deliver `frames` down one fanout branch, counting each delivery (or
+/// filtered-out frame) against `shared`.
+async fn send_branch(
+    out: &Out,
+    frames: Vec<(Option<Arc<str>>, Option<BytesMut>)>,
+    pool: Option<&Arc<WorkerPool>>,
+    sink_manager: &SinkManager,
+    shared: &Arc<FanoutAck>,
+) -> Result<()> {
+    for (prefix, frame) in frames {
+        let Some(frame) = frame else {
+            let _ = shared.ack().await;
+            continue;
+        };
+        out.forwarded.inc_by(event_count(&frame));
+        match &out.to {
+            NodeRef::Plugin { name } => {
+                if let Some(pool) = pool {
+                    let rec = Record::Inline {
+                        payload: frame,
+                        ack: Some(shared.clone()),
+                    };
+                    pool.dispatch(name, rec).await?;
+                } else {
+                    let _ = shared.ack().await;
                 }
             }
+            NodeRef::Sink { name, key_prefix } => {
+                sink_manager
+                    .enqueue_with_flush_interval(
+                        name.clone(),
+                        prefix.or_else(|| key_prefix.clone()),
+                        frame,
+                        vec![shared.clone()],
+                        out.flush_interval,
+                    )
+                    .await?;
+            }
+            NodeRef::Source { .. } => {
+                let _ = shared.ack().await;
+            }
         }
-        Ok(())
+    }
+    Ok(())
+}
+
+fn count_dropped(from: &NodeRef, events: u64) {
+    if events > 0 {
+        ROUTER_EVENTS_DROPPED_TOTAL
+            .with_label_values(&[from.name().as_ref()])
+            .inc_by(events);
+    }
+}
+
+/// Events in an NDJSON frame.
+fn event_count(frame: &[u8]) -> u64 {
+    let lines = memchr::memchr_iter(b'\n', frame).count();
+    let unterminated = frame.last().is_some_and(|&b| b != b'\n');
+    (lines + usize::from(unterminated)) as u64
+}
+
+/// The lines of NDJSON `frame` that take the edge to each of `tos`: those
+/// that pass its filter and, with `route_by`, map to its node. `None` for an
+/// edge no line takes. Also returns how many lines took no edge. Lines that
+/// aren't valid JSON never match a filtered edge.
+fn select_frames(tos: &[Out], frame: &BytesMut) -> (Vec<Option<BytesMut>>, u64) {
+    let mut kept: Vec<Option<BytesMut>> = tos
+        .iter()
+        .map(|o| o.takes_all().then(|| frame.clone()))
+        .collect();
+    if kept.iter().all(Option::is_some) {
+        return (kept, 0);
+    }
+    let any_takes_all = kept.iter().any(Option::is_some);
+
+    let mut parts = vec![BytesMut::new(); tos.len()];
+    let mut unmatched = 0;
+    for line in frame[..].split(|&b| b == b'\n').filter(|l| !l.is_empty()) {
+        let view = JsonLogView::from_bytes(BytesMut::from(line)).ok();
+        let mut taken = any_takes_all;
+        for ((out, part), whole) in tos.iter().zip(&mut parts).zip(&kept) {
+            if whole.is_none() && view.as_ref().is_some_and(|v| takes(out, v)) {
+                part.extend_from_slice(line);
+                part.extend_from_slice(b"\n");
+                taken = true;
+            }
+        }
+        unmatched += u64::from(!taken);
+    }
+
+    for (k, part) in kept.iter_mut().zip(parts) {
+        if k.is_none() && !part.is_empty() {
+            *k = Some(part);
+        }
+    }
+    (kept, unmatched)
+}
+
+/// Whether the event in `view` passes `out`'s filter and routing.
+fn takes(out: &Out, view: &JsonLogView) -> bool {
+    out.filter
+        .as_ref()
+        .is_none_or(|f| eval_edge_filter(f, view))
+        && out
+            .route
+            .as_ref()
+            .is_none_or(|r| routes_to(r, view, out.to.name()))
+}
+
+/// Whether `route` sends the event in `view` to the node named `node`.
+fn routes_to(route: &RouteBy, view: &JsonLogView, node: &str) -> bool {
+    let value = view
+        .lookup(&route.field)
+        .and_then(JsonLogView::to_scalar)
+        .and_then(|s| match s {
+            Scalar::Str(s) => Some(s),
+            Scalar::Int(i) => Some(i.to_string()),
+            Scalar::Float(f) => Some(f.to_string()),
+            Scalar::Boolean(b) => Some(b.to_string()),
+            Scalar::Bytes(_) => None,
+        });
+    route
+        .target(value.as_deref())
+        .is_some_and(|t| t.as_ref() == node)
+}
+
+/// Append `raw` to `out` as one NDJSON line with `"__tangent_error": error`
+/// added. Objects get the field spliced in before their closing brace so the
+/// original bytes are otherwise untouched; other JSON values are wrapped.
+pub(crate) fn tag_with_error(out: &mut BytesMut, raw: &[u8], error: &str) {
+    let err = serde_json::to_string(error).unwrap_or_else(|_| "\"\"".to_string());
+    let raw = raw.trim_ascii();
+
+    match raw.strip_suffix(b"}") {
+        Some(body) if raw.starts_with(b"{") => {
+            out.put_slice(body);
+            if !body[1..].trim_ascii().is_empty() {
+                out.put_u8(b',');
+            }
+            out.put_slice(b"\"__tangent_error\":");
+            out.put_slice(err.as_bytes());
+            out.put_u8(b'}');
+        }
+        _ => {
+            out.put_slice(b"{\"__tangent_event\":");
+            out.put_slice(raw);
+            out.put_slice(b",\"__tangent_error\":");
+            out.put_slice(err.as_bytes());
+            out.put_u8(b'}');
+        }
+    }
+    out.put_u8(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_letters_keep_original_bytes() {
+        let mut out = BytesMut::new();
+        tag_with_error(&mut out, b"{\"msg\": \"a\" }\n", "bad \"input\"");
+        tag_with_error(&mut out, b"{}", "e");
+        tag_with_error(&mut out, b"[1]", "e");
+        assert_eq!(
+            &out[..],
+            b"{\"msg\": \"a\" ,\"__tangent_error\":\"bad \\\"input\\\"\"}\n\
+              {\"__tangent_error\":\"e\"}\n\
+              {\"__tangent_event\":[1],\"__tangent_error\":\"e\"}\n"
+        );
+    }
+
+    fn plugin_router() -> Router {
+        let source = NodeRef::Source {
+            name: Arc::from("input"),
+        };
+        let plugin = NodeRef::Plugin {
+            name: Arc::from("mapper"),
+        };
+        let mut outs: HashMap<NodeRef, Vec<NodeRef>> = HashMap::default();
+        outs.insert(source, vec![plugin]);
+
+        Router::new(outs, Arc::new(SinkManager::for_test(Vec::new(), 1)))
+    }
+
+    #[tokio::test]
+    async fn forward_after_pool_dropped_is_noop() {
+        let router = plugin_router();
+        let from = NodeRef::Source {
+            name: Arc::from("input"),
+        };
+
+        let frames = || vec![BytesMut::from("{\"msg\":1}\n")];
+        assert!(router.forward(&from, frames(), Vec::new()).await.is_err());
+
+        let pool = Arc::new(WorkerPool::new_for_test(Vec::new()));
+        router.set_pool_weak(&pool);
+        drop(pool);
+
+        router.forward(&from, frames(), Vec::new()).await.unwrap();
+    }
+
+    struct CountAck(AtomicUsize);
+
+    #[async_trait]
+    impl Ack for CountAck {
+        async fn ack(&self) -> Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_reader_to_lone_plugin() {
+        let router = plugin_router();
+        let from = NodeRef::Source {
+            name: Arc::from("input"),
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let pool = Arc::new(WorkerPool::with_senders_for_test(vec![tx]));
+        router.set_pool(&pool);
+
+        let body = b"{\"a\":1}\n\n{\"a\":2}\n{\"a\":3}".to_vec();
+        let ack = Arc::new(CountAck(AtomicUsize::new(0)));
+        router
+            .forward_stream(
+                &from,
+                Box::pin(std::io::Cursor::new(body)),
+                ack.clone(),
+                1024,
+            )
+            .await
+            .unwrap();
+        drop(pool);
+
+        let mut lines = Vec::new();
+        while let Some(rec) = rx.recv().await {
+            let Record::Inline {
+                payload,
+                ack: Some(a),
+            } = rec
+            else {
+                panic!("expected an acked inline record");
+            };
+            assert_eq!(ack.0.load(Ordering::SeqCst), 0);
+            lines.push(payload);
+            a.ack().await.unwrap();
+        }
+        assert_eq!(lines, ["{\"a\":1}\n", "{\"a\":2}\n", "{\"a\":3}"]);
+        assert_eq!(ack.0.load(Ordering::SeqCst), 1);
     }
 }
@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use azure_identity::ImdsManagedIdentityCredential;
+use azure_storage::StorageCredentials;
+use azure_storage_blobs::blob::{BlobBlockType, BlockList};
+use azure_storage_blobs::prelude::{BlobClient, BlockId, ClientBuilder, ContainerClient};
+use secrecy::ExposeSecret;
+use std::path::Path;
+use std::sync::Arc;
+use tangent_shared::sinks::azure_blob::{AzureBlobConfig, AzureCredentials};
+use tangent_shared::sinks::common::{Compression, Encoding};
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+use crate::sinks::gcs::join_prefix;
+use crate::sinks::s3::{content_headers, object_key_from, S3SinkItem};
+use crate::sinks::wal::WALSink;
+
+/// Files below this size go up in a single Put Blob request.
+const SINGLE_PUT_MAX: u64 = 5 * 1024 * 1024;
+
+pub struct AzureBlobSink {
+    name: Arc<str>,
+    container: ContainerClient,
+    container_name: Arc<str>,
+    key_prefix: Option<Arc<str>>,
+    block_size: usize,
+}
+
+impl AzureBlobSink {
+    pub fn new(name: Arc<str>, cfg: &AzureBlobConfig) -> Result<Self> {
+        let credentials = match &cfg.credentials {
+            AzureCredentials::AccountKey { account_key } => StorageCredentials::access_key(
+                cfg.account_name.clone(),
+                account_key.expose_secret().to_string(),
+            ),
+            AzureCredentials::ManagedIdentity => StorageCredentials::token_credential(Arc::new(
+                ImdsManagedIdentityCredential::default(),
+            )),
+        };
+        let container = ClientBuilder::new(cfg.account_name.clone(), credentials)
+            .container_client(cfg.container_name.clone());
+
+        Ok(Self {
+            name,
+            container,
+            container_name: Arc::from(cfg.container_name.as_str()),
+            key_prefix: cfg.key_prefix.as_deref().map(Arc::from),
+            block_size: 8 * 1024 * 1024,
+        })
+    }
+
+    /// Stage the file as blocks and commit them as one block blob. Staged
+    /// blocks that are never committed are discarded by Azure after a week,
+    /// so a failed upload needs no cleanup.
+    async fn put_blocks(
+        &self,
+        blob: &BlobClient,
+        path: &Path,
+        key: &str,
+        content_type: &'static str,
+        content_encoding: Option<&'static str>,
+    ) -> Result<()> {
+        let mut file = File::open(path)
+            .await
+            .with_context(|| format!("open {}", path.display()))?;
+        let mut blocks: Vec<BlobBlockType> = Vec::new();
+        let mut buf = vec![0u8; self.block_size];
+
+        loop {
+            let mut filled = 0usize;
+            while filled < buf.len() {
+                let n = file.read(&mut buf[filled..]).await?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+            if filled == 0 {
+                break;
+            }
+
+            // Block ids within a blob must all have the same length.
+            let block_id = BlockId::new(format!("{:08}", blocks.len()));
+            blob.put_block(block_id.clone(), buf[..filled].to_vec())
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "put_block failed for sink {} key {} block {}: {e}",
+                        self.name,
+                        key,
+                        blocks.len()
+                    )
+                })?;
+            blocks.push(BlobBlockType::new_uncommitted(block_id));
+        }
+
+        if blocks.is_empty() {
+            anyhow::bail!("no data read for block upload: {}", path.display());
+        }
+
+        let mut commit = blob
+            .put_block_list(BlockList { blocks })
+            .content_type(content_type);
+        if let Some(enc) = content_encoding {
+            commit = commit.content_encoding(enc);
+        }
+        commit
+            .await
+            .map_err(|e| anyhow::anyhow!("put_block_list {}/{}: {e}", self.container_name, key))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WALSink for AzureBlobSink {
+    async fn write_path_with(
+        &self,
+        path: &Path,
+        encoding: &Encoding,
+        compression: &Compression,
+        meta: &S3SinkItem,
+    ) -> Result<()> {
+        let prefix = join_prefix(self.key_prefix.as_deref(), meta.key_prefix.as_deref());
+        let key = object_key_from(path, prefix.as_deref(), encoding, compression);
+        let blob = self.container.blob_client(key.clone());
+
+        let (content_type, content_encoding) = content_headers(path, encoding, compression);
+
+        let size = tokio::fs::metadata(path).await?.len();
+        if size < SINGLE_PUT_MAX {
+            let data = tokio::fs::read(path)
+                .await
+                .with_context(|| format!("reading {}", path.display()))?;
+            let mut put = blob.put_block_blob(data).content_type(content_type);
+            if let Some(enc) = content_encoding {
+                put = put.content_encoding(enc);
+            }
+            put.await.map_err(|e| {
+                anyhow::anyhow!(
+                    "put_block_blob for sink {} {}/{}: {e}",
+                    self.name,
+                    self.container_name,
+                    key
+                )
+            })?;
+        } else {
+            self.put_blocks(&blob, path, &key, content_type, content_encoding)
+                .await?;
+        }
+
+        tracing::info!("upload completed {} to {}", key, self.container_name);
+        Ok(())
+    }
+}
@@ -0,0 +1,507 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, RequestBuilder};
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+use tangent_shared::sinks::clickhouse::ClickHouseConfig;
+use tokio::sync::{OnceCell, Semaphore};
+
+use crate::sinks::manager::{DeadLetter, Sink, SinkWrite};
+use crate::{SINK_BYTES_TOTAL, SINK_OBJECTS_TOTAL};
+
+/// ClickHouse error codes for data that doesn't fit the table, as opposed to
+/// a problem with the server or the request itself.
+const ROW_ERROR_CODES: &[u32] = &[
+    6,   // CANNOT_PARSE_TEXT
+    16,  // NO_SUCH_COLUMN_IN_TABLE
+    26,  // CANNOT_PARSE_QUOTED_STRING
+    27,  // CANNOT_PARSE_INPUT_ASSERTION_FAILED
+    38,  // CANNOT_PARSE_DATE
+    41,  // CANNOT_PARSE_DATETIME
+    53,  // TYPE_MISMATCH
+    69,  // ARGUMENT_OUT_OF_BOUND
+    70,  // CANNOT_CONVERT_TYPE
+    72,  // CANNOT_PARSE_NUMBER
+    117, // INCORRECT_DATA
+    349, // CANNOT_INSERT_NULL_IN_ORDINARY_COLUMN
+];
+
+/// Why ClickHouse refused the rows of one insert.
+#[derive(Debug, Clone, PartialEq)]
+struct Refusal {
+    code: u32,
+    message: String,
+}
+
+pub struct ClickHouseSink {
+    name: Arc<str>,
+    client: Client,
+    url: String,
+    database: String,
+    table: String,
+    user: Option<String>,
+    password: Option<SecretString>,
+    batch_rows: usize,
+    auto_create_table: bool,
+    /// Set once `CREATE TABLE IF NOT EXISTS` has gone through.
+    table_created: OnceCell<()>,
+    /// One permit per request in flight, sized by `in_flight_limit`.
+    in_flight: Semaphore,
+    dead_letter: OnceLock<DeadLetter>,
+}
+
+impl ClickHouseSink {
+    pub fn new(
+        name: Arc<str>,
+        cfg: &ClickHouseConfig,
+        in_flight_limit: usize,
+    ) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            name,
+            client: Client::builder()
+                .pool_max_idle_per_host(in_flight_limit.max(1))
+                .build()
+                .context("building clickhouse client")?,
+            url: format!("{}/", cfg.endpoint.trim_end_matches('/')),
+            database: cfg.database.clone(),
+            table: cfg.table.clone(),
+            user: cfg.user.clone(),
+            password: cfg.password.clone(),
+            batch_rows: cfg.batch_rows.max(1),
+            auto_create_table: cfg.auto_create_table,
+            table_created: OnceCell::new(),
+            in_flight: Semaphore::new(in_flight_limit.max(1)),
+            dead_letter: OnceLock::new(),
+        }))
+    }
+
+    pub fn set_dead_letter(&self, dead_letter: DeadLetter) {
+        let _ = self.dead_letter.set(dead_letter);
+    }
+
+    fn table_ident(&self) -> String {
+        format!(
+            "{}.{}",
+            quote_ident(&self.database),
+            quote_ident(&self.table)
+        )
+    }
+
+    fn post(&self, query: &str) -> RequestBuilder {
+        let mut req = self.client.post(&self.url).query(&[("query", query)]);
+        if let Some(user) = &self.user {
+            req = req.header("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &self.password {
+            req = req.header("X-ClickHouse-Key", password.expose_secret());
+        }
+        req
+    }
+
+    /// Create the table from the columns of `rows`, unless it exists.
+    async fn create_table(&self, rows: &[&[u8]]) -> Result<()> {
+        let columns = infer_columns(rows);
+        if columns.is_empty() {
+            anyhow::bail!(
+                "no fields to infer the columns of {} from",
+                self.table_ident()
+            );
+        }
+        let ddl = create_table_sql(&self.table_ident(), &columns);
+        let _permit = self.in_flight.acquire().await?;
+        let resp = self
+            .post(&ddl)
+            .send()
+            .await
+            .with_context(|| format!("creating {}", self.table_ident()))?;
+        let status = resp.status();
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            anyhow::bail!(
+                "creating {} failed with {status}: {text}",
+                self.table_ident()
+            );
+        }
+        tracing::info!(sink = %self.name, "created clickhouse table {}", self.table_ident());
+        Ok(())
+    }
+
+    /// Insert `rows`. When ClickHouse refuses a batch because of its data,
+    /// the batch is split in halves and retried until the offending rows are
+    /// found on their own; those are dead-lettered and the rest inserted.
+    async fn insert_rows(&self, rows: &[&[u8]]) -> Result<()> {
+        let mut rejected = Vec::new();
+        if let Err(refusal) = self.insert(rows).await? {
+            self.bisect(rows, refusal, &mut rejected).await?;
+        }
+        let rejected = rejected
+            .iter()
+            .map(|(row, reason)| (*row, reason.as_str()))
+            .collect();
+        self.dead_letter(rejected).await;
+        Ok(())
+    }
+
+    /// Find the rows of a refused `batch` by inserting each half on its own.
+    /// When both halves are refused for the same reason as the whole, every
+    /// row is taken to be bad rather than sending one request per row.
+    fn bisect<'a: 'r, 'r>(
+        &'a self,
+        batch: &'a [&'a [u8]],
+        refusal: Refusal,
+        rejected: &'r mut Vec<(&'a [u8], String)>,
+    ) -> BoxFuture<'r, Result<()>> {
+        Box::pin(async move {
+            if batch.len() == 1 {
+                rejected.push((batch[0], refusal.message));
+                return Ok(());
+            }
+            tracing::warn!(
+                sink = %self.name,
+                "clickhouse refused {} row(s), splitting the batch to find the bad ones: {}",
+                batch.len(),
+                refusal.message
+            );
+            let (head, tail) = batch.split_at(batch.len() / 2);
+            match (self.insert(head).await?, self.insert(tail).await?) {
+                (Err(a), Err(b)) if a.code == refusal.code && b.code == refusal.code => {
+                    rejected.extend(batch.iter().map(|row| (*row, refusal.message.clone())));
+                }
+                (head_res, tail_res) => {
+                    if let Err(a) = head_res {
+                        self.bisect(head, a, &mut *rejected).await?;
+                    }
+                    if let Err(b) = tail_res {
+                        self.bisect(tail, b, rejected).await?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// POST one `INSERT`. The inner error is ClickHouse's refusal when it
+    /// was for the rows themselves; anything else fails the write.
+    ///
+    /// Each insert carries a deduplication token derived from its rows, so
+    /// when a later chunk fails and the whole write is retried, chunks that
+    /// already went in are dropped by ClickHouse instead of duplicated. This
+    /// needs a replicated table or `non_replicated_deduplication_window`,
+    /// which auto-created tables set.
+    async fn insert(&self, rows: &[&[u8]]) -> Result<Result<(), Refusal>> {
+        let body = rows.join(&b'\n');
+        let len = body.len() as u64;
+        let query = format!("INSERT INTO {} FORMAT JSONEachRow", self.table_ident());
+        let token = hex::encode(Sha256::digest(&body));
+        let _permit = self.in_flight.acquire().await?;
+        let resp = self
+            .post(&query)
+            .query(&[("insert_deduplication_token", token.as_str())])
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("insert request to {}", self.url))?;
+
+        let status = resp.status();
+        if status.is_success() {
+            SINK_OBJECTS_TOTAL.inc();
+            SINK_BYTES_TOTAL.inc_by(len);
+            return Ok(Ok(()));
+        }
+        let headers = resp.headers().clone();
+        let text = resp.text().await.unwrap_or_default();
+        match row_error(&headers, &text) {
+            Some(code) => Ok(Err(Refusal {
+                code,
+                message: text.trim().to_string(),
+            })),
+            None => anyhow::bail!(
+                "insert into {} failed with {status}: {text}",
+                self.table_ident()
+            ),
+        }
+    }
+
+    /// Send `rejected` rows to the dead-letter sink, or log and drop them
+    /// when there is none.
+    async fn dead_letter(&self, rejected: Vec<(&[u8], &str)>) {
+        if rejected.is_empty() {
+            return;
+        }
+        let Some(dl) = self.dead_letter.get() else {
+            tracing::error!(
+                sink = %self.name,
+                "clickhouse rejected {} row(s); dropping them: {}",
+                rejected.len(),
+                rejected[0].1
+            );
+            return;
+        };
+        if let Err(e) = dl.write(&rejected).await {
+            tracing::error!(
+                sink = %self.name,
+                "writing {} rejected row(s) to '{}' failed: {e:#}",
+                rejected.len(),
+                dl.name
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for ClickHouseSink {
+    async fn write(&self, req: SinkWrite) -> Result<()> {
+        let mut rows = Vec::new();
+        let mut invalid = Vec::new();
+        for line in req.payload[..].split(|&b| b == b'\n') {
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            // JSONEachRow takes one object per row.
+            if line.starts_with(b"{") && serde_json::from_slice::<Value>(line).is_ok() {
+                rows.push(line);
+            } else {
+                invalid.push((line, "not a JSON object"));
+            }
+        }
+        self.dead_letter(invalid).await;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        if self.auto_create_table {
+            self.table_created
+                .get_or_try_init(|| self.create_table(&rows))
+                .await?;
+        }
+        for chunk in rows.chunks(self.batch_rows) {
+            self.insert_rows(chunk).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The ClickHouse error code of a failed request when it was refused for
+/// the rows it carried. Servers send the code in `X-ClickHouse-Exception-Code`;
+/// older ones only start the body with `Code: <n>.`.
+fn row_error(headers: &HeaderMap, body: &str) -> Option<u32> {
+    let code = headers
+        .get("X-ClickHouse-Exception-Code")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .or_else(|| {
+            body.trim_start()
+                .strip_prefix("Code: ")
+                .and_then(|rest| rest.split(|c: char| !c.is_ascii_digit()).next())
+                .and_then(|code| code.parse().ok())
+        })?;
+    ROW_ERROR_CODES.contains(&code).then_some(code)
+}
+
+/// Column names and ClickHouse types for the top-level fields of `rows`,
+/// sorted by name.
+fn infer_columns(rows: &[&[u8]]) -> Vec<(String, &'static str)> {
+    let mut columns: BTreeMap<String, Option<&'static str>> = BTreeMap::new();
+    for row in rows {
+        let Ok(fields) = serde_json::from_slice::<Map<String, Value>>(row) else {
+            continue;
+        };
+        for (key, value) in fields {
+            let ty = column_type(&value);
+            columns
+                .entry(key)
+                .and_modify(|cur| *cur = widen(*cur, ty))
+                .or_insert(ty);
+        }
+    }
+    columns
+        .into_iter()
+        .map(|(k, ty)| (k, ty.unwrap_or("String")))
+        .collect()
+}
+
+/// The column type for one value; `None` for `null`, which fits any.
+fn column_type(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some("Bool"),
+        Value::Number(n) if n.is_i64() => Some("Int64"),
+        Value::Number(n) if n.is_u64() => Some("UInt64"),
+        Value::Number(_) => Some("Float64"),
+        _ => Some("String"),
+    }
+}
+
+fn widen(a: Option<&'static str>, b: Option<&'static str>) -> Option<&'static str> {
+    match (a, b) {
+        (None, t) | (t, None) => t,
+        (Some(a), Some(b)) if a == b => Some(a),
+        (Some("Int64" | "UInt64" | "Float64"), Some("Int64" | "UInt64" | "Float64")) => {
+            Some("Float64")
+        }
+        _ => Some("String"),
+    }
+}
+
+fn create_table_sql(table: &str, columns: &[(String, &str)]) -> String {
+    let columns: Vec<String> = columns
+        .iter()
+        .map(|(name, ty)| format!("{} Nullable({ty})", quote_ident(name)))
+        .collect();
+    format!(
+        "CREATE TABLE IF NOT EXISTS {table} ({}) ENGINE = MergeTree ORDER BY tuple() \
+         SETTINGS non_replicated_deduplication_window = 1000",
+        columns.join(", ")
+    )
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("`{}`", name.replace('\\', "\\\\").replace('`', "\\`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::manager::tests::RecordingSink;
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::post;
+    use bytes::BytesMut;
+    use reqwest::header::HeaderValue;
+    use reqwest::StatusCode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    #[test]
+    fn tables_are_inferred_from_the_first_batch() {
+        let rows: [&[u8]; 3] = [
+            br#"{"service":"checkout","status":200,"latency":1,"ok":true,"trace":null}"#,
+            br#"{"service":"cart","status":503,"latency":0.25,"tags":["a"]}"#,
+            br#"{"service":"cart","status":"timeout","user`id":7}"#,
+        ];
+        assert_eq!(
+            create_table_sql("`default`.`logs`", &infer_columns(&rows)),
+            "CREATE TABLE IF NOT EXISTS `default`.`logs` (\
+             `latency` Nullable(Float64), `ok` Nullable(Bool), `service` Nullable(String), \
+             `status` Nullable(String), `tags` Nullable(String), `trace` Nullable(String), \
+             `user\\`id` Nullable(Int64)) \
+             ENGINE = MergeTree ORDER BY tuple() \
+             SETTINGS non_replicated_deduplication_window = 1000"
+        );
+    }
+
+    #[test]
+    fn only_data_errors_are_row_errors() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            row_error(
+                &headers,
+                "Code: 27. DB::Exception: Cannot parse input: expected '\"' before: 'x'"
+            ),
+            Some(27)
+        );
+        assert_eq!(
+            row_error(&headers, "Code: 241. DB::Exception: Memory limit exceeded"),
+            None
+        );
+        // A 400 for a bad query is not the rows' fault.
+        assert_eq!(row_error(&headers, "Syntax error"), None);
+
+        headers.insert(
+            "X-ClickHouse-Exception-Code",
+            HeaderValue::from_static("53"),
+        );
+        assert_eq!(row_error(&headers, "Type mismatch"), Some(53));
+        headers.insert(
+            "X-ClickHouse-Exception-Code",
+            HeaderValue::from_static("62"),
+        );
+        assert_eq!(row_error(&headers, "Code: 27. "), None);
+    }
+
+    /// Serve a ClickHouse stand-in that refuses any insert containing
+    /// `"bad"` and records the rows of the rest.
+    async fn serve(inserted: Arc<Mutex<Vec<String>>>, requests: Arc<AtomicUsize>) -> String {
+        let app = axum::Router::new().route(
+            "/",
+            post(move |body: String| async move {
+                requests.fetch_add(1, Ordering::SeqCst);
+                if body.contains("\"bad\"") {
+                    let mut resp = (
+                        StatusCode::BAD_REQUEST,
+                        "Code: 27. DB::Exception: Cannot parse input",
+                    )
+                        .into_response();
+                    resp.headers_mut().insert(
+                        "X-ClickHouse-Exception-Code",
+                        HeaderValue::from_static("27"),
+                    );
+                    return resp;
+                }
+                inserted
+                    .lock()
+                    .unwrap()
+                    .extend(body.lines().map(str::to_string));
+                Response::default()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn refused_rows_are_found_and_dead_lettered() {
+        let inserted = Arc::new(Mutex::new(Vec::new()));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let endpoint = serve(Arc::clone(&inserted), Arc::clone(&requests)).await;
+        let cfg: ClickHouseConfig = serde_json::from_value(serde_json::json!({
+            "endpoint": endpoint,
+            "table": "logs",
+        }))
+        .unwrap();
+        let sink = ClickHouseSink::new("warehouse".into(), &cfg, 1).unwrap();
+        let dlq = RecordingSink::new();
+        sink.set_dead_letter(DeadLetter {
+            name: "dlq".into(),
+            sink: dlq.clone(),
+            s3: None,
+        });
+        let write = |rows: &[&str]| SinkWrite {
+            sink_name: "warehouse".into(),
+            payload: BytesMut::from(rows.join("\n").as_bytes()),
+            s3: None,
+            flush_interval: None,
+        };
+
+        // One bad row among eight: halves are retried until it stands alone.
+        let rows: Vec<String> = (0..8)
+            .map(|i| match i {
+                5 => r#"{"n":5,"v":"bad"}"#.to_string(),
+                i => format!(r#"{{"n":{i}}}"#),
+            })
+            .collect();
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        sink.write(write(&rows)).await.unwrap();
+        assert_eq!(inserted.lock().unwrap().len(), 7);
+        assert_eq!(requests.load(Ordering::SeqCst), 7);
+        let dead = dlq.take().await;
+        assert_eq!(dead.len(), 1);
+        let dead = String::from_utf8(dead[0].clone()).unwrap();
+        assert!(dead.contains(r#""n":5"#) && dead.contains("__tangent_error"));
+
+        // When both halves fail like the whole batch, it is all bad.
+        requests.store(0, Ordering::SeqCst);
+        let bad = [r#"{"v":"bad"}"#; 4];
+        sink.write(write(&bad)).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        let dead = String::from_utf8(dlq.take().await.concat()).unwrap();
+        assert_eq!(dead.matches("__tangent_error").count(), 4);
+    }
+}
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+use crate::sinks::manager::{Sink, SinkWrite};
+
+/// Stands in for every configured sink under `tangent run --dry-run`:
+/// prints each batch to stdout as pretty JSON instead of writing it.
+#[derive(Default)]
+pub struct DryRunSink {
+    written: CancellationToken,
+}
+
+impl DryRunSink {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Resolves once the first batch has been printed.
+    pub async fn written(&self) {
+        self.written.cancelled().await;
+    }
+}
+
+#[async_trait]
+impl Sink for DryRunSink {
+    async fn write(&self, req: SinkWrite) -> Result<()> {
+        let events: Vec<Value> = req
+            .payload
+            .split(|&b| b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(|l| {
+                serde_json::from_slice(l)
+                    .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(l).into_owned()))
+            })
+            .collect();
+        let batch = json!({ "sink": &*req.sink_name, "events": events });
+
+        let mut out = std::io::stdout().lock();
+        serde_json::to_writer_pretty(&mut out, &batch)?;
+        writeln!(out)?;
+        out.flush()?;
+        drop(out);
+
+        self.written.cancel();
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tangent_shared::sinks::elasticsearch::ElasticsearchConfig;
+use tokio::sync::Semaphore;
+
+use crate::sinks::manager::{DeadLetter, Sink, SinkWrite};
+use crate::{SINK_BYTES_TOTAL, SINK_OBJECTS_TOTAL};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+pub struct ElasticsearchSink {
+    name: Arc<str>,
+    client: Client,
+    bulk_url: String,
+    index_pattern: String,
+    api_key: Option<SecretString>,
+    batch_max_docs: usize,
+    /// One permit per request in flight, sized by `in_flight_limit`.
+    in_flight: Semaphore,
+    dead_letter: OnceLock<DeadLetter>,
+}
+
+#[derive(Deserialize)]
+struct BulkResponse {
+    #[serde(default)]
+    errors: bool,
+    #[serde(default)]
+    items: Vec<BTreeMap<String, BulkItem>>,
+}
+
+#[derive(Deserialize)]
+struct BulkItem {
+    status: u16,
+    #[serde(default)]
+    error: Option<Value>,
+}
+
+/// What happened to each document of one bulk request.
+#[derive(Debug, Default, PartialEq)]
+struct BulkOutcome {
+    /// Indexes of documents to send again, after a 429 or 503.
+    retry: Vec<usize>,
+    /// Indexes of documents Elasticsearch refused, with the reason.
+    rejected: Vec<(usize, String)>,
+}
+
+impl ElasticsearchSink {
+    pub fn new(
+        name: Arc<str>,
+        cfg: &ElasticsearchConfig,
+        in_flight_limit: usize,
+    ) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            name,
+            client: Client::builder()
+                .build()
+                .context("building elasticsearch client")?,
+            bulk_url: format!("{}/_bulk", cfg.endpoint.trim_end_matches('/')),
+            index_pattern: cfg.index_pattern.clone(),
+            api_key: cfg.api_key.clone(),
+            batch_max_docs: cfg.batch_max_docs.max(1),
+            in_flight: Semaphore::new(in_flight_limit.max(1)),
+            dead_letter: OnceLock::new(),
+        }))
+    }
+
+    pub fn set_dead_letter(&self, dead_letter: DeadLetter) {
+        let _ = self.dead_letter.set(dead_letter);
+    }
+
+    fn index_name(&self, now: DateTime<Utc>) -> String {
+        self.index_pattern
+            .replace("{YYYY-MM-DD}", &now.format("%Y-%m-%d").to_string())
+    }
+
+    /// Index `docs`, resending those Elasticsearch pushes back on with 429
+    /// or 503 until they land, and dead-lettering those it rejects.
+    async fn index_docs(&self, index: &str, mut docs: Vec<&[u8]>) -> Result<()> {
+        let mut delay = INITIAL_BACKOFF;
+        loop {
+            let outcome = self.post_bulk(index, &docs).await?;
+            let rejected = outcome
+                .rejected
+                .iter()
+                .map(|(i, reason)| (docs[*i], reason.as_str()))
+                .collect();
+            self.dead_letter(rejected).await;
+
+            if outcome.retry.is_empty() {
+                return Ok(());
+            }
+            tracing::debug!(
+                sink = %self.name,
+                "elasticsearch pushed back on {} document(s); retrying in {delay:?}",
+                outcome.retry.len()
+            );
+            docs = outcome.retry.iter().map(|i| docs[*i]).collect();
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    async fn post_bulk(&self, index: &str, docs: &[&[u8]]) -> Result<BulkOutcome> {
+        let body = bulk_body(index, docs);
+        let _permit = self.in_flight.acquire().await?;
+        let mut req = self
+            .client
+            .post(&self.bulk_url)
+            .header("Content-Type", "application/x-ndjson")
+            .body(body.clone());
+        if let Some(key) = &self.api_key {
+            req = req.header("Authorization", format!("ApiKey {}", key.expose_secret()));
+        }
+        let resp = req
+            .send()
+            .await
+            .with_context(|| format!("bulk request to {}", self.bulk_url))?;
+
+        let status = resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            return Ok(BulkOutcome {
+                retry: (0..docs.len()).collect(),
+                rejected: Vec::new(),
+            });
+        }
+        let text = resp.text().await.unwrap_or_default();
+        if !status.is_success() {
+            anyhow::bail!(
+                "bulk request to {} failed with {status}: {text}",
+                self.bulk_url
+            );
+        }
+
+        SINK_OBJECTS_TOTAL.inc();
+        SINK_BYTES_TOTAL.inc_by(body.len() as u64);
+        let resp: BulkResponse =
+            serde_json::from_str(&text).context("parsing elasticsearch bulk response")?;
+        Ok(bulk_outcome(&resp))
+    }
+
+    /// Send `rejected` documents to the dead-letter sink, or log and drop
+    /// them when there is none.
+    async fn dead_letter(&self, rejected: Vec<(&[u8], &str)>) {
+        if rejected.is_empty() {
+            return;
+        }
+        let Some(dl) = self.dead_letter.get() else {
+            tracing::error!(
+                sink = %self.name,
+                "elasticsearch rejected {} document(s); dropping them: {}",
+                rejected.len(),
+                rejected[0].1
+            );
+            return;
+        };
+        if let Err(e) = dl.write(&rejected).await {
+            tracing::error!(
+                sink = %self.name,
+                "writing {} rejected document(s) to '{}' failed: {e:#}",
+                rejected.len(),
+                dl.name
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for ElasticsearchSink {
+    async fn write(&self, req: SinkWrite) -> Result<()> {
+        let index = self.index_name(Utc::now());
+        let mut docs = Vec::new();
+        let mut invalid = Vec::new();
+        for line in req.payload[..].split(|&b| b == b'\n') {
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            // One bad line would make Elasticsearch refuse the whole request.
+            if line.starts_with(b"{") && serde_json::from_slice::<Value>(line).is_ok() {
+                docs.push(line);
+            } else {
+                invalid.push((line, "not a JSON object"));
+            }
+        }
+        self.dead_letter(invalid).await;
+
+        for chunk in docs.chunks(self.batch_max_docs) {
+            self.index_docs(&index, chunk.to_vec()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A `_bulk` body indexing each of `docs` into `index`.
+fn bulk_body(index: &str, docs: &[&[u8]]) -> Vec<u8> {
+    let action = serde_json::json!({ "index": { "_index": index } }).to_string();
+    let mut body = Vec::with_capacity(docs.iter().map(|d| d.len() + action.len() + 2).sum());
+    for doc in docs {
+        body.extend_from_slice(action.as_bytes());
+        body.push(b'\n');
+        body.extend_from_slice(doc);
+        body.push(b'\n');
+    }
+    body
+}
+
+/// Sort the per-document results of a bulk response, whose `items` are in
+/// request order.
+fn bulk_outcome(resp: &BulkResponse) -> BulkOutcome {
+    let mut outcome = BulkOutcome::default();
+    if !resp.errors {
+        return outcome;
+    }
+    for (i, item) in resp.items.iter().enumerate() {
+        let Some(item) = item.values().next() else {
+            continue;
+        };
+        match item.status {
+            200..=299 => {}
+            429 | 503 => outcome.retry.push(i),
+            status => {
+                let reason = match &item.error {
+                    Some(Value::Object(e)) => format!(
+                        "{}: {}",
+                        e.get("type").and_then(Value::as_str).unwrap_or("error"),
+                        e.get("reason").and_then(Value::as_str).unwrap_or_default()
+                    ),
+                    Some(e) => e.to_string(),
+                    None => format!("status {status}"),
+                };
+                outcome.rejected.push((i, reason));
+            }
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn bulk_responses_split_into_retries_and_rejections() {
+        let cfg: ElasticsearchConfig = serde_json::from_value(json!({
+            "endpoint": "http://es:9200/",
+            "index_pattern": "logs-{YYYY-MM-DD}"
+        }))
+        .unwrap();
+        let sink = ElasticsearchSink::new(Arc::from("search"), &cfg, 1).unwrap();
+        assert_eq!(sink.bulk_url, "http://es:9200/_bulk");
+        let day = DateTime::parse_from_rfc3339("2024-03-09T23:59:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(sink.index_name(day), "logs-2024-03-09");
+
+        let body = bulk_body("logs", &[b"{\"a\":1}", b"{\"a\":2}"]);
+        assert_eq!(
+            body,
+            b"{\"index\":{\"_index\":\"logs\"}}\n{\"a\":1}\n{\"index\":{\"_index\":\"logs\"}}\n{\"a\":2}\n"
+        );
+
+        let resp: BulkResponse = serde_json::from_value(json!({
+            "took": 3,
+            "errors": true,
+            "items": [
+                { "index": { "_index": "logs", "status": 201 } },
+                { "index": { "_index": "logs", "status": 429, "error": { "type": "es_rejected_execution_exception", "reason": "queue full" } } },
+                { "index": { "_index": "logs", "status": 400, "error": { "type": "mapper_parsing_exception", "reason": "failed to parse field [n]" } } }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            bulk_outcome(&resp),
+            BulkOutcome {
+                retry: vec![1],
+                rejected: vec![(
+                    2,
+                    "mapper_parsing_exception: failed to parse field [n]".into()
+                )],
+            }
+        );
+    }
+}
@@ -1,12 +1,16 @@
 use anyhow::Result;
 use apache_avro::Codec;
+use arrow_json::reader::infer_json_schema_from_iterator;
 use arrow_json::ReaderBuilder;
-use arrow_schema::Schema;
+use arrow_schema::{FieldRef, Schema};
 use bytes::{BufMut, Bytes, BytesMut};
 use memchr::{memchr, memchr_iter};
 use parquet::basic::{Compression as PqCompression, GzipLevel, ZstdLevel};
 use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
-use std::io::Cursor;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::path::Path;
 use std::sync::Arc;
 use tangent_shared::sinks::common::{Compression, Encoding};
 
@@ -34,17 +38,32 @@ pub fn ndjson_to_json_array(raw: &[u8]) -> Result<BytesMut> {
     Ok(out)
 }
 
+/// Lines read to infer a Parquet schema when the sink doesn't configure one.
+pub const PARQUET_SCHEMA_SAMPLE_LINES: usize = 1000;
+
 pub fn normalize_from_ndjson(
     enc: &Encoding,
     comp: &Compression,
+    row_group_size: usize,
     raw: BytesMut,
 ) -> Result<BytesMut> {
     match enc {
         Encoding::NDJSON => Ok(ndjson_ensure_newline(raw)),
         Encoding::JSON => ndjson_to_json_array(&raw),
         Encoding::Avro { schema: s } => ndjson_to_avro(&raw, s, comp),
-        Encoding::Parquet { schema: s } => ndjson_to_parquet(&raw, s, comp),
+        Encoding::Parquet { schema: s } => {
+            ndjson_to_parquet(&raw, s.as_deref(), comp, row_group_size)
+        }
+        Encoding::ArrowIpc { schema: s } => ndjson_to_arrow_ipc(&raw, s.as_deref()),
+    }
+}
+
+/// Fail early for encodings this build can't produce.
+pub fn ensure_supported(enc: &Encoding) -> Result<()> {
+    if matches!(enc, Encoding::ArrowIpc { .. }) && !cfg!(feature = "arrow-ipc") {
+        anyhow::bail!("arrow_ipc encoding needs tangent built with the `arrow-ipc` feature");
     }
+    Ok(())
 }
 
 fn ndjson_iter_lines(raw: &[u8]) -> impl Iterator<Item = &[u8]> {
@@ -65,30 +84,210 @@ pub fn ndjson_to_avro(raw: &[u8], avro_schema_json: &str, comp: &Compression) ->
     Ok(BytesMut::from(bytes.as_slice()))
 }
 
+/// Encode NDJSON as Parquet. Without `arrow_schema_json` the schema is
+/// inferred with `infer_arrow_schema`.
 pub fn ndjson_to_parquet(
     raw: &[u8],
-    arrow_schema_json: &str,
+    arrow_schema_json: Option<&str>,
     comp: &Compression,
+    row_group_size: usize,
 ) -> Result<BytesMut> {
-    let reader = Cursor::new(raw);
-    let arrow_schema: Schema = serde_json::from_str(arrow_schema_json)?;
-    let json_reader = ReaderBuilder::new(Arc::new(arrow_schema.clone())).build(reader);
+    let arrow_schema = match arrow_schema_json {
+        Some(s) => serde_json::from_str(s)?,
+        None => infer_arrow_schema(ndjson_iter_lines(raw).map(Ok))?,
+    };
 
-    let props = parquet_props_from(comp)?;
     let mut out = Cursor::new(Vec::<u8>::new());
-    let mut writer = ArrowWriter::try_new(&mut out, Arc::new(arrow_schema), Some(props))?;
+    write_parquet(
+        Cursor::new(raw),
+        &mut out,
+        arrow_schema,
+        comp,
+        row_group_size,
+    )?;
+
+    Ok(BytesMut::from(out.into_inner().as_slice()))
+}
+
+/// Like `ndjson_to_parquet`, streaming the NDJSON file at `src` into a
+/// Parquet file at `dst`. Blocking.
+pub fn ndjson_file_to_parquet(
+    src: &Path,
+    dst: &Path,
+    arrow_schema_json: Option<&str>,
+    comp: &Compression,
+    row_group_size: usize,
+) -> Result<()> {
+    let arrow_schema = match arrow_schema_json {
+        Some(s) => serde_json::from_str(s)?,
+        None => infer_arrow_schema(
+            BufReader::new(File::open(src)?)
+                .split(b'\n')
+                .map(|line| line.map_err(Into::into)),
+        )?,
+    };
+
+    let mut out = File::create(dst)?;
+    write_parquet(
+        BufReader::new(File::open(src)?),
+        &mut out,
+        arrow_schema,
+        comp,
+        row_group_size,
+    )?;
+    out.sync_data()?;
+    Ok(())
+}
+
+fn write_parquet<R: Read, W: Write + Send>(
+    ndjson: R,
+    out: W,
+    arrow_schema: Schema,
+    comp: &Compression,
+    row_group_size: usize,
+) -> Result<()> {
+    let arrow_schema = Arc::new(arrow_schema);
+    let json_reader = ReaderBuilder::new(Arc::clone(&arrow_schema))
+        .with_batch_size(row_group_size)
+        .build(BufReader::new(ndjson))?;
+
+    let props = parquet_props_from(comp, row_group_size)?;
+    let mut writer = ArrowWriter::try_new(out, arrow_schema, Some(props))?;
 
-    for maybe_batch in json_reader? {
+    for maybe_batch in json_reader {
         let batch = maybe_batch?;
         writer.write(&batch)?;
     }
     writer.close()?;
+    Ok(())
+}
 
-    Ok(BytesMut::from(out.into_inner().as_slice()))
+/// Encode NDJSON as an Arrow IPC stream. Without `arrow_schema_json` the
+/// schema is inferred with `infer_arrow_schema`.
+pub fn ndjson_to_arrow_ipc(raw: &[u8], arrow_schema_json: Option<&str>) -> Result<BytesMut> {
+    let arrow_schema = match arrow_schema_json {
+        Some(s) => serde_json::from_str(s)?,
+        None => infer_arrow_schema(ndjson_iter_lines(raw).map(Ok))?,
+    };
+
+    let mut out = Vec::<u8>::new();
+    write_arrow_ipc(Cursor::new(raw), &mut out, arrow_schema)?;
+    Ok(BytesMut::from(out.as_slice()))
 }
 
-fn parquet_props_from(comp: &Compression) -> Result<WriterProperties> {
-    let mut b = WriterProperties::builder();
+/// Like `ndjson_to_arrow_ipc`, streaming the NDJSON file at `src` into an
+/// Arrow IPC stream file at `dst`. Blocking.
+pub fn ndjson_file_to_arrow_ipc(
+    src: &Path,
+    dst: &Path,
+    arrow_schema_json: Option<&str>,
+) -> Result<()> {
+    let arrow_schema = match arrow_schema_json {
+        Some(s) => serde_json::from_str(s)?,
+        None => infer_arrow_schema(
+            BufReader::new(File::open(src)?)
+                .split(b'\n')
+                .map(|line| line.map_err(Into::into)),
+        )?,
+    };
+
+    let mut out = File::create(dst)?;
+    write_arrow_ipc(BufReader::new(File::open(src)?), &mut out, arrow_schema)?;
+    out.sync_data()?;
+    Ok(())
+}
+
+#[cfg(feature = "arrow-ipc")]
+fn write_arrow_ipc<R: Read, W: Write>(ndjson: R, out: W, arrow_schema: Schema) -> Result<()> {
+    let arrow_schema = Arc::new(arrow_schema);
+    let json_reader =
+        ReaderBuilder::new(Arc::clone(&arrow_schema)).build(BufReader::new(ndjson))?;
+
+    let mut writer = arrow_ipc::writer::StreamWriter::try_new(out, &arrow_schema)?;
+    for maybe_batch in json_reader {
+        writer.write(&maybe_batch?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "arrow-ipc"))]
+fn write_arrow_ipc<R: Read, W: Write>(_ndjson: R, _out: W, _arrow_schema: Schema) -> Result<()> {
+    ensure_supported(&Encoding::ArrowIpc { schema: None })
+}
+
+/// Decode an Arrow IPC stream into one NDJSON line per row.
+#[cfg(feature = "arrow-ipc")]
+pub fn arrow_ipc_to_ndjson(data: &[u8]) -> Result<Vec<u8>> {
+    let reader = arrow_ipc::reader::StreamReader::try_new(Cursor::new(data), None)?;
+    let mut writer = arrow_json::LineDelimitedWriter::new(Vec::with_capacity(data.len()));
+    for batch in reader {
+        writer.write(&batch?)?;
+    }
+    writer.finish()?;
+    Ok(writer.into_inner())
+}
+
+#[cfg(not(feature = "arrow-ipc"))]
+pub fn arrow_ipc_to_ndjson(_data: &[u8]) -> Result<Vec<u8>> {
+    ensure_supported(&Encoding::ArrowIpc { schema: None })?;
+    Ok(Vec::new())
+}
+
+/// Infer an Arrow schema from the first `PARQUET_SCHEMA_SAMPLE_LINES` lines.
+/// Top-level fields that only show up later are appended as nullable
+/// columns, so records gaining fields mid-file aren't truncated.
+fn infer_arrow_schema<L: AsRef<[u8]>>(
+    mut lines: impl Iterator<Item = Result<L>>,
+) -> Result<Schema> {
+    let mut sample = Vec::new();
+    for line in lines.by_ref() {
+        let line = line?;
+        if line.as_ref().is_empty() {
+            continue;
+        }
+        sample.push(serde_json::from_slice::<serde_json::Value>(line.as_ref())?);
+        if sample.len() == PARQUET_SCHEMA_SAMPLE_LINES {
+            break;
+        }
+    }
+    let schema = infer_json_schema_from_iterator(sample.iter().map(Ok))?;
+
+    let mut known: HashSet<String> = schema.fields().iter().map(|f| f.name().clone()).collect();
+    let mut late = Vec::new();
+    for line in lines {
+        let line = line?;
+        if line.as_ref().is_empty() {
+            continue;
+        }
+        let serde_json::Value::Object(obj) = serde_json::from_slice(line.as_ref())? else {
+            continue;
+        };
+        let new: serde_json::Map<String, serde_json::Value> = obj
+            .into_iter()
+            .filter(|(k, _)| !known.contains(k))
+            .collect();
+        if !new.is_empty() {
+            known.extend(new.keys().cloned());
+            late.push(serde_json::Value::Object(new));
+        }
+    }
+    if late.is_empty() {
+        return Ok(schema);
+    }
+
+    let late = infer_json_schema_from_iterator(late.iter().map(Ok))?;
+    let fields: Vec<FieldRef> = schema
+        .fields()
+        .iter()
+        .chain(late.fields().iter())
+        .map(|f| Arc::new(f.as_ref().clone().with_nullable(true)))
+        .collect();
+    Ok(Schema::new(fields))
+}
+
+fn parquet_props_from(comp: &Compression, row_group_size: usize) -> Result<WriterProperties> {
+    let mut b = WriterProperties::builder().set_max_row_group_size(row_group_size.max(1));
     let pq = match comp {
         Compression::None => PqCompression::UNCOMPRESSED,
         Compression::Gzip { level } => {
@@ -168,3 +367,33 @@ pub fn ndjson_chunk_slices(buf: Bytes, max_chunk: usize) -> Vec<Bytes> {
     }
     chunks
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inferred_parquet_schema_picks_up_late_fields() {
+        let mut raw = String::new();
+        for i in 0..PARQUET_SCHEMA_SAMPLE_LINES {
+            raw.push_str(&format!("{{\"msg\":\"m{i}\",\"n\":{i}}}\n"));
+        }
+        raw.push_str("{\"msg\":\"late\",\"n\":1,\"user\":\"alice\"}\n");
+
+        let schema = infer_arrow_schema(ndjson_iter_lines(raw.as_bytes()).map(Ok)).unwrap();
+        let user = schema.field_with_name("user").unwrap();
+        assert!(user.is_nullable());
+        assert_eq!(user.data_type(), &arrow_schema::DataType::Utf8);
+
+        let out = ndjson_to_parquet(raw.as_bytes(), None, &Compression::None, 256).unwrap();
+        assert!(out.starts_with(b"PAR1"));
+    }
+
+    #[cfg(feature = "arrow-ipc")]
+    #[test]
+    fn arrow_ipc_round_trips_through_ndjson() {
+        let raw = b"{\"msg\":\"a\",\"n\":1}\n{\"msg\":\"b\",\"n\":2}\n";
+        let ipc = ndjson_to_arrow_ipc(raw, None).unwrap();
+        assert_eq!(arrow_ipc_to_ndjson(&ipc).unwrap(), raw);
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::{Read, Write};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, ensure, Context, Result};
+
+/// Bytes of nonce stored in front of a file encrypted whole.
+pub const NONCE_LEN: usize = 12;
+
+/// Start of a file encrypted in chunks. Files without it are one
+/// nonce-prefixed ciphertext, as written by earlier versions.
+const STREAM_MAGIC: &[u8; 4] = b"TGE1";
+
+/// Random bytes shared by the nonces of a file's chunks; the rest of each
+/// nonce is the chunk's index and whether it is the last one.
+const NONCE_PREFIX_LEN: usize = 7;
+
+/// Plaintext bytes per encrypted chunk.
+pub const CHUNK_LEN: usize = 64 * 1024;
+
+const TAG_LEN: usize = 16;
+
+/// AES-256-GCM cipher for sealed WAL files. An encrypted file is
+/// `STREAM_MAGIC`, a random nonce prefix, then the file in `CHUNK_LEN`
+/// chunks, each sealed with its own tag. Numbering the chunks and flagging
+/// the last one in their nonces means reordered, dropped or truncated
+/// chunks fail to decrypt.
+#[derive(Clone)]
+pub struct WalCipher(Aes256Gcm);
+
+impl WalCipher {
+    pub fn from_hex(key_hex: &str) -> Result<Self> {
+        let key = hex::decode(key_hex.trim()).context("encryption key is not valid hex")?;
+        ensure!(
+            key.len() == 32,
+            "encryption key must be 32 bytes, got {}",
+            key.len()
+        );
+        Ok(Self(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+    }
+
+    /// Encrypt `src` into `dst` a chunk at a time, so only one chunk is held
+    /// in memory. Returns the bytes written.
+    pub fn encrypt_stream(&self, mut src: impl Read, mut dst: impl Write) -> Result<u64> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let prefix = &nonce[..NONCE_PREFIX_LEN];
+        dst.write_all(STREAM_MAGIC)?;
+        dst.write_all(prefix)?;
+        let mut written = (STREAM_MAGIC.len() + NONCE_PREFIX_LEN) as u64;
+
+        let mut chunk = read_up_to(&mut src, CHUNK_LEN)?;
+        let mut index = 0u32;
+        loop {
+            let next = read_up_to(&mut src, CHUNK_LEN)?;
+            let last = next.is_empty();
+            let sealed = self
+                .0
+                .encrypt(&chunk_nonce(prefix, index, last), chunk.as_slice())
+                .map_err(|_| anyhow!("AES-GCM encryption failed"))?;
+            dst.write_all(&sealed)?;
+            written += sealed.len() as u64;
+            if last {
+                break;
+            }
+            chunk = next;
+            index = index.checked_add(1).context("file too large to encrypt")?;
+        }
+        dst.flush()?;
+        Ok(written)
+    }
+
+    /// Decrypt a file written by `encrypt_stream`, or by earlier versions
+    /// that encrypted files whole, from `src` into `dst`.
+    pub fn decrypt_stream(&self, mut src: impl Read, mut dst: impl Write) -> Result<()> {
+        let mut head = read_up_to(&mut src, STREAM_MAGIC.len() + NONCE_PREFIX_LEN)?;
+        if !head.starts_with(STREAM_MAGIC) {
+            src.read_to_end(&mut head)?;
+            dst.write_all(&self.decrypt_whole(&head)?)?;
+            return Ok(dst.flush()?);
+        }
+        ensure!(
+            head.len() == STREAM_MAGIC.len() + NONCE_PREFIX_LEN,
+            "file is too short to be encrypted"
+        );
+        let prefix = &head[STREAM_MAGIC.len()..];
+
+        let mut chunk = read_up_to(&mut src, CHUNK_LEN + TAG_LEN)?;
+        let mut index = 0u32;
+        loop {
+            let next = read_up_to(&mut src, CHUNK_LEN + TAG_LEN)?;
+            let last = next.is_empty();
+            let plaintext = self
+                .0
+                .decrypt(&chunk_nonce(prefix, index, last), chunk.as_slice())
+                .map_err(|_| anyhow!("decryption failed: wrong key or corrupted file"))?;
+            dst.write_all(&plaintext)?;
+            if last {
+                break;
+            }
+            chunk = next;
+            index = index.checked_add(1).context("file too large to decrypt")?;
+        }
+        Ok(dst.flush()?)
+    }
+
+    fn decrypt_whole(&self, data: &[u8]) -> Result<Vec<u8>> {
+        ensure!(data.len() >= NONCE_LEN, "file is too short to be encrypted");
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("decryption failed: wrong key or corrupted file"))
+    }
+}
+
+fn chunk_nonce(prefix: &[u8], index: u32, last: bool) -> Nonce<<Aes256Gcm as AeadCore>::NonceSize> {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&index.to_be_bytes());
+    nonce[NONCE_LEN - 1] = u8::from(last);
+    *Nonce::from_slice(&nonce)
+}
+
+/// Read `len` bytes from `src`, or fewer at its end.
+fn read_up_to(src: &mut impl Read, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(len);
+    src.take(len as u64).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(cipher: &WalCipher, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut sealed = Vec::new();
+        cipher.encrypt_stream(plaintext, &mut sealed)?;
+        let mut out = Vec::new();
+        cipher.decrypt_stream(sealed.as_slice(), &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn round_trips_in_chunks_and_rejects_other_keys() {
+        let cipher = WalCipher::from_hex(&"11".repeat(32)).unwrap();
+        for len in [0, 17, CHUNK_LEN, 2 * CHUNK_LEN + 5] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            assert_eq!(round_trip(&cipher, &plaintext).unwrap(), plaintext);
+        }
+
+        let plaintext = vec![7u8; 2 * CHUNK_LEN];
+        let mut sealed = Vec::new();
+        let written = cipher
+            .encrypt_stream(plaintext.as_slice(), &mut sealed)
+            .unwrap();
+        assert_eq!(written, sealed.len() as u64);
+        assert_eq!(sealed.len(), 4 + 7 + 2 * (CHUNK_LEN + 16));
+
+        // Dropping the last chunk leaves a file that ends on a chunk not
+        // sealed as the last one.
+        let truncated = &sealed[..sealed.len() - (CHUNK_LEN + 16)];
+        assert!(cipher.decrypt_stream(truncated, std::io::sink()).is_err());
+
+        let other = WalCipher::from_hex(&"22".repeat(32)).unwrap();
+        assert!(other
+            .decrypt_stream(sealed.as_slice(), std::io::sink())
+            .is_err());
+        assert!(WalCipher::from_hex("abcd").is_err());
+    }
+
+    #[test]
+    fn files_encrypted_whole_still_decrypt() {
+        let cipher = WalCipher::from_hex(&"11".repeat(32)).unwrap();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            cipher
+                .0
+                .encrypt(&nonce, b"{\"msg\":\"hello\"}\n".as_slice())
+                .unwrap(),
+        );
+
+        let mut out = Vec::new();
+        cipher.decrypt_stream(sealed.as_slice(), &mut out).unwrap();
+        assert_eq!(out, b"{\"msg\":\"hello\"}\n");
+    }
+}
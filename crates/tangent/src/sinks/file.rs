@@ -20,6 +20,7 @@ pub struct FileSink {
     path: PathBuf,
     encoding: Encoding,
     compression: Compression,
+    row_group_size: usize,
     file: Mutex<tokio::fs::File>,
 }
 
@@ -41,6 +42,7 @@ impl FileSink {
             path,
             encoding: common.encoding.clone(),
             compression: common.compression.clone(),
+            row_group_size: common.parquet_row_group_size,
             file: Mutex::new(file),
         }))
     }
@@ -54,8 +56,12 @@ impl FileSink {
 impl Sink for FileSink {
     async fn write(&self, req: SinkWrite) -> Result<()> {
         let uncompressed_bytes = req.payload.len();
-        let normalized_payload =
-            encoding::normalize_from_ndjson(&self.encoding, &self.compression, req.payload)?;
+        let normalized_payload = encoding::normalize_from_ndjson(
+            &self.encoding,
+            &self.compression,
+            self.row_group_size,
+            req.payload,
+        )?;
 
         self.file
             .lock()
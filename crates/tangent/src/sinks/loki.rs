@@ -0,0 +1,185 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use secrecy::ExposeSecret;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tangent_shared::sinks::loki::{label_name, LokiBasicAuth, LokiConfig};
+use tokio::sync::Semaphore;
+
+use crate::sinks::manager::{Sink, SinkWrite};
+use crate::{SINK_BYTES_TOTAL, SINK_OBJECTS_TOTAL};
+
+/// A label's name and the dotted path of the JSON field it is read from.
+struct LabelField {
+    name: String,
+    path: Vec<String>,
+}
+
+pub struct LokiSink {
+    name: Arc<str>,
+    client: Client,
+    push_url: String,
+    tenant_id: Option<String>,
+    basic_auth: Option<LokiBasicAuth>,
+    labels: Vec<LabelField>,
+    /// One permit per request in flight, sized by `in_flight_limit`.
+    in_flight: Semaphore,
+}
+
+impl LokiSink {
+    pub fn new(name: Arc<str>, cfg: &LokiConfig, in_flight_limit: usize) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            name,
+            client: Client::builder().build().context("building loki client")?,
+            push_url: format!("{}/loki/api/v1/push", cfg.endpoint.trim_end_matches('/')),
+            tenant_id: cfg.tenant_id.clone(),
+            basic_auth: cfg.basic_auth.clone(),
+            labels: cfg
+                .labels
+                .iter()
+                .map(|field| LabelField {
+                    name: label_name(field),
+                    path: field.split('.').map(str::to_string).collect(),
+                })
+                .collect(),
+            in_flight: Semaphore::new(in_flight_limit.max(1)),
+        }))
+    }
+
+    /// The `/loki/api/v1/push` body for the NDJSON lines of `payload`, one
+    /// stream per distinct label set. Lines are stamped from `ts_ns` on, one
+    /// nanosecond apart so Loki keeps their order and doesn't take them for
+    /// duplicates.
+    fn push_request(&self, payload: &[u8], ts_ns: u128) -> Value {
+        let mut streams: BTreeMap<BTreeMap<&str, String>, Vec<Value>> = BTreeMap::new();
+        let lines = payload.split(|&b| b == b'\n').filter(|l| !l.is_empty());
+        for (i, line) in lines.enumerate() {
+            let doc: Option<Value> = serde_json::from_slice(line).ok();
+            // Loki refuses streams without labels.
+            let sink = ("tangent_sink", self.name.to_string());
+            let labels = std::iter::once(sink)
+                .chain(self.labels.iter().filter_map(|l| {
+                    let v = l.path.iter().try_fold(doc.as_ref()?, |v, key| v.get(key))?;
+                    let v = match v {
+                        Value::String(s) => s.clone(),
+                        Value::Number(n) => n.to_string(),
+                        Value::Bool(b) => b.to_string(),
+                        _ => return None,
+                    };
+                    (!v.is_empty()).then_some((l.name.as_str(), v))
+                }))
+                .collect();
+            let ts = (ts_ns + i as u128).to_string();
+            streams
+                .entry(labels)
+                .or_default()
+                .push(json!([ts, String::from_utf8_lossy(line)]));
+        }
+
+        let streams: Vec<Value> = streams
+            .into_iter()
+            .map(|(labels, values)| json!({ "stream": labels, "values": values }))
+            .collect();
+        json!({ "streams": streams })
+    }
+}
+
+#[async_trait]
+impl Sink for LokiSink {
+    async fn write(&self, req: SinkWrite) -> Result<()> {
+        let ts_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let body = serde_json::to_vec(&self.push_request(&req.payload, ts_ns))?;
+        let len = body.len() as u64;
+
+        let _permit = self.in_flight.acquire().await?;
+        let mut http = self
+            .client
+            .post(&self.push_url)
+            .header("Content-Type", "application/json")
+            .body(body);
+        if let Some(tenant) = &self.tenant_id {
+            http = http.header("X-Scope-OrgID", tenant);
+        }
+        if let Some(auth) = &self.basic_auth {
+            http = http.basic_auth(&auth.username, Some(auth.password.expose_secret()));
+        }
+        let resp = http
+            .send()
+            .await
+            .with_context(|| format!("loki push to {}", self.push_url))?;
+
+        let status = resp.status();
+        if status.is_success() {
+            SINK_OBJECTS_TOTAL.inc();
+            SINK_BYTES_TOTAL.inc_by(len);
+            return Ok(());
+        }
+
+        let text = resp.text().await.unwrap_or_default();
+        // Loki rejects bad batches (too old, too large, ...) with a 4xx that
+        // retrying won't fix; 429 and 5xx are retried by the sink manager.
+        if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+            tracing::error!(
+                sink = %self.name,
+                "loki rejected batch with {status}; dropping it: {text}"
+            );
+            return Ok(());
+        }
+        anyhow::bail!(
+            "loki push to {} failed with {status}: {text}",
+            self.push_url
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_grouped_into_streams_by_label_values() {
+        let cfg: LokiConfig = serde_json::from_value(json!({
+            "endpoint": "http://loki:3100/",
+            "labels": ["service", "k8s.namespace", "level"]
+        }))
+        .unwrap();
+        let sink = LokiSink::new(Arc::from("logs"), &cfg, 1).unwrap();
+        assert_eq!(sink.push_url, "http://loki:3100/loki/api/v1/push");
+
+        let payload = concat!(
+            "{\"service\":\"api\",\"k8s\":{\"namespace\":\"prod\"},\"level\":\"error\",\"n\":1}\n",
+            "{\"service\":\"api\",\"n\":2}\n",
+            "{\"service\":\"api\",\"k8s\":{\"namespace\":\"prod\"},\"level\":\"error\",\"n\":3}\n",
+            "not json\n",
+        );
+        let body = sink.push_request(payload.as_bytes(), 42);
+        assert_eq!(
+            body,
+            json!({ "streams": [
+                {
+                    "stream": {
+                        "k8s_namespace": "prod",
+                        "level": "error",
+                        "service": "api",
+                        "tangent_sink": "logs"
+                    },
+                    "values": [
+                        ["42", "{\"service\":\"api\",\"k8s\":{\"namespace\":\"prod\"},\"level\":\"error\",\"n\":1}"],
+                        ["44", "{\"service\":\"api\",\"k8s\":{\"namespace\":\"prod\"},\"level\":\"error\",\"n\":3}"]
+                    ]
+                },
+                {
+                    "stream": { "service": "api", "tangent_sink": "logs" },
+                    "values": [["43", "{\"service\":\"api\",\"n\":2}"]]
+                },
+                { "stream": { "tangent_sink": "logs" }, "values": [["45", "not json"]] }
+            ]})
+        );
+    }
+}
@@ -1,29 +1,45 @@
 use ahash::AHasher;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use bytes::BytesMut;
 use rand::{rng, Rng};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::hash::Hasher;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::{sync::Arc, time::Duration};
-use tangent_shared::sinks::common::{SinkConfig, SinkKind};
-use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tangent_shared::runtime::ShardStrategy;
+use tangent_shared::sinks::common::{CircuitBreakerConfig, SinkKind};
+use tangent_shared::Config;
+use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore};
 use tokio::task::{JoinHandle, JoinSet};
-use tokio::time::{sleep, Instant};
+use tokio::time::{sleep, timeout_at, Instant};
+use tokio_util::sync::CancellationToken;
 
+use crate::router::{tag_with_error, FanoutAck};
 use crate::sinks::blackhole;
+use crate::sinks::clickhouse::ClickHouseSink;
+use crate::sinks::dry_run::DryRunSink;
+use crate::sinks::elasticsearch::ElasticsearchSink;
 use crate::sinks::file;
+use crate::sinks::loki::LokiSink;
+use crate::sinks::prometheus_remote_write::PrometheusRemoteWriteSink;
 use crate::sinks::s3::S3SinkItem;
-use crate::INFLIGHT;
+use crate::sinks::{azure_blob, encoding, gcs};
 use crate::{
     sinks::{s3, wal},
     worker::Ack,
 };
+use crate::{INFLIGHT, SINK_CIRCUIT_STATE};
 
 pub struct SinkWrite {
     pub sink_name: Arc<str>,
     pub payload: BytesMut,
     pub s3: Option<S3SinkItem>,
+    /// The `flush_interval_ms` of the edge the payload came in on. WAL-backed
+    /// sinks seal the file it lands in no later than this; others ignore it.
+    pub flush_interval: Option<Duration>,
 }
 
 #[async_trait]
@@ -33,6 +49,39 @@ pub trait Sink: Send + Sync {
     async fn flush(&self) -> Result<()> {
         Ok(())
     }
+
+    /// On-disk files holding data that has not reached its destination yet.
+    async fn pending_files(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// Where a sink writes the events its destination rejects, resolved by the
+/// sink manager once every sink has been built.
+pub struct DeadLetter {
+    pub name: Arc<str>,
+    pub sink: Arc<dyn Sink>,
+    /// Bucket to write under when the dead-letter sink is WAL-backed.
+    pub s3: Option<S3SinkItem>,
+}
+
+impl DeadLetter {
+    /// Write each rejected event tagged with `__tangent_error` set to the
+    /// reason it was rejected.
+    pub async fn write(&self, rejected: &[(&[u8], &str)]) -> Result<()> {
+        let mut payload = BytesMut::new();
+        for (event, reason) in rejected {
+            tag_with_error(&mut payload, event, reason);
+        }
+        self.sink
+            .write(SinkWrite {
+                sink_name: Arc::clone(&self.name),
+                payload,
+                s3: self.s3.clone(),
+                flush_interval: None,
+            })
+            .await
+    }
 }
 
 pub struct SinkItem {
@@ -56,30 +105,361 @@ enum SinkEntry {
     },
 }
 
+impl SinkEntry {
+    fn sink(&self) -> &Arc<dyn Sink> {
+        match self {
+            Self::S3 { sink, .. } => sink,
+            Self::Other { sink } => sink,
+        }
+    }
+}
+
+/// A sink's `key_prefix_field`: events are grouped by the field's value and
+/// each group is written under `<edge prefix>/<value>`.
+struct PrefixSplit {
+    path: Vec<String>,
+    fallback: Option<Arc<str>>,
+    max_values: usize,
+    /// Values already given their own prefix, at most `max_values` of them.
+    seen: std::sync::Mutex<HashSet<String>>,
+    warned: AtomicBool,
+}
+
+impl PrefixSplit {
+    fn new(field: &str, fallback: Option<&str>, max_values: usize) -> Self {
+        Self {
+            path: field.split('.').map(str::to_string).collect(),
+            fallback: fallback.map(Arc::from),
+            max_values,
+            seen: std::sync::Mutex::new(HashSet::new()),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// Group the NDJSON lines of `payload` by the key prefix each is written
+    /// under, in order of first appearance.
+    fn split(&self, base: Option<&Arc<str>>, payload: &[u8]) -> Vec<(Option<Arc<str>>, BytesMut)> {
+        let mut groups: Vec<(Option<Arc<str>>, BytesMut)> = Vec::new();
+        let mut index: HashMap<Option<String>, usize> = HashMap::new();
+        let mut seen = self.seen.lock().unwrap();
+        for line in payload.split(|&b| b == b'\n').filter(|l| !l.is_empty()) {
+            let value = self.value_of(line).filter(|v| {
+                if seen.contains(v) {
+                    return true;
+                }
+                if seen.len() < self.max_values {
+                    seen.insert(v.clone());
+                    return true;
+                }
+                if !self.warned.swap(true, Ordering::Relaxed) {
+                    tracing::warn!(
+                        "more than key_prefix_max_values ({}) key_prefix_field values; new ones are written under the fallback prefix",
+                        self.max_values
+                    );
+                }
+                false
+            });
+            let ix = *index.entry(value.clone()).or_insert_with(|| {
+                let prefix = match value.as_deref().or(self.fallback.as_deref()) {
+                    Some(v) => Some(join_prefix(base, v)),
+                    None => base.cloned(),
+                };
+                groups.push((prefix, BytesMut::new()));
+                groups.len() - 1
+            });
+            let buf = &mut groups[ix].1;
+            buf.extend_from_slice(line);
+            buf.extend_from_slice(b"\n");
+        }
+        groups
+    }
+
+    fn value_of(&self, line: &[u8]) -> Option<String> {
+        let doc: serde_json::Value = serde_json::from_slice(line).ok()?;
+        let v = self.path.iter().try_fold(&doc, |v, key| v.get(key))?;
+        let s = match v {
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Number(n) => n.to_string(),
+            serde_json::Value::Bool(b) => b.to_string(),
+            _ => return None,
+        };
+        (!s.is_empty()).then(|| key_segment(&s))
+    }
+}
+
+/// `value` as a single object key segment: separators and control characters
+/// become `_`, as do `.` and `..` so the key can't climb out of its prefix.
+fn key_segment(value: &str) -> String {
+    if value == "." || value == ".." {
+        return "_".repeat(value.len());
+    }
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn join_prefix(base: Option<&Arc<str>>, value: &str) -> Arc<str> {
+    match base {
+        Some(b) if !b.is_empty() => Arc::from(format!("{}/{value}", b.trim_end_matches('/'))),
+        _ => Arc::from(value),
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DrainStats {
+    /// Payload bytes written to sinks while draining.
+    pub flushed_bytes: u64,
+    /// Payload bytes still queued or mid-write when the deadline passed.
+    pub dropped_bytes: u64,
+    /// WAL files left on disk that can be replayed on the next start.
+    pub pending_files: Vec<PathBuf>,
+}
+
+/// Returned (inside `anyhow::Error`) when `drain_timeout` misses its deadline.
+#[derive(Debug)]
+pub struct DrainTimeout {
+    pub timeout: Duration,
+    pub stats: DrainStats,
+}
+
+impl fmt::Display for DrainTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sink drain timed out after {:?}: {} bytes not flushed, {} files pending",
+            self.timeout,
+            self.stats.dropped_bytes,
+            self.stats.pending_files.len()
+        )
+    }
+}
+
+impl std::error::Error for DrainTimeout {}
+
+/// Bytes/items accepted by `enqueue` that haven't finished writing yet.
+#[derive(Default)]
+struct Pending {
+    items: AtomicUsize,
+    bytes: AtomicU64,
+    flushed_bytes: AtomicU64,
+    idle: Notify,
+}
+
+impl Pending {
+    fn add(&self, bytes: u64) {
+        self.items.fetch_add(1, Ordering::AcqRel);
+        self.bytes.fetch_add(bytes, Ordering::AcqRel);
+    }
+
+    fn done(&self, bytes: u64, flushed: bool) {
+        if flushed {
+            self.flushed_bytes.fetch_add(bytes, Ordering::AcqRel);
+        }
+        self.bytes.fetch_sub(bytes, Ordering::AcqRel);
+        if self.items.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+
+    async fn wait_idle(&self) {
+        loop {
+            let idle = self.idle.notified();
+            if self.items.load(Ordering::Acquire) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Circuit {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { probes: u32 },
+}
+
+/// Per-sink circuit breaker. Shards ask it before each write attempt and
+/// report the result; `enqueue` diverts new items to `dead_letter` while it
+/// is open.
+struct CircuitBreaker {
+    sink: Arc<str>,
+    failure_threshold: u32,
+    probe_interval: Duration,
+    half_open_max_calls: u32,
+    dead_letter: Option<Arc<str>>,
+    state: std::sync::Mutex<Circuit>,
+}
+
+impl CircuitBreaker {
+    fn new(sink: Arc<str>, cfg: &CircuitBreakerConfig) -> Self {
+        let initial = Circuit::Closed { failures: 0 };
+        let cb = Self {
+            sink,
+            failure_threshold: cfg.failure_threshold.max(1),
+            probe_interval: Duration::from_secs(cfg.probe_interval_secs),
+            half_open_max_calls: cfg.half_open_max_calls.max(1),
+            dead_letter: cfg.dead_letter.clone(),
+            state: std::sync::Mutex::new(initial),
+        };
+        cb.publish(initial);
+        cb
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Circuit> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn publish(&self, state: Circuit) {
+        let gauge = match state {
+            Circuit::Closed { .. } => 0,
+            Circuit::HalfOpen { .. } => 1,
+            Circuit::Open { .. } => 2,
+        };
+        SINK_CIRCUIT_STATE
+            .with_label_values(&[self.sink.as_ref()])
+            .set(gauge);
+    }
+
+    /// Where a new item for this sink should go while the circuit is open.
+    fn divert_to(&self) -> Option<&Arc<str>> {
+        let open = matches!(*self.lock(), Circuit::Open { until } if Instant::now() < until);
+        self.dead_letter.as_ref().filter(|_| open)
+    }
+
+    /// Claim a write attempt, or how long to wait before asking again.
+    fn try_call(&self) -> Result<(), Duration> {
+        let mut state = self.lock();
+        match *state {
+            Circuit::Closed { .. } => return Ok(()),
+            Circuit::Open { until } => {
+                let now = Instant::now();
+                if now < until {
+                    return Err(until - now);
+                }
+                tracing::info!(sink = %self.sink, "sink circuit half-open; probing");
+                *state = Circuit::HalfOpen { probes: 1 };
+            }
+            Circuit::HalfOpen { probes } if probes < self.half_open_max_calls => {
+                *state = Circuit::HalfOpen { probes: probes + 1 };
+            }
+            // Wait for the probes in flight to settle the circuit.
+            Circuit::HalfOpen { .. } => {
+                return Err(self.probe_interval.min(Duration::from_millis(500)))
+            }
+        }
+        self.publish(*state);
+        Ok(())
+    }
+
+    /// Report the outcome of an attempt claimed with `try_call`.
+    fn record(&self, ok: bool) {
+        let mut state = self.lock();
+        let next = match (*state, ok) {
+            (Circuit::Closed { .. }, true) => Circuit::Closed { failures: 0 },
+            (_, true) => {
+                tracing::info!(sink = %self.sink, "sink circuit closed");
+                Circuit::Closed { failures: 0 }
+            }
+            (Circuit::Closed { failures }, false) if failures + 1 < self.failure_threshold => {
+                Circuit::Closed {
+                    failures: failures + 1,
+                }
+            }
+            // A write claimed before the circuit opened.
+            (Circuit::Open { .. }, false) => return,
+            (_, false) => {
+                tracing::warn!(
+                    sink = %self.sink,
+                    probe_in = ?self.probe_interval,
+                    "sink circuit open after repeated write failures"
+                );
+                Circuit::Open {
+                    until: Instant::now() + self.probe_interval,
+                }
+            }
+        };
+        *state = next;
+        self.publish(next);
+    }
+}
+
 pub struct SinkManager {
     shards: Vec<Shard>,
     sinks: Arc<HashMap<Arc<str>, SinkEntry>>,
+    breakers: Arc<HashMap<Arc<str>, Arc<CircuitBreaker>>>,
+    pending: Arc<Pending>,
+    strategy: ShardStrategy,
+    next_shard: AtomicUsize,
+    prefix_splits: HashMap<Arc<str>, PrefixSplit>,
+    dry_run: Option<Arc<DryRunSink>>,
 }
 
 impl SinkManager {
-    pub async fn new(cfgs: &BTreeMap<Arc<str>, SinkConfig>) -> Result<Self> {
+    /// Build every configured sink. With `dry_run` set, each one is replaced
+    /// by a `DryRunSink` that prints its batches instead. `shutdown` stops
+    /// WAL-backed sinks holding writes for `max_wal_bytes`.
+    pub async fn new(config: &Config, dry_run: bool, shutdown: &CancellationToken) -> Result<Self> {
+        let cfgs = &config.sinks;
+        let dry_run = dry_run.then(DryRunSink::new);
         let mut sinks: HashMap<Arc<str>, SinkEntry> = HashMap::with_capacity(cfgs.len());
+        let mut prefix_splits = HashMap::new();
+        let mut breakers = HashMap::new();
+        let mut dead_letters: Vec<(Arc<str>, Box<dyn FnOnce(DeadLetter) + Send>)> = Vec::new();
 
         let total_inflight: usize = cfgs.values().map(|c| c.common.in_flight_limit).sum();
 
         for (name, cfg) in cfgs {
+            encoding::ensure_supported(&cfg.common.encoding)
+                .with_context(|| format!("sink {name}"))?;
+            if let Some(field) = &cfg.common.key_prefix_field {
+                prefix_splits.insert(
+                    Arc::clone(name),
+                    PrefixSplit::new(
+                        field,
+                        cfg.common.key_prefix_fallback.as_deref(),
+                        cfg.common.key_prefix_max_values,
+                    ),
+                );
+            }
+            if let Some(dry) = &dry_run {
+                let sink = Arc::clone(dry) as Arc<dyn Sink>;
+                sinks.insert(Arc::clone(name), SinkEntry::Other { sink });
+                continue;
+            }
+            if let Some(cb) = &cfg.common.circuit_breaker {
+                breakers.insert(
+                    Arc::clone(name),
+                    Arc::new(CircuitBreaker::new(Arc::clone(name), cb)),
+                );
+            }
             match &cfg.kind {
                 SinkKind::S3(s3cfg) => {
                     let bucket: Arc<str> = Arc::<str>::from(s3cfg.bucket_name.clone());
-                    let remote = Arc::new(s3::S3Sink::new(Arc::clone(&name), bucket).await?);
+                    let tags = s3cfg
+                        .tags
+                        .as_ref()
+                        .map(|t| s3::ObjectTags::new(t, name, &config.upstream_sources(name)));
+                    let remote = Arc::new(s3::S3Sink::new(Arc::clone(&name), bucket, tags).await?);
                     let s3_sink = wal::DurableFileSink::new(
                         remote,
                         s3cfg.wal_path.clone(),
                         cfg.common.in_flight_limit,
                         cfg.common.object_max_bytes,
                         Duration::from_secs(s3cfg.max_file_age_seconds),
+                        s3cfg.wal_alert_age_secs.map(Duration::from_secs),
+                        cfg.common.max_wal_bytes,
                         cfg.common.compression.clone(),
                         cfg.common.encoding.clone(),
+                        cfg.common.parquet_row_group_size,
+                        cfg.common.zstd_dict.as_deref(),
+                        cfg.common.encryption.as_ref(),
+                        shutdown.clone(),
                     )
                     .await?;
                     sinks.insert(
@@ -90,6 +470,70 @@ impl SinkManager {
                         },
                     );
                 }
+                SinkKind::Gcs(gcscfg) => {
+                    let bucket: Arc<str> = Arc::<str>::from(gcscfg.bucket_name.clone());
+                    let remote = Arc::new(
+                        gcs::GcsSink::new(
+                            Arc::clone(&name),
+                            Arc::clone(&bucket),
+                            gcscfg.key_prefix.as_deref().map(Arc::<str>::from),
+                        )
+                        .await?,
+                    );
+                    let gcs_sink = wal::DurableFileSink::new(
+                        remote,
+                        gcscfg.wal_path.clone(),
+                        cfg.common.in_flight_limit,
+                        cfg.common.object_max_bytes,
+                        Duration::from_secs(gcscfg.max_file_age_seconds),
+                        gcscfg.wal_alert_age_secs.map(Duration::from_secs),
+                        cfg.common.max_wal_bytes,
+                        cfg.common.compression.clone(),
+                        cfg.common.encoding.clone(),
+                        cfg.common.parquet_row_group_size,
+                        cfg.common.zstd_dict.as_deref(),
+                        cfg.common.encryption.as_ref(),
+                        shutdown.clone(),
+                    )
+                    .await?;
+                    // Same WAL routing as S3: the shard fills in the bucket
+                    // and key prefix for each write.
+                    sinks.insert(
+                        Arc::clone(&name),
+                        SinkEntry::S3 {
+                            sink: gcs_sink as Arc<dyn Sink>,
+                            bucket,
+                        },
+                    );
+                }
+                SinkKind::AzureBlob(azcfg) => {
+                    let container: Arc<str> = Arc::<str>::from(azcfg.container_name.clone());
+                    let remote =
+                        Arc::new(azure_blob::AzureBlobSink::new(Arc::clone(&name), azcfg)?);
+                    let azure_sink = wal::DurableFileSink::new(
+                        remote,
+                        azcfg.wal_path.clone(),
+                        cfg.common.in_flight_limit,
+                        cfg.common.object_max_bytes,
+                        Duration::from_secs(azcfg.max_file_age_seconds),
+                        azcfg.wal_alert_age_secs.map(Duration::from_secs),
+                        cfg.common.max_wal_bytes,
+                        cfg.common.compression.clone(),
+                        cfg.common.encoding.clone(),
+                        cfg.common.parquet_row_group_size,
+                        cfg.common.zstd_dict.as_deref(),
+                        cfg.common.encryption.as_ref(),
+                        shutdown.clone(),
+                    )
+                    .await?;
+                    sinks.insert(
+                        Arc::clone(&name),
+                        SinkEntry::S3 {
+                            sink: azure_sink as Arc<dyn Sink>,
+                            bucket: container,
+                        },
+                    );
+                }
                 SinkKind::File(filecfg) => {
                     let file_sink = file::FileSink::new(filecfg, &cfg.common).await?;
                     sinks.insert(Arc::clone(&name), SinkEntry::Other { sink: file_sink });
@@ -98,23 +542,105 @@ impl SinkManager {
                     let bh = blackhole::BlackholeSink::new();
                     sinks.insert(Arc::clone(&name), SinkEntry::Other { sink: bh });
                 }
+                SinkKind::PrometheusRemoteWrite(prwcfg) => {
+                    let prw = PrometheusRemoteWriteSink::new(
+                        Arc::clone(&name),
+                        prwcfg,
+                        cfg.common.in_flight_limit,
+                    )?;
+                    sinks.insert(Arc::clone(&name), SinkEntry::Other { sink: prw });
+                }
+                SinkKind::Loki(lokicfg) => {
+                    let loki =
+                        LokiSink::new(Arc::clone(&name), lokicfg, cfg.common.in_flight_limit)?;
+                    sinks.insert(Arc::clone(&name), SinkEntry::Other { sink: loki });
+                }
+                SinkKind::Elasticsearch(escfg) => {
+                    let es = ElasticsearchSink::new(
+                        Arc::clone(&name),
+                        escfg,
+                        cfg.common.in_flight_limit,
+                    )?;
+                    if let Some(dl) = &escfg.dead_letter {
+                        let es = Arc::clone(&es);
+                        dead_letters
+                            .push((Arc::clone(dl), Box::new(move |d| es.set_dead_letter(d))));
+                    }
+                    sinks.insert(Arc::clone(&name), SinkEntry::Other { sink: es });
+                }
+                SinkKind::ClickHouse(chcfg) => {
+                    let ch =
+                        ClickHouseSink::new(Arc::clone(&name), chcfg, cfg.common.in_flight_limit)?;
+                    if let Some(dl) = &chcfg.dead_letter {
+                        let ch = Arc::clone(&ch);
+                        dead_letters
+                            .push((Arc::clone(dl), Box::new(move |d| ch.set_dead_letter(d))));
+                    }
+                    sinks.insert(Arc::clone(&name), SinkEntry::Other { sink: ch });
+                }
             }
         }
 
-        Ok(Self::from_entries(sinks, total_inflight))
+        // Rejected events are written straight to the dead-letter sink, so
+        // it can only be looked up once every sink exists.
+        for (dl, set_dead_letter) in dead_letters {
+            let entry = sinks
+                .get(&dl)
+                .with_context(|| format!("dead_letter sink {dl} not found"))?;
+            let s3 = match entry {
+                SinkEntry::S3 { bucket, .. } => Some(S3SinkItem {
+                    bucket_name: Arc::clone(bucket),
+                    key_prefix: None,
+                }),
+                SinkEntry::Other { .. } => None,
+            };
+            set_dead_letter(DeadLetter {
+                name: dl,
+                sink: Arc::clone(entry.sink()),
+                s3,
+            });
+        }
+
+        let mut manager = Self::from_entries(
+            sinks,
+            breakers,
+            total_inflight,
+            config.runtime.shard_strategy,
+        );
+        manager.prefix_splits = prefix_splits;
+        manager.dry_run = dry_run;
+        Ok(manager)
     }
 
-    fn from_entries(sinks: HashMap<Arc<str>, SinkEntry>, total_inflight: usize) -> Self {
+    /// Resolves once a dry-run sink has printed its first batch; never for
+    /// a manager built without `dry_run`.
+    pub async fn dry_run_written(&self) {
+        match &self.dry_run {
+            Some(dry) => dry.written().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn from_entries(
+        sinks: HashMap<Arc<str>, SinkEntry>,
+        breakers: HashMap<Arc<str>, Arc<CircuitBreaker>>,
+        total_inflight: usize,
+        strategy: ShardStrategy,
+    ) -> Self {
         let num_shards = 4usize;
         let mut shards = Vec::with_capacity(num_shards);
 
         let sem = Arc::new(Semaphore::new(total_inflight.max(1)));
         let sinks = Arc::new(sinks);
+        let breakers = Arc::new(breakers);
+        let pending = Arc::new(Pending::default());
 
         for _ in 0..num_shards {
             let (tx, mut rx) = mpsc::channel::<SinkItem>(4096);
             let sinks_map = Arc::clone(&sinks);
+            let breakers = Arc::clone(&breakers);
             let sem = sem.clone();
+            let pending = Arc::clone(&pending);
 
             let handle = tokio::spawn(async move {
                 let mut js = JoinSet::new();
@@ -124,12 +650,14 @@ impl SinkManager {
                             let Some(mut item) = maybe else { break };
 
                             let sink_name = item.req.sink_name.clone();
+                            let item_bytes = item.req.payload.len() as u64;
 
                             let entry = match sinks_map.get(&sink_name) {
                                 Some(e) => e,
                                 None => {
                                     tracing::warn!("no sink named '{sink_name}'; dropping item");
                                     for a in item.acks.drain(..) { let _ = a.ack().await; }
+                                    pending.done(item_bytes, false);
                                     continue;
                                 }
                             };
@@ -144,13 +672,12 @@ impl SinkManager {
                                 item.req.s3 = None;
                             }
 
-                            let sink: Arc<dyn Sink> = match entry {
-                                SinkEntry::S3 { sink, .. } => sink.clone(),
-                                SinkEntry::Other { sink } => sink.clone(),
-                            };
+                            let sink: Arc<dyn Sink> = entry.sink().clone();
+                            let breaker = breakers.get(&sink_name).cloned();
 
                             let Ok(permit) = sem.clone().acquire_owned().await else { break };
 
+                            let pending = Arc::clone(&pending);
                             js.spawn(async move {
                                 let _permit: OwnedSemaphorePermit = permit;
                                 let start = Instant::now();
@@ -159,6 +686,12 @@ impl SinkManager {
                                 let frozen = item.req.payload.clone().freeze();
                                 let mut first_attempt = true;
                                 loop {
+                                    if let Some(cb) = &breaker {
+                                        if let Err(wait) = cb.try_call() {
+                                            sleep(wait).await;
+                                            continue;
+                                        }
+                                    }
                                     let payload_to_send = if first_attempt {
                                         first_attempt = false;
                                         std::mem::take(&mut item.req.payload)
@@ -170,8 +703,12 @@ impl SinkManager {
                                         sink_name: sink_name.clone(),
                                         payload: payload_to_send,
                                         s3: item.req.s3.clone(),
+                                        flush_interval: item.req.flush_interval,
                                     }).await {
                                         Ok(()) => {
+                                            if let Some(cb) = &breaker {
+                                                cb.record(true);
+                                            }
                                             for a in item.acks.drain(..) {
                                                 if let Err(e) = a.ack().await {
                                                     tracing::warn!("ack failed: {e}");
@@ -182,10 +719,14 @@ impl SinkManager {
                                                 "wrote sink item"
                                             );
                                             INFLIGHT.dec();
+                                            pending.done(item_bytes, true);
                                             break;
                                         }
                                         Err(e) => {
                                             tracing::warn!("sink write failed: {e}");
+                                            if let Some(cb) = &breaker {
+                                                cb.record(false);
+                                            }
                                             let j = rng().random_range(0..=delay.as_millis() as u64 / 4);
                                             sleep(delay + Duration::from_millis(j)).await;
                                             delay = (delay * 2).min(Duration::from_secs(5));
@@ -202,7 +743,16 @@ impl SinkManager {
             shards.push(Shard { tx, handle });
         }
 
-        Self { shards, sinks }
+        Self {
+            shards,
+            sinks,
+            breakers,
+            pending,
+            strategy,
+            next_shard: AtomicUsize::new(0),
+            prefix_splits: HashMap::new(),
+            dry_run: None,
+        }
     }
 
     #[cfg(test)]
@@ -211,9 +761,33 @@ impl SinkManager {
             .into_iter()
             .map(|(name, sink)| (name, SinkEntry::Other { sink }))
             .collect();
-        Self::from_entries(entries, total_inflight)
+        Self::from_entries(
+            entries,
+            HashMap::new(),
+            total_inflight,
+            ShardStrategy::HashByKeyPrefix,
+        )
+    }
+
+    fn shard_for(&self, sink_name: &str, key_prefix: Option<&str>) -> usize {
+        match self.strategy {
+            ShardStrategy::HashByKeyPrefix => {
+                let mut h = AHasher::default();
+                h.write(sink_name.as_bytes());
+                if let Some(prefix) = key_prefix {
+                    h.write_u8(b'|');
+                    h.write(prefix.as_bytes());
+                }
+                (h.finish() as usize) % self.shards.len()
+            }
+            ShardStrategy::RoundRobin => {
+                self.next_shard.fetch_add(1, Ordering::Relaxed) % self.shards.len()
+            }
+        }
     }
 
+    /// Queue `payload` for `sink_name`. Sinks with `key_prefix_field` get one
+    /// item per prefix found in the payload; `acks` fire once all have landed.
     pub async fn enqueue(
         &self,
         sink_name: Arc<str>,
@@ -221,14 +795,76 @@ impl SinkManager {
         payload: BytesMut,
         acks: Vec<Arc<dyn Ack>>,
     ) -> Result<()> {
-        let shard_ix = {
-            let mut h = AHasher::default();
-            h.write(sink_name.as_bytes());
-            if let Some(prefix) = key_prefix.clone() {
-                h.write_u8(b'|');
-                h.write(prefix.as_bytes());
+        self.enqueue_with_flush_interval(sink_name, key_prefix, payload, acks, None)
+            .await
+    }
+
+    /// Like `enqueue`, but the payload's WAL file is sealed within
+    /// `flush_interval` of being opened.
+    pub async fn enqueue_with_flush_interval(
+        &self,
+        sink_name: Arc<str>,
+        key_prefix: Option<Arc<str>>,
+        payload: BytesMut,
+        acks: Vec<Arc<dyn Ack>>,
+        flush_interval: Option<Duration>,
+    ) -> Result<()> {
+        let Some(split) = self.prefix_splits.get(&sink_name) else {
+            return self
+                .enqueue_one(sink_name, key_prefix, payload, acks, flush_interval)
+                .await;
+        };
+
+        let mut groups = split.split(key_prefix.as_ref(), &payload);
+        match groups.len() {
+            0 => {
+                self.enqueue_one(sink_name, key_prefix, payload, acks, flush_interval)
+                    .await
+            }
+            1 => {
+                let (prefix, part) = groups.remove(0);
+                self.enqueue_one(sink_name, prefix, part, acks, flush_interval)
+                    .await
+            }
+            n => {
+                let shared: Arc<dyn Ack> = Arc::new(FanoutAck::new(acks, n));
+                for (prefix, part) in groups {
+                    self.enqueue_one(
+                        sink_name.clone(),
+                        prefix,
+                        part,
+                        vec![shared.clone()],
+                        flush_interval,
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn enqueue_one(
+        &self,
+        sink_name: Arc<str>,
+        key_prefix: Option<Arc<str>>,
+        payload: BytesMut,
+        acks: Vec<Arc<dyn Ack>>,
+        flush_interval: Option<Duration>,
+    ) -> Result<()> {
+        let sink_name = match self.breakers.get(&sink_name).and_then(|cb| cb.divert_to()) {
+            Some(dead_letter) => {
+                tracing::debug!("sink '{sink_name}' circuit open; sending item to '{dead_letter}'");
+                Arc::clone(dead_letter)
             }
-            (h.finish() as usize) % self.shards.len()
+            None => sink_name,
+        };
+        let shard_ix = self.shard_for(&sink_name, key_prefix.as_deref());
+        // A dry run must leave the sources as it found them: never delete SQS
+        // messages or commit Kafka offsets for batches that were only printed.
+        let acks = if self.dry_run.is_some() {
+            Vec::new()
+        } else {
+            acks
         };
 
         if !self.sinks.contains_key(&sink_name) {
@@ -236,6 +872,7 @@ impl SinkManager {
             anyhow::bail!("unknown sink: {sink_name}");
         }
 
+        let item_bytes = payload.len() as u64;
         let sink_item = SinkItem {
             acks,
             req: SinkWrite {
@@ -245,9 +882,11 @@ impl SinkManager {
                     bucket_name: Arc::<str>::from(""), // placeholder; filled in shard
                     key_prefix: Some(kp),
                 }),
+                flush_interval,
             },
         };
 
+        self.pending.add(item_bytes);
         self.shards[shard_ix]
             .tx
             .send(sink_item)
@@ -255,26 +894,74 @@ impl SinkManager {
             .map(|()| {
                 INFLIGHT.inc();
             })
-            .map_err(|e| anyhow::anyhow!("send to shard {shard_ix} failed: {e}"))
+            .map_err(|e| {
+                self.pending.done(item_bytes, false);
+                anyhow::anyhow!("send to shard {shard_ix} failed: {e}")
+            })
     }
 
-    /// Drain and flush everything.
-    pub async fn join(self) -> Result<()> {
-        let Self { shards, sinks, .. } = self;
+    /// Wait for queued writes to land and flush every sink, giving up at
+    /// `timeout`. On timeout the returned error wraps a [`DrainTimeout`] and
+    /// any WAL files still on disk are logged so they can be replayed.
+    ///
+    /// Stop enqueueing before calling this; writes that arrive while draining
+    /// extend the wait.
+    pub async fn drain_timeout(&self, timeout: Duration) -> Result<DrainStats> {
+        let deadline = Instant::now() + timeout;
+        let flushed_before = self.pending.flushed_bytes.load(Ordering::Acquire);
+
+        let drained = timeout_at(deadline, async {
+            self.pending.wait_idle().await;
+            for (nm, entry) in self.sinks.iter() {
+                if let Err(e) = entry.sink().flush().await {
+                    tracing::warn!("sink '{nm}' flush failed during drain: {e}");
+                    return Err(e);
+                }
+            }
+            Ok(())
+        })
+        .await;
 
-        for sh in shards.into_iter() {
+        let mut stats = DrainStats {
+            flushed_bytes: self.pending.flushed_bytes.load(Ordering::Acquire) - flushed_before,
+            dropped_bytes: 0,
+            pending_files: Vec::new(),
+        };
+        for entry in self.sinks.values() {
+            stats
+                .pending_files
+                .extend(entry.sink().pending_files().await);
+        }
+
+        match drained {
+            Ok(res) => res.map(|()| stats),
+            Err(_) => {
+                stats.dropped_bytes = self.pending.bytes.load(Ordering::Acquire);
+                for path in &stats.pending_files {
+                    tracing::warn!(path = %path.display(), "sink file still pending after drain timeout");
+                }
+                Err(DrainTimeout { timeout, stats }.into())
+            }
+        }
+    }
+
+    /// Stop accepting items and wait for the shards to finish their writes.
+    pub async fn close(self) {
+        for sh in self.shards {
             drop(sh.tx);
             if let Err(e) = sh.handle.await {
                 tracing::warn!("shard join error: {e}");
             }
         }
+    }
+
+    /// Drain and flush everything.
+    pub async fn join(self) -> Result<()> {
+        let sinks = Arc::clone(&self.sinks);
+        self.close().await;
 
         for (nm, entry) in sinks.iter() {
-            let sink: &Arc<dyn Sink> = match entry {
-                SinkEntry::S3 { sink, .. } => sink,
-                SinkEntry::Other { sink } => sink,
-            };
-            if let Err(e) = sink.flush().await {
+            if let Err(e) = entry.sink().flush().await {
                 tracing::warn!("sink '{nm}' flush failed during shutdown: {e}");
                 return Err(e);
             }
@@ -284,7 +971,7 @@ impl SinkManager {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::worker::Ack;
     use anyhow::Result;
@@ -293,19 +980,26 @@ mod tests {
     use std::sync::atomic::{AtomicUsize, Ordering};
     use tokio::sync::Mutex;
 
+    /// Keeps every payload written to it, with its key prefix.
     #[derive(Default)]
-    struct RecordingSink {
-        writes: Mutex<Vec<Vec<u8>>>,
+    pub(crate) struct RecordingSink {
+        writes: Mutex<Vec<(Option<Arc<str>>, Vec<u8>)>>,
     }
 
     impl RecordingSink {
-        fn new() -> Arc<Self> {
-            Arc::new(Self {
-                writes: Mutex::new(Vec::new()),
-            })
+        pub(crate) fn new() -> Arc<Self> {
+            Arc::new(Self::default())
         }
 
-        async fn take(&self) -> Vec<Vec<u8>> {
+        pub(crate) async fn take(&self) -> Vec<Vec<u8>> {
+            self.take_prefixed()
+                .await
+                .into_iter()
+                .map(|(_, p)| p)
+                .collect()
+        }
+
+        pub(crate) async fn take_prefixed(&self) -> Vec<(Option<Arc<str>>, Vec<u8>)> {
             self.writes.lock().await.drain(..).collect()
         }
     }
@@ -313,7 +1007,11 @@ mod tests {
     #[async_trait]
     impl Sink for RecordingSink {
         async fn write(&self, req: SinkWrite) -> Result<()> {
-            self.writes.lock().await.push(req.payload.freeze().to_vec());
+            let prefix = req.s3.and_then(|m| m.key_prefix);
+            self.writes
+                .lock()
+                .await
+                .push((prefix, req.payload.freeze().to_vec()));
             Ok(())
         }
     }
@@ -337,6 +1035,62 @@ mod tests {
         }
     }
 
+    struct StuckSink;
+
+    #[async_trait]
+    impl Sink for StuckSink {
+        async fn write(&self, _req: SinkWrite) -> Result<()> {
+            std::future::pending().await
+        }
+
+        async fn pending_files(&self) -> Vec<PathBuf> {
+            vec![PathBuf::from("/wal/stuck.bin.sealed")]
+        }
+    }
+
+    #[tokio::test]
+    async fn drain_timeout_reports_flushed_bytes() {
+        let sink_name: Arc<str> = Arc::from("recorder");
+        let recorder = RecordingSink::new();
+        let manager = SinkManager::for_test(vec![(sink_name.clone(), recorder.clone())], 2);
+
+        let payload = "{\"msg\":1}\n";
+        manager
+            .enqueue(sink_name, None, BytesMut::from(payload), Vec::new())
+            .await
+            .unwrap();
+
+        let stats = manager.drain_timeout(Duration::from_secs(1)).await.unwrap();
+        assert_eq!(stats.flushed_bytes, payload.len() as u64);
+        assert_eq!(stats.dropped_bytes, 0);
+        assert!(stats.pending_files.is_empty());
+        assert_eq!(recorder.take().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn drain_timeout_errors_with_pending_state() {
+        let sink_name: Arc<str> = Arc::from("stuck");
+        let manager = SinkManager::for_test(vec![(sink_name.clone(), Arc::new(StuckSink))], 2);
+
+        let payload = "{\"msg\":1}\n";
+        manager
+            .enqueue(sink_name, None, BytesMut::from(payload), Vec::new())
+            .await
+            .unwrap();
+
+        let err = manager
+            .drain_timeout(Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        let timeout = err.downcast_ref::<DrainTimeout>().unwrap();
+        assert_eq!(timeout.stats.flushed_bytes, 0);
+        assert_eq!(timeout.stats.dropped_bytes, payload.len() as u64);
+        assert_eq!(
+            timeout.stats.pending_files,
+            vec![PathBuf::from("/wal/stuck.bin.sealed")]
+        );
+    }
+
     #[tokio::test]
     async fn join_drains_inflight_items() {
         let sink_name: Arc<str> = Arc::from("recorder");
@@ -371,4 +1125,216 @@ mod tests {
         assert_eq!(writes.len(), 2);
         assert_eq!(ack.count(), 1);
     }
+
+    #[tokio::test]
+    async fn dry_run_never_acks_the_source() {
+        let sink_name: Arc<str> = Arc::from("lake");
+        let dry = DryRunSink::new();
+        let mut manager =
+            SinkManager::for_test(vec![(sink_name.clone(), dry.clone() as Arc<dyn Sink>)], 1);
+        manager.dry_run = Some(dry);
+
+        let ack = Arc::new(TestAck::default());
+        manager
+            .enqueue(
+                sink_name,
+                None,
+                BytesMut::from("{\"msg\":1}\n"),
+                vec![ack.clone() as Arc<dyn Ack>],
+            )
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(1), manager.dry_run_written())
+            .await
+            .unwrap();
+        manager.join().await.unwrap();
+
+        assert_eq!(ack.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn key_prefix_field_splits_batches_per_value() {
+        let sink_name: Arc<str> = Arc::from("lake");
+        let recorder = RecordingSink::new();
+        let entries = HashMap::from([(
+            sink_name.clone(),
+            SinkEntry::S3 {
+                sink: recorder.clone() as Arc<dyn Sink>,
+                bucket: Arc::from("bucket"),
+            },
+        )]);
+        let mut manager =
+            SinkManager::from_entries(entries, HashMap::new(), 2, ShardStrategy::HashByKeyPrefix);
+        manager.prefix_splits.insert(
+            sink_name.clone(),
+            PrefixSplit::new("tenant.id", Some("unknown"), 1000),
+        );
+
+        let ack = Arc::new(TestAck::default());
+        let payload = concat!(
+            "{\"tenant\":{\"id\":\"acme\"},\"n\":1}\n",
+            "{\"tenant\":{\"id\":42},\"n\":2}\n",
+            "{\"n\":3}\n",
+            "{\"tenant\":{\"id\":\"acme\"},\"n\":4}\n",
+        );
+        manager
+            .enqueue(
+                sink_name,
+                Some(Arc::from("logs/")),
+                BytesMut::from(payload),
+                vec![ack.clone() as Arc<dyn Ack>],
+            )
+            .await
+            .unwrap();
+        manager.join().await.unwrap();
+
+        let mut writes = recorder.take_prefixed().await;
+        writes.sort();
+        let expected: Vec<(Option<Arc<str>>, Vec<u8>)> = vec![
+            (
+                Some(Arc::from("logs/42")),
+                b"{\"tenant\":{\"id\":42},\"n\":2}\n".to_vec(),
+            ),
+            (
+                Some(Arc::from("logs/acme")),
+                b"{\"tenant\":{\"id\":\"acme\"},\"n\":1}\n{\"tenant\":{\"id\":\"acme\"},\"n\":4}\n"
+                    .to_vec(),
+            ),
+            (Some(Arc::from("logs/unknown")), b"{\"n\":3}\n".to_vec()),
+        ];
+        assert_eq!(writes, expected);
+        assert_eq!(ack.count(), 1);
+    }
+
+    #[test]
+    fn prefix_values_are_sanitized_and_capped() {
+        let split = PrefixSplit::new("t", Some("other"), 2);
+        let payload = concat!(
+            "{\"t\":\"a\"}\n",
+            "{\"t\":\"b/c\\u0007\"}\n",
+            "{\"t\":\"..\"}\n",
+            "{\"t\":\"a\"}\n",
+        );
+        let groups: Vec<(Option<Arc<str>>, usize)> = split
+            .split(Some(&Arc::from("logs")), payload.as_bytes())
+            .into_iter()
+            .map(|(prefix, part)| (prefix, part.len()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (Some(Arc::from("logs/a")), 20),
+                (Some(Arc::from("logs/b_c_")), 18),
+                (Some(Arc::from("logs/other")), 11),
+            ]
+        );
+    }
+
+    /// Fails every write until `healthy` is set.
+    #[derive(Default)]
+    struct FlakySink {
+        healthy: std::sync::atomic::AtomicBool,
+        attempts: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Sink for FlakySink {
+        async fn write(&self, _req: SinkWrite) -> Result<()> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            if self.healthy.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                anyhow::bail!("sink unavailable")
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn open_circuit_diverts_new_items_to_dead_letter() {
+        let (flaky_name, dlq_name): (Arc<str>, Arc<str>) = (Arc::from("flaky"), Arc::from("dlq"));
+        let flaky = Arc::new(FlakySink::default());
+        let dlq = RecordingSink::new();
+        let entries = HashMap::from([
+            (
+                flaky_name.clone(),
+                SinkEntry::Other {
+                    sink: flaky.clone() as Arc<dyn Sink>,
+                },
+            ),
+            (
+                dlq_name.clone(),
+                SinkEntry::Other {
+                    sink: dlq.clone() as Arc<dyn Sink>,
+                },
+            ),
+        ]);
+        let mut breaker = CircuitBreaker::new(
+            flaky_name.clone(),
+            &CircuitBreakerConfig {
+                failure_threshold: 2,
+                probe_interval_secs: 0,
+                half_open_max_calls: 1,
+                dead_letter: Some(dlq_name.clone()),
+            },
+        );
+        breaker.probe_interval = Duration::from_millis(300);
+        let breaker = Arc::new(breaker);
+        let manager = SinkManager::from_entries(
+            entries,
+            HashMap::from([(flaky_name.clone(), breaker.clone())]),
+            4,
+            ShardStrategy::HashByKeyPrefix,
+        );
+        let gauge = || SINK_CIRCUIT_STATE.with_label_values(&["flaky"]).get();
+
+        manager
+            .enqueue(
+                flaky_name.clone(),
+                None,
+                BytesMut::from("{\"n\":1}\n"),
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        while !matches!(*breaker.lock(), Circuit::Open { .. }) {
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(gauge(), 2);
+
+        manager
+            .enqueue(
+                flaky_name.clone(),
+                None,
+                BytesMut::from("{\"n\":2}\n"),
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        flaky.healthy.store(true, Ordering::SeqCst);
+        manager.join().await.unwrap();
+
+        assert_eq!(dlq.take().await, vec![b"{\"n\":2}\n".to_vec()]);
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(gauge(), 0);
+    }
+
+    #[tokio::test]
+    async fn round_robin_spreads_one_prefix_across_shards() {
+        let sink_name: Arc<str> = Arc::from("recorder");
+        let mut manager = SinkManager::for_test(vec![(sink_name.clone(), RecordingSink::new())], 2);
+
+        let hashed = manager.shard_for(&sink_name, Some("logs/"));
+        assert!((0..10).all(|_| manager.shard_for(&sink_name, Some("logs/")) == hashed));
+
+        manager.strategy = ShardStrategy::RoundRobin;
+        let mut counts = vec![0usize; manager.shards.len()];
+        for _ in 0..400 {
+            counts[manager.shard_for(&sink_name, Some("logs/"))] += 1;
+        }
+        assert!(
+            counts.iter().all(|c| *c == 400 / counts.len()),
+            "{counts:?}"
+        );
+    }
 }
@@ -1,6 +1,14 @@
+pub mod azure_blob;
 pub mod blackhole;
+pub mod clickhouse;
+pub mod dry_run;
+pub mod elasticsearch;
 pub mod encoding;
+pub mod encryption;
 pub mod file;
+pub mod gcs;
+pub mod loki;
 pub mod manager;
+pub mod prometheus_remote_write;
 pub mod s3;
 pub mod wal;
@@ -0,0 +1,251 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use prost::Message;
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tangent_shared::sinks::prometheus_remote_write::PrometheusRemoteWriteConfig;
+use tokio::sync::Semaphore;
+
+use crate::sinks::manager::{Sink, SinkWrite};
+use crate::{SINK_BYTES_TOTAL, SINK_BYTES_UNCOMPRESSED_TOTAL, SINK_OBJECTS_TOTAL};
+
+/// Cap on one remote_write request, so an endpoint that stops responding
+/// can't hold an in-flight slot forever.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `prometheus.WriteRequest` from the remote_write 1.0 protocol.
+#[derive(Clone, PartialEq, Message)]
+pub struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TimeSeries {
+    /// Sorted by name, `__name__` included.
+    #[prost(message, repeated, tag = "1")]
+    pub labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Sample {
+    #[prost(double, tag = "1")]
+    pub value: f64,
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+}
+
+pub struct PrometheusRemoteWriteSink {
+    name: Arc<str>,
+    client: Client,
+    endpoint: String,
+    bearer_token: Option<SecretString>,
+    batch_max_samples: usize,
+    /// One permit per request in flight, sized by `in_flight_limit`.
+    in_flight: Semaphore,
+}
+
+impl PrometheusRemoteWriteSink {
+    pub fn new(
+        name: Arc<str>,
+        cfg: &PrometheusRemoteWriteConfig,
+        in_flight_limit: usize,
+    ) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            name,
+            client: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .context("building remote_write client")?,
+            endpoint: cfg.endpoint.clone(),
+            bearer_token: cfg.bearer_token.clone(),
+            batch_max_samples: cfg.batch_max_samples.max(1),
+            in_flight: Semaphore::new(in_flight_limit.max(1)),
+        }))
+    }
+
+    async fn send(&self, series: Vec<TimeSeries>) -> Result<()> {
+        let body = WriteRequest { timeseries: series }.encode_to_vec();
+        let compressed = snap::raw::Encoder::new()
+            .compress_vec(&body)
+            .context("snappy-compressing WriteRequest")?;
+
+        let _permit = self.in_flight.acquire().await?;
+        let mut req = self
+            .client
+            .post(&self.endpoint)
+            .header("Content-Encoding", "snappy")
+            .header("Content-Type", "application/x-protobuf")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(compressed.clone());
+        if let Some(token) = &self.bearer_token {
+            req = req.bearer_auth(token.expose_secret());
+        }
+        let resp = req
+            .send()
+            .await
+            .with_context(|| format!("remote_write to {}", self.endpoint))?;
+
+        let status = resp.status();
+        if status.is_success() {
+            SINK_OBJECTS_TOTAL.inc();
+            SINK_BYTES_TOTAL.inc_by(compressed.len() as u64);
+            SINK_BYTES_UNCOMPRESSED_TOTAL.inc_by(body.len() as u64);
+            return Ok(());
+        }
+
+        let text = resp.text().await.unwrap_or_default();
+        // Per the spec, 4xx (other than 429) won't succeed on retry.
+        if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+            tracing::error!(
+                sink = %self.name,
+                "remote_write rejected batch with {status}; dropping it: {text}"
+            );
+            return Ok(());
+        }
+        anyhow::bail!(
+            "remote_write to {} failed with {status}: {text}",
+            self.endpoint
+        )
+    }
+}
+
+#[async_trait]
+impl Sink for PrometheusRemoteWriteSink {
+    async fn write(&self, req: SinkWrite) -> Result<()> {
+        let mut samples = Vec::new();
+        let mut skipped = 0usize;
+        for line in req.payload[..]
+            .split(|&b| b == b'\n')
+            .filter(|l| !l.is_empty())
+        {
+            match parse_sample(line) {
+                Some(s) => samples.push(s),
+                None => skipped += 1,
+            }
+        }
+        if skipped > 0 {
+            tracing::warn!(
+                sink = %self.name,
+                "skipped {skipped} line(s) without a __name__ and numeric __value__"
+            );
+        }
+
+        for chunk in samples.chunks(self.batch_max_samples) {
+            self.send(group_series(chunk)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// One NDJSON line as sorted labels and a sample. `None` if it has no
+/// `__name__` or no numeric `__value__`.
+fn parse_sample(line: &[u8]) -> Option<(Vec<Label>, Sample)> {
+    let obj: Map<String, Value> = serde_json::from_slice(line).ok()?;
+
+    let value = match obj.get("__value__")? {
+        Value::Number(n) => n.as_f64()?,
+        // Prometheus accepts "NaN", "+Inf" and "-Inf".
+        Value::String(s) => s.parse().ok()?,
+        _ => return None,
+    };
+    let timestamp = match obj.get("__timestamp_ms__") {
+        Some(v) => v.as_i64()?,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64,
+    };
+    if !matches!(obj.get("__name__"), Some(Value::String(n)) if !n.is_empty()) {
+        return None;
+    }
+
+    let mut labels: Vec<Label> = obj
+        .into_iter()
+        .filter(|(k, _)| k != "__value__" && k != "__timestamp_ms__")
+        .filter_map(|(name, v)| {
+            let value = match v {
+                Value::String(s) => s,
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => return None,
+            };
+            Some(Label { name, value })
+        })
+        .collect();
+    labels.sort();
+    Some((labels, Sample { value, timestamp }))
+}
+
+/// Merge samples with the same labels into one series, oldest first.
+fn group_series(samples: &[(Vec<Label>, Sample)]) -> Vec<TimeSeries> {
+    let mut by_labels: BTreeMap<&[Label], Vec<Sample>> = BTreeMap::new();
+    for (labels, sample) in samples {
+        by_labels
+            .entry(labels.as_slice())
+            .or_default()
+            .push(sample.clone());
+    }
+    by_labels
+        .into_iter()
+        .map(|(labels, mut samples)| {
+            samples.sort_by_key(|s| s.timestamp);
+            TimeSeries {
+                labels: labels.to_vec(),
+                samples,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_become_sorted_series() {
+        let lines = [
+            r#"{"__name__":"http_requests","__value__":3,"__timestamp_ms__":2000,"route":"/a","ok":true}"#,
+            r#"{"__name__":"http_requests","__value__":1,"__timestamp_ms__":1000,"ok":true,"route":"/a"}"#,
+            r#"{"__name__":"queue_depth","__value__":"NaN","__timestamp_ms__":1000}"#,
+            r#"{"__name__":"missing_value","__timestamp_ms__":1000}"#,
+        ];
+        let samples: Vec<_> = lines
+            .iter()
+            .filter_map(|l| parse_sample(l.as_bytes()))
+            .collect();
+        assert_eq!(samples.len(), 3);
+
+        let series = group_series(&samples);
+        assert_eq!(series.len(), 2);
+        let names: Vec<&str> = series[0].labels.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["__name__", "ok", "route"]);
+        let ts: Vec<i64> = series[0].samples.iter().map(|s| s.timestamp).collect();
+        assert_eq!(ts, [1000, 2000]);
+        assert!(series[1].samples[0].value.is_nan());
+
+        let decoded = WriteRequest::decode(
+            WriteRequest {
+                timeseries: series.clone(),
+            }
+            .encode_to_vec()
+            .as_slice(),
+        )
+        .unwrap();
+        assert_eq!(decoded.timeseries.len(), 2);
+    }
+}
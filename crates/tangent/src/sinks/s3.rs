@@ -4,6 +4,8 @@ use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
 use aws_sdk_s3::Client;
 use aws_smithy_runtime_api::client::result::SdkError;
 use aws_smithy_types::byte_stream::ByteStream;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 use tangent_shared::sinks::common::{Compression, Encoding};
@@ -17,6 +19,52 @@ pub struct S3Sink {
     client: Client,
     bucket_name: Arc<str>,
     part_size: usize,
+    tags: Option<ObjectTags>,
+}
+
+/// Unreserved URL characters are left as-is in the `Tagging` header.
+const TAG_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Configured object tags with `{sink_name}` and `{source_name}` filled in.
+/// `{date}` changes between uploads, so it's substituted per request.
+pub struct ObjectTags {
+    pairs: Vec<(String, String)>,
+}
+
+impl ObjectTags {
+    pub fn new(tags: &HashMap<String, String>, sink_name: &str, sources: &[Arc<str>]) -> Self {
+        let source_name = sources.join("+");
+        let mut pairs: Vec<(String, String)> = tags
+            .iter()
+            .map(|(k, v)| {
+                let v = v
+                    .replace("{sink_name}", sink_name)
+                    .replace("{source_name}", &source_name);
+                (k.clone(), v)
+            })
+            .collect();
+        pairs.sort();
+        Self { pairs }
+    }
+
+    /// URL-encoded `k=v&k2=v2` form expected by the S3 `Tagging` parameter.
+    pub fn tagging(&self, date: &str) -> String {
+        self.pairs
+            .iter()
+            .map(|(k, v)| {
+                format!(
+                    "{}={}",
+                    utf8_percent_encode(k, TAG_ENCODE),
+                    utf8_percent_encode(&v.replace("{date}", date), TAG_ENCODE)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
 }
 
 #[derive(Clone)]
@@ -37,16 +85,13 @@ impl WALSink for S3Sink {
     ) -> Result<()> {
         let key = object_key_from(path, meta.key_prefix.as_deref(), encoding, compression);
 
-        let content_type = Encoding::content_type(encoding);
-        let content_encoding = match compression {
-            Compression::None => None,
-            Compression::Gzip { .. } => Some("gzip"),
-            Compression::Zstd { .. } => Some("zstd"),
-            Compression::Snappy { .. } => None,
-            Compression::Deflate { .. } => None,
-        };
+        let (content_type, content_encoding) = content_headers(path, encoding, compression);
 
         let size = tokio::fs::metadata(path).await?.len();
+        let tagging = self.tags.as_ref().map(|t| {
+            let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+            t.tagging(&date)
+        });
 
         if size < 5 * 1024 * 1024 {
             let mut put = self
@@ -60,6 +105,9 @@ impl WALSink for S3Sink {
             if let Some(enc) = content_encoding {
                 put = put.content_encoding(enc);
             }
+            if let Some(tags) = &tagging {
+                put = put.tagging(tags);
+            }
             put.send().await.map_err(|e| {
                 if let SdkError::ServiceError(se) = &e {
                     let err = se.err();
@@ -88,6 +136,11 @@ impl WALSink for S3Sink {
         if let Some(enc) = content_encoding {
             create = create.content_encoding(enc);
         }
+        // Multipart uploads take their tags at creation; CompleteMultipartUpload
+        // has no Tagging parameter.
+        if let Some(tags) = &tagging {
+            create = create.tagging(tags);
+        }
 
         let create = create.send().await.map_err(|e| {
             if let SdkError::ServiceError(se) = &e {
@@ -204,20 +257,65 @@ impl WALSink for S3Sink {
 }
 
 impl S3Sink {
-    pub async fn new(name: Arc<str>, bucket_name: Arc<str>) -> Result<Self> {
+    pub async fn new(
+        name: Arc<str>,
+        bucket_name: Arc<str>,
+        tags: Option<ObjectTags>,
+    ) -> Result<Self> {
         let aws_cfg = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
         let client = Client::new(&aws_cfg);
 
-        Ok(Self {
+        Ok(Self::with_client(name, client, bucket_name, tags))
+    }
+
+    fn with_client(
+        name: Arc<str>,
+        client: Client,
+        bucket_name: Arc<str>,
+        tags: Option<ObjectTags>,
+    ) -> Self {
+        Self {
             name: name,
             client,
             bucket_name: bucket_name,
             part_size: 8 * 1024 * 1024,
-        })
+            tags,
+        }
+    }
+}
+
+/// `Content-Encoding` for objects written with `comp`, when the codec has a
+/// standard HTTP name.
+pub(crate) fn content_encoding_for(comp: &Compression) -> Option<&'static str> {
+    match comp {
+        Compression::None => None,
+        Compression::Gzip { .. } => Some("gzip"),
+        Compression::Zstd { .. } => Some("zstd"),
+        Compression::Snappy { .. } => None,
+        Compression::Deflate { .. } => Some("deflate"),
     }
 }
 
-fn object_key_from(
+/// `Content-Type` and `Content-Encoding` for the object uploaded from
+/// `local_path`. Encrypted files are opaque bytes: a client honouring
+/// `Content-Encoding` would try to inflate ciphertext.
+pub(crate) fn content_headers(
+    local_path: &Path,
+    enc: &Encoding,
+    comp: &Compression,
+) -> (&'static str, Option<&'static str>) {
+    if is_encrypted(local_path) {
+        ("application/octet-stream", None)
+    } else {
+        (enc.content_type(), content_encoding_for(comp))
+    }
+}
+
+fn is_encrypted(local_path: &Path) -> bool {
+    local_path.extension().is_some_and(|e| e == "enc")
+}
+
+pub(crate) fn object_key_from(
     local_path: &Path,
     prefix: Option<&str>,
     enc: &Encoding,
@@ -229,6 +327,9 @@ fn object_key_from(
     let mut name = String::from(stem.as_ref());
     name.push_str(enc.extension());
     name.push_str(comp.extension());
+    if is_encrypted(local_path) {
+        name.push_str(".enc");
+    }
 
     if let Some(p) = prefix {
         if p.is_empty() {
@@ -240,3 +341,82 @@ fn object_key_from(
         name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::operation::put_object::PutObjectOutput;
+    use aws_smithy_mocks::{mock, mock_client};
+
+    fn tags() -> ObjectTags {
+        let cfg = HashMap::from([
+            ("team".to_string(), "data platform".to_string()),
+            ("sink".to_string(), "{sink_name}".to_string()),
+            ("source".to_string(), "{source_name}".to_string()),
+            ("day".to_string(), "{date}".to_string()),
+        ]);
+        ObjectTags::new(&cfg, "lake", &["files".into(), "kafka".into()])
+    }
+
+    #[test]
+    fn tagging_substitutes_and_url_encodes() {
+        assert_eq!(
+            tags().tagging("2025-01-02"),
+            "day=2025-01-02&sink=lake&source=files%2Bkafka&team=data%20platform"
+        );
+    }
+
+    #[tokio::test]
+    async fn put_object_sends_tagging() {
+        let put = mock!(aws_sdk_s3::Client::put_object)
+            .match_requests(|req| {
+                req.tagging().is_some_and(|t| {
+                    t.contains("sink=lake&source=files%2Bkafka&team=data%20platform")
+                })
+            })
+            .then_output(|| PutObjectOutput::builder().build());
+        let client = mock_client!(aws_sdk_s3, &[&put]);
+        let sink = S3Sink::with_client("lake".into(), client, "bucket".into(), Some(tags()));
+
+        let path = std::env::temp_dir().join(format!("tangent-s3-{}.ndjson", ulid::Ulid::new()));
+        tokio::fs::write(&path, b"{\"msg\":\"a\"}\n").await.unwrap();
+
+        let res = sink
+            .write_path_with(
+                &path,
+                &Encoding::NDJSON,
+                &Compression::None,
+                &S3SinkItem {
+                    bucket_name: "bucket".into(),
+                    key_prefix: None,
+                },
+            )
+            .await;
+        let _ = tokio::fs::remove_file(&path).await;
+
+        res.unwrap();
+        assert_eq!(put.num_calls(), 1);
+    }
+
+    #[test]
+    fn encrypted_objects_keep_the_enc_suffix_and_drop_content_encoding() {
+        let gzip = Compression::Gzip { level: 6 };
+        let plain = Path::new("/wal/01J.bin.sealed.gz");
+        let sealed = Path::new("/wal/01J.bin.sealed.gz.enc");
+
+        let key = object_key_from(plain, Some("logs/"), &Encoding::NDJSON, &gzip);
+        assert!(key.starts_with("logs/01J") && key.ends_with(".gz"));
+        assert_eq!(
+            object_key_from(sealed, Some("logs/"), &Encoding::NDJSON, &gzip),
+            format!("{key}.enc")
+        );
+        assert_eq!(
+            content_headers(plain, &Encoding::NDJSON, &gzip),
+            (Encoding::NDJSON.content_type(), Some("gzip"))
+        );
+        assert_eq!(
+            content_headers(sealed, &Encoding::NDJSON, &gzip),
+            ("application/octet-stream", None)
+        );
+    }
+}
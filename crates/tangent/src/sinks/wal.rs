@@ -1,29 +1,35 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
-use flate2::write::GzEncoder;
+use flate2::write::{GzEncoder, ZlibEncoder};
 use flate2::Compression as f2Compression;
+use secrecy::ExposeSecret;
 use std::cmp::max;
 use std::collections::HashMap;
 use std::fs::File as stdFile;
-use std::io::copy;
+use std::io::{copy, BufReader, BufWriter, Read};
 use std::path::{Path, PathBuf};
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
+    atomic::{AtomicU64, AtomicUsize, Ordering},
     Arc,
 };
-use tangent_shared::sinks::common::{Compression, Encoding};
+use tangent_shared::sinks::common::{Compression, Encoding, EncryptionConfig};
 use tokio::fs::{self, File};
 use tokio::io::AsyncWriteExt;
-use tokio::sync::{Mutex, Semaphore};
+use tokio::sync::{Mutex, Notify, Semaphore};
 use tokio::task::{spawn_blocking, JoinHandle, JoinSet};
 use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
+use crate::sinks::encoding;
+use crate::sinks::encryption::WalCipher;
 use crate::sinks::manager::{Sink, SinkWrite};
 use crate::sinks::s3;
 use crate::SINK_BYTES_UNCOMPRESSED_TOTAL;
 use crate::{
-    SINK_BYTES_TOTAL, SINK_OBJECTS_TOTAL, WAL_PENDING_BYTES, WAL_PENDING_FILES,
-    WAL_SEALED_BYTES_TOTAL, WAL_SEALED_FILES_TOTAL,
+    SINK_BYTES_TOTAL, SINK_OBJECTS_TOTAL, WAL_CORRUPT_FILES_TOTAL, WAL_OLDEST_FILE_AGE_SECONDS,
+    WAL_OLDEST_SEALED_AGE_SECONDS, WAL_PENDING_BYTES, WAL_PENDING_FILES, WAL_QUOTA_WAITS_TOTAL,
+    WAL_RECOVERED_BYTES_TOTAL, WAL_RECOVERED_FILES_TOTAL, WAL_SEALED_BYTES_TOTAL,
+    WAL_SEALED_FILES_TOTAL,
 };
 
 pub struct DurableFileSink {
@@ -34,9 +40,20 @@ pub struct DurableFileSink {
     max_inflight: Arc<Semaphore>,
     max_file_size: usize,
     max_file_age: Duration,
+    alert_age: Option<Duration>,
+    max_wal_bytes: Option<u64>,
+    /// Bytes in `dir` as of the last scan plus those written since.
+    wal_bytes: AtomicU64,
+    /// Cancelled on shutdown, releasing writes held by `max_wal_bytes`.
+    shutdown: CancellationToken,
     compression: Compression,
     encoding: Encoding,
+    parquet_row_group_size: usize,
+    zstd_dict: Option<Arc<[u8]>>,
+    cipher: Option<WalCipher>,
     rotator: Mutex<Option<JoinHandle<()>>>,
+    /// Wakes the rotator when a write shortens a file's age limit.
+    rotator_wake: Notify,
     uploads: tokio::sync::Mutex<JoinSet<()>>,
 }
 
@@ -47,6 +64,26 @@ struct WalMeta {
 
     encoding: Encoding,
     compression: Compression,
+
+    /// CRC-32 of the sealed file, recorded when it is sealed. Metas written
+    /// before checksums were added have none and are uploaded unchecked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum: Option<u32>,
+}
+
+/// Outcome of checking a sealed file against the checksum in its `.meta`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verified {
+    Ok,
+    /// No checksum to compare with: the meta predates checksums, or the file
+    /// is a compressed or encrypted copy made during upload.
+    Unchecked,
+    Corrupt {
+        expected: u32,
+        actual: u32,
+    },
+    /// The file or its meta couldn't be read.
+    Unreadable(String),
 }
 
 #[derive(Hash, Eq, PartialEq, Clone)]
@@ -66,6 +103,19 @@ struct Current {
     file: Option<File>,
     bytes: usize,
     created_at: Instant,
+    /// Shortest `flush_interval` of the writes in this file, if any.
+    flush_interval: Option<Duration>,
+    /// CRC-32 of the bytes written so far, stored in the meta when sealed.
+    hasher: crc32fast::Hasher,
+}
+
+impl Current {
+    /// How long this file may stay open: the sink's `max_file_age`, or the
+    /// shortest flush interval of the writes it holds if that is sooner.
+    fn max_age(&self, max_file_age: Duration) -> Duration {
+        self.flush_interval
+            .map_or(max_file_age, |fi| fi.min(max_file_age))
+    }
 }
 
 #[async_trait]
@@ -80,18 +130,45 @@ pub trait WALSink: Send + Sync {
 }
 
 impl DurableFileSink {
+    /// `alert_age`, when set, logs an error on every rotator tick while the
+    /// oldest sealed file in `dir` is older than it. `max_wal_bytes` holds
+    /// writes back while `dir` is that full, until `shutdown` is cancelled.
+    /// `zstd_dict` is a trained dictionary used for `zstd` compression. With
+    /// `encryption`, each file is encrypted after compression, just before
+    /// upload.
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         inner: Arc<dyn WALSink>,
         dir: impl AsRef<Path>,
         max_inflight: usize,
         max_file_size: usize,
         max_file_age: Duration,
+        alert_age: Option<Duration>,
+        max_wal_bytes: Option<u64>,
         compression: Compression,
         encoding: Encoding,
+        parquet_row_group_size: usize,
+        zstd_dict: Option<&Path>,
+        encryption: Option<&EncryptionConfig>,
+        shutdown: CancellationToken,
     ) -> Result<Arc<Self>> {
         let dir = dir.as_ref().to_path_buf();
         tokio::fs::create_dir_all(&dir).await?;
+        let zstd_dict: Option<Arc<[u8]>> = match zstd_dict {
+            Some(p) => {
+                let dict = fs::read(p)
+                    .await
+                    .with_context(|| format!("reading zstd dictionary {}", p.display()))?;
+                Some(dict.into())
+            }
+            None => None,
+        };
+        let cipher = encryption
+            .map(|e| WalCipher::from_hex(e.key_hex.expose_secret()))
+            .transpose()
+            .context("invalid encryption.key_hex")?;
 
+        let wal_bytes = wal_dir_bytes(&dir).await;
         let s = Arc::new(Self {
             inner,
             dir,
@@ -100,30 +177,55 @@ impl DurableFileSink {
             max_inflight: Arc::new(Semaphore::new(max_inflight)),
             max_file_size,
             max_file_age,
+            alert_age,
+            max_wal_bytes,
+            wal_bytes: AtomicU64::new(wal_bytes),
+            shutdown,
             compression,
             encoding,
+            parquet_row_group_size,
+            zstd_dict,
+            cipher,
             rotator: Mutex::new(None),
+            rotator_wake: Notify::new(),
             uploads: Mutex::new(JoinSet::new()),
         });
-        s.retry_leftovers(false).await;
+        s.retry_leftovers(true).await;
 
         let s_cloned = s.clone();
         let handle = tokio::spawn(async move {
             let tick = max(Duration::from_millis(250), max_file_age / 4);
+            let mut next = tick;
             loop {
-                tokio::select! {
-                    () = sleep(tick) => {
-                        let to_rotate: Vec<RouteKey> = {
-                            let routes = s_cloned.routes.lock().await;
-                            routes.iter()
-                                .filter(|(_, rs)| rs.cur.bytes > 0 && rs.cur.created_at.elapsed() >= s_cloned.max_file_age)
-                                .map(|(k, _)| k.clone())
-                                .collect()
-                        };
-                        for k in to_rotate {
-                            let _ = s_cloned.rotate_route(k).await;
+                let ticked = tokio::select! {
+                    () = sleep(next) => true,
+                    () = s_cloned.rotator_wake.notified() => false,
+                };
+                // Files with a flush interval shorter than the tick are
+                // checked again as soon as they are due.
+                next = tick;
+                let to_rotate: Vec<RouteKey> = {
+                    let routes = s_cloned.routes.lock().await;
+                    let mut due = Vec::new();
+                    for (k, rs) in routes.iter().filter(|(_, rs)| rs.cur.bytes > 0) {
+                        let max_age = rs.cur.max_age(s_cloned.max_file_age);
+                        match max_age.checked_sub(rs.cur.created_at.elapsed()) {
+                            Some(left) if !left.is_zero() => next = next.min(left),
+                            _ => due.push(k.clone()),
                         }
                     }
+                    due
+                };
+                for k in to_rotate {
+                    let _ = s_cloned.rotate_route(k).await;
+                }
+                if ticked {
+                    s_cloned.check_sealed_age().await;
+                    if s_cloned.max_wal_bytes.is_some() {
+                        s_cloned
+                            .wal_bytes
+                            .store(wal_dir_bytes(&s_cloned.dir).await, Ordering::Relaxed);
+                    }
                 }
             }
         });
@@ -132,8 +234,77 @@ impl DurableFileSink {
         Ok(s)
     }
 
+    /// Publish the age of the oldest sealed file, and of the oldest WAL file
+    /// of any kind. Sealed files only linger when uploads keep failing, so
+    /// this surfaces stuck uploads early.
+    async fn check_sealed_age(&self) {
+        WAL_OLDEST_FILE_AGE_SECONDS.set(
+            oldest_file_age(&self.dir)
+                .await
+                .map_or(0, |age| age.as_secs() as i64),
+        );
+
+        let oldest = oldest_sealed_file(&self.dir).await;
+        WAL_OLDEST_SEALED_AGE_SECONDS
+            .set(oldest.as_ref().map_or(0, |(_, age)| age.as_secs() as i64));
+
+        if let (Some((path, age)), Some(limit)) = (oldest, self.alert_age) {
+            if age > limit {
+                tracing::error!(
+                    "sealed WAL file {:?} is {}s old (alert threshold {}s); uploads may be failing",
+                    path,
+                    age.as_secs(),
+                    limit.as_secs()
+                );
+            }
+        }
+    }
+
+    /// Wait while the WAL directory holds `max_wal_bytes` or more, until
+    /// uploads bring it under 80% of that. The running estimate is only
+    /// checked against the directory once it reaches the cap. Once shutdown
+    /// starts, writes go through regardless, so draining never waits on
+    /// uploads that may not come back.
+    async fn wait_for_quota(&self) {
+        let Some(limit) = self.max_wal_bytes else {
+            return;
+        };
+        if self.shutdown.is_cancelled() {
+            return;
+        }
+        if self.wal_bytes.load(Ordering::Relaxed) < limit {
+            return;
+        }
+        let mut total = wal_dir_bytes(&self.dir).await;
+        self.wal_bytes.store(total, Ordering::Relaxed);
+        if total < limit {
+            return;
+        }
+
+        WAL_QUOTA_WAITS_TOTAL.inc();
+        tracing::warn!(
+            "WAL directory {:?} holds {total} bytes (max_wal_bytes {limit}); holding writes until uploads drain it",
+            self.dir
+        );
+        let resume = limit / 10 * 8;
+        while total > resume {
+            tokio::select! {
+                () = sleep(Duration::from_millis(250)) => {}
+                () = self.shutdown.cancelled() => {
+                    tracing::warn!(
+                        "shutting down; writing past max_wal_bytes in WAL directory {:?}",
+                        self.dir
+                    );
+                    break;
+                }
+            }
+            total = wal_dir_bytes(&self.dir).await;
+        }
+        self.wal_bytes.store(total, Ordering::Relaxed);
+    }
+
     async fn rotate_route(&self, rkey: RouteKey) -> anyhow::Result<()> {
-        let (sealed_ready, sealed_bytes, meta) = {
+        let (old, meta) = {
             let mut routes = self.routes.lock().await;
             let rs = routes
                 .get_mut(&rkey)
@@ -142,29 +313,45 @@ impl DurableFileSink {
                 return Ok(());
             }
 
-            if let Some(f) = rs.cur.file.take() {
-                f.sync_data().await?;
-            }
-
-            let mut sealed = rs.cur.path.clone();
-            sealed.set_extension("bin.sealed");
-            fs::rename(&rs.cur.path, &sealed).await?;
-            let sealed_bytes = rs.cur.bytes as u64;
-
-            rs.cur = open_route_current(
+            let next = open_route_current(
                 &self.dir,
                 &WalMeta {
                     bucket_name: rs.meta.bucket_name.clone(),
                     key_prefix: rs.meta.key_prefix.clone(),
                     encoding: self.encoding.clone(),
                     compression: self.compression.clone(),
+                    checksum: None,
                 },
             )
             .await?;
-
-            (sealed, sealed_bytes, rs.meta.clone())
+            (std::mem::replace(&mut rs.cur, next), rs.meta.clone())
         };
 
+        // The route writes to its new file from here on, so sealing the old
+        // one doesn't hold up other writers.
+        if let Some(f) = old.file {
+            f.sync_data().await?;
+        }
+
+        // Record the checksum before sealing, so every sealed file's meta
+        // has one.
+        write_meta_atomic(
+            &meta_path_for(&old.path),
+            &WalMeta {
+                bucket_name: meta.bucket_name.clone(),
+                key_prefix: meta.key_prefix.clone(),
+                encoding: self.encoding.clone(),
+                compression: self.compression.clone(),
+                checksum: Some(old.hasher.finalize()),
+            },
+        )
+        .await?;
+
+        let mut sealed_ready = old.path.clone();
+        sealed_ready.set_extension("bin.sealed");
+        fs::rename(&old.path, &sealed_ready).await?;
+        let sealed_bytes = old.bytes as u64;
+
         WAL_SEALED_FILES_TOTAL.inc();
         WAL_SEALED_BYTES_TOTAL.inc_by(sealed_bytes);
         WAL_PENDING_FILES.inc();
@@ -175,7 +362,10 @@ impl DurableFileSink {
         Ok(())
     }
 
-    async fn retry_leftovers(&self, incr_counters: bool) {
+    /// Upload every sealed file in `dir`. The startup pass picks up files
+    /// left by a previous run; those are counted as recovered and kept out of
+    /// this run's sink and pending counters.
+    async fn retry_leftovers(&self, at_startup: bool) {
         let Ok(mut rd) = fs::read_dir(&self.dir).await else {
             return;
         };
@@ -188,6 +378,12 @@ impl DurableFileSink {
             if !is_sealed_file_name(&name) {
                 continue;
             }
+            if name.ends_with(".enc") {
+                // Encrypted copies are removed before their sealed source, so
+                // the source is still here and will be re-encrypted.
+                let _ = fs::remove_file(&p).await;
+                continue;
+            }
 
             let meta_path = meta_path_for(&p);
             let meta = match read_meta(&meta_path).await {
@@ -200,6 +396,10 @@ impl DurableFileSink {
             };
 
             if let Ok(md) = fs::metadata(&p).await {
+                if at_startup {
+                    WAL_RECOVERED_FILES_TOTAL.inc();
+                    WAL_RECOVERED_BYTES_TOTAL.inc_by(md.len());
+                }
                 self.spawn_upload_with_meta(
                     p.clone(),
                     md.len(),
@@ -207,7 +407,7 @@ impl DurableFileSink {
                         bucket_name: meta.bucket_name,
                         key_prefix: meta.key_prefix,
                     },
-                    incr_counters,
+                    !at_startup,
                 )
                 .await;
             }
@@ -228,6 +428,9 @@ impl DurableFileSink {
         let inflight = self.inflight.clone();
         let compression = self.compression.clone();
         let encoding = self.encoding.clone();
+        let row_group_size = self.parquet_row_group_size;
+        let zstd_dict = self.zstd_dict.clone();
+        let cipher = self.cipher.clone();
         let sealed_path_clone = sealed_path.clone();
 
         let fut = async move {
@@ -239,31 +442,104 @@ impl DurableFileSink {
                 key_prefix: route_meta.key_prefix.clone(),
                 encoding: encoding.clone(),
                 compression: compression.clone(),
+                checksum: None,
             });
 
-            let (upload_path, upload_size) = match compression {
-                Compression::None => (sealed_path_clone.clone(), orig_size),
-                Compression::Gzip { level } => match encoding {
+            if let Verified::Corrupt { expected, actual } =
+                check_sealed(&sealed_path_clone, &wal_meta).await
+            {
+                // Uploading would only ship the corruption; retrying won't
+                // fix it either.
+                tracing::warn!(
+                    "discarding corrupt WAL file {:?}: checksum {actual:08x}, expected {expected:08x}",
+                    sealed_path_clone
+                );
+                WAL_CORRUPT_FILES_TOTAL.inc();
+                let _ = fs::remove_file(&sealed_path_clone).await;
+                let _ = fs::remove_file(&meta_path).await;
+                return Ok(None);
+            }
+
+            let mut upload_encoding = wal_meta.encoding.clone();
+            let mut upload_compression = wal_meta.compression.clone();
+
+            let (staged_path, staged_size) = match (&wal_meta.encoding, compression) {
+                // Parquet compresses its own pages, so the object itself is
+                // uploaded without a content encoding.
+                (Encoding::Parquet { schema }, comp) => {
+                    upload_compression = Compression::None;
+                    match convert_parquet_to_file(
+                        &sealed_path_clone,
+                        schema.clone(),
+                        comp,
+                        row_group_size,
+                    )
+                    .await
+                    {
+                        Ok(converted) => converted,
+                        Err(e) => {
+                            // Retrying won't fix the data; ship it as NDJSON
+                            // rather than leaving it stuck in the WAL.
+                            tracing::warn!(
+                                "parquet conversion failed for {:?}, uploading as ndjson: {e:#}",
+                                sealed_path_clone
+                            );
+                            upload_encoding = Encoding::NDJSON;
+                            (sealed_path_clone.clone(), orig_size)
+                        }
+                    }
+                }
+                // Arrow IPC batches aren't compressed; like Parquet it ships
+                // without a content encoding.
+                (Encoding::ArrowIpc { schema }, _) => {
+                    upload_compression = Compression::None;
+                    match convert_arrow_ipc_to_file(&sealed_path_clone, schema.clone()).await {
+                        Ok(converted) => converted,
+                        Err(e) => {
+                            tracing::warn!(
+                                "arrow ipc conversion failed for {:?}, uploading as ndjson: {e:#}",
+                                sealed_path_clone
+                            );
+                            upload_encoding = Encoding::NDJSON;
+                            (sealed_path_clone.clone(), orig_size)
+                        }
+                    }
+                }
+                (_, Compression::None) => (sealed_path_clone.clone(), orig_size),
+                (_, Compression::Gzip { level }) => match encoding {
                     Encoding::NDJSON | Encoding::JSON => {
                         compress_gzip_to_file(&sealed_path_clone, level).await?
                     }
                     _ => (sealed_path_clone.clone(), orig_size),
                 },
-                Compression::Zstd { level } => match encoding {
+                (_, Compression::Zstd { level }) => match encoding {
                     Encoding::NDJSON | Encoding::JSON => {
-                        compress_zstd_to_file(&sealed_path_clone, level).await?
+                        compress_zstd_to_file(&sealed_path_clone, level, zstd_dict).await?
                     }
                     _ => (sealed_path_clone.clone(), orig_size),
                 },
-                Compression::Snappy { .. } => (sealed_path_clone.clone(), orig_size),
-                Compression::Deflate { .. } => (sealed_path_clone.clone(), orig_size),
+                (_, Compression::Snappy { .. }) => (sealed_path_clone.clone(), orig_size),
+                (_, Compression::Deflate { level }) => match encoding {
+                    Encoding::NDJSON | Encoding::JSON => {
+                        compress_deflate_to_file(&sealed_path_clone, level).await?
+                    }
+                    // Avro deflates its own blocks.
+                    _ => {
+                        upload_compression = Compression::None;
+                        (sealed_path_clone.clone(), orig_size)
+                    }
+                },
+            };
+            let (upload_path, upload_size) = match cipher {
+                Some(cipher) => encrypt_to_file(&staged_path, cipher).await?,
+                None => (staged_path.clone(), staged_size),
             };
 
             inner
                 .write_path_with(
                     &upload_path,
-                    &wal_meta.encoding,
-                    &wal_meta.compression,
+                    &upload_encoding,
+                    &upload_compression,
                     &s3::S3SinkItem {
                         bucket_name: wal_meta.bucket_name,
                         key_prefix: wal_meta.key_prefix,
@@ -272,17 +548,24 @@ impl DurableFileSink {
                 .await?;
 
             let _ = fs::remove_file(&upload_path).await;
+            let _ = fs::remove_file(&staged_path).await;
             let _ = fs::remove_file(&sealed_path_clone).await;
             let _ = fs::remove_file(&meta_path).await;
 
-            Ok::<u64, anyhow::Error>(upload_size)
+            Ok::<Option<u64>, anyhow::Error>(Some(upload_size))
         };
 
         let mut js = self.uploads.lock().await;
 
         js.spawn(async move {
             match fut.await {
-                Ok(uploaded) => {
+                Ok(None) => {
+                    if incr_metrics {
+                        WAL_PENDING_FILES.dec();
+                        WAL_PENDING_BYTES.sub(orig_size as i64);
+                    }
+                }
+                Ok(Some(uploaded)) => {
                     // Don't incr metrics on restart.
                     if incr_metrics {
                         SINK_OBJECTS_TOTAL.inc();
@@ -312,6 +595,7 @@ impl Sink for DurableFileSink {
             sink_name: req.sink_name,
             prefix: meta.key_prefix.clone(),
         };
+        self.wait_for_quota().await;
 
         let mut need_create = false;
         {
@@ -329,6 +613,7 @@ impl Sink for DurableFileSink {
                     key_prefix: meta.key_prefix.clone(),
                     encoding: self.encoding.clone(),
                     compression: self.compression.clone(),
+                    checksum: None,
                 },
             )
             .await?;
@@ -348,26 +633,44 @@ impl Sink for DurableFileSink {
             }
         }
 
-        loop {
+        let (flush_now, sooner) = loop {
             let mut routes = self.routes.lock().await;
             let rs = routes.get_mut(&rkey).expect("route exists after create");
             if rs.cur.bytes + req.payload.len() <= self.max_file_size {
                 let f = rs.cur.file.as_mut().expect("current file missing");
                 f.write_all(&req.payload).await?;
+                rs.cur.hasher.update(&req.payload);
                 rs.cur.bytes += req.payload.len();
                 rs.last_used = Instant::now();
-                break;
+                self.wal_bytes
+                    .fetch_add(req.payload.len() as u64, Ordering::Relaxed);
+                let Some(fi) = req.flush_interval else {
+                    break (false, false);
+                };
+                let before = rs.cur.max_age(self.max_file_age);
+                rs.cur.flush_interval = Some(rs.cur.flush_interval.map_or(fi, |cur| cur.min(fi)));
+                let max_age = rs.cur.max_age(self.max_file_age);
+                break (rs.cur.created_at.elapsed() >= max_age, max_age < before);
             }
             drop(routes);
             self.rotate_route(rkey.clone()).await?;
+        };
+
+        if flush_now {
+            self.rotate_route(rkey).await?;
+        } else if sooner {
+            // Only a deadline that moved earlier needs the rotator to re-plan.
+            self.rotator_wake.notify_one();
         }
 
         Ok(())
     }
 
     async fn flush(&self) -> Result<()> {
+        // The rotator loops forever; stop it so it can't race the final rotation.
         let value = self.rotator.lock().await.take();
         if let Some(h) = value {
+            h.abort();
             let _ = h.await;
         }
 
@@ -384,7 +687,7 @@ impl Sink for DurableFileSink {
         }
 
         loop {
-            self.retry_leftovers(true).await;
+            self.retry_leftovers(false).await;
             let mut js = {
                 let mut g = self.uploads.lock().await;
                 std::mem::take(&mut *g)
@@ -403,9 +706,32 @@ impl Sink for DurableFileSink {
         }
         Ok(())
     }
+
+    async fn pending_files(&self) -> Vec<PathBuf> {
+        let mut out = Vec::new();
+        let Ok(mut rd) = fs::read_dir(&self.dir).await else {
+            return out;
+        };
+        while let Ok(Some(ent)) = rd.next_entry().await {
+            let Ok(name) = ent.file_name().into_string() else {
+                continue;
+            };
+            let open_with_data =
+                name.ends_with(".bin") && ent.metadata().await.is_ok_and(|md| md.len() > 0);
+            if open_with_data || is_sealed_file_name(&name) {
+                out.push(ent.path());
+            }
+        }
+        out.sort();
+        out
+    }
 }
 
-async fn compress_zstd_to_file(src: &Path, level: i32) -> Result<(PathBuf, u64)> {
+async fn compress_zstd_to_file(
+    src: &Path,
+    level: i32,
+    dict: Option<Arc<[u8]>>,
+) -> Result<(PathBuf, u64)> {
     let dst = src.with_extension("sealed.zst");
     let dst_tmp = dst.with_extension("sealed.zst.tmp");
     let src = src.to_path_buf();
@@ -413,7 +739,10 @@ async fn compress_zstd_to_file(src: &Path, level: i32) -> Result<(PathBuf, u64)>
     let size = spawn_blocking(move || -> Result<u64> {
         let mut fin = stdFile::open(&src)?;
         let mut fout = stdFile::create(&dst_tmp)?;
-        let mut enc = zstd::stream::Encoder::new(&mut fout, level)?;
+        let mut enc = match &dict {
+            Some(dict) => zstd::stream::Encoder::with_dictionary(&mut fout, level, dict)?,
+            None => zstd::stream::Encoder::new(&mut fout, level)?,
+        };
         copy(&mut fin, &mut enc)?;
         enc.finish()?;
 
@@ -443,6 +772,82 @@ async fn compress_gzip_to_file(src: &Path, level: u32) -> Result<(PathBuf, u64)>
     Ok((dst, size))
 }
 
+async fn compress_deflate_to_file(src: &Path, level: u32) -> Result<(PathBuf, u64)> {
+    let dst = src.with_extension("sealed.deflate");
+    let dst_tmp = dst.with_extension("sealed.deflate.tmp");
+    let src = src.to_path_buf();
+    let dst_clone = dst.clone();
+    let size = spawn_blocking(move || -> Result<u64> {
+        let mut fin = stdFile::open(&src)?;
+        let mut fout = stdFile::create(&dst_tmp)?;
+        let mut enc = ZlibEncoder::new(&mut fout, f2Compression::new(level));
+        copy(&mut fin, &mut enc)?;
+        enc.finish()?;
+
+        std::fs::rename(&dst_tmp, &dst_clone)?;
+        Ok(std::fs::metadata(&dst_clone)?.len())
+    })
+    .await??;
+    Ok((dst, size))
+}
+
+/// Encrypt a sealed (and possibly compressed) file to `<src>.enc`.
+async fn encrypt_to_file(src: &Path, cipher: WalCipher) -> Result<(PathBuf, u64)> {
+    let mut name = src.as_os_str().to_owned();
+    name.push(".enc");
+    let dst = PathBuf::from(name);
+    let dst_tmp = dst.with_extension("enc.tmp");
+    let src = src.to_path_buf();
+    let dst_clone = dst.clone();
+    let size = spawn_blocking(move || -> Result<u64> {
+        let fin = BufReader::new(stdFile::open(&src)?);
+        let fout = BufWriter::new(stdFile::create(&dst_tmp)?);
+        let size = cipher.encrypt_stream(fin, fout)?;
+
+        std::fs::rename(&dst_tmp, &dst_clone)?;
+        Ok(size)
+    })
+    .await??;
+    Ok((dst, size))
+}
+
+/// Re-encode a sealed NDJSON file as Parquet next to it.
+async fn convert_parquet_to_file(
+    src: &Path,
+    schema: Option<String>,
+    comp: Compression,
+    row_group_size: usize,
+) -> Result<(PathBuf, u64)> {
+    let dst = src.with_extension("sealed.parquet");
+    let dst_tmp = dst.with_extension("parquet.tmp");
+    let src = src.to_path_buf();
+    let dst_clone = dst.clone();
+    let size = spawn_blocking(move || -> Result<u64> {
+        encoding::ndjson_file_to_parquet(&src, &dst_tmp, schema.as_deref(), &comp, row_group_size)?;
+
+        std::fs::rename(&dst_tmp, &dst_clone)?;
+        Ok(std::fs::metadata(&dst_clone)?.len())
+    })
+    .await??;
+    Ok((dst, size))
+}
+
+/// Re-encode a sealed NDJSON file as an Arrow IPC stream next to it.
+async fn convert_arrow_ipc_to_file(src: &Path, schema: Option<String>) -> Result<(PathBuf, u64)> {
+    let dst = src.with_extension("sealed.arrows");
+    let dst_tmp = dst.with_extension("arrows.tmp");
+    let src = src.to_path_buf();
+    let dst_clone = dst.clone();
+    let size = spawn_blocking(move || -> Result<u64> {
+        encoding::ndjson_file_to_arrow_ipc(&src, &dst_tmp, schema.as_deref())?;
+
+        std::fs::rename(&dst_tmp, &dst_clone)?;
+        Ok(std::fs::metadata(&dst_clone)?.len())
+    })
+    .await??;
+    Ok((dst, size))
+}
+
 #[must_use]
 pub fn base_for(path: &Path) -> PathBuf {
     let Some(name) = path.file_name().and_then(|s| s.to_str()) else {
@@ -450,7 +855,16 @@ pub fn base_for(path: &Path) -> PathBuf {
     };
     let mut out = name.to_owned();
 
-    if out.ends_with(".gz") || out.ends_with(".zst") {
+    if out.ends_with(".enc") {
+        out.truncate(out.len() - ".enc".len());
+    }
+
+    if out.ends_with(".gz")
+        || out.ends_with(".zst")
+        || out.ends_with(".deflate")
+        || out.ends_with(".parquet")
+        || out.ends_with(".arrows")
+    {
         if let Some(idx) = out.rfind('.') {
             out.truncate(idx);
         }
@@ -495,10 +909,137 @@ async fn read_meta(meta_path: &Path) -> anyhow::Result<WalMeta> {
     Ok(serde_json::from_slice(&bytes)?)
 }
 
+/// Check every sealed file in `dir` against its meta without uploading
+/// anything, in file name order.
+pub async fn verify_dir(dir: &Path) -> Result<Vec<(PathBuf, Verified)>> {
+    let mut rd = fs::read_dir(dir)
+        .await
+        .with_context(|| format!("reading {}", dir.display()))?;
+    let mut sealed = Vec::new();
+    while let Some(ent) = rd.next_entry().await? {
+        if ent.file_name().to_str().is_some_and(is_sealed_file_name) {
+            sealed.push(ent.path());
+        }
+    }
+    sealed.sort();
+
+    let mut out = Vec::with_capacity(sealed.len());
+    for path in sealed {
+        let verified = match read_meta(&meta_path_for(&path)).await {
+            Ok(meta) => check_sealed(&path, &meta).await,
+            Err(e) => Verified::Unreadable(format!("meta: {e}")),
+        };
+        out.push((path, verified));
+    }
+    Ok(out)
+}
+
+/// Compare `path` with `meta.checksum`. Only the sealed file itself is
+/// checked; the copies made from it during upload are not what was summed.
+async fn check_sealed(path: &Path, meta: &WalMeta) -> Verified {
+    let Some(expected) = meta.checksum else {
+        return Verified::Unchecked;
+    };
+    if !path.to_str().is_some_and(|p| p.ends_with(".bin.sealed")) {
+        return Verified::Unchecked;
+    }
+    match crc32_file(path.to_path_buf()).await {
+        Ok(actual) if actual == expected => Verified::Ok,
+        Ok(actual) => Verified::Corrupt { expected, actual },
+        Err(e) => Verified::Unreadable(format!("{e:#}")),
+    }
+}
+
+async fn crc32_file(path: PathBuf) -> Result<u32> {
+    spawn_blocking(move || -> Result<u32> {
+        let mut f = stdFile::open(&path).with_context(|| format!("opening {}", path.display()))?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = f.read(&mut buf)?;
+            if n == 0 {
+                return Ok(hasher.finalize());
+            }
+            hasher.update(&buf[..n]);
+        }
+    })
+    .await?
+}
+
 fn is_sealed_file_name(name: &str) -> bool {
     name.ends_with(".bin.sealed")
         || name.ends_with(".bin.sealed.gz")
         || name.ends_with(".bin.sealed.zst")
+        || name.ends_with(".bin.sealed.deflate")
+        || name.ends_with(".bin.sealed.enc")
+        || name.ends_with(".bin.sealed.gz.enc")
+        || name.ends_with(".bin.sealed.zst.enc")
+        || name.ends_with(".bin.sealed.deflate.enc")
+        || name.ends_with(".bin.sealed.parquet.enc")
+        || name.ends_with(".bin.sealed.arrows.enc")
+}
+
+/// Path and age of the least recently modified sealed file in `dir`.
+async fn oldest_sealed_file(dir: &Path) -> Option<(PathBuf, Duration)> {
+    let mut rd = fs::read_dir(dir).await.ok()?;
+    let mut oldest: Option<(PathBuf, std::time::SystemTime)> = None;
+    while let Ok(Some(ent)) = rd.next_entry().await {
+        if !ent.file_name().to_str().is_some_and(is_sealed_file_name) {
+            continue;
+        }
+        let Ok(modified) = ent.metadata().await.and_then(|md| md.modified()) else {
+            continue;
+        };
+        if oldest.as_ref().is_none_or(|(_, t)| modified < *t) {
+            oldest = Some((ent.path(), modified));
+        }
+    }
+    let (path, modified) = oldest?;
+    Some((path, modified.elapsed().unwrap_or_default()))
+}
+
+/// Age of the oldest open or sealed WAL file in `dir`, going by the ULID
+/// each one is named after.
+async fn oldest_file_age(dir: &Path) -> Option<Duration> {
+    let mut rd = fs::read_dir(dir).await.ok()?;
+    let mut oldest_ms: Option<u64> = None;
+    while let Ok(Some(ent)) = rd.next_entry().await {
+        let Some(name) = ent.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        if !(name.ends_with(".bin") || is_sealed_file_name(&name)) {
+            continue;
+        }
+        let Some(Ok(id)) = name.split('.').next().map(ulid::Ulid::from_string) else {
+            continue;
+        };
+        let ms = id.timestamp_ms();
+        if oldest_ms.is_none_or(|o| ms < o) {
+            oldest_ms = Some(ms);
+        }
+    }
+    let created = std::time::UNIX_EPOCH + Duration::from_millis(oldest_ms?);
+    Some(created.elapsed().unwrap_or_default())
+}
+
+/// Total size of the open and sealed WAL files in `dir`.
+async fn wal_dir_bytes(dir: &Path) -> u64 {
+    let Ok(mut rd) = fs::read_dir(dir).await else {
+        return 0;
+    };
+    let mut total = 0;
+    while let Ok(Some(ent)) = rd.next_entry().await {
+        let Some(name) = ent.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        if !(name.ends_with(".bin") || is_sealed_file_name(&name)) {
+            continue;
+        }
+        if let Ok(md) = ent.metadata().await {
+            total += md.len();
+        }
+    }
+    total
 }
 
 fn make_base_ulid(dir: &Path) -> PathBuf {
@@ -533,5 +1074,196 @@ async fn open_route_current(dir: &Path, meta: &WalMeta) -> anyhow::Result<Curren
         file: Some(file),
         bytes: 0,
         created_at: Instant::now(),
+        flush_interval: None,
+        hasher: crc32fast::Hasher::new(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    #[tokio::test]
+    async fn zstd_dictionary_is_required_to_decompress() {
+        let dir = std::env::temp_dir().join(format!("tangent-wal-dict-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let samples: Vec<Vec<u8>> = (0..1000)
+            .map(|i| format!(r#"{{"service":"checkout","level":"info","request_id":{i}}}"#).into())
+            .collect();
+        let dict: Arc<[u8]> = zstd::dict::from_samples(&samples, 4096).unwrap().into();
+
+        let src = dir.join("route.bin.sealed");
+        std::fs::write(&src, samples.concat()).unwrap();
+        let (dst, _) = compress_zstd_to_file(&src, 3, Some(dict.clone()))
+            .await
+            .unwrap();
+        let compressed = std::fs::read(&dst).unwrap();
+
+        assert!(zstd::stream::decode_all(&compressed[..]).is_err());
+
+        let ddict = zstd::dict::DecoderDictionary::copy(&dict);
+        let mut dec =
+            zstd::stream::read::Decoder::with_prepared_dictionary(&compressed[..], &ddict).unwrap();
+        let mut out = Vec::new();
+        dec.read_to_end(&mut out).unwrap();
+        assert_eq!(out, samples.concat());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn deflate_compresses_to_a_sealed_deflate_file() {
+        let dir = std::env::temp_dir().join(format!("tangent-wal-deflate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let body = "{\"service\":\"checkout\"}\n".repeat(100);
+        let src = dir.join("route.bin.sealed");
+        std::fs::write(&src, &body).unwrap();
+        let (dst, size) = compress_deflate_to_file(&src, 6).await.unwrap();
+        assert_eq!(dst, dir.join("route.bin.sealed.deflate"));
+        assert!(is_sealed_file_name("route.bin.sealed.deflate"));
+        assert_eq!(base_for(&dst), dir.join("route"));
+
+        let compressed = std::fs::read(&dst).unwrap();
+        assert_eq!(compressed.len() as u64, size);
+        let mut out = String::new();
+        flate2::read::ZlibDecoder::new(&compressed[..])
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, body);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sealed_files_are_checked_against_their_meta() {
+        let dir = std::env::temp_dir().join(format!("tangent-wal-crc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let body = b"{\"service\":\"checkout\"}\n";
+        let sealed = |name: &str, checksum| {
+            let meta = WalMeta {
+                bucket_name: Arc::from("logs"),
+                key_prefix: None,
+                encoding: Encoding::NDJSON,
+                compression: Compression::None,
+                checksum,
+            };
+            std::fs::write(
+                dir.join(format!("{name}.meta")),
+                serde_json::to_vec(&meta).unwrap(),
+            )
+            .unwrap();
+            std::fs::write(dir.join(format!("{name}.bin.sealed")), body).unwrap();
+        };
+        sealed("a", Some(crc32fast::hash(body)));
+        sealed("b", Some(crc32fast::hash(body)));
+        sealed("c", None);
+        std::fs::write(dir.join("b.bin.sealed"), b"{\"service\":\"checkouu\"}\n").unwrap();
+        std::fs::write(dir.join("d.bin.sealed"), body).unwrap();
+
+        let verified: Vec<_> = verify_dir(&dir)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(p, v)| (p.file_name().unwrap().to_str().unwrap().to_string(), v))
+            .collect();
+        assert_eq!(verified[0], ("a.bin.sealed".into(), Verified::Ok));
+        assert!(
+            matches!(verified[1], (_, Verified::Corrupt { expected, .. }) if expected == crc32fast::hash(body))
+        );
+        assert_eq!(verified[2], ("c.bin.sealed".into(), Verified::Unchecked));
+        assert!(matches!(verified[3], (_, Verified::Unreadable(_))));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn oldest_file_age_reads_the_ulid_of_wal_files_only() {
+        let dir = std::env::temp_dir().join(format!("tangent-wal-age-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(oldest_file_age(&dir).await, None);
+
+        let now_ms = ulid::Ulid::new().timestamp_ms();
+        let named = |ago_secs: u64, ext: &str| {
+            let id = ulid::Ulid::from_parts(now_ms - ago_secs * 1000, 0);
+            dir.join(format!("{id}.{ext}"))
+        };
+        std::fs::write(named(0, "bin"), b"{}\n").unwrap();
+        std::fs::write(named(60, "bin.sealed.gz"), b"").unwrap();
+        std::fs::write(named(3600, "meta"), b"{}").unwrap();
+
+        let age = oldest_file_age(&dir).await.unwrap();
+        assert!((60..120).contains(&age.as_secs()), "{age:?}");
+        assert_eq!(wal_dir_bytes(&dir).await, 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    struct NoUpload;
+
+    #[async_trait]
+    impl WALSink for NoUpload {
+        async fn write_path_with(
+            &self,
+            _path: &Path,
+            _encoding: &Encoding,
+            _compression: &Compression,
+            _meta: &s3::S3SinkItem,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_releases_writes_held_by_max_wal_bytes() {
+        let dir = std::env::temp_dir().join(format!("tangent-wal-quota-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("stale.bin"), [b'x'; 100]).unwrap();
+
+        let shutdown = CancellationToken::new();
+        let sink = DurableFileSink::new(
+            Arc::new(NoUpload),
+            &dir,
+            1,
+            1 << 20,
+            Duration::from_secs(3600),
+            None,
+            Some(10),
+            Compression::None,
+            Encoding::NDJSON,
+            1024,
+            None,
+            None,
+            shutdown.clone(),
+        )
+        .await
+        .unwrap();
+
+        let write = tokio::spawn({
+            let sink = Arc::clone(&sink);
+            async move {
+                sink.write(SinkWrite {
+                    sink_name: Arc::from("archive"),
+                    payload: BytesMut::from(&b"{}\n"[..]),
+                    s3: Some(s3::S3SinkItem {
+                        bucket_name: Arc::from("logs"),
+                        key_prefix: None,
+                    }),
+                    flush_interval: None,
+                })
+                .await
+            }
+        });
+        sleep(Duration::from_millis(600)).await;
+        assert!(!write.is_finished());
+
+        shutdown.cancel();
+        write.await.unwrap().unwrap();
+        assert_eq!(wal_dir_bytes(&dir).await, 103);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
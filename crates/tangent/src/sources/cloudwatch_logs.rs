@@ -0,0 +1,306 @@
+use anyhow::{Context, Result};
+use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
+use aws_sdk_cloudwatchlogs::Client;
+use bytes::BytesMut;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tangent_shared::dag::NodeRef;
+use tangent_shared::sources::cloudwatch_logs::CloudWatchLogsConfig;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use crate::cache::CacheHandle;
+use crate::router::Router;
+use crate::wasm::host::tangent::logs::log::Scalar;
+use crate::CLOUDWATCH_LOGS_EVENTS_TOTAL;
+
+/// Poll `cfg.log_group_name` with `FilterLogEvents` and forward each event
+/// as an NDJSON line. The query's start time and `next_token` are
+/// checkpointed in the runtime cache so a restart resumes mid-query; the
+/// event IDs seen in the lookback window are not, so events in that window
+/// may be forwarded again after a restart.
+pub async fn run_consumer(
+    name: Arc<str>,
+    cfg: CloudWatchLogsConfig,
+    router: Arc<Router>,
+    cache: Arc<CacheHandle>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let aws_cfg = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let client = Client::new(&aws_cfg);
+    let events_total = CLOUDWATCH_LOGS_EVENTS_TOTAL.with_label_values(&[name.as_ref()]);
+    let checkpoint = Checkpoint::new(cache, &name);
+    let from = NodeRef::Source { name };
+
+    let mut cursor = checkpoint.load().unwrap_or_else(|| Cursor {
+        start_time_ms: now_ms(),
+        next_token: None,
+        newest_ms: None,
+        seen: HashMap::new(),
+    });
+    let lookback_ms = Duration::from_secs(cfg.lookback_secs).as_millis() as i64;
+
+    let mut ticker = interval(Duration::from_secs(cfg.poll_interval_secs.max(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    tracing::info!(
+        "cloudwatch_logs source polling {} from {}",
+        cfg.log_group_name,
+        cursor.start_time_ms
+    );
+
+    loop {
+        tokio::select! {
+            () = shutdown.cancelled() => break,
+            _ = ticker.tick() => {}
+        }
+
+        // Page through everything since the cursor before waiting again.
+        loop {
+            let resp = client
+                .filter_log_events()
+                .log_group_name(&cfg.log_group_name)
+                .set_log_stream_name_prefix(cfg.log_stream_prefix.clone())
+                .set_filter_pattern(cfg.filter_pattern.clone())
+                .start_time(cursor.start_time_ms)
+                .set_next_token(cursor.next_token.clone())
+                .send()
+                .await;
+            let out = match resp {
+                Ok(out) => out,
+                // Tokens expire; restart the query from the cursor's time.
+                Err(e) if cursor.next_token.is_some() => {
+                    tracing::warn!(
+                        "cloudwatch_logs query with saved token failed, restarting it: {e}"
+                    );
+                    cursor.next_token = None;
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("FilterLogEvents on {}", cfg.log_group_name))
+                }
+            };
+
+            let fresh = cursor.advance(
+                out.events(),
+                out.next_token().map(str::to_string),
+                lookback_ms,
+            );
+            if !fresh.is_empty() {
+                let frames: Vec<BytesMut> = fresh.iter().copied().map(to_ndjson).collect();
+                router
+                    .forward(&from, frames, Vec::new())
+                    .await
+                    .context("router.forward failed for cloudwatch_logs")?;
+                events_total.inc_by(fresh.len() as u64);
+            }
+            checkpoint.save(&cursor);
+            if cursor.next_token.is_none() {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Where the next `FilterLogEvents` call picks up: mid-query while
+/// `next_token` is set, otherwise a new query from `start_time_ms`.
+#[derive(Debug, Clone, PartialEq)]
+struct Cursor {
+    start_time_ms: i64,
+    next_token: Option<String>,
+    /// Newest event timestamp seen in the current query.
+    newest_ms: Option<i64>,
+    /// Timestamps of the events already forwarded since `start_time_ms`, by
+    /// `eventId`.
+    seen: HashMap<String, i64>,
+}
+
+impl Cursor {
+    /// Record a page of `events` and return those not forwarded before. Once
+    /// the query has no more pages the next one starts `lookback_ms` before
+    /// the newest event, so events ingested late with an older timestamp are
+    /// still picked up.
+    fn advance<'a>(
+        &mut self,
+        events: &'a [FilteredLogEvent],
+        next_token: Option<String>,
+        lookback_ms: i64,
+    ) -> Vec<&'a FilteredLogEvent> {
+        let fresh = events
+            .iter()
+            .filter(|ev| match (ev.event_id(), ev.timestamp()) {
+                (Some(id), Some(ts)) => self.seen.insert(id.to_string(), ts).is_none(),
+                _ => true,
+            })
+            .collect();
+        let newest = events.iter().filter_map(FilteredLogEvent::timestamp).max();
+        self.newest_ms = self.newest_ms.max(newest);
+        self.next_token = next_token;
+        if self.next_token.is_none() {
+            if let Some(newest) = self.newest_ms.take() {
+                self.start_time_ms = self.start_time_ms.max(newest + 1 - lookback_ms);
+                let start = self.start_time_ms;
+                self.seen.retain(|_, ts| *ts >= start);
+            }
+        }
+        fresh
+    }
+}
+
+fn to_ndjson(ev: &FilteredLogEvent) -> BytesMut {
+    let line = json!({
+        "message": ev.message(),
+        "ingestion_time": ev.ingestion_time(),
+        "log_stream": ev.log_stream_name(),
+    });
+    let mut buf = BytesMut::with_capacity(256);
+    buf.extend_from_slice(line.to_string().as_bytes());
+    buf.extend_from_slice(b"\n");
+    buf
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+/// The source's `Cursor`, kept in the runtime cache.
+struct Checkpoint {
+    cache: Arc<CacheHandle>,
+    prefix: String,
+}
+
+impl Checkpoint {
+    fn new(cache: Arc<CacheHandle>, source: &str) -> Self {
+        Self {
+            cache,
+            prefix: format!("tangent:cloudwatch_logs:{source}"),
+        }
+    }
+
+    fn get(&self, field: &str) -> Option<Scalar> {
+        match self.cache.get(&format!("{}:{field}", self.prefix)) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!("reading cloudwatch_logs {field} failed: {e}");
+                None
+            }
+        }
+    }
+
+    fn load(&self) -> Option<Cursor> {
+        let Some(Scalar::Int(start_time_ms)) = self.get("start_time") else {
+            return None;
+        };
+        let next_token = match self.get("next_token") {
+            Some(Scalar::Str(t)) if !t.is_empty() => Some(t),
+            _ => None,
+        };
+        let newest_ms = match self.get("newest") {
+            Some(Scalar::Int(t)) if next_token.is_some() => Some(t),
+            _ => None,
+        };
+        Some(Cursor {
+            start_time_ms,
+            next_token,
+            newest_ms,
+            seen: HashMap::new(),
+        })
+    }
+
+    fn save(&self, cursor: &Cursor) {
+        let token = cursor.next_token.clone().unwrap_or_default();
+        let res = self
+            .cache
+            .set(
+                &format!("{}:start_time", self.prefix),
+                &Scalar::Int(cursor.start_time_ms),
+                None,
+            )
+            .and_then(|()| {
+                self.cache.set(
+                    &format!("{}:next_token", self.prefix),
+                    &Scalar::Str(token),
+                    None,
+                )
+            })
+            .and_then(|()| {
+                self.cache.set(
+                    &format!("{}:newest", self.prefix),
+                    &Scalar::Int(cursor.newest_ms.unwrap_or_default()),
+                    None,
+                )
+            });
+        if let Err(e) = res {
+            tracing::warn!("writing cloudwatch_logs checkpoint failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: &str, ts: i64) -> FilteredLogEvent {
+        FilteredLogEvent::builder()
+            .event_id(id)
+            .log_stream_name("2024/01/01/[$LATEST]abc")
+            .timestamp(ts)
+            .ingestion_time(ts + 5)
+            .message("hello")
+            .build()
+    }
+
+    fn ids(events: Vec<&FilteredLogEvent>) -> Vec<&str> {
+        events.iter().filter_map(|ev| ev.event_id()).collect()
+    }
+
+    #[test]
+    fn cursor_looks_back_for_late_events_and_drops_repeats() {
+        let mut cursor = Cursor {
+            start_time_ms: 1_000,
+            next_token: None,
+            newest_ms: None,
+            seen: HashMap::new(),
+        };
+
+        let page = [event("a", 1_500), event("b", 1_200)];
+        assert_eq!(
+            ids(cursor.advance(&page, Some("page-2".into()), 100)),
+            ["a", "b"]
+        );
+        assert_eq!(cursor.start_time_ms, 1_000);
+        assert_eq!(cursor.next_token.as_deref(), Some("page-2"));
+
+        assert_eq!(ids(cursor.advance(&[event("c", 1_300)], None, 100)), ["c"]);
+        assert_eq!(cursor.start_time_ms, 1_401);
+        assert_eq!(cursor.next_token, None);
+        // Only "a" is recent enough to be read again.
+        assert_eq!(cursor.seen.len(), 1);
+
+        // The next query overlaps the last one and turns up a late event.
+        let page = [event("a", 1_500), event("late", 1_450)];
+        assert_eq!(ids(cursor.advance(&page, None, 100)), ["late"]);
+        assert_eq!(cursor.start_time_ms, 1_401);
+
+        cursor.advance(&[], None, 100);
+        assert_eq!(cursor.start_time_ms, 1_401);
+
+        let line: serde_json::Value = serde_json::from_slice(&to_ndjson(&event("x", 7))).unwrap();
+        assert_eq!(
+            line,
+            json!({
+                "message": "hello",
+                "ingestion_time": 12,
+                "log_stream": "2024/01/01/[$LATEST]abc"
+            })
+        );
+    }
+}
@@ -1,10 +1,17 @@
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use bytes::{BufMut, Bytes, BytesMut};
 use memchr::{memchr, memchr_iter};
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
 use serde::Deserialize;
-use tangent_shared::sources::common::{DecodeCompression, DecodeFormat};
+use simd_json::prelude::Writable;
+use tangent_shared::sources::common::{DecodeCompression, DecodeFormat, Decoding};
+use tokio::io::AsyncRead;
+use tokio_util::io::{StreamReader, SyncIoBridge};
 
 pub fn decompress_bytes(comp: &DecodeCompression, data: BytesMut) -> Result<BytesMut> {
     Ok(match comp {
@@ -24,9 +31,59 @@ pub fn decompress_bytes(comp: &DecodeCompression, data: BytesMut) -> Result<Byte
             std::io::copy(&mut dec, &mut w)?;
             out
         }
+        DecodeCompression::Lz4 => {
+            let mut dec = lz4_flex::frame::FrameDecoder::new(&data[..]);
+            let mut out = BytesMut::new();
+            let mut w = BytesMutWriter(&mut out);
+            std::io::copy(&mut dec, &mut w)?;
+            out
+        }
     })
 }
 
+/// A body that decompresses to more than the source allows.
+#[derive(Debug)]
+pub struct DecompressedTooLarge {
+    pub limit: usize,
+}
+
+impl std::fmt::Display for DecompressedTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "payload decompresses to more than {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for DecompressedTooLarge {}
+
+/// Like `decompress_bytes`, but fails with `DecompressedTooLarge` as soon as
+/// the output passes `limit` bytes instead of inflating it all.
+pub fn decompress_bytes_capped(
+    comp: &DecodeCompression,
+    data: BytesMut,
+    limit: usize,
+) -> Result<BytesMut> {
+    let dec: Box<dyn Read + '_> = match comp {
+        DecodeCompression::None | DecodeCompression::Auto => {
+            if data.len() > limit {
+                return Err(DecompressedTooLarge { limit }.into());
+            }
+            return Ok(data);
+        }
+        DecodeCompression::Gzip => Box::new(flate2::read::GzDecoder::new(&data[..])),
+        DecodeCompression::Zstd => Box::new(zstd::stream::read::Decoder::new(&data[..])?),
+        DecodeCompression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(&data[..])),
+    };
+    let mut out = BytesMut::new();
+    let copied = io::copy(
+        &mut dec.take(limit as u64 + 1),
+        &mut BytesMutWriter(&mut out),
+    )?;
+    if copied > limit as u64 {
+        return Err(DecompressedTooLarge { limit }.into());
+    }
+    Ok(out)
+}
+
 pub fn decompress_vec(comp: &DecodeCompression, data: &[u8]) -> Result<BytesMut> {
     Ok(match comp {
         DecodeCompression::None | DecodeCompression::Auto => BytesMut::from(data),
@@ -45,9 +102,56 @@ pub fn decompress_vec(comp: &DecodeCompression, data: &[u8]) -> Result<BytesMut>
             std::io::copy(&mut dec, &mut w)?;
             out
         }
+        DecodeCompression::Lz4 => {
+            let mut dec = lz4_flex::frame::FrameDecoder::new(&data[..]);
+            let mut out = BytesMut::new();
+            let mut w = BytesMutWriter(&mut out);
+            std::io::copy(&mut dec, &mut w)?;
+            out
+        }
     })
 }
 
+/// Decompress `reader` as it is read, for bodies too large to buffer. The
+/// decoder runs on a blocking thread; corrupt input surfaces as a read error
+/// rather than an early end of stream.
+pub fn decompress_reader(
+    comp: &DecodeCompression,
+    reader: impl AsyncRead + Send + Unpin + 'static,
+) -> Pin<Box<dyn AsyncRead + Send>> {
+    if matches!(comp, DecodeCompression::None | DecodeCompression::Auto) {
+        return Box::pin(reader);
+    }
+    let comp = comp.clone();
+    let src = SyncIoBridge::new(reader);
+    let (tx, rx) = tokio::sync::mpsc::channel::<io::Result<Bytes>>(4);
+    tokio::task::spawn_blocking(move || {
+        let copy = || -> io::Result<()> {
+            let mut dec: Box<dyn Read> = match comp {
+                DecodeCompression::None | DecodeCompression::Auto => Box::new(src),
+                DecodeCompression::Gzip => Box::new(flate2::read::GzDecoder::new(src)),
+                DecodeCompression::Zstd => Box::new(zstd::stream::read::Decoder::new(src)?),
+                DecodeCompression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(src)),
+            };
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                let n = dec.read(&mut buf)?;
+                let chunk = Bytes::copy_from_slice(&buf[..n]);
+                if n == 0 || tx.blocking_send(Ok(chunk)).is_err() {
+                    return Ok(());
+                }
+            }
+        };
+        if let Err(e) = copy() {
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    let chunks = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Box::pin(StreamReader::new(chunks))
+}
+
 struct BytesMutWriter<'a>(&'a mut BytesMut);
 
 impl<'a> Write for BytesMutWriter<'a> {
@@ -80,6 +184,58 @@ pub fn json_to_ndjson(v: &serde_json::Value) -> BytesMut {
     }
 }
 
+/// Fields identifying the connection a line arrived on, prepended to every
+/// NDJSON object from that connection. The prefix is encoded once at accept.
+pub struct ConnectionMetadata {
+    prefix: Vec<u8>,
+}
+
+impl ConnectionMetadata {
+    pub fn new(peer: Option<SocketAddr>) -> Self {
+        let id = ulid::Ulid::new().to_string();
+        let meta = match peer {
+            Some(addr) => simd_json::json!({
+                "_src_ip": addr.ip().to_string(),
+                "_src_port": addr.port(),
+                "_connection_id": id,
+            }),
+            None => simd_json::json!({ "_connection_id": id }),
+        };
+        let mut prefix = meta.encode().into_bytes();
+        // Keep the opening brace and fields; the frame supplies the rest.
+        prefix.pop();
+        Self { prefix }
+    }
+
+    /// Splice the metadata into each frame that holds a JSON object. Anything
+    /// else is forwarded untouched.
+    pub fn inject(&self, frames: Vec<BytesMut>) -> Vec<BytesMut> {
+        frames.into_iter().map(|f| self.inject_one(f)).collect()
+    }
+
+    fn inject_one(&self, frame: BytesMut) -> BytesMut {
+        let Some(open) = frame.iter().position(|b| !b.is_ascii_whitespace()) else {
+            return frame;
+        };
+        if frame[open] != b'{' {
+            return frame;
+        }
+        let rest = &frame[open + 1..];
+        let empty = rest
+            .iter()
+            .find(|b| !b.is_ascii_whitespace())
+            .is_some_and(|b| *b == b'}');
+
+        let mut out = BytesMut::with_capacity(self.prefix.len() + frame.len() + 1);
+        out.extend_from_slice(&self.prefix);
+        if !empty {
+            out.put_u8(b',');
+        }
+        out.extend_from_slice(rest);
+        out
+    }
+}
+
 pub fn msgpack_to_ndjson(data: &[u8]) -> Result<BytesMut> {
     use rmp_serde::Deserializer;
     let mut de = Deserializer::from_read_ref(data);
@@ -87,8 +243,114 @@ pub fn msgpack_to_ndjson(data: &[u8]) -> Result<BytesMut> {
     Ok(json_to_ndjson(&val))
 }
 
-pub fn normalize_to_ndjson(fmt: &DecodeFormat, mut raw: BytesMut) -> Result<BytesMut> {
+/// A source's `DecodeFormat` with anything it needs loaded up front. Sources
+/// build theirs on startup, so a missing descriptor set fails the source
+/// instead of every payload, and payloads decode without file I/O or locks.
+#[derive(Clone)]
+pub struct Decoder {
+    format: DecodeFormat,
+    protobuf: Option<MessageDescriptor>,
+}
+
+impl Decoder {
+    pub fn new(format: &DecodeFormat) -> Result<Self> {
+        let protobuf = match format {
+            DecodeFormat::Protobuf {
+                descriptor_path,
+                message_type,
+            } => Some(protobuf_descriptor(descriptor_path, message_type)?),
+            _ => None,
+        };
+        Ok(Self {
+            format: format.clone(),
+            protobuf,
+        })
+    }
+
+    pub fn normalize(&self, raw: BytesMut) -> Result<BytesMut> {
+        match (&self.format, &self.protobuf) {
+            // No text fallback: undecodable protobuf is binary noise downstream.
+            (DecodeFormat::Protobuf { message_type, .. }, Some(desc)) => {
+                protobuf_to_ndjson(desc, &raw).with_context(|| format!("decoding {message_type}"))
+            }
+            (fmt, _) => normalize_to_ndjson(fmt, raw),
+        }
+    }
+}
+
+fn protobuf_descriptor(path: &Path, message_type: &str) -> Result<MessageDescriptor> {
+    let pool = if path.extension().is_some_and(|e| e == "proto") {
+        compile_proto(path)?
+    } else {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("reading protobuf descriptor set {}", path.display()))?;
+        DescriptorPool::decode(bytes.as_slice())
+            .with_context(|| format!("parsing protobuf descriptor set {}", path.display()))?
+    };
+    pool.get_message_by_name(message_type).with_context(|| {
+        format!(
+            "message type {message_type:?} not found in {}",
+            path.display()
+        )
+    })
+}
+
+/// Compile a `.proto` file, resolving imports next to it.
+fn compile_proto(path: &Path) -> Result<DescriptorPool> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file = path
+        .file_name()
+        .context("proto schema path has no file name")?;
+    let pool = protox::Compiler::new([dir])
+        .and_then(|mut c| {
+            c.include_imports(true).open_files([file])?;
+            Ok(c.descriptor_pool())
+        })
+        .with_context(|| format!("compiling {}", path.display()))?;
+    Ok(pool)
+}
+
+/// Decode a single message and emit it as one JSON line, using the proto3
+/// JSON mapping (camelCase field names).
+pub fn protobuf_to_ndjson(desc: &MessageDescriptor, data: &[u8]) -> Result<BytesMut> {
+    let msg = DynamicMessage::decode(desc.clone(), data)?;
+    let mut buf = BytesMut::new();
+    serde_json::to_writer((&mut buf).writer(), &msg)?;
+    buf.put_u8(b'\n');
+    Ok(buf)
+}
+
+/// Magic bytes that open an Avro object container file.
+const AVRO_MAGIC: &[u8] = b"Obj\x01";
+
+/// Decode an Avro object container file with its embedded schema, one JSON
+/// line per record.
+pub fn avro_to_ndjson(data: &[u8]) -> Result<BytesMut> {
+    let reader = apache_avro::Reader::new(data)?;
+    let mut buf = BytesMut::new();
+    for value in reader {
+        let json = serde_json::Value::try_from(value?)?;
+        serde_json::to_writer((&mut buf).writer(), &json)?;
+        buf.put_u8(b'\n');
+    }
+    Ok(buf)
+}
+
+fn normalize_to_ndjson(fmt: &DecodeFormat, mut raw: BytesMut) -> Result<BytesMut> {
     match fmt {
+        DecodeFormat::Auto if raw.starts_with(AVRO_MAGIC) => {
+            avro_to_ndjson(&raw).context("decoding avro container")
+        }
+        DecodeFormat::Auto => match serde_json::from_slice::<serde_json::Value>(&raw) {
+            Ok(v) => Ok(json_to_ndjson(&v)),
+            // NDJSON, or text, passed through as is.
+            Err(_) => {
+                if !raw.ends_with(b"\n") {
+                    raw.put_u8(b'\n');
+                }
+                Ok(raw)
+            }
+        },
         DecodeFormat::Ndjson | DecodeFormat::Text => {
             if !raw.starts_with(b"{") {
                 anyhow::bail!("input is not valid ndjson")
@@ -120,6 +382,9 @@ pub fn normalize_to_ndjson(fmt: &DecodeFormat, mut raw: BytesMut) -> Result<Byte
                 Ok(raw)
             }
         },
+        DecodeFormat::Protobuf { message_type, .. } => {
+            anyhow::bail!("no descriptor loaded for {message_type}")
+        }
     }
 }
 
@@ -136,6 +401,24 @@ pub fn chunk_ndjson(buf: &mut BytesMut, chunks: usize) -> Vec<BytesMut> {
     out
 }
 
+/// Decompress and decode one message body from a source with no
+/// content-encoding or filename to go on, and split it into frames.
+pub fn decode_payload(
+    dc: &Decoding,
+    decoder: &Decoder,
+    body: BytesMut,
+    chunks: usize,
+) -> Result<Vec<BytesMut>> {
+    if body.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sniff = &body[..body.len().min(8)];
+    let comp = dc.resolve_compression(None, None, sniff);
+    let raw = decompress_bytes(&comp, body)?;
+    let mut ndjson = decoder.normalize(raw)?;
+    Ok(chunk_ndjson(&mut ndjson, chunks))
+}
+
 pub fn ndjson_chunk_slices(buf: Bytes, max_chunk: usize) -> Vec<Bytes> {
     let mut line_ends: Vec<usize> = memchr_iter(b'\n', &buf).collect();
     if line_ends.last().is_none_or(|&i| i + 1 != buf.len()) {
@@ -174,3 +457,202 @@ pub fn ndjson_chunk_slices(buf: Bytes, max_chunk: usize) -> Vec<Bytes> {
     }
     chunks
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINES: &[u8] = b"{\"msg\":\"a\"}\n{\"msg\":\"b\"}\n";
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    fn zstd(data: &[u8]) -> Vec<u8> {
+        zstd::stream::encode_all(data, 3).unwrap()
+    }
+
+    fn lz4(data: &[u8]) -> Vec<u8> {
+        let mut enc = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    fn auto() -> Decoding {
+        Decoding {
+            format: DecodeFormat::Ndjson,
+            compression: DecodeCompression::default(),
+        }
+    }
+
+    fn roundtrip(filename: Option<&str>, compressed: Vec<u8>) -> BytesMut {
+        let sniff = &compressed[..compressed.len().min(8)];
+        let comp = auto().resolve_compression(None, filename, sniff);
+        decompress_bytes(&comp, BytesMut::from(&compressed[..])).unwrap()
+    }
+
+    #[test]
+    fn decompresses_by_file_extension() {
+        assert_eq!(&roundtrip(Some("app.log.gz"), gzip(LINES))[..], LINES);
+        assert_eq!(&roundtrip(Some("app.log.zst"), zstd(LINES))[..], LINES);
+        assert_eq!(&roundtrip(Some("app.log.lz4"), lz4(LINES))[..], LINES);
+        assert_eq!(&roundtrip(Some("app.log"), LINES.to_vec())[..], LINES);
+    }
+
+    #[test]
+    fn decompresses_by_magic_bytes() {
+        assert_eq!(&roundtrip(None, gzip(LINES))[..], LINES);
+        assert_eq!(&roundtrip(None, zstd(LINES))[..], LINES);
+        assert_eq!(&roundtrip(None, lz4(LINES))[..], LINES);
+    }
+
+    #[test]
+    fn payloads_are_decompressed_and_decoded() {
+        let dc = Decoding {
+            format: DecodeFormat::JsonArray,
+            compression: DecodeCompression::default(),
+        };
+        let decoder = Decoder::new(&dc.format).unwrap();
+        let body = BytesMut::from(&gzip(br#"[{"msg":"a"},{"msg":"b"}]"#)[..]);
+        let frames = decode_payload(&dc, &decoder, body, 1).unwrap();
+        assert_eq!(frames.concat(), LINES);
+        assert!(decode_payload(&dc, &decoder, BytesMut::new(), 1)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn readers_are_decompressed_as_they_are_read() {
+        use tokio::io::AsyncReadExt;
+
+        let mut out = Vec::new();
+        let mut reader = decompress_reader(&DecodeCompression::Gzip, io::Cursor::new(gzip(LINES)));
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, LINES);
+
+        let mut corrupt = gzip(LINES);
+        corrupt.truncate(corrupt.len() / 2);
+        let mut reader = decompress_reader(&DecodeCompression::Gzip, io::Cursor::new(corrupt));
+        assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+    }
+
+    #[test]
+    fn connection_metadata_is_prepended_to_objects() {
+        let meta = ConnectionMetadata::new(Some("10.1.2.3:5514".parse().unwrap()));
+        let frames = vec![
+            BytesMut::from(&b"{\"msg\":\"a\"}\n"[..]),
+            BytesMut::from(&b"{}\n"[..]),
+            BytesMut::from(&b"not json\n"[..]),
+        ];
+        let out = meta.inject(frames);
+
+        let a: serde_json::Value = serde_json::from_slice(&out[0]).unwrap();
+        assert_eq!(a["msg"], "a");
+        assert_eq!(a["_src_ip"], "10.1.2.3");
+        assert_eq!(a["_src_port"], 5514);
+        let id = a["_connection_id"].as_str().unwrap();
+        assert!(ulid::Ulid::from_string(id).is_ok());
+
+        let empty: serde_json::Value = serde_json::from_slice(&out[1]).unwrap();
+        assert_eq!(empty["_connection_id"], id);
+        assert_eq!(&out[2][..], b"not json\n");
+    }
+
+    #[test]
+    fn protobuf_roundtrips_to_camel_case_json() {
+        use prost_reflect::prost::Message;
+        use prost_reflect::prost_types::field_descriptor_proto::{Label, Type};
+        use prost_reflect::prost_types::{
+            DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        };
+
+        let field = |name: &str, number: i32, ty: Type| FieldDescriptorProto {
+            name: Some(name.into()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(ty as i32),
+            ..Default::default()
+        };
+        let set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("event.proto".into()),
+                package: Some("acme.logs".into()),
+                syntax: Some("proto3".into()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Event".into()),
+                    field: vec![
+                        field("user_name", 1, Type::String),
+                        field("status_code", 2, Type::Int32),
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+        let path = std::env::temp_dir().join(format!("tangent-proto-{}.pb", ulid::Ulid::new()));
+        std::fs::write(&path, set.encode_to_vec()).unwrap();
+
+        let fmt = DecodeFormat::Protobuf {
+            descriptor_path: path.clone(),
+            message_type: "acme.logs.Event".into(),
+        };
+        let decoder = Decoder::new(&fmt).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let mut msg = DynamicMessage::new(decoder.protobuf.clone().unwrap());
+        msg.set_field_by_name("user_name", prost_reflect::Value::String("ana".into()));
+        msg.set_field_by_name("status_code", prost_reflect::Value::I32(404));
+
+        // Decoding never goes back to the descriptor file.
+        let out = decoder
+            .normalize(BytesMut::from(&msg.encode_to_vec()[..]))
+            .unwrap();
+        assert_eq!(&out[..], b"{\"userName\":\"ana\",\"statusCode\":404}\n");
+
+        std::fs::write(&path, set.encode_to_vec()).unwrap();
+        let missing = DecodeFormat::Protobuf {
+            descriptor_path: path.clone(),
+            message_type: "acme.logs.Missing".into(),
+        };
+        assert!(Decoder::new(&missing).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn auto_format_detects_avro_containers() {
+        let schema = apache_avro::Schema::parse_str(
+            r#"{"type":"record","name":"Click","fields":[{"name":"user","type":"string"},{"name":"n","type":"long"}]}"#,
+        )
+        .unwrap();
+        let mut writer = apache_avro::Writer::new(&schema, Vec::new());
+        for (user, n) in [("ana", 1_i64), ("bo", 2)] {
+            let mut rec = apache_avro::types::Record::new(&schema).unwrap();
+            rec.put("user", user);
+            rec.put("n", n);
+            writer.append(rec).unwrap();
+        }
+        let avro = writer.into_inner().unwrap();
+
+        let out = normalize_to_ndjson(&DecodeFormat::Auto, BytesMut::from(&avro[..])).unwrap();
+        let records: Vec<serde_json::Value> = out[..]
+            .split(|&b| b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_slice(l).unwrap())
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                serde_json::json!({ "user": "ana", "n": 1 }),
+                serde_json::json!({ "user": "bo", "n": 2 })
+            ]
+        );
+
+        let out = normalize_to_ndjson(&DecodeFormat::Auto, BytesMut::from(LINES)).unwrap();
+        assert_eq!(&out[..], LINES);
+        let out =
+            normalize_to_ndjson(&DecodeFormat::Auto, BytesMut::from(&b"[{\"a\":1}]"[..])).unwrap();
+        assert_eq!(&out[..], b"{\"a\":1}\n");
+    }
+}
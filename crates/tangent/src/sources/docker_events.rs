@@ -0,0 +1,214 @@
+use anyhow::{Context, Result};
+use bytes::BytesMut;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tangent_shared::dag::NodeRef;
+use tangent_shared::sources::docker_events::DockerEventsConfig;
+use tokio_util::sync::CancellationToken;
+
+use crate::router::Router;
+
+/// Host part of request URLs; ignored, since the client only talks to the
+/// socket.
+const EVENTS_URL: &str = "http://docker/events";
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+/// Stream `GET /events` from the Docker daemon at `cfg.socket_path` and
+/// forward each event as a normalized NDJSON line. Dropped connections are
+/// retried with exponential backoff, resuming just after the last event
+/// forwarded.
+pub async fn run_consumer(
+    name: Arc<str>,
+    cfg: DockerEventsConfig,
+    router: Arc<Router>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .unix_socket(cfg.socket_path.clone())
+        .build()
+        .context("building docker client")?;
+    let filters = (!cfg.filters.is_empty())
+        .then(|| serde_json::to_string(&cfg.filters))
+        .transpose()?;
+    let from = NodeRef::Source { name };
+    let max_delay = Duration::from_secs(cfg.max_restart_delay_secs).max(INITIAL_RECONNECT_DELAY);
+
+    tracing::info!(
+        "docker_events source streaming from {}",
+        cfg.socket_path.display()
+    );
+
+    let mut since: Option<i64> = None;
+    let mut delay = INITIAL_RECONNECT_DELAY;
+    loop {
+        let res = follow(
+            &client,
+            filters.as_deref(),
+            &mut since,
+            &mut delay,
+            &router,
+            &from,
+            &shutdown,
+        )
+        .await;
+        if let Err(e) = res {
+            tracing::warn!(
+                "docker_events stream from {} failed: {e:#}, reconnecting in {}s",
+                cfg.socket_path.display(),
+                delay.as_secs_f64()
+            );
+        }
+        tokio::select! {
+            () = shutdown.cancelled() => break,
+            () = tokio::time::sleep(delay) => {}
+        }
+        delay = (delay * 2).min(max_delay);
+    }
+
+    Ok(())
+}
+
+/// Read one `/events` connection until it drops or `shutdown` fires, moving
+/// `since` to the newest forwarded event. `delay` is reset once connected.
+async fn follow(
+    client: &reqwest::Client,
+    filters: Option<&str>,
+    since: &mut Option<i64>,
+    delay: &mut Duration,
+    router: &Router,
+    from: &NodeRef,
+    shutdown: &CancellationToken,
+) -> Result<()> {
+    let mut query = Vec::new();
+    if let Some(filters) = filters {
+        query.push(("filters", filters.to_string()));
+    }
+    if let Some(ns) = *since {
+        query.push(("since", since_param(ns + 1)));
+    }
+    let mut resp = client
+        .get(EVENTS_URL)
+        .query(&query)
+        .send()
+        .await
+        .context("connecting to docker")?
+        .error_for_status()
+        .context("docker rejected the events request")?;
+    *delay = INITIAL_RECONNECT_DELAY;
+
+    let mut buf = BytesMut::new();
+    loop {
+        let chunk = tokio::select! {
+            () = shutdown.cancelled() => return Ok(()),
+            chunk = resp.chunk() => chunk.context("reading docker events")?,
+        };
+        let Some(chunk) = chunk else {
+            anyhow::bail!("docker closed the events stream");
+        };
+        buf.extend_from_slice(&chunk);
+
+        let mut frames = Vec::new();
+        let mut newest = None;
+        while let Some(pos) = memchr::memchr(b'\n', &buf) {
+            let line = buf.split_to(pos + 1);
+            match normalize(&line[..pos]) {
+                Ok((time_nano, frame)) => {
+                    frames.push(frame);
+                    newest = newest.max(Some(time_nano));
+                }
+                Err(e) => tracing::warn!("skipping unreadable docker event: {e}"),
+            }
+        }
+        if frames.is_empty() {
+            continue;
+        }
+        router
+            .forward(from, frames, Vec::new())
+            .await
+            .context("router.forward failed for docker_events")?;
+        *since = since.max(newest);
+    }
+}
+
+#[derive(Deserialize)]
+struct DockerEvent {
+    #[serde(rename = "Type")]
+    kind: String,
+    #[serde(rename = "Action")]
+    action: String,
+    #[serde(rename = "Actor", default)]
+    actor: Actor,
+    #[serde(default)]
+    scope: Option<String>,
+    #[serde(rename = "timeNano")]
+    time_nano: i64,
+}
+
+#[derive(Deserialize, Default)]
+struct Actor {
+    #[serde(rename = "ID", default)]
+    id: String,
+    #[serde(rename = "Attributes", default)]
+    attributes: BTreeMap<String, String>,
+}
+
+/// The event's `timeNano` and its NDJSON line.
+fn normalize(line: &[u8]) -> Result<(i64, BytesMut)> {
+    let ev: DockerEvent = serde_json::from_slice(line)?;
+    let out = json!({
+        "kind": "docker_event",
+        "type": ev.kind,
+        "action": ev.action,
+        "actor_id": ev.actor.id,
+        "attributes": ev.actor.attributes,
+        "scope": ev.scope,
+        "time_nano": ev.time_nano,
+    });
+    let mut buf = BytesMut::with_capacity(256);
+    buf.extend_from_slice(out.to_string().as_bytes());
+    buf.extend_from_slice(b"\n");
+    Ok((ev.time_nano, buf))
+}
+
+/// `since` as Docker takes it: `<seconds>.<nanoseconds>`.
+fn since_param(time_nano: i64) -> String {
+    format!(
+        "{}.{:09}",
+        time_nano.div_euclid(1_000_000_000),
+        time_nano.rem_euclid(1_000_000_000)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_normalized() {
+        let line = br#"{"status":"die","id":"4f1c","from":"nginx","Type":"container","Action":"die","Actor":{"ID":"4f1c","Attributes":{"exitCode":"137","name":"web"}},"scope":"local","time":1700000000,"timeNano":1700000000123456789}"#;
+        let (time_nano, frame) = normalize(line).unwrap();
+        assert_eq!(time_nano, 1_700_000_000_123_456_789);
+        let ev: serde_json::Value = serde_json::from_slice(&frame).unwrap();
+        assert_eq!(
+            ev,
+            json!({
+                "kind": "docker_event",
+                "type": "container",
+                "action": "die",
+                "actor_id": "4f1c",
+                "attributes": { "exitCode": "137", "name": "web" },
+                "scope": "local",
+                "time_nano": 1_700_000_000_123_456_789_i64
+            })
+        );
+
+        assert_eq!(
+            since_param(1_700_000_000_000_000_001),
+            "1700000000.000000001"
+        );
+        assert!(normalize(b"not json").is_err());
+    }
+}
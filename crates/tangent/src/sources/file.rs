@@ -1,24 +1,50 @@
-use anyhow::Result;
+use ahash::AHashSet as HashSet;
+use anyhow::{Context, Result};
 use bytes::BytesMut;
-use std::path::PathBuf;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::io::{ErrorKind, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tangent_shared::dag::NodeRef;
+use tangent_shared::sources::common::{DecodeCompression, DecodeFormat};
 use tangent_shared::sources::file::FileConfig;
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 
+use crate::cache::CacheHandle;
 use crate::router::Router;
-use crate::sources::decoding;
-use crate::sources::decoding::normalize_to_ndjson;
+use crate::sources::decoding::{self, Decoder};
+use crate::wasm::host::tangent::logs::log::Scalar;
+
+/// Sleep between reads that hit EOF in tail mode.
+const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Forward buffered lines once this many bytes are waiting, even if the
+/// file has more to read.
+const TAIL_FLUSH_BYTES: usize = 1 << 20;
 
 pub async fn run_consumer(
     name: Arc<str>,
     cfg: FileConfig,
     chunks: usize,
     router: Arc<Router>,
+    cache: Arc<CacheHandle>,
     shutdown: CancellationToken,
 ) -> Result<()> {
+    if let Some(pattern) = cfg.glob.clone() {
+        return follow_glob(name, &pattern, cfg, chunks, router, cache, shutdown).await;
+    }
+    if cfg.tail {
+        return tail(name, cfg, chunks, router, shutdown).await;
+    }
+
+    let decoder = Decoder::new(&cfg.decoding.format)?;
     let path: PathBuf = cfg.path;
     let dc = cfg.decoding.clone();
 
@@ -31,7 +57,7 @@ pub async fn run_consumer(
     let comp = dc.resolve_compression(None, path.file_name().and_then(|s| s.to_str()), sniff);
     let raw = decoding::decompress_bytes(&comp, buf)?;
 
-    let mut ndjson = normalize_to_ndjson(&cfg.decoding.format, raw)?;
+    let mut ndjson = decoder.normalize(raw)?;
     let frames = decoding::chunk_ndjson(&mut ndjson, chunks);
 
     let from = NodeRef::Source { name: name };
@@ -40,3 +66,501 @@ pub async fn run_consumer(
     let () = shutdown.cancelled().await;
     Ok(())
 }
+
+/// Follow `cfg.path` like `tail -F`: read to EOF, then poll for appended
+/// bytes, reopening the path when it is replaced (rotation) and rewinding
+/// when it is truncated. Waits for the file if it doesn't exist yet.
+async fn tail(
+    name: Arc<str>,
+    cfg: FileConfig,
+    chunks: usize,
+    router: Arc<Router>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    check_followable(&name, &cfg)?;
+    tracing::info!("file source tailing {}", cfg.path.display());
+
+    let from = NodeRef::Source { name };
+    follow(&cfg.path, &cfg, chunks, &router, &from, None, &shutdown).await
+}
+
+/// Follow every file matching `pattern` in a task of its own, and start on
+/// new matches whenever the watcher sees a file created or renamed under
+/// the pattern's directory. A file that is removed is read to its end and
+/// its task finishes.
+async fn follow_glob(
+    name: Arc<str>,
+    pattern: &str,
+    cfg: FileConfig,
+    chunks: usize,
+    router: Arc<Router>,
+    cache: Arc<CacheHandle>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    check_followable(&name, &cfg)?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(ev)
+            if matches!(
+                ev.kind,
+                EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+            ) =>
+        {
+            let _ = tx.send(());
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("file source watcher error: {e}"),
+    })
+    .context("starting file watcher")?;
+    let root = glob_root(pattern);
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .with_context(|| format!("watching {}", root.display()))?;
+    tracing::info!("file source following {pattern}");
+
+    let cfg = Arc::new(cfg);
+    let from = Arc::new(NodeRef::Source {
+        name: Arc::clone(&name),
+    });
+    let offsets = Arc::new(Offsets::new(cache, &name));
+    let mut following: HashSet<PathBuf> = HashSet::new();
+    let mut readers: JoinSet<(PathBuf, Result<()>)> = JoinSet::new();
+
+    loop {
+        let matches = glob::glob(pattern).with_context(|| format!("invalid glob {pattern}"))?;
+        for path in matches.filter_map(Result::ok) {
+            if !path.is_file() || !following.insert(path.clone()) {
+                continue;
+            }
+            tracing::info!("file source {name} following {}", path.display());
+            let (cfg, router, from, offsets, shutdown) = (
+                Arc::clone(&cfg),
+                Arc::clone(&router),
+                Arc::clone(&from),
+                Arc::clone(&offsets),
+                shutdown.clone(),
+            );
+            readers.spawn(async move {
+                let res = follow(
+                    &path,
+                    &cfg,
+                    chunks,
+                    &router,
+                    &from,
+                    Some(&offsets),
+                    &shutdown,
+                )
+                .await;
+                (path, res)
+            });
+        }
+
+        tokio::select! {
+            () = shutdown.cancelled() => break,
+            Some(()) = rx.recv() => {
+                // One rescan covers a burst of events.
+                while rx.try_recv().is_ok() {}
+            }
+            Some(done) = readers.join_next() => {
+                let (path, res) = done?;
+                following.remove(&path);
+                res.with_context(|| format!("following {}", path.display()))?;
+            }
+        }
+    }
+
+    while let Some(done) = readers.join_next().await {
+        let (path, res) = done?;
+        res.with_context(|| format!("following {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn check_followable(name: &str, cfg: &FileConfig) -> Result<()> {
+    if !matches!(
+        cfg.decoding.compression,
+        DecodeCompression::Auto | DecodeCompression::None
+    ) {
+        anyhow::bail!("file source {name}: tail and glob modes only read uncompressed files");
+    }
+    if matches!(cfg.decoding.format, DecodeFormat::Protobuf { .. }) {
+        anyhow::bail!("file source {name}: tail and glob modes only read line-delimited formats");
+    }
+    Ok(())
+}
+
+/// The directory to watch for `pattern`: its leading components up to the
+/// first one with a wildcard.
+fn glob_root(pattern: &str) -> PathBuf {
+    let mut root = PathBuf::new();
+    for c in Path::new(pattern).components() {
+        if c.as_os_str()
+            .to_str()
+            .is_some_and(|s| s.contains(['*', '?', '[']))
+        {
+            break;
+        }
+        root.push(c);
+    }
+    if root == Path::new(pattern) {
+        root.pop();
+    }
+    if root.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        root
+    }
+}
+
+/// Read `path` to EOF and keep polling it for appended bytes, reopening it
+/// when it is replaced and rewinding when it is truncated. With `offsets`
+/// (glob mode) reading resumes from the saved offset, progress is saved
+/// after every forward, and the file is finished once it is removed;
+/// otherwise a missing file is waited for.
+async fn follow(
+    path: &Path,
+    cfg: &FileConfig,
+    chunks: usize,
+    router: &Router,
+    from: &NodeRef,
+    offsets: Option<&Offsets>,
+    shutdown: &CancellationToken,
+) -> Result<()> {
+    // Followed files are line-delimited, so this never loads a descriptor.
+    let decoder = Decoder::new(&cfg.decoding.format)?;
+    let mut lines = LineBuffer::new(cfg.max_line_bytes);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut current: Option<Followed> = None;
+
+    loop {
+        let Some(followed) = current.as_mut() else {
+            match Followed::open(path).await {
+                Ok(mut f) => {
+                    if let Some(offsets) = offsets {
+                        f.resume(offsets, path).await?;
+                    }
+                    current = Some(f);
+                }
+                // Matched, but removed before we got to it.
+                Err(e) if e.kind() == ErrorKind::NotFound && offsets.is_some() => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    tokio::select! {
+                        () = shutdown.cancelled() => break,
+                        () = tokio::time::sleep(TAIL_POLL_INTERVAL) => {}
+                    }
+                }
+                Err(e) => return Err(e).with_context(|| format!("opening {}", path.display())),
+            }
+            continue;
+        };
+
+        let n = tokio::select! {
+            () = shutdown.cancelled() => break,
+            res = followed.file.read(&mut buf) => res?,
+        };
+        if n > 0 {
+            followed.pos += n as u64;
+            lines.push(&buf[..n]);
+            if lines.ready() >= TAIL_FLUSH_BYTES {
+                forward(path, cfg, &decoder, chunks, router, from, &mut lines).await?;
+                followed.checkpoint(offsets, path, &lines);
+            }
+            continue;
+        }
+
+        forward(path, cfg, &decoder, chunks, router, from, &mut lines).await?;
+        followed.checkpoint(offsets, path, &lines);
+        match followed.change(path).await? {
+            Some(Change::Replaced) => {
+                tracing::info!("{} was rotated; reopening", path.display());
+                // Nothing more will be appended to the old file, so its
+                // unterminated last line is complete.
+                lines.end_line();
+                forward(path, cfg, &decoder, chunks, router, from, &mut lines).await?;
+                current = None;
+            }
+            Some(Change::Truncated) => {
+                tracing::info!("{} was truncated; rewinding", path.display());
+                lines.discard_partial();
+                followed.file.seek(SeekFrom::Start(0)).await?;
+                followed.pos = 0;
+            }
+            Some(Change::Removed) if offsets.is_some() => {
+                lines.end_line();
+                forward(path, cfg, &decoder, chunks, router, from, &mut lines).await?;
+                if let Some(offsets) = offsets {
+                    offsets.forget(path);
+                }
+                return Ok(());
+            }
+            // Moved away and not recreated yet; keep the old file until the
+            // new one shows up.
+            Some(Change::Removed) | None => {
+                tokio::select! {
+                    () = shutdown.cancelled() => break,
+                    () = tokio::time::sleep(TAIL_POLL_INTERVAL) => {}
+                }
+            }
+        }
+    }
+
+    forward(path, cfg, &decoder, chunks, router, from, &mut lines).await?;
+    if let Some(followed) = current.as_mut() {
+        followed.checkpoint(offsets, path, &lines);
+    }
+    Ok(())
+}
+
+async fn forward(
+    path: &Path,
+    cfg: &FileConfig,
+    decoder: &Decoder,
+    chunks: usize,
+    router: &Router,
+    from: &NodeRef,
+    lines: &mut LineBuffer,
+) -> Result<()> {
+    if lines.dropped > 0 {
+        tracing::warn!(
+            "dropped {} line(s) from {} longer than max_line_bytes ({})",
+            lines.dropped,
+            path.display(),
+            cfg.max_line_bytes
+        );
+        lines.dropped = 0;
+    }
+    let ready = lines.take();
+    if ready.is_empty() {
+        return Ok(());
+    }
+    let mut ndjson = decoder.normalize(ready)?;
+    let frames = decoding::chunk_ndjson(&mut ndjson, chunks);
+    router.forward(from, frames, Vec::new()).await
+}
+
+/// How far each file followed in glob mode has been forwarded, kept in the
+/// runtime cache by path. The inode is saved alongside so a file rotated
+/// into the same path starts from the beginning.
+struct Offsets {
+    cache: Arc<CacheHandle>,
+    prefix: String,
+}
+
+impl Offsets {
+    fn new(cache: Arc<CacheHandle>, source: &str) -> Self {
+        Self {
+            cache,
+            prefix: format!("tangent:file:{source}"),
+        }
+    }
+
+    fn key(&self, path: &Path) -> String {
+        format!("{}:{}", self.prefix, path.display())
+    }
+
+    /// The offset saved for `path` while it was inode `ino`.
+    fn load(&self, path: &Path, ino: u64) -> Option<u64> {
+        match self.cache.get(&self.key(path)) {
+            Ok(Some(Scalar::Str(v))) => {
+                let (saved_ino, offset) = v.split_once(':')?;
+                (saved_ino.parse() == Ok(ino))
+                    .then(|| offset.parse().ok())
+                    .flatten()
+            }
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!("reading file offset for {} failed: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    fn save(&self, path: &Path, ino: u64, offset: u64) {
+        let value = Scalar::Str(format!("{ino}:{offset}"));
+        if let Err(e) = self.cache.set(&self.key(path), &value, None) {
+            tracing::warn!("saving file offset for {} failed: {e}", path.display());
+        }
+    }
+
+    fn forget(&self, path: &Path) {
+        if let Err(e) = self.cache.del(&self.key(path)) {
+            tracing::warn!("clearing file offset for {} failed: {e}", path.display());
+        }
+    }
+}
+
+/// The open file being tailed and how far into it we've read.
+struct Followed {
+    file: File,
+    dev: u64,
+    ino: u64,
+    pos: u64,
+    /// Offset last handed to `Offsets::save`.
+    saved: u64,
+}
+
+enum Change {
+    /// The path now names a different file.
+    Replaced,
+    /// The file is shorter than what we've already read.
+    Truncated,
+    /// Nothing exists at the path any more.
+    Removed,
+}
+
+impl Followed {
+    async fn open(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path).await?;
+        let md = file.metadata().await?;
+        Ok(Self {
+            file,
+            dev: md.dev(),
+            ino: md.ino(),
+            pos: 0,
+            saved: 0,
+        })
+    }
+
+    /// Seek to the offset saved for this file, unless it has since shrunk
+    /// below it.
+    async fn resume(&mut self, offsets: &Offsets, path: &Path) -> Result<()> {
+        let len = self.file.metadata().await?.len();
+        if let Some(offset) = offsets.load(path, self.ino).filter(|o| *o <= len) {
+            self.file.seek(SeekFrom::Start(offset)).await?;
+            self.pos = offset;
+            self.saved = offset;
+        }
+        Ok(())
+    }
+
+    /// Save how far the file has been forwarded: everything read except an
+    /// unterminated last line still in `lines`.
+    fn checkpoint(&mut self, offsets: Option<&Offsets>, path: &Path, lines: &LineBuffer) {
+        let Some(offsets) = offsets else {
+            return;
+        };
+        let done = self.pos.saturating_sub(lines.partial.len() as u64);
+        if done != self.saved {
+            offsets.save(path, self.ino, done);
+            self.saved = done;
+        }
+    }
+
+    async fn change(&self, path: &Path) -> Result<Option<Change>> {
+        let md = match fs::metadata(path).await {
+            Ok(md) => md,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Some(Change::Removed)),
+            Err(e) => return Err(e).with_context(|| format!("stat {}", path.display())),
+        };
+        if md.dev() != self.dev || md.ino() != self.ino {
+            return Ok(Some(Change::Replaced));
+        }
+        if md.len() < self.pos {
+            return Ok(Some(Change::Truncated));
+        }
+        Ok(None)
+    }
+}
+
+/// Splits tailed bytes into complete lines, holding back a trailing partial
+/// line and dropping any line longer than `max_line_bytes`.
+struct LineBuffer {
+    complete: BytesMut,
+    partial: BytesMut,
+    /// Inside a line that was already too long; skip to its newline.
+    skipping: bool,
+    max_line_bytes: usize,
+    dropped: usize,
+}
+
+impl LineBuffer {
+    fn new(max_line_bytes: usize) -> Self {
+        Self {
+            complete: BytesMut::new(),
+            partial: BytesMut::new(),
+            skipping: false,
+            max_line_bytes,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let (segment, ended) = match memchr::memchr(b'\n', data) {
+                Some(i) => (&data[..i], true),
+                None => (data, false),
+            };
+            data = &data[segment.len() + usize::from(ended)..];
+
+            if self.skipping {
+                self.skipping = !ended;
+                continue;
+            }
+            if self.partial.len() + segment.len() > self.max_line_bytes {
+                self.partial.clear();
+                self.dropped += 1;
+                self.skipping = !ended;
+                continue;
+            }
+            self.partial.extend_from_slice(segment);
+            if ended {
+                self.end_line();
+            }
+        }
+    }
+
+    /// Treat whatever partial line is buffered as complete.
+    fn end_line(&mut self) {
+        if !self.partial.is_empty() {
+            self.complete.extend_from_slice(&self.partial);
+            self.complete.extend_from_slice(b"\n");
+            self.partial.clear();
+        }
+        self.skipping = false;
+    }
+
+    fn discard_partial(&mut self) {
+        self.partial.clear();
+        self.skipping = false;
+    }
+
+    /// Bytes of complete lines waiting to be taken.
+    fn ready(&self) -> usize {
+        self.complete.len()
+    }
+
+    fn take(&mut self) -> BytesMut {
+        self.complete.split()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_lines_wait_and_long_lines_are_dropped() {
+        let mut lines = LineBuffer::new(8);
+        lines.push(b"{\"a\":1}\n{\"b\"");
+        assert_eq!(&lines.take()[..], b"{\"a\":1}\n");
+
+        lines.push(b":2}\n0123456789");
+        lines.push(b"abcdef\n{}\n");
+        assert_eq!(&lines.take()[..], b"{\"b\":2}\n{}\n");
+        assert_eq!(lines.dropped, 1);
+
+        lines.push(b"tail");
+        assert!(lines.take().is_empty());
+        lines.end_line();
+        assert_eq!(&lines.take()[..], b"tail\n");
+    }
+
+    #[test]
+    fn glob_root_stops_at_the_first_wildcard() {
+        assert_eq!(glob_root("logs/**/*.log"), Path::new("logs"));
+        assert_eq!(glob_root("/var/log/app-*/current"), Path::new("/var/log"));
+        assert_eq!(glob_root("*.log"), Path::new("."));
+        assert_eq!(glob_root("/var/log/app.log"), Path::new("/var/log"));
+    }
+}
@@ -1,4 +1,8 @@
-use std::{io::Read, sync::Arc};
+use std::{
+    io::Read,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Context, Result};
 use axum::{
@@ -12,14 +16,19 @@ use axum::{
 use bytes::{BufMut, BytesMut};
 use hmac::{Hmac, Mac};
 use http_body_util::BodyExt;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
 use lazy_static::lazy_static;
 use memchr::memchr;
 use regex::Regex;
+use serde::Deserialize;
 use serde_json::{json, Value};
 use sha2::Sha256;
 use tangent_shared::dag::NodeRef;
 use tangent_shared::sources::github_webhook::GithubWebhookConfig;
-use tokio::{net::TcpListener, sync::mpsc};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, RwLock},
+};
 use tokio_util::sync::CancellationToken;
 
 use crate::router::Router;
@@ -28,11 +37,74 @@ type HmacSha256 = Hmac<Sha256>;
 
 const LOGS_FETCHER_CHANNEL_CAPACITY: usize = 512;
 
+const OIDC_ISSUER: &str = "https://token.actions.githubusercontent.com";
+const OIDC_JWKS_URL: &str = "https://token.actions.githubusercontent.com/.well-known/jwks";
+const OIDC_TOKEN_HEADER: &str = "X-GitHub-OIDC-Token";
+const JWKS_TTL: Duration = Duration::from_secs(60 * 60);
+/// Least time between JWKS fetches forced by tokens with an unknown `kid`.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
 #[derive(Clone)]
 struct WebhookState {
     cfg: Arc<GithubWebhookConfig>,
     err_tx: mpsc::Sender<anyhow::Error>,
     logs_tx: mpsc::Sender<BytesMut>,
+    jwks: Arc<JwksCache>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OidcClaims {
+    repository: Option<String>,
+}
+
+/// In-memory cache of GitHub's OIDC signing keys.
+struct JwksCache {
+    client: reqwest::Client,
+    keys: RwLock<Option<(Instant, Arc<JwkSet>)>>,
+}
+
+impl JwksCache {
+    fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            keys: RwLock::new(None),
+        }
+    }
+
+    /// The cached keys, fetched again once `JWKS_TTL` old. `force_refresh`
+    /// fetches sooner, but at most once per `JWKS_MIN_REFRESH`, so tokens
+    /// with made-up `kid`s can't make every request call GitHub.
+    async fn get(&self, force_refresh: bool) -> Result<Arc<JwkSet>> {
+        let max_age = if force_refresh {
+            JWKS_MIN_REFRESH
+        } else {
+            JWKS_TTL
+        };
+        if let Some((fetched_at, keys)) = self.keys.read().await.as_ref() {
+            if fetched_at.elapsed() < max_age {
+                return Ok(keys.clone());
+            }
+        }
+
+        let bytes = self
+            .client
+            .get(OIDC_JWKS_URL)
+            .header("User-Agent", "tangent-logs")
+            .send()
+            .await
+            .context("request for github OIDC JWKS failed")?
+            .error_for_status()
+            .context("github returned error for OIDC JWKS")?
+            .bytes()
+            .await
+            .context("failed to read github OIDC JWKS body")?;
+        let keys: JwkSet =
+            serde_json::from_slice(&bytes).context("failed to parse github OIDC JWKS")?;
+
+        let keys = Arc::new(keys);
+        *self.keys.write().await = Some((Instant::now(), keys.clone()));
+        Ok(keys)
+    }
 }
 
 lazy_static! {
@@ -108,10 +180,15 @@ pub async fn run_consumer(
         }
     });
 
+    if cfg.verify_oidc && cfg.oidc_audience.is_none() {
+        return Err(anyhow!("oidc_audience is required when verify_oidc is enabled"));
+    }
+
     let state = WebhookState {
         cfg: cfg.clone(),
         err_tx,
         logs_tx,
+        jwks: Arc::new(JwksCache::new(reqwest::Client::new())),
     };
 
     let listener = TcpListener::bind(cfg.bind_address).await.with_context(|| {
@@ -149,6 +226,18 @@ async fn webhook_handler(
     State(state): State<WebhookState>,
     req: Request<Body>,
 ) -> impl IntoResponse {
+    if state.cfg.verify_oidc {
+        let token = req
+            .headers()
+            .get(OIDC_TOKEN_HEADER)
+            .and_then(|v| v.to_str().ok());
+
+        if let Err(e) = verify_oidc_token(token, &state.cfg, &state.jwks).await {
+            tracing::warn!("rejecting github webhook: {e:#}");
+            return (StatusCode::UNAUTHORIZED, "unauthorized");
+        }
+    }
+
     let result = handle_request(req, state.cfg.clone(), state.logs_tx.clone()).await;
     if let Err(err) = result {
         let _ = state.err_tx.send(err).await;
@@ -231,6 +320,68 @@ async fn handle_request(
     Ok(())
 }
 
+/// Verify a GitHub Actions OIDC token: signature against the JWKS, then
+/// `iss`, `aud` and `repository` claims against the source config.
+async fn verify_oidc_token(
+    token: Option<&str>,
+    cfg: &GithubWebhookConfig,
+    jwks: &JwksCache,
+) -> Result<()> {
+    let token = token.ok_or_else(|| anyhow!("missing {OIDC_TOKEN_HEADER} header"))?;
+    let audience = cfg
+        .oidc_audience
+        .as_deref()
+        .ok_or_else(|| anyhow!("oidc_audience not configured"))?;
+
+    // Tokens that can't be for us are turned away before any key lookup.
+    decode::<OidcClaims>(
+        token,
+        &DecodingKey::from_secret(&[]),
+        &oidc_validation(audience, false),
+    )
+    .context("OIDC token validation failed")?;
+
+    let header = decode_header(token).context("invalid OIDC token header")?;
+    let kid = header
+        .kid
+        .ok_or_else(|| anyhow!("OIDC token header missing kid"))?;
+
+    // Keys rotate; refetch once if the kid is unknown to the cached set.
+    let mut keys = jwks.get(false).await?;
+    if keys.find(&kid).is_none() {
+        keys = jwks.get(true).await?;
+    }
+    let jwk = keys
+        .find(&kid)
+        .ok_or_else(|| anyhow!("no JWKS key for kid {kid}"))?;
+    let key = DecodingKey::from_jwk(jwk).context("invalid JWKS key")?;
+
+    let claims = decode::<OidcClaims>(token, &key, &oidc_validation(audience, true))
+        .context("OIDC token validation failed")?
+        .claims;
+
+    check_repository_claim(claims.repository.as_deref(), &cfg.oidc_repositories)
+}
+
+fn oidc_validation(audience: &str, check_signature: bool) -> Validation {
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.set_issuer(&[OIDC_ISSUER]);
+    validation.set_audience(&[audience]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+    if !check_signature {
+        validation.insecure_disable_signature_validation();
+    }
+    validation
+}
+
+fn check_repository_claim(repository: Option<&str>, allowed: &[String]) -> Result<()> {
+    let repository = repository.ok_or_else(|| anyhow!("OIDC token missing repository claim"))?;
+    if !allowed.is_empty() && !allowed.iter().any(|r| r.eq_ignore_ascii_case(repository)) {
+        return Err(anyhow!("repository {repository} is not allowed"));
+    }
+    Ok(())
+}
+
 async fn consume_body(body: Body) -> Result<()> {
     let _ = body.collect().await?;
     Ok(())
@@ -458,7 +609,12 @@ async fn process_webhook_frame(
 
 #[cfg(test)]
 mod tests {
-    use super::split_timestamp_and_message;
+    use super::{
+        check_repository_claim, oidc_validation, split_timestamp_and_message, OidcClaims,
+        OIDC_ISSUER,
+    };
+    use jsonwebtoken::{decode, DecodingKey};
+    use serde_json::json;
 
     #[test]
     fn splits_timestamp_and_message_with_bom() {
@@ -477,4 +633,38 @@ mod tests {
         assert!(timestamp.is_none());
         assert_eq!(message, "plain log without ts");
     }
+
+    #[test]
+    fn repository_claim_checked_against_allowlist() {
+        let allowed = vec!["telophasehq/tangent".to_string()];
+
+        assert!(check_repository_claim(Some("telophasehq/tangent"), &allowed).is_ok());
+        assert!(check_repository_claim(Some("someone/else"), &allowed).is_err());
+        assert!(check_repository_claim(Some("someone/else"), &[]).is_ok());
+        assert!(check_repository_claim(None, &[]).is_err());
+    }
+
+    #[test]
+    fn claims_are_checked_before_the_signature() {
+        let exp = chrono::Utc::now().timestamp() + 60;
+        let token = |iss: &str, aud: &str| {
+            jsonwebtoken::encode(
+                &jsonwebtoken::Header::default(),
+                &json!({ "iss": iss, "aud": aud, "exp": exp }),
+                &jsonwebtoken::EncodingKey::from_secret(b"not github's key"),
+            )
+            .unwrap()
+        };
+        let precheck = |t: &str| {
+            decode::<OidcClaims>(
+                t,
+                &DecodingKey::from_secret(&[]),
+                &oidc_validation("tangent", false),
+            )
+        };
+
+        assert!(precheck(&token(OIDC_ISSUER, "tangent")).is_ok());
+        assert!(precheck(&token(OIDC_ISSUER, "someone-else")).is_err());
+        assert!(precheck(&token("https://evil.example", "tangent")).is_err());
+    }
 }
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use bytes::BytesMut;
+use once_cell::sync::Lazy;
+use prost::Message;
+use prost_reflect::prost_types::field_descriptor_proto::{Label, Type};
+use prost_reflect::prost_types::{
+    DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+    MethodDescriptorProto, ServiceDescriptorProto,
+};
+use tangent_shared::dag::NodeRef;
+use tangent_shared::sources::grpc::GrpcSourceConfig;
+use tokio_util::sync::CancellationToken;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::router::Router;
+use crate::sources::decoding;
+
+// `ingest_server::{Ingest, IngestServer}`, generated by build.rs.
+include!(concat!(env!("OUT_DIR"), "/tangent.ingest.v1.Ingest.rs"));
+
+/// `tangent.ingest.v1.IngestRequest`.
+#[derive(Clone, PartialEq, Message)]
+pub struct IngestRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub payload: Vec<u8>,
+}
+
+/// `tangent.ingest.v1.IngestResponse`.
+#[derive(Clone, PartialEq, Message)]
+pub struct IngestResponse {
+    #[prost(uint64, tag = "1")]
+    pub accepted: u64,
+}
+
+/// Encoded `FileDescriptorSet` for `assets/proto/ingest.proto`, served by
+/// the reflection service.
+static FILE_DESCRIPTOR_SET: Lazy<Vec<u8>> = Lazy::new(|| {
+    let field = |name: &str, number: i32, ty: Type| FieldDescriptorProto {
+        name: Some(name.into()),
+        number: Some(number),
+        label: Some(Label::Optional as i32),
+        r#type: Some(ty as i32),
+        ..Default::default()
+    };
+    let message = |name: &str, field: FieldDescriptorProto| DescriptorProto {
+        name: Some(name.into()),
+        field: vec![field],
+        ..Default::default()
+    };
+    FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("tangent/ingest/v1/ingest.proto".into()),
+            package: Some("tangent.ingest.v1".into()),
+            syntax: Some("proto3".into()),
+            message_type: vec![
+                message("IngestRequest", field("payload", 1, Type::Bytes)),
+                message("IngestResponse", field("accepted", 1, Type::Uint64)),
+            ],
+            service: vec![ServiceDescriptorProto {
+                name: Some("Ingest".into()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("Stream".into()),
+                    input_type: Some(".tangent.ingest.v1.IngestRequest".into()),
+                    output_type: Some(".tangent.ingest.v1.IngestResponse".into()),
+                    client_streaming: Some(true),
+                    server_streaming: Some(false),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+    .encode_to_vec()
+});
+
+struct IngestService {
+    name: Arc<str>,
+    cfg: Arc<GrpcSourceConfig>,
+    decoder: decoding::Decoder,
+    chunks: usize,
+    router: Arc<Router>,
+    from: NodeRef,
+}
+
+#[tonic::async_trait]
+impl ingest_server::Ingest for IngestService {
+    async fn stream(
+        &self,
+        request: Request<Streaming<IngestRequest>>,
+    ) -> std::result::Result<Response<IngestResponse>, Status> {
+        let mut stream = request.into_inner();
+        let mut accepted = 0;
+        while let Some(req) = stream.message().await? {
+            let payload = BytesMut::from(req.payload.as_slice());
+            let dc = &self.cfg.decoding;
+            let frames = match decoding::decode_payload(dc, &self.decoder, payload, self.chunks) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    tracing::warn!(source = %self.name, "rejecting grpc payload: {e:#}");
+                    return Err(Status::invalid_argument(format!("{e:#}")));
+                }
+            };
+            if !frames.is_empty() {
+                self.router
+                    .forward(&self.from, frames, Vec::new())
+                    .await
+                    .map_err(|e| {
+                        tracing::error!(source = %self.name, "grpc source forward failed: {e:#}");
+                        Status::internal("internal error")
+                    })?;
+            }
+            accepted += 1;
+        }
+        Ok(Response::new(IngestResponse { accepted }))
+    }
+}
+
+/// Run a gRPC server for `tangent.ingest.v1.Ingest`, decoding each streamed
+/// payload with the source's `decoding` and forwarding it to the Router.
+pub async fn run_consumer(
+    name: Arc<str>,
+    cfg: GrpcSourceConfig,
+    chunks: usize,
+    router: Arc<Router>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let decoder = decoding::Decoder::new(&cfg.decoding.format)?;
+    let addr = cfg.bind_address;
+
+    let reflection = if cfg.reflection {
+        Some(
+            tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(&FILE_DESCRIPTOR_SET)
+                .build_v1()
+                .context("building grpc reflection service")?,
+        )
+    } else {
+        None
+    };
+    let service = IngestService {
+        name: name.clone(),
+        cfg: Arc::new(cfg),
+        decoder,
+        chunks,
+        router,
+        from: NodeRef::Source { name },
+    };
+
+    tracing::info!("grpc source listening on {:?}", addr);
+
+    tonic::transport::Server::builder()
+        .add_service(ingest_server::IngestServer::new(service))
+        .add_optional_service(reflection)
+        .serve_with_shutdown(addr, shutdown.cancelled())
+        .await
+        .with_context(|| format!("grpc source server on {addr}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::{DescriptorPool, DynamicMessage};
+
+    #[test]
+    fn descriptor_matches_prost_messages() {
+        let pool = DescriptorPool::decode(FILE_DESCRIPTOR_SET.as_slice()).unwrap();
+        let svc = pool
+            .get_service_by_name("tangent.ingest.v1.Ingest")
+            .unwrap();
+        let method = svc.methods().next().unwrap();
+        assert_eq!(method.name(), "Stream");
+        assert!(method.is_client_streaming() && !method.is_server_streaming());
+
+        let req = IngestRequest {
+            payload: b"{\"msg\":\"hi\"}\n".to_vec(),
+        };
+        let decoded =
+            DynamicMessage::decode(method.input(), req.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(
+            decoded
+                .get_field_by_name("payload")
+                .unwrap()
+                .as_bytes()
+                .unwrap(),
+            &req.payload[..]
+        );
+    }
+}
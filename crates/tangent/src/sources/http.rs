@@ -0,0 +1,335 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE},
+        HeaderMap, StatusCode,
+    },
+    response::IntoResponse,
+    routing::post,
+    serve, Router as AxumRouter,
+};
+use bytes::BytesMut;
+use secrecy::ExposeSecret;
+use tangent_shared::dag::NodeRef;
+use tangent_shared::sources::common::DecodeFormat;
+use tangent_shared::sources::http::HttpSourceConfig;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+use crate::router::Router;
+use crate::sources::decoding::{self, Decoder};
+
+#[derive(Clone)]
+struct HttpState {
+    name: Arc<str>,
+    cfg: Arc<HttpSourceConfig>,
+    decoder: Arc<Decoder>,
+    /// For bodies sent as `application/x-protobuf`, when `proto_schema` is set.
+    proto: Option<Arc<Decoder>>,
+    chunks: usize,
+    router: Arc<Router>,
+    from: NodeRef,
+}
+
+/// Run an HTTP server that accepts POSTed payloads on `cfg.path`, decodes
+/// each body with the source's `decoding` and forwards it to the Router.
+pub async fn run_consumer(
+    name: Arc<str>,
+    cfg: HttpSourceConfig,
+    chunks: usize,
+    router: Arc<Router>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let decoder = Arc::new(Decoder::new(&cfg.decoding.format)?);
+    let proto = match cfg.proto_format() {
+        Some(proto) => Some(Arc::new(Decoder::new(&proto)?)),
+        None => None,
+    };
+    let cfg = Arc::new(cfg);
+
+    let limit = DefaultBodyLimit::max(cfg.max_body_bytes);
+    let state = HttpState {
+        name: name.clone(),
+        cfg: cfg.clone(),
+        decoder,
+        proto,
+        chunks,
+        router,
+        from: NodeRef::Source { name },
+    };
+
+    let listener = TcpListener::bind(cfg.bind_address)
+        .await
+        .with_context(|| format!("failed to bind http listener on {}", cfg.bind_address))?;
+
+    let app = AxumRouter::new()
+        .route(cfg.path.as_str(), post(ingest_handler))
+        .layer(limit)
+        .with_state(state);
+
+    let server = serve(listener, app).with_graceful_shutdown(async move {
+        shutdown.cancelled().await;
+    });
+
+    tracing::info!(
+        "http source listening on {:?}{}",
+        &cfg.bind_address,
+        cfg.path
+    );
+
+    server
+        .await
+        .map_err(|e| anyhow!("http source server error: {e}"))
+}
+
+async fn ingest_handler(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    if let Some(token) = &state.cfg.bearer_token {
+        if !authorized(&headers, token.expose_secret()) {
+            return (StatusCode::UNAUTHORIZED, "unauthorized");
+        }
+    }
+
+    let frames = match decode_body(&state, &headers, body) {
+        Ok(frames) => frames,
+        Err(e) if e.is::<decoding::DecompressedTooLarge>() => {
+            tracing::warn!(source = %state.name, "rejecting http payload: {e:#}");
+            return (StatusCode::PAYLOAD_TOO_LARGE, "payload too large");
+        }
+        Err(e) => {
+            tracing::warn!(source = %state.name, "rejecting http payload: {e:#}");
+            return (StatusCode::BAD_REQUEST, "bad request");
+        }
+    };
+
+    if !frames.is_empty() {
+        if let Err(e) = state.router.forward(&state.from, frames, Vec::new()).await {
+            tracing::error!(source = %state.name, "http source forward failed: {e:#}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "internal error");
+        }
+    }
+
+    (StatusCode::ACCEPTED, "accepted")
+}
+
+fn decode_body(state: &HttpState, headers: &HeaderMap, body: Bytes) -> Result<Vec<BytesMut>> {
+    let cfg = &state.cfg;
+    if body.is_empty() {
+        return Ok(Vec::new());
+    }
+    let content_encoding = headers.get(CONTENT_ENCODING).and_then(|v| v.to_str().ok());
+
+    let body = BytesMut::from(body.as_ref());
+    let sniff = &body[..body.len().min(8)];
+    let comp = cfg
+        .decoding
+        .resolve_compression(content_encoding, None, sniff);
+    let raw = decoding::decompress_bytes_capped(&comp, body, cfg.max_decompressed_bytes)?;
+    let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    let mut ndjson = match body_format(cfg, content_type, &raw)? {
+        Cow::Borrowed(_) => state.decoder.normalize(raw)?,
+        Cow::Owned(DecodeFormat::Protobuf { .. }) => state
+            .proto
+            .as_ref()
+            .context("protobuf body but the source has no proto_schema")?
+            .normalize(raw)?,
+        Cow::Owned(format) => Decoder::new(&format)?.normalize(raw)?,
+    };
+    Ok(decoding::chunk_ndjson(&mut ndjson, state.chunks))
+}
+
+/// How to read a body: by its `Content-Type` when that names JSON, NDJSON
+/// or protobuf, else by the source's `decoding.format`. NDJSON bodies that
+/// start with `[` are read as a JSON array.
+fn body_format<'a>(
+    cfg: &'a HttpSourceConfig,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<Cow<'a, DecodeFormat>> {
+    let mime = content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|m| m.trim().to_ascii_lowercase());
+
+    let format = match mime.as_deref() {
+        Some("application/x-protobuf" | "application/protobuf") => {
+            let proto = cfg
+                .proto_format()
+                .context("protobuf body but the source has no proto_schema")?;
+            return Ok(Cow::Owned(proto));
+        }
+        Some("application/x-ndjson" | "application/ndjson" | "application/jsonl") => {
+            Cow::Owned(DecodeFormat::Ndjson)
+        }
+        // Parsed whole; NDJSON sent as JSON fails to parse and is passed
+        // through line by line.
+        Some("application/json") => Cow::Owned(DecodeFormat::Json),
+        _ => Cow::Borrowed(&cfg.decoding.format),
+    };
+
+    let is_array = body
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|&b| b == b'[');
+    if is_array && matches!(*format, DecodeFormat::Ndjson) {
+        return Ok(Cow::Owned(DecodeFormat::JsonArray));
+    }
+    Ok(format)
+}
+
+/// Whether `headers` carry `Authorization: Bearer <token>`.
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    let Some(provided) = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    constant_time_eq::constant_time_eq(provided.as_bytes(), token.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::manager::tests::RecordingSink;
+    use crate::sinks::manager::{Sink, SinkManager};
+    use ahash::AHashMap as HashMap;
+    use axum::http::HeaderValue;
+    use std::io::Write;
+    use std::time::Duration;
+
+    fn gzip(data: &[u8]) -> Bytes {
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap().into()
+    }
+
+    #[tokio::test]
+    async fn handler_authorizes_decodes_and_caps_inflated_bodies() {
+        let cfg: HttpSourceConfig = serde_json::from_value(serde_json::json!({
+            "bearer_token": "s3cret",
+            "max_decompressed_bytes": 1024,
+            "decoding": { "format": { "type": "ndjson" } }
+        }))
+        .unwrap();
+        let sink = RecordingSink::new();
+        let from = NodeRef::Source {
+            name: Arc::from("http"),
+        };
+        let mut outs: HashMap<NodeRef, Vec<NodeRef>> = HashMap::default();
+        outs.insert(
+            from.clone(),
+            vec![NodeRef::Sink {
+                name: Arc::from("recorder"),
+                key_prefix: None,
+            }],
+        );
+        let state = HttpState {
+            name: Arc::from("http"),
+            decoder: Arc::new(Decoder::new(&cfg.decoding.format).unwrap()),
+            proto: None,
+            cfg: Arc::new(cfg),
+            chunks: 1,
+            router: Arc::new(Router::new(
+                outs,
+                Arc::new(SinkManager::for_test(
+                    vec![(Arc::from("recorder"), sink.clone() as Arc<dyn Sink>)],
+                    1,
+                )),
+            )),
+            from,
+        };
+        let post = |headers: HeaderMap, body: Bytes| {
+            let state = state.clone();
+            async move {
+                ingest_handler(State(state), headers, body)
+                    .await
+                    .into_response()
+                    .status()
+            }
+        };
+
+        let body = gzip(b"{\"a\":1}\n{\"a\":2}\n");
+        assert_eq!(
+            post(HeaderMap::new(), body.clone()).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer s3cret"));
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        assert_eq!(post(headers.clone(), body).await, StatusCode::ACCEPTED);
+
+        // A few bytes on the wire that would inflate to a megabyte.
+        let bomb = gzip(&vec![b' '; 1 << 20]);
+        assert!(bomb.len() < 1024);
+        assert_eq!(post(headers, bomb).await, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let written = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let writes = sink.take().await;
+                if !writes.is_empty() {
+                    return writes.concat();
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(written, b"{\"a\":1}\n{\"a\":2}\n");
+    }
+
+    #[test]
+    fn body_format_follows_content_type_then_first_byte() {
+        let cfg: HttpSourceConfig = serde_json::from_value(serde_json::json!({
+            "decoding": { "format": { "type": "ndjson" } }
+        }))
+        .unwrap();
+        let format = |ct: Option<&str>, body: &str| {
+            body_format(&cfg, ct, body.as_bytes()).map(|f| format!("{:?}", *f))
+        };
+
+        assert_eq!(format(None, "{\"a\":1}\n").unwrap(), "Ndjson");
+        assert_eq!(format(None, "  [{\"a\":1}]").unwrap(), "JsonArray");
+        assert_eq!(
+            format(Some("application/x-ndjson; charset=utf-8"), "[{}]").unwrap(),
+            "JsonArray"
+        );
+        assert_eq!(
+            format(Some("Application/JSON"), "{\"a\":1}").unwrap(),
+            "Json"
+        );
+        assert!(format(Some("application/x-protobuf"), "").is_err());
+
+        let cfg = HttpSourceConfig {
+            proto_schema: Some("events.proto".into()),
+            proto_message_type: Some("acme.Event".into()),
+            ..cfg
+        };
+        assert!(matches!(
+            *body_format(&cfg, Some("application/x-protobuf"), b"").unwrap(),
+            DecodeFormat::Protobuf { .. }
+        ));
+    }
+
+    #[test]
+    fn bearer_token_must_match_exactly() {
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, "s3cret"));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer s3cret"));
+        assert!(authorized(&headers, "s3cret"));
+        assert!(!authorized(&headers, "s3cre"));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic s3cret"));
+        assert!(!authorized(&headers, "s3cret"));
+    }
+}
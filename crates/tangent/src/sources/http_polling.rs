@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use bytes::BytesMut;
+use reqwest::header::{CONTENT_ENCODING, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Method, StatusCode};
+use secrecy::ExposeSecret;
+use std::sync::Arc;
+use std::time::Duration;
+use tangent_shared::dag::NodeRef;
+use tangent_shared::sources::http_polling::{HttpPollingAuth, HttpPollingConfig};
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+
+use crate::cache::CacheHandle;
+use crate::router::Router;
+use crate::sources::decoding;
+use crate::wasm::host::tangent::logs::log::Scalar;
+use crate::HTTP_POLLING_REQUESTS_TOTAL;
+
+/// Cap on one poll, so a server that stops responding can't stall the
+/// source.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Poll `cfg.url` on an interval and emit each changed response body.
+pub async fn run_consumer(
+    name: Arc<str>,
+    cfg: HttpPollingConfig,
+    chunks: usize,
+    router: Arc<Router>,
+    cache: Arc<CacheHandle>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let decoder = decoding::Decoder::new(&cfg.decoding.format)?;
+    let method = Method::from_bytes(cfg.method.to_ascii_uppercase().as_bytes())
+        .with_context(|| format!("invalid http_polling method {:?}", cfg.method))?;
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .context("building http_polling client")?;
+    let validators = Validators::new(cache, &name);
+    let from = NodeRef::Source { name: name.clone() };
+
+    let interval_secs = cfg.poll_interval_secs.max(1);
+    let mut ticker = interval(Duration::from_secs(interval_secs));
+
+    tracing::info!(
+        "http_polling source starting: url={}, interval={}s",
+        cfg.url,
+        interval_secs
+    );
+
+    loop {
+        tokio::select! {
+            () = shutdown.cancelled() => {
+                tracing::info!("http_polling source shutting down");
+                break;
+            }
+
+            _ = ticker.tick() => {
+                if let Err(e) = poll(&name, &cfg, &decoder, &method, chunks, &client, &validators, &router, &from).await {
+                    tracing::warn!(source = %name, "http_polling poll error: {e:#}");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn poll(
+    name: &str,
+    cfg: &HttpPollingConfig,
+    decoder: &decoding::Decoder,
+    method: &Method,
+    chunks: usize,
+    client: &reqwest::Client,
+    validators: &Validators,
+    router: &Arc<Router>,
+    from: &NodeRef,
+) -> Result<()> {
+    let mut req = client.request(method.clone(), &cfg.url);
+    for (k, v) in &cfg.headers {
+        req = req.header(k, v);
+    }
+    match &cfg.auth {
+        Some(HttpPollingAuth::Bearer { token }) => req = req.bearer_auth(token.expose_secret()),
+        Some(HttpPollingAuth::Basic { username, password }) => {
+            req = req.basic_auth(username, Some(password.expose_secret()));
+        }
+        None => {}
+    }
+    if let Some(etag) = validators.get("etag") {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(modified) = validators.get("last_modified") {
+        req = req.header(IF_MODIFIED_SINCE, modified);
+    }
+
+    let resp = match req.send().await {
+        Ok(r) => r,
+        Err(e) => {
+            HTTP_POLLING_REQUESTS_TOTAL
+                .with_label_values(&[name, "error"])
+                .inc();
+            return Err(e).context("http_polling request failed");
+        }
+    };
+    let status = resp.status();
+    HTTP_POLLING_REQUESTS_TOTAL
+        .with_label_values(&[name, status.as_str()])
+        .inc();
+
+    if status == StatusCode::NOT_MODIFIED {
+        return Ok(());
+    }
+    if !status.is_success() {
+        anyhow::bail!("{} returned status {status}", cfg.url);
+    }
+
+    let header = |h| {
+        resp.headers()
+            .get(h)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let content_encoding = header(CONTENT_ENCODING);
+
+    let body = resp
+        .bytes()
+        .await
+        .context("failed to read http_polling response body")?;
+    if !body.is_empty() {
+        let body = BytesMut::from(body.as_ref());
+        let sniff = &body[..body.len().min(8)];
+        let comp = cfg
+            .decoding
+            .resolve_compression(content_encoding.as_deref(), None, sniff);
+        let raw = decoding::decompress_bytes(&comp, body)?;
+        let mut ndjson = decoder.normalize(raw)?;
+        let frames = decoding::chunk_ndjson(&mut ndjson, chunks);
+        router.forward(from, frames, Vec::new()).await?;
+    }
+
+    // Only remember validators once the body has been handed off, so a failed
+    // forward is retried on the next poll.
+    validators.set("etag", etag.as_deref());
+    validators.set("last_modified", last_modified.as_deref());
+    Ok(())
+}
+
+/// `ETag` / `Last-Modified` of the last emitted response, kept in the runtime
+/// cache so unchanged responses are skipped across restarts too.
+struct Validators {
+    cache: Arc<CacheHandle>,
+    prefix: String,
+}
+
+impl Validators {
+    fn new(cache: Arc<CacheHandle>, source: &str) -> Self {
+        Self {
+            cache,
+            prefix: format!("tangent:http_polling:{source}"),
+        }
+    }
+
+    fn get(&self, field: &str) -> Option<String> {
+        match self.cache.get(&format!("{}:{field}", self.prefix)) {
+            Ok(Some(Scalar::Str(v))) => Some(v),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!("reading http_polling {field} failed: {e}");
+                None
+            }
+        }
+    }
+
+    fn set(&self, field: &str, value: Option<&str>) {
+        let key = format!("{}:{field}", self.prefix);
+        let res = match value {
+            Some(v) => self.cache.set(&key, &Scalar::Str(v.to_string()), None),
+            None => self.cache.del(&key).map(|_| ()),
+        };
+        if let Err(e) = res {
+            tracing::warn!("writing http_polling {field} failed: {e}");
+        }
+    }
+}
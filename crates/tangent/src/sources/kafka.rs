@@ -0,0 +1,292 @@
+use ahash::HashMap;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use parking_lot::Mutex;
+use rdkafka::{
+    config::ClientConfig,
+    consumer::{BaseConsumer, Consumer, ConsumerContext, Rebalance, StreamConsumer},
+    message::BorrowedMessage,
+    ClientContext, Message,
+};
+use secrecy::ExposeSecret;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tangent_shared::dag::NodeRef;
+use tangent_shared::sources::common::Decoding;
+use tangent_shared::sources::kafka::KafkaSourceConfig;
+use tokio_util::sync::CancellationToken;
+
+use crate::router::Router;
+use crate::sources::decoding;
+use crate::sources::msk::header_str;
+use crate::worker::Ack;
+use crate::KAFKA_REBALANCE_TOTAL;
+
+/// Consume `cfg.topic` as a member of `cfg.group_id`. Offsets are stored for
+/// the periodic auto-commit only once the pipeline has acked a message and
+/// every earlier one on its partition, so a crash re-reads anything that
+/// wasn't delivered.
+pub async fn run_consumer(
+    name: Arc<str>,
+    cfg: KafkaSourceConfig,
+    chunks: usize,
+    router: Arc<Router>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let decoder = decoding::Decoder::new(&cfg.decoding.format)?;
+    let consumer = Arc::new(build_consumer(Arc::clone(&name), &cfg)?);
+    consumer.subscribe(&[cfg.topic.as_str()])?;
+    let from = NodeRef::Source {
+        name: Arc::clone(&name),
+    };
+
+    tracing::info!(
+        "kafka source consuming {} from {} as {}",
+        cfg.topic,
+        cfg.brokers,
+        cfg.group_id
+    );
+
+    loop {
+        let m = tokio::select! {
+            () = shutdown.cancelled() => break,
+            m = consumer.recv() => m,
+        };
+        let m = match m {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!(source = %name, "kafka recv error: {e}");
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                continue;
+            }
+        };
+
+        consumer
+            .context()
+            .offsets
+            .start(m.topic(), m.partition(), m.offset());
+        let ack = Arc::new(KafkaAck {
+            consumer: Arc::clone(&consumer),
+            topic: m.topic().to_string(),
+            partition: m.partition(),
+            offset: m.offset(),
+        });
+        let frames = decode_message(&cfg.decoding, &decoder, &m, chunks);
+        drop(m);
+
+        match frames {
+            Ok(frames) if !frames.is_empty() => router
+                .forward(&from, frames, vec![ack])
+                .await
+                .context("router.forward failed for kafka")?,
+            Ok(_) => ack.ack().await?,
+            Err(e) => {
+                tracing::warn!(
+                    source = %name,
+                    partition = ack.partition,
+                    offset = ack.offset,
+                    "skipping undecodable message: {e:#}"
+                );
+                ack.ack().await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_message(
+    dc: &Decoding,
+    decoder: &decoding::Decoder,
+    m: &BorrowedMessage<'_>,
+    chunks: usize,
+) -> Result<Vec<BytesMut>> {
+    let Some(p) = m.payload() else {
+        return Ok(Vec::new());
+    };
+    let sniff = &p[..p.len().min(8)];
+    let comp = dc.resolve_compression(
+        header_str(m, "content-encoding"),
+        header_str(m, "filename"),
+        sniff,
+    );
+    let raw = decoding::decompress_vec(&comp, p)?;
+    let mut ndjson = decoder.normalize(raw)?;
+    Ok(decoding::chunk_ndjson(&mut ndjson, chunks))
+}
+
+/// Stores the offset after a message once it and every earlier in-flight
+/// message on its partition have been acked.
+struct KafkaAck {
+    consumer: Arc<StreamConsumer<KafkaCtx>>,
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
+#[async_trait]
+impl Ack for KafkaAck {
+    async fn ack(&self) -> Result<()> {
+        let offsets = &self.consumer.context().offsets;
+        let Some(next) = offsets.finish(&self.topic, self.partition, self.offset) else {
+            return Ok(());
+        };
+        // Fails once the partition has been revoked; its new owner re-reads
+        // from the last commit.
+        if let Err(e) = self
+            .consumer
+            .store_offset(&self.topic, self.partition, next)
+        {
+            tracing::debug!(
+                topic = %self.topic,
+                partition = self.partition,
+                "storing kafka offset {next} failed: {e}"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Offsets read from each partition that are still waiting on their ack.
+#[derive(Default)]
+pub(crate) struct OffsetTracker {
+    partitions: Mutex<HashMap<(String, i32), PartitionOffsets>>,
+}
+
+#[derive(Default)]
+struct PartitionOffsets {
+    in_flight: BTreeSet<i64>,
+    /// One past the highest acked offset.
+    acked_through: i64,
+    /// Last offset handed to `store_offset`, or the first one read.
+    stored: i64,
+}
+
+impl OffsetTracker {
+    pub(crate) fn start(&self, topic: &str, partition: i32, offset: i64) {
+        self.partitions
+            .lock()
+            .entry((topic.to_string(), partition))
+            .or_insert_with(|| PartitionOffsets {
+                stored: offset,
+                ..Default::default()
+            })
+            .in_flight
+            .insert(offset);
+    }
+
+    /// Mark `offset` acked. Returns the offset to store when the partition's
+    /// commit point moved: the oldest message still in flight, or one past
+    /// the newest acked when nothing is.
+    pub(crate) fn finish(&self, topic: &str, partition: i32, offset: i64) -> Option<i64> {
+        let mut partitions = self.partitions.lock();
+        let p = partitions.get_mut(&(topic.to_string(), partition))?;
+        if !p.in_flight.remove(&offset) {
+            return None;
+        }
+        p.acked_through = p.acked_through.max(offset + 1);
+        let next = p.in_flight.first().copied().unwrap_or(p.acked_through);
+        (next > p.stored).then(|| {
+            p.stored = next;
+            next
+        })
+    }
+
+    /// Forget revoked partitions; acks still in flight for them are ignored.
+    fn revoke(&self, revoked: impl IntoIterator<Item = (String, i32)>) {
+        let mut partitions = self.partitions.lock();
+        for tp in revoked {
+            partitions.remove(&tp);
+        }
+    }
+}
+
+struct KafkaCtx {
+    source: Arc<str>,
+    offsets: OffsetTracker,
+}
+
+impl ClientContext for KafkaCtx {}
+
+impl ConsumerContext for KafkaCtx {
+    fn post_rebalance(&self, _base_consumer: &BaseConsumer<Self>, rebalance: &Rebalance<'_>) {
+        match rebalance {
+            Rebalance::Assign(_) => {
+                KAFKA_REBALANCE_TOTAL
+                    .with_label_values(&[&self.source])
+                    .inc();
+            }
+            Rebalance::Revoke(tpl) => {
+                KAFKA_REBALANCE_TOTAL
+                    .with_label_values(&[&self.source])
+                    .inc();
+                self.offsets.revoke(
+                    tpl.elements()
+                        .iter()
+                        .map(|e| (e.topic().to_string(), e.partition())),
+                );
+            }
+            Rebalance::Error(e) => {
+                tracing::warn!(source = %self.source, "kafka rebalance error: {e}");
+            }
+        }
+    }
+}
+
+fn build_consumer(source: Arc<str>, kc: &KafkaSourceConfig) -> Result<StreamConsumer<KafkaCtx>> {
+    let mut cfg = ClientConfig::new();
+    cfg.set("bootstrap.servers", &kc.brokers)
+        .set("group.id", &kc.group_id)
+        .set("enable.auto.commit", "true")
+        .set("enable.auto.offset.store", "false")
+        .set("enable.partition.eof", "false")
+        .set("auto.offset.reset", "latest")
+        .set("security.protocol", kc.security_protocol());
+
+    if let Some(p) = &kc.ssl_ca_pem_path {
+        cfg.set("ssl.ca.location", p.to_string_lossy());
+    }
+    if let (Some(mechanism), Some(username), Some(password)) =
+        (&kc.sasl_mechanism, &kc.sasl_username, &kc.sasl_password)
+    {
+        cfg.set("sasl.mechanism", mechanism)
+            .set("sasl.username", username)
+            .set("sasl.password", password.expose_secret());
+    }
+
+    cfg.set("fetch.max.bytes", "10485760");
+    cfg.set("max.partition.fetch.bytes", "10485760");
+
+    let ctx = KafkaCtx {
+        source,
+        offsets: OffsetTracker::default(),
+    };
+    cfg.create_with_context(ctx)
+        .map_err(|e| anyhow!("creating StreamConsumer failed: {e:#?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_are_stored_only_past_contiguous_acks() {
+        let t = OffsetTracker::default();
+        for offset in 10..14 {
+            t.start("logs", 0, offset);
+        }
+
+        // 11 and 12 finish first, but 10 is still in flight.
+        assert_eq!(t.finish("logs", 0, 11), None);
+        assert_eq!(t.finish("logs", 0, 12), None);
+        assert_eq!(t.finish("logs", 0, 10), Some(13));
+        assert_eq!(t.finish("logs", 0, 13), Some(14));
+        assert_eq!(t.finish("logs", 0, 13), None);
+
+        t.start("logs", 1, 5);
+        t.revoke([("logs".to_string(), 1)]);
+        assert_eq!(t.finish("logs", 1, 5), None);
+    }
+}
@@ -1,15 +1,22 @@
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use rdkafka::{
     config::ClientConfig,
-    consumer::{Consumer, ConsumerContext, StreamConsumer},
-    ClientContext, Message,
+    consumer::{BaseConsumer, Consumer, ConsumerContext, Rebalance, StreamConsumer},
+    ClientContext, Message, Offset, TopicPartitionList,
 };
 use secrecy::ExposeSecret;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
 
-use crate::{router::Router, sources::decoding::normalize_to_ndjson};
+use crate::cache::CacheHandle;
+use crate::router::Router;
+use crate::sources::kafka::OffsetTracker;
+use crate::wasm::host::tangent::logs::log::Scalar;
+use crate::worker::Ack;
+use crate::{KAFKA_CONSUMER_LAG, KAFKA_REBALANCE_TOTAL};
 use rdkafka::message::Headers;
 use tangent_shared::{
     dag::NodeRef,
@@ -43,8 +50,15 @@ fn lag_from_stats(s: &rdkafka::statistics::Statistics) -> (i64, i64, usize) {
     (total_lag, max_lag, assigned_parts)
 }
 
+const LAG_INTERVAL: Duration = Duration::from_secs(15);
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct Ctx {
+    source: Arc<str>,
     state: Arc<Mutex<StatState>>,
+    /// Partitions assigned since the consumer loop last looked.
+    newly_assigned: Mutex<Vec<(String, i32)>>,
 }
 
 impl ClientContext for Ctx {
@@ -78,9 +92,124 @@ impl ClientContext for Ctx {
         st.prev_rxmsg_bytes = s.rxmsg_bytes;
     }
 }
-impl ConsumerContext for Ctx {}
+impl ConsumerContext for Ctx {
+    fn post_rebalance(&self, _base_consumer: &BaseConsumer<Self>, rebalance: &Rebalance<'_>) {
+        match rebalance {
+            Rebalance::Assign(tpl) => {
+                KAFKA_REBALANCE_TOTAL
+                    .with_label_values(&[&self.source])
+                    .inc();
+                self.newly_assigned.lock().unwrap().extend(
+                    tpl.elements()
+                        .iter()
+                        .map(|e| (e.topic().to_string(), e.partition())),
+                );
+            }
+            Rebalance::Revoke(_) => {
+                KAFKA_REBALANCE_TOTAL
+                    .with_label_values(&[&self.source])
+                    .inc();
+            }
+            Rebalance::Error(e) => {
+                tracing::warn!(source = %self.source, "kafka rebalance error: {e}");
+            }
+        }
+    }
+}
+
+/// Consumed offsets mirrored into the runtime cache, for clusters where the
+/// broker's offset storage can't be relied on. Stored values are the next
+/// offset to read, past every acked message. They are written in batches
+/// every `CHECKPOINT_INTERVAL`, and still expire after the cache's
+/// `max_ttl_ms` if a partition sits idle.
+pub struct OffsetCheckpoint {
+    cache: Arc<CacheHandle>,
+    group_id: String,
+    offsets: OffsetTracker,
+    /// Offsets to store at the next flush, by partition.
+    dirty: Mutex<HashMap<(String, i32), i64>>,
+}
+
+impl OffsetCheckpoint {
+    pub fn new(cache: Arc<CacheHandle>, group_id: &str) -> Self {
+        Self {
+            cache,
+            group_id: group_id.to_string(),
+            offsets: OffsetTracker::default(),
+            dirty: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn key(&self, topic: &str, partition: i32) -> String {
+        format!("tangent:msk:{}:{topic}:{partition}", self.group_id)
+    }
+
+    pub fn committed_offset(&self, topic: &str, partition: i32) -> Option<i64> {
+        match self.cache.get(&self.key(topic, partition)) {
+            Ok(Some(Scalar::Int(offset))) => Some(offset),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!(topic, partition, "reading offset checkpoint failed: {e}");
+                None
+            }
+        }
+    }
 
-fn header_str<'a>(m: &'a rdkafka::message::BorrowedMessage<'a>, key: &str) -> Option<&'a str> {
+    fn acked(&self, topic: &str, partition: i32, offset: i64) {
+        if let Some(next) = self.offsets.finish(topic, partition, offset) {
+            self.dirty
+                .lock()
+                .unwrap()
+                .insert((topic.to_string(), partition), next);
+        }
+    }
+
+    /// Store the offsets acked since the last flush, in one transaction.
+    /// Blocks on SQLite.
+    fn flush(&self) {
+        let dirty = std::mem::take(&mut *self.dirty.lock().unwrap());
+        if dirty.is_empty() {
+            return;
+        }
+        let res = self.cache.transaction(|tx| {
+            for ((topic, partition), next) in &dirty {
+                tx.set(&self.key(topic, *partition), &Scalar::Int(*next), None)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = res {
+            tracing::warn!("writing offset checkpoints failed: {e}");
+        }
+    }
+}
+
+impl Drop for OffsetCheckpoint {
+    /// The last acks land after the consumer loop has stopped.
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+struct CheckpointAck {
+    checkpoint: Arc<OffsetCheckpoint>,
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
+#[async_trait]
+impl Ack for CheckpointAck {
+    async fn ack(&self) -> Result<()> {
+        self.checkpoint
+            .acked(&self.topic, self.partition, self.offset);
+        Ok(())
+    }
+}
+
+pub(crate) fn header_str<'a>(
+    m: &'a rdkafka::message::BorrowedMessage<'a>,
+    key: &str,
+) -> Option<&'a str> {
     m.headers().and_then(|hs| {
         (0..hs.count()).find_map(|i| {
             let h = hs.get(i);
@@ -98,11 +227,27 @@ pub async fn run_consumer(
     kc: MSKConfig,
     chunks: usize,
     router: Arc<Router>,
+    cache: Arc<CacheHandle>,
     shutdown: CancellationToken,
 ) -> Result<()> {
-    let consumer: StreamConsumer<Ctx> = build_consumer(&kc)?;
+    let decoder = decoding::Decoder::new(&kc.decoding.format)?;
+    let consumer: Arc<StreamConsumer<Ctx>> = Arc::new(build_consumer(Arc::clone(&name), &kc)?);
     consumer.subscribe(&[kc.topic.as_str()])?;
 
+    let lag_task = tokio::spawn(report_lag_periodically(
+        Arc::clone(&consumer),
+        shutdown.clone(),
+    ));
+    let checkpoint = kc
+        .checkpoint_offsets
+        .then(|| Arc::new(OffsetCheckpoint::new(cache, &kc.group_id)));
+    let flush_task = checkpoint.as_ref().map(|cp| {
+        tokio::spawn(flush_checkpoints_periodically(
+            Arc::clone(cp),
+            shutdown.clone(),
+        ))
+    });
+
     let fwd_shutdown = shutdown.clone();
     let dc = kc.decoding.clone();
     let from = NodeRef::Source { name: name };
@@ -113,6 +258,38 @@ pub async fn run_consumer(
             msg = consumer.recv() => {
                 match msg {
                     Ok(m) => {
+                        let mut acks: Vec<Arc<dyn Ack>> = Vec::new();
+                        if let Some(cp) = &checkpoint {
+                            let assigned = std::mem::take(
+                                &mut *consumer.context().newly_assigned.lock().unwrap(),
+                            );
+                            if !assigned.is_empty() {
+                                let (c, cp) = (Arc::clone(&consumer), Arc::clone(cp));
+                                // committed_offsets() and seek() block on broker round trips.
+                                let rewound = tokio::task::spawn_blocking(move || {
+                                    restore_from_checkpoint(&c, &cp, assigned)
+                                })
+                                .await?;
+                                if rewound.contains(&(m.topic().to_string(), m.partition())) {
+                                    // Fetched before the seek; the checkpointed offset re-reads it.
+                                    continue;
+                                }
+                            }
+
+                            cp.offsets.start(m.topic(), m.partition(), m.offset());
+                            let ack = CheckpointAck {
+                                checkpoint: Arc::clone(cp),
+                                topic: m.topic().to_string(),
+                                partition: m.partition(),
+                                offset: m.offset(),
+                            };
+                            if m.payload().is_none() {
+                                ack.ack().await?;
+                            } else {
+                                acks.push(Arc::new(ack));
+                            }
+                        }
+
                         if let Some(p) = m.payload() {
                             let meta_ce   = header_str(&m, "content-encoding");
                             let filename  = header_str(&m, "filename");
@@ -121,10 +298,10 @@ pub async fn run_consumer(
 
                             let raw = decoding::decompress_vec(&comp, p)?;
 
-                            let mut ndjson = normalize_to_ndjson(&kc.decoding.format, raw)?;
+                            let mut ndjson = decoder.normalize(raw)?;
                             let frames_mut = decoding::chunk_ndjson(&mut ndjson, chunks);
 
-                            router.forward(&from, frames_mut, vec![]).await?;
+                            router.forward(&from, frames_mut, acks).await?;
                         }
                     }
                     Err(e) => {
@@ -136,10 +313,120 @@ pub async fn run_consumer(
         }
     }
 
+    lag_task.abort();
+    if let Some(task) = flush_task {
+        let _ = task.await;
+    }
+    Ok(())
+}
+
+async fn flush_checkpoints_periodically(
+    checkpoint: Arc<OffsetCheckpoint>,
+    shutdown: CancellationToken,
+) {
+    let mut tick = tokio::time::interval(CHECKPOINT_INTERVAL);
+    loop {
+        let stop = tokio::select! {
+            () = shutdown.cancelled() => true,
+            _ = tick.tick() => false,
+        };
+        let cp = Arc::clone(&checkpoint);
+        if let Err(e) = tokio::task::spawn_blocking(move || cp.flush()).await {
+            tracing::warn!("offset checkpoint task failed: {e}");
+        }
+        if stop {
+            break;
+        }
+    }
+}
+
+/// Seek newly `assigned` partitions that have no broker-committed offset to
+/// their cached checkpoint. Returns the partitions that were moved. Blocks
+/// on the broker and on SQLite.
+fn restore_from_checkpoint(
+    consumer: &StreamConsumer<Ctx>,
+    checkpoint: &OffsetCheckpoint,
+    assigned: Vec<(String, i32)>,
+) -> Vec<(String, i32)> {
+    let mut tpl = TopicPartitionList::new();
+    for (topic, partition) in &assigned {
+        tpl.add_partition(topic, *partition);
+    }
+    let committed = match consumer.committed_offsets(tpl, QUERY_TIMEOUT) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::warn!("fetching committed offsets failed: {e}");
+            return Vec::new();
+        }
+    };
+
+    let mut rewound = Vec::new();
+    for e in committed.elements() {
+        if matches!(e.offset(), Offset::Offset(_)) {
+            continue;
+        }
+        let Some(offset) = checkpoint.committed_offset(e.topic(), e.partition()) else {
+            continue;
+        };
+        match consumer.seek(
+            e.topic(),
+            e.partition(),
+            Offset::Offset(offset),
+            QUERY_TIMEOUT,
+        ) {
+            Ok(()) => {
+                tracing::info!(
+                    topic = e.topic(),
+                    partition = e.partition(),
+                    offset,
+                    "resumed from cached offset checkpoint"
+                );
+                rewound.push((e.topic().to_string(), e.partition()));
+            }
+            Err(err) => tracing::warn!(
+                topic = e.topic(),
+                partition = e.partition(),
+                "seek to checkpoint failed: {err}"
+            ),
+        }
+    }
+    rewound
+}
+
+async fn report_lag_periodically(consumer: Arc<StreamConsumer<Ctx>>, shutdown: CancellationToken) {
+    let mut tick = tokio::time::interval(LAG_INTERVAL);
+    loop {
+        tokio::select! {
+            () = shutdown.cancelled() => break,
+            _ = tick.tick() => {
+                let c = Arc::clone(&consumer);
+                // committed() and fetch_watermarks() block on broker round trips.
+                match tokio::task::spawn_blocking(move || report_lag(&c)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => tracing::debug!("kafka lag query failed: {e}"),
+                    Err(e) => tracing::warn!("kafka lag task failed: {e}"),
+                }
+            }
+        }
+    }
+}
+
+fn report_lag(consumer: &StreamConsumer<Ctx>) -> Result<()> {
+    let committed = consumer.committed(QUERY_TIMEOUT)?;
+    for e in committed.elements() {
+        let (low, high) = consumer.fetch_watermarks(e.topic(), e.partition(), QUERY_TIMEOUT)?;
+        let lag = match e.offset() {
+            Offset::Offset(o) => high - o,
+            _ => high - low,
+        };
+        KAFKA_CONSUMER_LAG
+            .with_label_values(&[e.topic(), &e.partition().to_string()])
+            .set(lag.max(0));
+    }
     Ok(())
 }
 
-pub fn build_consumer(kc: &MSKConfig) -> Result<StreamConsumer<Ctx>> {
+pub fn build_consumer(source: Arc<str>, kc: &MSKConfig) -> Result<StreamConsumer<Ctx>> {
     let mut cfg = ClientConfig::new();
     cfg.set("bootstrap.servers", &kc.bootstrap_servers)
         .set("group.id", &kc.group_id)
@@ -175,7 +462,9 @@ pub fn build_consumer(kc: &MSKConfig) -> Result<StreamConsumer<Ctx>> {
     cfg.set("max.partition.fetch.bytes", "10485760");
 
     let ctx = Ctx {
+        source,
         state: Arc::new(Mutex::new(StatState::default())),
+        newly_assigned: Mutex::new(Vec::new()),
     };
     let consumer: StreamConsumer<Ctx> = cfg
         .create_with_context(ctx)
@@ -183,3 +472,33 @@ pub fn build_consumer(kc: &MSKConfig) -> Result<StreamConsumer<Ctx>> {
 
     Ok(consumer)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tangent_shared::runtime::CacheConfig;
+
+    #[test]
+    fn checkpoints_store_acked_offsets_on_flush() {
+        let dir = std::env::temp_dir().join(format!("tangent-msk-{}", ulid::Ulid::new()));
+        let cfg = CacheConfig {
+            path: dir.join("cache.sqlite"),
+            ..CacheConfig::default()
+        };
+        let cache = Arc::new(CacheHandle::open(&cfg, &dir).unwrap());
+        let cp = OffsetCheckpoint::new(cache, "group");
+        for offset in 10..13 {
+            cp.offsets.start("logs", 0, offset);
+        }
+
+        // 10 is still in flight, so nothing can be stored yet.
+        cp.acked("logs", 0, 11);
+        cp.flush();
+        assert_eq!(cp.committed_offset("logs", 0), None);
+
+        cp.acked("logs", 0, 10);
+        assert_eq!(cp.committed_offset("logs", 0), None);
+        cp.flush();
+        assert_eq!(cp.committed_offset("logs", 0), Some(12));
+    }
+}
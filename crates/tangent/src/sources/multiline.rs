@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use bytes::BytesMut;
+use regex::Regex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tangent_shared::sources::common::MultilineConfig;
+
+/// Compiled `multiline` settings, shared by every connection of a source.
+pub struct Multiline {
+    start: Regex,
+    max_lines: usize,
+    max_bytes: usize,
+    timeout: Duration,
+}
+
+impl Multiline {
+    pub fn new(cfg: &MultilineConfig) -> Result<Arc<Self>> {
+        let start = Regex::new(&cfg.start_pattern)
+            .with_context(|| format!("invalid multiline start_pattern {:?}", cfg.start_pattern))?;
+        Ok(Arc::new(Self {
+            start,
+            max_lines: cfg.max_lines.max(1),
+            max_bytes: cfg.max_event_bytes.max(1),
+            timeout: Duration::from_millis(cfg.timeout_ms),
+        }))
+    }
+}
+
+/// Per-connection state: the lines of the event being assembled.
+pub struct Assembler {
+    rules: Arc<Multiline>,
+    text: String,
+    lines: usize,
+    last_line: Instant,
+}
+
+impl Assembler {
+    pub fn new(rules: Arc<Multiline>) -> Self {
+        Self {
+            rules,
+            text: String::new(),
+            lines: 0,
+            last_line: Instant::now(),
+        }
+    }
+
+    /// Add newline-terminated `lines` and return the events they complete,
+    /// each a `{"_raw": ...}` NDJSON line.
+    pub fn push(&mut self, lines: Vec<BytesMut>) -> Vec<BytesMut> {
+        let mut out = Vec::new();
+        for line in lines {
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if self.lines == 0 && line.is_empty() {
+                continue;
+            }
+            if self.lines > 0
+                && (self.rules.start.is_match(line)
+                    || self.text.len() + 1 + line.len() > self.rules.max_bytes)
+            {
+                out.extend(self.flush());
+            }
+
+            if self.lines > 0 {
+                self.text.push('\n');
+            }
+            self.text.push_str(truncate(line, self.rules.max_bytes));
+            self.lines += 1;
+            self.last_line = Instant::now();
+
+            if self.lines >= self.rules.max_lines || self.text.len() >= self.rules.max_bytes {
+                out.extend(self.flush());
+            }
+        }
+        out
+    }
+
+    /// Emit the event being assembled, if any.
+    pub fn flush(&mut self) -> Option<BytesMut> {
+        if self.lines == 0 {
+            return None;
+        }
+        self.lines = 0;
+        let text = std::mem::take(&mut self.text);
+        let mut line = serde_json::to_vec(&serde_json::json!({ "_raw": text }))
+            .expect("serializing a string cannot fail");
+        line.push(b'\n');
+        Some(BytesMut::from(&line[..]))
+    }
+
+    /// When the pending event times out if no more lines arrive.
+    fn deadline(&self) -> Option<Instant> {
+        (self.lines > 0).then(|| self.last_line + self.rules.timeout)
+    }
+}
+
+/// The longest prefix of `s` that fits in `max` bytes.
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Resolves when `asm` holds an event that has waited `timeout_ms` for more
+/// lines; never resolves otherwise. Meant for a `select!` arm next to reads.
+pub async fn idle(asm: Option<&Assembler>) {
+    match asm.and_then(Assembler::deadline) {
+        Some(at) => tokio::time::sleep_until(at.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<BytesMut> {
+        text.split_inclusive('\n').map(BytesMut::from).collect()
+    }
+
+    fn raw(frame: &BytesMut) -> String {
+        let v: serde_json::Value = serde_json::from_slice(frame).unwrap();
+        v["_raw"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn continuation_lines_join_until_next_start_or_max_lines() {
+        let rules = Multiline::new(&MultilineConfig {
+            start_pattern: r"^\d{4}-\d{2}-\d{2} ".into(),
+            max_lines: 3,
+            timeout_ms: 1000,
+            max_event_bytes: 1 << 20,
+        })
+        .unwrap();
+        let mut asm = Assembler::new(rules);
+
+        let out = asm.push(lines(
+            "2024-01-01 ERROR boom\r\n  at a()\n  at b()\n  at c()\n2024-01-01 INFO ok\n",
+        ));
+        assert_eq!(out.len(), 2);
+        assert_eq!(raw(&out[0]), "2024-01-01 ERROR boom\n  at a()\n  at b()");
+        assert_eq!(raw(&out[1]), "  at c()");
+        assert!(asm.deadline().is_some());
+
+        assert_eq!(raw(&asm.flush().unwrap()), "2024-01-01 INFO ok");
+        assert!(asm.flush().is_none());
+        assert!(asm.deadline().is_none());
+    }
+
+    #[test]
+    fn events_are_cut_at_max_event_bytes() {
+        let rules = Multiline::new(&MultilineConfig {
+            start_pattern: "^START".into(),
+            max_lines: 100,
+            timeout_ms: 1000,
+            max_event_bytes: 16,
+        })
+        .unwrap();
+        let mut asm = Assembler::new(rules);
+
+        let out = asm.push(lines("START one\n  two\n  three\nSTART ééééééééé\n"));
+        assert_eq!(out.len(), 3);
+        assert_eq!(raw(&out[0]), "START one\n  two");
+        assert_eq!(raw(&out[1]), "  three");
+        assert_eq!(raw(&out[2]), "START ééééé");
+        assert!(asm.flush().is_none());
+    }
+}
@@ -1,9 +1,11 @@
 // tangent_runtime/src/sources/npm_registry.rs
 
-use ahash::HashMap;
+use ahash::{HashMap, HashSet};
 use anyhow::{anyhow, Context, Result};
 use bytes::BytesMut;
-use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::sync::Arc;
 use std::time::Duration;
@@ -12,29 +14,48 @@ use tangent_shared::sources::npm_registry::NpmRegistryConfig;
 use tokio::time::interval;
 use tokio_util::sync::CancellationToken;
 
+use crate::cache::CacheHandle;
 use crate::router::Router;
+use crate::wasm::host::tangent::logs::log::Scalar;
 
-/// Poll the NPM registry for configured packages and emit new versions as NDJSON.
+const REGISTRY_URL: &str = "https://registry.npmjs.org";
+const REPLICATE_URL: &str = "https://replicate.npmjs.com/registry";
+/// Changes fetched per `_changes` request.
+const CHANGES_PAGE_SIZE: usize = 500;
+
+/// Follow the registry's CouchDB `_changes` feed and emit one NDJSON event per
+/// newly published version of a watched package. The feed position is
+/// checkpointed in the runtime cache, so restarts resume where they stopped.
 pub async fn run_consumer(
     name: Arc<str>,
     cfg: NpmRegistryConfig,
     router: Arc<Router>,
+    cache: Arc<CacheHandle>,
     shutdown: CancellationToken,
 ) -> Result<()> {
+    if cfg.packages.is_none() && cfg.orgs.is_none() {
+        anyhow::bail!("must configure either npm packages or orgs.")
+    }
+    let checkpoint = Checkpoint::new(cache, &name);
     let from = NodeRef::Source { name };
 
     let client = reqwest::Client::new();
 
-    let interval_secs = cfg.interval_secs.max(5); // sane minimum
+    let interval_secs = cfg.poll_interval_secs.max(5); // sane minimum
     let mut ticker = interval(Duration::from_secs(interval_secs));
 
-    if cfg.packages.is_none() && cfg.orgs.is_none() {
-        anyhow::bail!("must configure either npm packages or orgs.")
-    }
+    let (mut since, mut floor) = match checkpoint.load() {
+        Some((seq, at)) => (seq, Some(at)),
+        None => match cfg.since_sequence {
+            Some(seq) => (seq, None),
+            None => (current_sequence(&client).await?, Some(Utc::now())),
+        },
+    };
 
     tracing::info!(
-        "npm_registry source starting: packages={:?}, interval={}s",
+        "npm_registry source starting at sequence {since}: packages={:?}, orgs={:?}, interval={}s",
         cfg.packages,
+        cfg.orgs,
         interval_secs
     );
 
@@ -46,23 +67,17 @@ pub async fn run_consumer(
             }
 
             _ = ticker.tick() => {
-                let mut packages = Vec::<String>::new();
-                if cfg.packages.as_ref().is_some() {
-                    packages.extend_from_slice(cfg.packages.as_ref().unwrap());
-                } else {
-                    for org in cfg.orgs.as_ref().unwrap() {
-                        let org_packages = list_org_packages(org, &client, &cfg).await?;
-                        packages.extend_from_slice(&org_packages);
-                    }
-                }
-
-                tracing::info!("polling {:?} packages", packages);
-                for pkg in packages {
-                    if let Err(e) =
-                        poll_package(&pkg, &client, &cfg, &router, &from).await
-                    {
-                        tracing::warn!(package = %pkg, "npm_registry poll error: {e:#}");
+                let started = Utc::now();
+                let res = match watched_packages(&client, &cfg).await {
+                    Ok(watched) => follow_changes(&client, &cfg, &mut since, floor, &watched, &router, &from, &checkpoint).await,
+                    Err(e) => Err(e),
+                };
+                match res {
+                    Ok(()) => {
+                        floor = Some(started);
+                        checkpoint.save(since, started);
                     }
+                    Err(e) => tracing::warn!("npm_registry poll error at sequence {since}: {e:#}"),
                 }
             }
         }
@@ -71,65 +86,91 @@ pub async fn run_consumer(
     Ok(())
 }
 
-async fn poll_package(
-    package: &str,
+#[derive(Deserialize)]
+struct ChangesPage {
+    results: Vec<Change>,
+    last_seq: u64,
+}
+
+#[derive(Deserialize)]
+struct Change {
+    id: String,
+    #[serde(default)]
+    deleted: bool,
+}
+
+/// Read the feed from `since` to its end, forwarding new versions page by
+/// page and advancing `since` (and its checkpoint) after each page.
+#[allow(clippy::too_many_arguments)]
+async fn follow_changes(
     client: &reqwest::Client,
     cfg: &NpmRegistryConfig,
+    since: &mut u64,
+    floor: Option<DateTime<Utc>>,
+    watched: &HashSet<String>,
     router: &Arc<Router>,
     from: &NodeRef,
+    checkpoint: &Checkpoint,
 ) -> Result<()> {
-    let url = format!("https://registry.npmjs.org/{}", package);
+    loop {
+        let url = format!("{REPLICATE_URL}/_changes?since={since}&limit={CHANGES_PAGE_SIZE}");
+        let page: ChangesPage = get_json(client, cfg, &url)
+            .await?
+            .context("npm changes feed not found")?;
+
+        let mut frames: Vec<BytesMut> = Vec::new();
+        for change in &page.results {
+            if change.deleted || !watched.contains(&change.id) {
+                continue;
+            }
+            let url = format!("{REGISTRY_URL}/{}", change.id.replace('/', "%2F"));
+            // Unpublished packages 404 but still appear in the feed.
+            let Some(doc) = get_json::<Value>(client, cfg, &url).await? else {
+                continue;
+            };
+            frames.extend(new_versions(&change.id, &doc, floor));
+        }
 
-    let mut req = client.get(&url);
-    if let Some(token) = &cfg.token {
-        req = req.bearer_auth(token);
-    }
+        if !frames.is_empty() {
+            router
+                .forward(from, frames, Vec::new())
+                .await
+                .context("router.forward failed for npm_registry")?;
+        }
 
-    let resp = req.send().await.context("request to npm registry failed")?;
-    if !resp.status().is_success() {
-        return Err(anyhow!(
-            "npm registry returned status {} for {}",
-            resp.status(),
-            url
-        ));
+        let done = page.results.len() < CHANGES_PAGE_SIZE || page.last_seq <= *since;
+        *since = page.last_seq;
+        if let Some(at) = floor {
+            checkpoint.save(*since, at);
+        }
+        if done {
+            return Ok(());
+        }
     }
+}
 
-    let bytes = resp
-        .text()
-        .await
-        .context("failed to read npm response body")?;
-
-    let doc: Value = serde_json::from_str(&bytes).context("failed to parse npm package doc")?;
-
-    let package_name = doc
-        .get("name")
-        .and_then(Value::as_str)
-        .map(|s| s.to_string())
-        .unwrap_or_else(|| package.to_string());
-    let time_map = doc.get("time").and_then(Value::as_object);
-    let cutoff = Utc::now() - ChronoDuration::minutes(RECENT_WINDOW_MINUTES);
-
-    let versions = match doc.get("versions").and_then(Value::as_object) {
-        Some(map) => map,
-        None => return Ok(()),
+/// NDJSON events for the versions in `doc` published after `floor`, or for
+/// every version when there is no floor.
+fn new_versions(package: &str, doc: &Value, floor: Option<DateTime<Utc>>) -> Vec<BytesMut> {
+    let (Some(times), Some(versions)) = (
+        doc.get("time").and_then(Value::as_object),
+        doc.get("versions").and_then(Value::as_object),
+    ) else {
+        return Vec::new();
     };
+    let name = doc.get("name").and_then(Value::as_str).unwrap_or(package);
+    let dist_tags = doc.get("dist-tags").cloned().unwrap_or(Value::Null);
 
-    let mut frames: Vec<BytesMut> = Vec::new();
-
+    let mut frames = Vec::new();
     for (version, vinfo) in versions {
-        let ts_str = match time_map
-            .and_then(|m| m.get(version))
-            .and_then(Value::as_str)
-        {
-            Some(ts) => ts,
-            None => continue,
+        let Some(ts) = times.get(version).and_then(Value::as_str) else {
+            continue;
         };
-
-        let published_at = match DateTime::parse_from_rfc3339(ts_str) {
+        let published_at = match DateTime::parse_from_rfc3339(ts) {
             Ok(parsed) => parsed.with_timezone(&Utc),
             Err(err) => {
                 tracing::debug!(
-                    package = %package_name,
+                    package = %name,
                     version = %version,
                     error = %err,
                     "skipping npm version without parsable timestamp"
@@ -137,45 +178,72 @@ async fn poll_package(
                 continue;
             }
         };
-
-        if published_at < cutoff {
+        if floor.is_some_and(|f| published_at <= f) {
             continue;
         }
 
         let event = json!({
-            "kind": "npm_package_version",
-            "npm": vinfo,
+            "name": name,
+            "version": version,
+            "dist_tags": dist_tags,
+            "tarball_url": vinfo.pointer("/dist/tarball"),
+            "published_at": ts,
         });
-
         let mut buf = BytesMut::with_capacity(256);
         buf.extend_from_slice(event.to_string().as_bytes());
         buf.extend_from_slice(b"\n");
         frames.push(buf);
     }
+    frames
+}
 
-    if !frames.is_empty() {
-        router
-            .forward(from, frames, Vec::new())
-            .await
-            .context("router.forward failed for npm_registry")?;
+/// Packages named in `packages` plus those of every org in `orgs`.
+async fn watched_packages(
+    client: &reqwest::Client,
+    cfg: &NpmRegistryConfig,
+) -> Result<HashSet<String>> {
+    let mut packages: HashSet<String> = cfg.packages.iter().flatten().cloned().collect();
+    for org in cfg.orgs.iter().flatten() {
+        packages.extend(list_org_packages(org, client, cfg).await?);
     }
+    Ok(packages)
+}
 
-    Ok(())
+/// The feed's current end, where a source without a checkpoint starts.
+async fn current_sequence(client: &reqwest::Client) -> Result<u64> {
+    #[derive(Deserialize)]
+    struct DbInfo {
+        update_seq: u64,
+    }
+    let bytes = client
+        .get(format!("{REPLICATE_URL}/"))
+        .send()
+        .await
+        .context("request to npm replicate failed")?
+        .error_for_status()?
+        .bytes()
+        .await
+        .context("failed to read npm replicate response body")?;
+    let info: DbInfo =
+        serde_json::from_slice(&bytes).context("failed to parse npm replicate response")?;
+    Ok(info.update_seq)
 }
 
-async fn list_org_packages(
-    org: &str,
+/// GET `url` as JSON, with the configured token. `None` on a 404.
+async fn get_json<T: serde::de::DeserializeOwned>(
     client: &reqwest::Client,
     cfg: &NpmRegistryConfig,
-) -> Result<Vec<String>> {
-    let url = format!("https://registry.npmjs.org/-/user/{}/package", org);
-
-    let mut req = client.get(&url);
+    url: &str,
+) -> Result<Option<T>> {
+    let mut req = client.get(url);
     if let Some(token) = &cfg.token {
         req = req.bearer_auth(token);
     }
 
     let resp = req.send().await.context("request to npm registry failed")?;
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
     if !resp.status().is_success() {
         return Err(anyhow!(
             "npm registry returned status {} for {}",
@@ -188,11 +256,113 @@ async fn list_org_packages(
         .bytes()
         .await
         .context("failed to read npm response body")?;
+    serde_json::from_slice(&bytes)
+        .map(Some)
+        .with_context(|| format!("failed to parse npm response from {url}"))
+}
+
+async fn list_org_packages(
+    org: &str,
+    client: &reqwest::Client,
+    cfg: &NpmRegistryConfig,
+) -> Result<Vec<String>> {
+    let url = format!("{REGISTRY_URL}/-/user/{}/package", org);
 
-    let packages: HashMap<String, String> =
-        serde_json::from_slice(&bytes).context("failed to parse npm package doc")?;
+    let packages: HashMap<String, String> = get_json(client, cfg, &url)
+        .await?
+        .with_context(|| format!("npm org {org} not found"))?;
 
     Ok(packages.keys().map(|x| x.to_owned()).collect())
 }
 
-const RECENT_WINDOW_MINUTES: i64 = 500;
+/// Feed sequence read up to, and the time the poll that reached it started,
+/// kept in the runtime cache.
+struct Checkpoint {
+    cache: Arc<CacheHandle>,
+    prefix: String,
+}
+
+impl Checkpoint {
+    fn new(cache: Arc<CacheHandle>, source: &str) -> Self {
+        Self {
+            cache,
+            prefix: format!("tangent:npm_registry:{source}"),
+        }
+    }
+
+    fn load(&self) -> Option<(u64, DateTime<Utc>)> {
+        let get = |field: &str| match self.cache.get(&format!("{}:{field}", self.prefix)) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!("reading npm_registry {field} failed: {e}");
+                None
+            }
+        };
+        let (Some(Scalar::Int(seq)), Some(Scalar::Str(at))) = (get("seq"), get("at")) else {
+            return None;
+        };
+        let at = DateTime::parse_from_rfc3339(&at).ok()?.with_timezone(&Utc);
+        Some((u64::try_from(seq).ok()?, at))
+    }
+
+    fn save(&self, seq: u64, at: DateTime<Utc>) {
+        let res = self
+            .cache
+            .set(
+                &format!("{}:seq", self.prefix),
+                &Scalar::Int(seq as i64),
+                None,
+            )
+            .and_then(|()| {
+                self.cache.set(
+                    &format!("{}:at", self.prefix),
+                    &Scalar::Str(at.to_rfc3339()),
+                    None,
+                )
+            });
+        if let Err(e) = res {
+            tracing::warn!("writing npm_registry checkpoint failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_versions_after_the_floor_are_emitted() {
+        let doc = json!({
+            "name": "left-pad",
+            "dist-tags": { "latest": "1.1.0" },
+            "time": {
+                "created": "2024-01-01T00:00:00.000Z",
+                "1.0.0": "2024-01-01T00:00:00.000Z",
+                "1.1.0": "2024-06-01T00:00:00.000Z"
+            },
+            "versions": {
+                "1.0.0": { "dist": { "tarball": "https://registry.npmjs.org/left-pad/-/left-pad-1.0.0.tgz" } },
+                "1.1.0": { "dist": { "tarball": "https://registry.npmjs.org/left-pad/-/left-pad-1.1.0.tgz" } }
+            }
+        });
+        let floor = DateTime::parse_from_rfc3339("2024-03-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let frames = new_versions("left-pad", &doc, Some(floor));
+        assert_eq!(frames.len(), 1);
+        let event: Value = serde_json::from_slice(&frames[0]).unwrap();
+        assert_eq!(
+            event,
+            json!({
+                "name": "left-pad",
+                "version": "1.1.0",
+                "dist_tags": { "latest": "1.1.0" },
+                "tarball_url": "https://registry.npmjs.org/left-pad/-/left-pad-1.1.0.tgz",
+                "published_at": "2024-06-01T00:00:00.000Z"
+            })
+        );
+
+        assert_eq!(new_versions("left-pad", &doc, None).len(), 2);
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::TryStreamExt;
+use pulsar::consumer::DeadLetterPolicy;
+use pulsar::proto::MessageIdData;
+use pulsar::{
+    ConnectionRetryOptions, Consumer, OperationRetryOptions, Pulsar, SubType, TokioExecutor,
+};
+use std::sync::Arc;
+use tangent_shared::dag::NodeRef;
+use tangent_shared::sources::pulsar::{PulsarSourceConfig, PulsarSubscriptionType};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::router::Router;
+use crate::sources::decoding;
+use crate::worker::Ack;
+
+/// Acks waiting to be sent to the broker. Senders wait for room, and the
+/// consumer loop keeps draining while a forward is in flight.
+const ACK_CHANNEL_CAPACITY: usize = 1024;
+
+/// Consume `cfg.topic` on `cfg.subscription`, acking each message once the
+/// pipeline has delivered it. Dropped connections are re-established by the
+/// client's own retry; messages left unacked are redelivered by the broker.
+pub async fn run_consumer(
+    name: Arc<str>,
+    cfg: PulsarSourceConfig,
+    chunks: usize,
+    router: Arc<Router>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let decoder = decoding::Decoder::new(&cfg.decoding.format)?;
+    let client: Pulsar<TokioExecutor> = Pulsar::builder(cfg.service_url.as_str(), TokioExecutor)
+        .with_connection_retry_options(ConnectionRetryOptions::default())
+        .with_operation_retry_options(OperationRetryOptions::default())
+        .build()
+        .await
+        .with_context(|| format!("connecting to pulsar at {}", cfg.service_url))?;
+    let mut builder = client
+        .consumer()
+        .with_topic(&cfg.topic)
+        .with_consumer_name(format!("tangent-{name}"))
+        .with_subscription_type(sub_type(cfg.subscription_type))
+        .with_subscription(&cfg.subscription);
+    if let Some(topic) = &cfg.dead_letter_topic {
+        builder = builder.with_dead_letter_policy(DeadLetterPolicy {
+            max_redeliver_count: cfg.max_redeliveries,
+            dead_letter_topic: topic.clone(),
+        });
+    }
+    let mut consumer: Consumer<Vec<u8>, TokioExecutor> = builder
+        .build()
+        .await
+        .with_context(|| format!("subscribing to {} as {}", cfg.topic, cfg.subscription))?;
+
+    // Acks fire from sink tasks, but acking needs `&mut consumer`, so they
+    // are funneled back through this loop.
+    let (ack_tx, mut ack_rx) = mpsc::channel::<(String, MessageIdData)>(ACK_CHANNEL_CAPACITY);
+    let from = NodeRef::Source { name: name.clone() };
+
+    tracing::info!(
+        "pulsar source consuming {} as {} ({:?})",
+        cfg.topic,
+        cfg.subscription,
+        cfg.subscription_type
+    );
+
+    loop {
+        tokio::select! {
+            () = shutdown.cancelled() => break,
+            Some((topic, id)) = ack_rx.recv() => ack_id(&mut consumer, &name, &topic, id).await,
+            msg = consumer.try_next() => {
+                let Some(msg) = msg.context("receiving from pulsar")? else {
+                    anyhow::bail!("pulsar consumer stream ended");
+                };
+                let body = BytesMut::from(&msg.payload.data[..]);
+                let frames = match decoding::decode_payload(&cfg.decoding, &decoder, body, chunks) {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        // Redelivered until `dead_letter_topic` takes it, if set.
+                        tracing::warn!(source = %name, "nacking undecodable message: {e:#}");
+                        if let Err(e) = consumer.nack(&msg).await {
+                            tracing::warn!(source = %name, "pulsar nack failed: {e}");
+                        }
+                        continue;
+                    }
+                };
+                if frames.is_empty() {
+                    if let Err(e) = consumer.ack(&msg).await {
+                        tracing::warn!(source = %name, "pulsar ack failed: {e}");
+                    }
+                    continue;
+                }
+
+                let ack = PulsarAck {
+                    tx: ack_tx.clone(),
+                    topic: msg.topic.clone(),
+                    id: msg.message_id().clone(),
+                };
+                // Sinks ack while the forward waits on them, so keep draining
+                // acks or a full channel would stall both.
+                let forward = router.forward(&from, frames, vec![Arc::new(ack)]);
+                tokio::pin!(forward);
+                let res = loop {
+                    tokio::select! {
+                        res = &mut forward => break res,
+                        Some((topic, id)) = ack_rx.recv() => {
+                            ack_id(&mut consumer, &name, &topic, id).await;
+                        }
+                    }
+                };
+                if let Err(e) = res {
+                    tracing::error!(source = %name, "pulsar forward failed: {e:#}");
+                    if let Err(e) = consumer.nack(&msg).await {
+                        tracing::warn!(source = %name, "pulsar nack failed: {e}");
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn sub_type(t: PulsarSubscriptionType) -> SubType {
+    match t {
+        PulsarSubscriptionType::Exclusive => SubType::Exclusive,
+        PulsarSubscriptionType::Shared => SubType::Shared,
+        PulsarSubscriptionType::Failover => SubType::Failover,
+    }
+}
+
+async fn ack_id(
+    consumer: &mut Consumer<Vec<u8>, TokioExecutor>,
+    name: &str,
+    topic: &str,
+    id: MessageIdData,
+) {
+    if let Err(e) = consumer.ack_with_id(topic, id).await {
+        tracing::warn!(source = %name, "pulsar ack failed: {e}");
+    }
+}
+
+/// Hands a delivered message's id back to the consumer loop for acking.
+pub struct PulsarAck {
+    tx: mpsc::Sender<(String, MessageIdData)>,
+    topic: String,
+    id: MessageIdData,
+}
+
+#[async_trait]
+impl Ack for PulsarAck {
+    async fn ack(&self) -> Result<()> {
+        self.tx
+            .send((self.topic.clone(), self.id.clone()))
+            .await
+            .map_err(|_| anyhow::anyhow!("pulsar consumer for {} has stopped", self.topic))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn config_defaults() {
+        let cfg: PulsarSourceConfig = serde_json::from_value(serde_json::json!({
+            "service_url": "pulsar://localhost:6650",
+            "topic": "logs",
+            "subscription": "tangent",
+            "decoding": { "format": { "type": "json-array" } }
+        }))
+        .unwrap();
+        assert_eq!(cfg.subscription_type, PulsarSubscriptionType::Exclusive);
+        assert_eq!(cfg.dead_letter_topic, None);
+        assert_eq!(cfg.max_redeliveries, 3);
+    }
+
+    #[tokio::test]
+    async fn acks_wait_for_room_and_fail_once_the_consumer_stops() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let ack = |entry_id| PulsarAck {
+            tx: tx.clone(),
+            topic: "logs".into(),
+            id: MessageIdData {
+                entry_id,
+                ..Default::default()
+            },
+        };
+
+        ack(1).ack().await.unwrap();
+        let second = ack(2);
+        let pending = second.ack();
+        tokio::pin!(pending);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), &mut pending)
+                .await
+                .is_err(),
+            "a full channel holds the ack back"
+        );
+
+        let (topic, id) = rx.recv().await.unwrap();
+        assert_eq!((topic.as_str(), id.entry_id), ("logs", 1));
+        pending.await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().1.entry_id, 2);
+
+        drop(rx);
+        assert!(ack(3).ack().await.is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use redis::aio::ConnectionManager;
+use redis::streams::{
+    StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamReadOptions, StreamReadReply,
+};
+use redis::AsyncCommands;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tangent_shared::dag::NodeRef;
+use tangent_shared::sources::redis_streams::RedisStreamsConfig;
+use tokio_util::sync::CancellationToken;
+
+use crate::router::Router;
+use crate::sources::decoding;
+use crate::worker::Ack;
+
+/// Consume `cfg.stream` as a member of `cfg.group`, acking entries once the
+/// pipeline has delivered them and reclaiming entries other consumers left
+/// pending for longer than `cfg.pending_timeout_ms`.
+pub async fn run_consumer(
+    name: Arc<str>,
+    cfg: RedisStreamsConfig,
+    chunks: usize,
+    router: Arc<Router>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let decoder = decoding::Decoder::new(&cfg.decoding.format)?;
+    let client = redis::Client::open(cfg.url.as_str()).context("invalid redis url")?;
+    // XREADGROUP blocks its connection, so acks go over a second one.
+    let mut reader = ConnectionManager::new(client.clone())
+        .await
+        .context("connecting to redis")?;
+    let acker = ConnectionManager::new(client)
+        .await
+        .context("connecting to redis")?;
+
+    create_group(&mut reader, &cfg).await?;
+
+    let cfg = Arc::new(cfg);
+    let from = NodeRef::Source { name: name.clone() };
+    let read_opts = StreamReadOptions::default()
+        .group(&cfg.group, &cfg.consumer)
+        .count(cfg.batch_size)
+        .block(cfg.block_ms as usize);
+    let keys = [cfg.stream.as_str()];
+    let pending_timeout = Duration::from_millis(cfg.pending_timeout_ms);
+    let mut claim_cursor = "0-0".to_string();
+    let mut last_claim = Instant::now();
+
+    tracing::info!(
+        "redis_streams source consuming {} as {}/{}",
+        cfg.stream,
+        cfg.group,
+        cfg.consumer
+    );
+
+    loop {
+        if last_claim.elapsed() >= pending_timeout {
+            let reply: StreamAutoClaimReply = reader
+                .xautoclaim_options(
+                    &cfg.stream,
+                    &cfg.group,
+                    &cfg.consumer,
+                    cfg.pending_timeout_ms,
+                    &claim_cursor,
+                    StreamAutoClaimOptions::default().count(cfg.batch_size),
+                )
+                .await
+                .context("XAUTOCLAIM failed")?;
+            if !reply.claimed.is_empty() {
+                tracing::info!(
+                    source = %name,
+                    "reclaimed {} stale pending entries",
+                    reply.claimed.len()
+                );
+            }
+            // Keep walking the PEL until it wraps, then wait another timeout.
+            if reply.next_stream_id == "0-0" {
+                last_claim = Instant::now();
+            }
+            claim_cursor = reply.next_stream_id;
+            deliver(
+                &name,
+                &cfg,
+                &decoder,
+                chunks,
+                &router,
+                &from,
+                &acker,
+                reply.claimed,
+            )
+            .await;
+            continue;
+        }
+
+        let reply: Option<StreamReadReply> = tokio::select! {
+            () = shutdown.cancelled() => break,
+            res = reader.xread_options(&keys, &[">"], &read_opts) => {
+                res.context("XREADGROUP failed")?
+            }
+        };
+        let entries = reply
+            .into_iter()
+            .flat_map(|r| r.keys)
+            .flat_map(|k| k.ids)
+            .collect();
+        deliver(
+            &name, &cfg, &decoder, chunks, &router, &from, &acker, entries,
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// `XGROUP CREATE ... $ MKSTREAM`, tolerating a group that already exists.
+async fn create_group(conn: &mut ConnectionManager, cfg: &RedisStreamsConfig) -> Result<()> {
+    let res: redis::RedisResult<()> = conn
+        .xgroup_create_mkstream(&cfg.stream, &cfg.group, "$")
+        .await;
+    match res {
+        Ok(()) => Ok(()),
+        Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("creating consumer group {}", cfg.group)),
+    }
+}
+
+/// Decode `entries` and forward them with a single ack covering every id.
+/// On a failed forward the entries stay pending and are reclaimed later.
+#[allow(clippy::too_many_arguments)]
+async fn deliver(
+    name: &str,
+    cfg: &Arc<RedisStreamsConfig>,
+    decoder: &decoding::Decoder,
+    chunks: usize,
+    router: &Arc<Router>,
+    from: &NodeRef,
+    acker: &ConnectionManager,
+    entries: Vec<StreamId>,
+) {
+    if entries.is_empty() {
+        return;
+    }
+
+    let mut frames = Vec::new();
+    let mut ids = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match decode_entry(cfg, decoder, &entry, chunks) {
+            Ok(f) => frames.extend(f),
+            Err(e) => tracing::warn!(source = %name, id = %entry.id, "skipping entry: {e:#}"),
+        }
+        ids.push(entry.id);
+    }
+
+    let ack = RedisStreamsAck {
+        conn: acker.clone(),
+        cfg: cfg.clone(),
+        ids,
+    };
+    if frames.is_empty() {
+        if let Err(e) = ack.ack().await {
+            tracing::warn!(source = %name, "ack empty batch failed: {e:#}");
+        }
+        return;
+    }
+    if let Err(e) = router.forward(from, frames, vec![Arc::new(ack)]).await {
+        tracing::error!(source = %name, "redis_streams forward failed: {e:#}");
+    }
+}
+
+fn decode_entry(
+    cfg: &RedisStreamsConfig,
+    decoder: &decoding::Decoder,
+    entry: &StreamId,
+    chunks: usize,
+) -> Result<Vec<BytesMut>> {
+    let Some(value) = entry.map.get(&cfg.field) else {
+        anyhow::bail!("no {:?} field", cfg.field);
+    };
+    let body: Vec<u8> = redis::from_redis_value(value)
+        .with_context(|| format!("field {:?} is not a string", cfg.field))?;
+    let body = BytesMut::from(body.as_slice());
+    decoding::decode_payload(&cfg.decoding, decoder, body, chunks)
+}
+
+pub struct RedisStreamsAck {
+    conn: ConnectionManager,
+    cfg: Arc<RedisStreamsConfig>,
+    ids: Vec<String>,
+}
+
+#[async_trait]
+impl Ack for RedisStreamsAck {
+    async fn ack(&self) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: usize = conn
+            .xack(&self.cfg.stream, &self.cfg.group, &self.ids)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config() -> RedisStreamsConfig {
+        serde_json::from_value(serde_json::json!({
+            "url": "redis://localhost",
+            "stream": "logs",
+            "group": "tangent",
+            "decoding": { "format": { "type": "ndjson" } }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn payload_is_read_from_the_configured_field() {
+        let cfg = config();
+        let decoder = decoding::Decoder::new(&cfg.decoding.format).unwrap();
+        let entry = StreamId {
+            id: "1-0".into(),
+            map: HashMap::from([(
+                "data".to_string(),
+                redis::Value::BulkString(b"{\"a\":1}\n{\"a\":2}\n".to_vec()),
+            )]),
+        };
+        let frames = decode_entry(&cfg, &decoder, &entry, 1).unwrap();
+        let joined: Vec<u8> = frames.iter().flat_map(|f| f.to_vec()).collect();
+        assert_eq!(joined, b"{\"a\":1}\n{\"a\":2}\n");
+
+        let missing = StreamId {
+            id: "2-0".into(),
+            map: HashMap::new(),
+        };
+        assert!(decode_entry(&cfg, &decoder, &missing, 1).is_err());
+    }
+}
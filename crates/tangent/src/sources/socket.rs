@@ -11,6 +11,8 @@ use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 
 use crate::router::Router;
+use crate::sources::decoding::ConnectionMetadata;
+use crate::sources::multiline::{self, Assembler, Multiline};
 use tangent_shared::sources::socket::SocketConfig;
 
 fn drain_ndjson_lines(buf: &mut BytesMut) -> Vec<BytesMut> {
@@ -34,6 +36,8 @@ pub async fn run_consumer(
     let listener = UnixListener::bind(&cfg.socket_path)?;
 
     let read_buf_cap: usize = 512 * 1024;
+    let inject_meta = cfg.inject_connection_metadata;
+    let multiline = cfg.multiline.as_ref().map(Multiline::new).transpose()?;
 
     let (err_tx, mut err_rx) = mpsc::channel::<anyhow::Error>(64);
 
@@ -49,26 +53,61 @@ pub async fn run_consumer(
                 let from = from.clone();
                 let router = router.clone();
                 let shutdown2 = shutdown.clone();
+                let meta = inject_meta.then(|| ConnectionMetadata::new(None));
+                let mut asm = multiline.clone().map(Assembler::new);
 
                 js.spawn(async move {
                     let mut buf = BytesMut::with_capacity(read_buf_cap);
 
                     loop {
                         tokio::select!{
-                            _ = shutdown2.cancelled() => break,
+                            _ = shutdown2.cancelled() => {
+                                // Don't lose the event still waiting for more lines.
+                                if let Some(event) = asm.as_mut().and_then(Assembler::flush) {
+                                    let mut frames = vec![event];
+                                    if let Some(m) = &meta { frames = m.inject(frames); }
+                                    if let Err(e) = router.forward(&from, frames, Vec::new()).await {
+                                        let _ = err_tx.send(e).await;
+                                    }
+                                }
+                                break;
+                            }
+                            () = multiline::idle(asm.as_ref()) => {
+                                if let Some(event) = asm.as_mut().and_then(Assembler::flush) {
+                                    let mut frames = vec![event];
+                                    if let Some(m) = &meta { frames = m.inject(frames); }
+                                    if let Err(e) = router.forward(&from, frames, Vec::new()).await {
+                                        let _ = err_tx.send(e).await;
+                                        break;
+                                    }
+                                }
+                            }
                             r = us.read_buf(&mut buf) => {
                                 match r {
                                 Ok(0) => {
-                                    if !buf.is_empty() {
-                                        if !buf.ends_with(b"\n") { buf.extend_from_slice(b"\n"); }
-                                        let frames = drain_ndjson_lines(&mut buf);
+                                    if !buf.is_empty() && !buf.ends_with(b"\n") {
+                                        buf.extend_from_slice(b"\n");
+                                    }
+                                    let mut frames = drain_ndjson_lines(&mut buf);
+                                    if let Some(asm) = asm.as_mut() {
+                                        frames = asm.push(frames);
+                                        frames.extend(asm.flush());
+                                    }
+                                    if !frames.is_empty() {
+                                        if let Some(m) = &meta { frames = m.inject(frames); }
                                         let _ = router.forward(&from, frames, Vec::new()).await;
                                     }
                                     break;
                                 }
                                 Ok(_n) => {
-                                    let frames = drain_ndjson_lines(&mut buf);
+                                    let mut frames = drain_ndjson_lines(&mut buf);
+                                    if let Some(asm) = asm.as_mut() {
+                                        frames = asm.push(frames);
+                                    }
                                     if !frames.is_empty() {
+                                        if let Some(m) = &meta {
+                                            frames = m.inject(frames);
+                                        }
                                         if let Err(e) = router.forward(&from, frames, Vec::new()).await {
                                             let _ = err_tx.send(e).await;
                                             break;
@@ -7,10 +7,20 @@ use aws_smithy_runtime_api::client::result::SdkError;
 use bytes::BytesMut;
 use percent_encoding::percent_decode_str;
 use std::{sync::Arc, time::Duration};
+use tangent_shared::sources::common::DecodeFormat;
 use tangent_shared::{dag::NodeRef, sources::sqs::SQSConfig};
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio_util::sync::CancellationToken;
 
-use crate::{router::Router, sources::decoding, worker::Ack};
+use crate::{
+    router::Router,
+    sources::decoding,
+    worker::{Ack, StreamAck},
+};
+
+/// Frame size for streamed S3 objects whose edges can't take the reader
+/// whole.
+const STREAM_FRAME_BYTES: usize = 1 << 20;
 
 pub async fn run_consumer(
     name: Arc<str>,
@@ -19,11 +29,19 @@ pub async fn run_consumer(
     router: Arc<Router>,
     shutdown: CancellationToken,
 ) -> Result<()> {
+    let decoder = decoding::Decoder::new(&cfg.decoding.format)?;
     let aws_cfg = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
     let sqs_client = SQSClient::new(&aws_cfg);
     let s3_client = S3Client::new(&aws_cfg);
     let qurl = Arc::new(cfg.queue_url);
     let dc = cfg.decoding.clone();
+    let visibility_timeout = cfg.visibility_timeout_secs;
+    // Line-delimited objects pass through unchanged, so they can be streamed
+    // from S3 instead of read into memory first.
+    let stream_objects = matches!(
+        cfg.decoding.format,
+        DecodeFormat::Ndjson | DecodeFormat::Text
+    );
     let from = NodeRef::Source { name: name };
 
     loop {
@@ -33,6 +51,7 @@ pub async fn run_consumer(
             res = sqs_client.receive_message()
                 .queue_url(qurl.as_str())
                 .wait_time_seconds(20)
+                .visibility_timeout(visibility_timeout as i32)
                 .max_number_of_messages(10)
                 .send() => {
 
@@ -43,11 +62,17 @@ pub async fn run_consumer(
                                 continue;
                             };
 
-                            let ack: Arc<dyn Ack> = Arc::new(SqsAck::new(
+                            let mut sqs_ack = SqsAck::new(
                                 sqs_client.clone(),
                                 qurl.clone(),
                                 handle,
-                            ));
+                            );
+                            if cfg.extend_visibility_timeout {
+                                sqs_ack = sqs_ack.with_visibility_extension(visibility_timeout);
+                            }
+                            // Fires once the buffered frames and every
+                            // streamed object have been delivered.
+                            let ack = StreamAck::new(Arc::new(sqs_ack));
 
                             let mut frames_all: Vec<BytesMut> = Vec::new();
 
@@ -66,6 +91,22 @@ pub async fn run_consumer(
                                             }
 
                                             match s3_client.get_object().bucket(bucket).key(&key).send().await {
+                                                Ok(obj) if stream_objects => {
+                                                    let aws_sdk_s3::operation::get_object::GetObjectOutput { body, content_encoding, .. } = obj;
+                                                    let mut raw = BufReader::new(Box::pin(body.into_async_read()));
+                                                    let sniff = match raw.fill_buf().await {
+                                                        Ok(head) => head[..head.len().min(8)].to_vec(),
+                                                        Err(e) => {
+                                                            tracing::error!("S3 read {bucket}/{key}: {e}");
+                                                            continue;
+                                                        }
+                                                    };
+                                                    let comp = dc.resolve_compression(content_encoding.as_deref(), Some(&key), &sniff);
+                                                    let reader = decoding::decompress_reader(&comp, raw);
+                                                    if let Err(e) = router.forward_stream(&from, reader, ack.part(), STREAM_FRAME_BYTES).await {
+                                                        tracing::error!("S3 stream {bucket}/{key}: {e:#}");
+                                                    }
+                                                }
                                                 Ok(obj) => {
                                                     let aws_sdk_s3::operation::get_object::GetObjectOutput { body: body_stream, .. } = obj;
                                                     match body_stream.collect().await {
@@ -73,7 +114,7 @@ pub async fn run_consumer(
                                                             let bytes = collected.into_bytes();
                                                             let raw = BytesMut::from(bytes.as_ref());
 
-                                                            let mut ndjson = decoding::normalize_to_ndjson(&cfg.decoding.format, raw)?;
+                                                            let mut ndjson = decoder.normalize(raw)?;
                                                             frames_all.extend(decoding::chunk_ndjson(&mut ndjson, chunks));
                                                         }
                                                         Err(e) => {
@@ -104,18 +145,17 @@ pub async fn run_consumer(
                                     }
                                 };
 
-                                let mut ndjson = decoding::normalize_to_ndjson(&cfg.decoding.format, raw)?;
+                                let mut ndjson = decoder.normalize(raw)?;
                                 frames_all.extend(decoding::chunk_ndjson(&mut ndjson, chunks));
                             }
 
                             if !frames_all.is_empty() {
-                                if let Err(e) = router.forward(&from, frames_all, vec![ack]).await {
+                                if let Err(e) = router.forward(&from, frames_all, vec![ack.part()]).await {
                                     tracing::error!("push_from_source error: {e:#}");
                                 }
-                            } else {
-                                if let Err(e) = SqsAck::new(sqs_client.clone(), qurl.clone(), msg.receipt_handle().unwrap_or_default().to_string()).ack().await {
-                                    tracing::warn!("ack empty message failed: {e}");
-                                }
+                            }
+                            if let Err(e) = ack.ack().await {
+                                tracing::warn!("ack SQS message failed: {e}");
                             }
                         }
                     }
@@ -159,6 +199,7 @@ pub struct SqsAck {
     client: SQSClient,
     queue_url: Arc<String>,
     receipt_handle: String,
+    extender: Option<CancellationToken>,
 }
 
 impl SqsAck {
@@ -168,13 +209,64 @@ impl SqsAck {
             client,
             queue_url,
             receipt_handle,
+            extender: None,
         }
     }
+
+    /// Spawn a task that pushes the message's visibility timeout out to
+    /// `timeout_secs` every `timeout_secs / 2` until this ack fires or is
+    /// dropped.
+    #[must_use]
+    pub fn with_visibility_extension(mut self, timeout_secs: u32) -> Self {
+        let cancel = CancellationToken::new();
+        let client = self.client.clone();
+        let queue_url = self.queue_url.clone();
+        let receipt_handle = self.receipt_handle.clone();
+        let stop = cancel.clone();
+        let every = Duration::from_secs(u64::from(timeout_secs / 2).max(1));
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    () = stop.cancelled() => break,
+                    () = tokio::time::sleep(every) => {
+                        let res = client
+                            .change_message_visibility()
+                            .queue_url(queue_url.as_str())
+                            .receipt_handle(&receipt_handle)
+                            .visibility_timeout(timeout_secs as i32)
+                            .send()
+                            .await;
+                        if let Err(e) = res {
+                            tracing::warn!(error = ?e, "SQS ChangeMessageVisibility failed; message may be redelivered");
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        self.extender = Some(cancel);
+        self
+    }
+
+    fn stop_extending(&self) {
+        if let Some(cancel) = &self.extender {
+            cancel.cancel();
+        }
+    }
+}
+
+impl Drop for SqsAck {
+    fn drop(&mut self) {
+        self.stop_extending();
+    }
 }
 
 #[async_trait]
 impl Ack for SqsAck {
     async fn ack(&self) -> Result<()> {
+        self.stop_extending();
         self.client
             .delete_message()
             .queue_url(self.queue_url.as_str())
@@ -1,17 +1,25 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use bytes::BytesMut;
 use memchr::memchr;
 use std::io;
+use std::path::Path;
 use std::sync::Arc;
 use tangent_shared::dag::NodeRef;
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
 use tokio::task::JoinSet;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{crypto::ring, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
 use tokio_util::sync::CancellationToken;
 
 use crate::router::Router;
-use tangent_shared::sources::tcp::TcpConfig;
+use crate::sources::decoding::ConnectionMetadata;
+use crate::sources::multiline::{self, Assembler, Multiline};
+use tangent_shared::sources::tcp::{TcpConfig, TcpTlsConfig};
 
 fn drain_ndjson_lines(buf: &mut BytesMut) -> Vec<BytesMut> {
     let mut out = Vec::with_capacity(500);
@@ -30,9 +38,22 @@ pub async fn run_consumer(
     router: Arc<Router>,
     shutdown: CancellationToken,
 ) -> Result<()> {
+    let tls = cfg.tls.as_ref().map(tls_acceptor).transpose()?;
     let listener = TcpListener::bind(cfg.bind_address).await?;
+    serve(name, &cfg, listener, tls, router, shutdown).await
+}
 
+async fn serve(
+    name: Arc<str>,
+    cfg: &TcpConfig,
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+    router: Arc<Router>,
+    shutdown: CancellationToken,
+) -> Result<()> {
     let read_buf_cap = cfg.read_buffer_size.max(8 * 1024);
+    let inject_meta = cfg.inject_connection_metadata;
+    let multiline = cfg.multiline.as_ref().map(Multiline::new).transpose()?;
 
     let (err_tx, mut err_rx) = mpsc::channel::<anyhow::Error>(64);
 
@@ -44,7 +65,7 @@ pub async fn run_consumer(
             () = shutdown.cancelled() => break,
 
             accept_res = listener.accept() => {
-                let (mut stream, remote_addr) = match accept_res {
+                let (stream, remote_addr) = match accept_res {
                     Ok(pair) => pair,
                     Err(e) => {
                         tracing::warn!("tcp accept error: {e}");
@@ -56,61 +77,35 @@ pub async fn run_consumer(
                     tracing::debug!("failed to enable TCP_NODELAY: {e}");
                 }
 
-                let err_tx = err_tx.clone();
-                let rtr = router.clone();
-                let addr = remote_addr;
-                let from = from.clone();
-
-                let shutdown2 = shutdown.clone();
+                let conn = Connection {
+                    router: router.clone(),
+                    from: from.clone(),
+                    err_tx: err_tx.clone(),
+                    shutdown: shutdown.clone(),
+                    meta: inject_meta.then(|| ConnectionMetadata::new(Some(remote_addr))),
+                    remote_addr,
+                    read_buf_cap,
+                    multiline: multiline.clone(),
+                };
+                let tls = tls.clone();
                 js.spawn(async move {
-                    let mut buf = BytesMut::with_capacity(read_buf_cap);
-
-                    loop {
-                        tokio::select! {
-                            _ = shutdown2.cancelled() => break,
-                            r = stream.read_buf(&mut buf) => {
-                                match r {
-                                    Ok(0) => {
-                                        if !buf.is_empty() {
-                                            if !buf.ends_with(b"\n") {
-                                                buf.extend_from_slice(b"\n");
-                                            }
-                                            let frames = drain_ndjson_lines(&mut buf);
-                                            if let Err(e) = rtr
-                                                .forward(&from, frames, Vec::new())
-                                                .await
-                                            {
-                                                let _ = err_tx.send(e).await;
-                                            }
-                                        }
-                                        break;
-                                    }
-                                    Ok(_) => {
-                                        let frames = drain_ndjson_lines(&mut buf);
-                                        if !frames.is_empty() {
-                                            if let Err(e) = rtr
-                                            .forward(&from, frames, Vec::new())
-                                                .await
-                                            {
-                                                let _ = err_tx.send(e).await;
-                                                break;
-                                            }
-                                        }
-
-                                        if buf.capacity() > read_buf_cap * 8 && buf.len() < read_buf_cap {
-                                            let mut new_buf = BytesMut::with_capacity(read_buf_cap);
-                                            new_buf.extend_from_slice(&buf[..]);
-                                            buf = new_buf;
-                                        }
-                                    }
-                                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    match tls {
+                        Some(acceptor) => {
+                            // Handshake inside the connection task so a slow
+                            // client can't hold up the accept loop.
+                            let stream = tokio::select! {
+                                _ = conn.shutdown.cancelled() => return,
+                                r = acceptor.accept(stream) => match r {
+                                    Ok(s) => s,
                                     Err(e) => {
-                                        tracing::warn!(remote = ?addr, "tcp read error: {e}");
-                                        break;
+                                        tracing::warn!(remote = ?remote_addr, "tls handshake failed: {e}");
+                                        return;
                                     }
-                                }
-                            }
+                                },
+                            };
+                            conn.read(stream).await;
                         }
+                        None => conn.read(stream).await,
                     }
                 });
 
@@ -130,3 +125,239 @@ pub async fn run_consumer(
 
     Ok(())
 }
+
+/// Per-connection state for reading NDJSON off a plain or TLS stream.
+struct Connection {
+    router: Arc<Router>,
+    from: NodeRef,
+    err_tx: mpsc::Sender<anyhow::Error>,
+    shutdown: CancellationToken,
+    meta: Option<ConnectionMetadata>,
+    remote_addr: std::net::SocketAddr,
+    read_buf_cap: usize,
+    multiline: Option<Arc<Multiline>>,
+}
+
+impl Connection {
+    async fn read<S: AsyncRead + Unpin>(self, mut stream: S) {
+        let read_buf_cap = self.read_buf_cap;
+        let mut buf = BytesMut::with_capacity(read_buf_cap);
+        let mut asm = self.multiline.clone().map(Assembler::new);
+
+        loop {
+            tokio::select! {
+                _ = self.shutdown.cancelled() => {
+                    // Don't lose the event still waiting for more lines.
+                    let frames = asm.as_mut().and_then(Assembler::flush).into_iter().collect();
+                    if let Err(e) = self.forward(frames).await {
+                        let _ = self.err_tx.send(e).await;
+                    }
+                    break;
+                }
+                () = multiline::idle(asm.as_ref()) => {
+                    let frames = asm.as_mut().and_then(Assembler::flush).into_iter().collect();
+                    if let Err(e) = self.forward(frames).await {
+                        let _ = self.err_tx.send(e).await;
+                        break;
+                    }
+                }
+                r = stream.read_buf(&mut buf) => {
+                    match r {
+                        Ok(0) => {
+                            if !buf.is_empty() && !buf.ends_with(b"\n") {
+                                buf.extend_from_slice(b"\n");
+                            }
+                            let mut frames = drain_ndjson_lines(&mut buf);
+                            if let Some(asm) = asm.as_mut() {
+                                frames = asm.push(frames);
+                                frames.extend(asm.flush());
+                            }
+                            if let Err(e) = self.forward(frames).await {
+                                let _ = self.err_tx.send(e).await;
+                            }
+                            break;
+                        }
+                        Ok(_) => {
+                            let mut frames = drain_ndjson_lines(&mut buf);
+                            if let Some(asm) = asm.as_mut() {
+                                frames = asm.push(frames);
+                            }
+                            if let Err(e) = self.forward(frames).await {
+                                let _ = self.err_tx.send(e).await;
+                                break;
+                            }
+
+                            if buf.capacity() > read_buf_cap * 8 && buf.len() < read_buf_cap {
+                                let mut new_buf = BytesMut::with_capacity(read_buf_cap);
+                                new_buf.extend_from_slice(&buf[..]);
+                                buf = new_buf;
+                            }
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => {
+                            tracing::warn!(remote = ?self.remote_addr, "tcp read error: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn forward(&self, mut frames: Vec<BytesMut>) -> Result<()> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+        if let Some(m) = &self.meta {
+            frames = m.inject(frames);
+        }
+        self.router.forward(&self.from, frames, Vec::new()).await
+    }
+}
+
+/// Build a TLS acceptor from PEM files, requiring client certificates when
+/// `ca_pem_path` is set.
+fn tls_acceptor(cfg: &TcpTlsConfig) -> Result<TlsAcceptor> {
+    let certs = load_certs(&cfg.cert_pem_path)?;
+    let key = PrivateKeyDer::from_pem_file(&cfg.key_pem_path).with_context(|| {
+        format!(
+            "failed to read private key from {}",
+            cfg.key_pem_path.display()
+        )
+    })?;
+
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &cfg.ca_pem_path {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca)? {
+                roots.add(cert)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .context("invalid client CA certificates")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let server = builder
+        .with_single_cert(certs, key)
+        .context("invalid TLS certificate or key")?;
+    Ok(TlsAcceptor::from(Arc::new(server)))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .with_context(|| format!("failed to read certificates from {}", path.display()))?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid certificate PEM in {}", path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("no certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::manager::tests::RecordingSink;
+    use crate::sinks::manager::{Sink, SinkManager};
+    use ahash::AHashMap as HashMap;
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::TlsConnector;
+
+    #[tokio::test]
+    async fn tls_connections_are_framed_as_ndjson() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir().join(format!("tangent-tcp-tls-{}", ulid::Ulid::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("cert.pem"), cert.cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), cert.key_pair.serialize_pem()).unwrap();
+
+        let cfg: TcpConfig = serde_json::from_value(serde_json::json!({
+            "bind_address": "127.0.0.1:0",
+            "tls": {
+                "cert_pem_path": dir.join("cert.pem"),
+                "key_pem_path": dir.join("key.pem"),
+            },
+        }))
+        .unwrap();
+
+        let sink = RecordingSink::new();
+        let source = NodeRef::Source {
+            name: Arc::from("tls"),
+        };
+        let mut outs: HashMap<NodeRef, Vec<NodeRef>> = HashMap::default();
+        outs.insert(
+            source,
+            vec![NodeRef::Sink {
+                name: Arc::from("recorder"),
+                key_prefix: None,
+            }],
+        );
+        let router = Arc::new(Router::new(
+            outs,
+            Arc::new(SinkManager::for_test(
+                vec![(Arc::from("recorder"), sink.clone() as Arc<dyn Sink>)],
+                1,
+            )),
+        ));
+
+        let listener = TcpListener::bind(cfg.bind_address).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = tls_acceptor(cfg.tls.as_ref().unwrap()).unwrap();
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                serve(
+                    Arc::from("tls"),
+                    &cfg,
+                    listener,
+                    Some(acceptor),
+                    router,
+                    shutdown,
+                )
+                .await
+            }
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let client = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut stream = TlsConnector::from(Arc::new(client))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+        stream.write_all(b"{\"a\":1}\n{\"a\":2}").await.unwrap();
+        stream.shutdown().await.unwrap();
+
+        let expected = b"{\"a\":1}\n{\"a\":2}\n";
+        let mut written = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while written.len() < expected.len() {
+                written.extend(sink.take().await.concat());
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(&written[..], expected);
+
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ahash::HashMap;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use tangent_shared::plugins::{PluginConfig, PluginConfigOverrides};
+use tokio::time::{self, Instant, Interval, MissedTickBehavior};
+
+/// Where workers find `tangent plugin set-config` overrides and the config
+/// from `tangent.yaml` they are layered over.
+#[derive(Debug, Clone)]
+pub struct PluginConfigSource {
+    path: PathBuf,
+    interval: Duration,
+    base: Arc<BTreeMap<Arc<str>, HashMap<String, Value>>>,
+}
+
+impl PluginConfigSource {
+    pub fn new(
+        cfg_path: &Path,
+        interval: Duration,
+        plugins: &BTreeMap<Arc<str>, PluginConfig>,
+    ) -> Self {
+        Self {
+            path: PluginConfigOverrides::path_for(cfg_path),
+            interval,
+            base: Arc::new(
+                plugins
+                    .iter()
+                    .map(|(name, p)| (Arc::clone(name), p.config.clone()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// A poller for one worker. Its first `poll` reads the sidecar straight
+    /// away; later ones wait for the next tick.
+    pub fn poller(&self) -> ConfigPoller {
+        let mut tick = time::interval_at(Instant::now() + self.interval, self.interval);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ConfigPoller {
+            src: self.clone(),
+            tick,
+            last: None,
+        }
+    }
+}
+
+pub struct ConfigPoller {
+    src: PluginConfigSource,
+    tick: Interval,
+    /// Sidecar contents at the last poll, so an unchanged file is not
+    /// re-parsed.
+    last: Option<Vec<u8>>,
+}
+
+impl ConfigPoller {
+    pub async fn tick(&mut self) {
+        self.tick.tick().await;
+    }
+
+    /// Every plugin's merged config if the sidecar changed since the last
+    /// poll, `None` otherwise. A missing sidecar means no overrides.
+    pub async fn poll(&mut self) -> Result<Option<Vec<(Arc<str>, HashMap<String, Value>)>>> {
+        let bytes = match tokio::fs::read(&self.src.path).await {
+            Ok(b) => b,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("reading {}", self.src.path.display()))
+            }
+        };
+        if self.last.as_ref() == Some(&bytes) {
+            return Ok(None);
+        }
+
+        // Remember a broken file too, so it is reported once rather than on
+        // every tick until someone fixes it.
+        let overrides = if bytes.is_empty() {
+            Ok(PluginConfigOverrides::default())
+        } else {
+            PluginConfigOverrides::from_slice(&bytes)
+        };
+        self.last = Some(bytes);
+        let overrides = overrides.with_context(|| self.src.path.display().to_string())?;
+
+        Ok(Some(
+            self.src
+                .base
+                .iter()
+                .map(|(name, base)| (Arc::clone(name), overrides.apply(name, base)))
+                .collect(),
+        ))
+    }
+}
@@ -1,28 +1,153 @@
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use ahash::{HashMap, HashMapExt};
 use anyhow::Result;
 
 use serde_json::Value;
-use wasmtime::component::{Component, Linker};
-use wasmtime::{Engine, Store};
+use tangent_shared::plugins::{PluginCapability, PluginConfig, PluginEncoding};
+use wasmtime::component::{Component, Instance, Linker};
+use wasmtime::{Engine, ResourceLimiter, Store};
 use wasmtime_wasi::WasiCtxBuilder;
 
 use crate::cache::CacheHandle;
 use crate::wasm::host::tangent::logs::{cache, config, lock, log, remote};
 use crate::wasm::host::{HostEngine, Processor};
+
+/// Interval at which the epoch ticker advances interruptible engines.
+pub const EPOCH_TICK: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Ticks a single guest call may run before trapping when tracing (30s).
+pub const EPOCH_DEADLINE_TICKS: u64 = 300;
+
+/// Per-plugin settings handed to each store's `HostEngine`.
+struct PluginSettings {
+    config: Arc<HashMap<String, Value>>,
+    remote_call_concurrency: usize,
+    timeout: Option<Duration>,
+    max_memory_bytes: Option<usize>,
+    encoding: PluginEncoding,
+    remote_calls: bool,
+    cache: bool,
+    env_vars: bool,
+    selector_priority: Option<u8>,
+}
+
+impl PluginSettings {
+    fn from_config(cfg: &PluginConfig) -> Self {
+        Self {
+            config: Arc::new(cfg.config.clone()),
+            remote_call_concurrency: cfg.remote_call_concurrency,
+            timeout: cfg.timeout_ms.map(Duration::from_millis),
+            max_memory_bytes: cfg.max_memory_mb.map(|mb| mb << 20),
+            encoding: cfg.encoding,
+            remote_calls: cfg.allows(PluginCapability::RemoteCalls),
+            cache: cfg.allows(PluginCapability::Cache),
+            env_vars: cfg.allows(PluginCapability::EnvVars),
+            selector_priority: cfg.selector_priority,
+        }
+    }
+}
+
+/// Refuses linear memory growth past `max_bytes`, trapping the guest, and
+/// remembers that it did so the host can tell the trap apart from others.
+pub struct MemoryLimit {
+    max_bytes: usize,
+    exceeded: bool,
+}
+
+impl MemoryLimit {
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl ResourceLimiter for MemoryLimit {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        if desired > self.max_bytes {
+            self.exceeded = true;
+            anyhow::bail!(
+                "plugin memory limit of {} MiB exceeded",
+                self.max_bytes >> 20
+            );
+        }
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        Ok(true)
+    }
+}
+
 pub struct WasmEngine {
     engine: Engine,
     linker: Linker<HostEngine>,
     cache: std::sync::Arc<CacheHandle>,
-    config: HashMap<Arc<str>, Arc<HashMap<String, Value>>>,
+    config: HashMap<Arc<str>, PluginSettings>,
     disable_remote_calls: bool,
+    /// Built with epoch interruption, so guest calls yield every tick.
+    interruptible: bool,
+    epoch_deadline: Option<u64>,
 }
 
 impl WasmEngine {
     pub fn new(cache: std::sync::Arc<CacheHandle>, disable_remote_calls: bool) -> Result<Self> {
-        let engine = tangent_shared::wasm_engine::build()?;
+        Self::with_engine(
+            tangent_shared::wasm_engine::build()?,
+            cache,
+            disable_remote_calls,
+        )
+    }
+
+    /// Engine with epoch interruption enabled, so a plugin's `timeout_ms`
+    /// can fire while its guest is busy. Someone must call
+    /// `Engine::increment_epoch` every `EPOCH_TICK`.
+    pub fn new_interruptible(
+        cache: std::sync::Arc<CacheHandle>,
+        disable_remote_calls: bool,
+    ) -> Result<Self> {
+        let mut this = Self::with_engine(
+            tangent_shared::wasm_engine::build_interruptible()?,
+            cache,
+            disable_remote_calls,
+        )?;
+        this.interruptible = true;
+        Ok(this)
+    }
+
+    /// Engine with epoch interruption enabled; guest calls trap with a
+    /// backtrace after `EPOCH_DEADLINE_TICKS`. Someone must call
+    /// `Engine::increment_epoch` every `EPOCH_TICK`.
+    pub fn new_traced(
+        cache: std::sync::Arc<CacheHandle>,
+        disable_remote_calls: bool,
+    ) -> Result<Self> {
+        let mut this = Self::with_engine(
+            tangent_shared::wasm_engine::build_traced()?,
+            cache,
+            disable_remote_calls,
+        )?;
+        this.interruptible = true;
+        this.epoch_deadline = Some(EPOCH_DEADLINE_TICKS);
+        Ok(this)
+    }
+
+    fn with_engine(
+        engine: Engine,
+        cache: std::sync::Arc<CacheHandle>,
+        disable_remote_calls: bool,
+    ) -> Result<Self> {
         let mut linker = Linker::<HostEngine>::new(&engine);
         wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
         log::add_to_linker::<HostEngine, HostEngine>(&mut linker, |host: &mut HostEngine| host)?;
@@ -37,9 +162,26 @@ impl WasmEngine {
             cache,
             disable_remote_calls,
             config: HashMap::new(),
+            interruptible: false,
+            epoch_deadline: None,
         })
     }
 
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Whether components must be compiled from source for this engine
+    /// rather than loaded from `.cwasm`.
+    pub fn interruptible(&self) -> bool {
+        self.interruptible
+    }
+
+    /// Per-call epoch deadline, set only for traced engines.
+    pub fn epoch_deadline(&self) -> Option<u64> {
+        self.epoch_deadline
+    }
+
     pub fn load_component(&self, loc: &Path) -> Result<Component> {
         Component::from_file(&self.engine, loc)
     }
@@ -48,36 +190,192 @@ impl WasmEngine {
         &mut self,
         name: Arc<str>,
         loc: &Path,
-        cfg: HashMap<String, Value>,
+        cfg: &PluginConfig,
     ) -> Result<Component> {
         let comp = unsafe { Component::deserialize_file(&self.engine, &loc)? };
 
-        self.config.insert(name, Arc::new(cfg));
+        self.config.insert(name, PluginSettings::from_config(cfg));
 
         Ok(comp)
     }
 
+    /// Compile a component from its `.component.wasm` rather than loading the
+    /// `.cwasm`, for engines whose settings differ from the precompile engine.
+    pub fn load_source(
+        &mut self,
+        name: Arc<str>,
+        loc: &Path,
+        cfg: &PluginConfig,
+    ) -> Result<Component> {
+        let comp = self.load_component(loc)?;
+
+        self.config.insert(name, PluginSettings::from_config(cfg));
+
+        Ok(comp)
+    }
+
+    /// Load a new build of an already configured plugin from `loc`, keeping
+    /// its settings. Interruptible engines compile the `.component.wasm`;
+    /// others deserialize the `.cwasm`.
+    pub fn reload(&self, name: &Arc<str>, loc: &Path) -> Result<Component> {
+        if !self.config.contains_key(name) {
+            anyhow::bail!("plugin {name} was never loaded");
+        }
+        if self.interruptible {
+            self.load_component(loc)
+        } else {
+            Ok(unsafe { Component::deserialize_file(&self.engine, loc)? })
+        }
+    }
+
+    /// Config that new stores for plugin `name` start with.
+    pub fn plugin_config(&self, name: &Arc<str>) -> Option<&Arc<HashMap<String, Value>>> {
+        self.config.get(name).map(|s| &s.config)
+    }
+
+    /// Replace plugin `name`'s config for stores made from now on, including
+    /// ones made by `Mappers::reload` and `Mappers::restart`.
+    pub fn set_plugin_config(&mut self, name: &Arc<str>, config: Arc<HashMap<String, Value>>) {
+        if let Some(s) = self.config.get_mut(name) {
+            s.config = config;
+        }
+    }
+
+    /// Wall-clock limit for one call into plugin `name`, if configured.
+    pub fn call_timeout(&self, name: &Arc<str>) -> Option<Duration> {
+        self.config.get(name).and_then(|s| s.timeout)
+    }
+
+    /// Priority for plugin `name`'s selectors that the guest leaves unset.
+    pub fn selector_priority(&self, name: &Arc<str>) -> Option<u8> {
+        self.config.get(name).and_then(|s| s.selector_priority)
+    }
+
+    /// Output format plugin `name` was configured with.
+    pub fn output_encoding(&self, name: &Arc<str>) -> PluginEncoding {
+        self.config
+            .get(name)
+            .map_or_else(Default::default, |s| s.encoding)
+    }
+
     pub fn make_store(&self, component_name: &Arc<str>) -> Store<HostEngine> {
-        Store::new(
+        let settings = self.config.get(component_name).unwrap();
+        let mut wasi = WasiCtxBuilder::new();
+        wasi.inherit_stdout().inherit_stderr();
+        if settings.env_vars {
+            wasi.inherit_env();
+        }
+        let mut store = Store::new(
             &self.engine,
             HostEngine::new(
-                WasiCtxBuilder::new()
-                    .inherit_stdout()
-                    .inherit_stderr()
-                    .inherit_env()
-                    .build(),
+                wasi.build(),
                 self.cache.clone(),
-                self.config.get(component_name).unwrap().clone(),
+                settings.config.clone(),
                 self.disable_remote_calls,
+                Arc::clone(component_name),
+                settings.remote_call_concurrency,
             ),
-        )
+        );
+        // The linker is shared by every plugin, so denied interfaces stay
+        // linked and refuse calls instead.
+        store.data_mut().remote_calls_allowed = settings.remote_calls;
+        store.data_mut().cache_allowed = settings.cache;
+        if self.interruptible {
+            match self.epoch_deadline {
+                Some(ticks) if settings.timeout.is_none() => store.set_epoch_deadline(ticks),
+                // Yield on every tick instead of trapping, so the caller's
+                // timeout can fire while the guest is busy.
+                _ => {
+                    store.set_epoch_deadline(1);
+                    store.epoch_deadline_async_yield_and_update(1);
+                }
+            }
+        }
+        if let Some(max_bytes) = settings.max_memory_bytes {
+            store.data_mut().memory_limit = Some(MemoryLimit {
+                max_bytes,
+                exceeded: false,
+            });
+            store.limiter(|host| {
+                host.memory_limit
+                    .as_mut()
+                    .expect("memory limit set with limiter")
+            });
+        }
+        store
     }
 
+    /// Instantiate `component`, returning the raw `Instance` as well so
+    /// callers can look up optional exports outside the `processor` world.
     pub async fn make_processor(
         &self,
         store: &mut Store<HostEngine>,
         component: &Component,
-    ) -> Result<Processor> {
-        Processor::instantiate_async(store, component, &self.linker).await
+    ) -> Result<(Processor, Instance)> {
+        let instance = self
+            .linker
+            .instantiate_async(&mut *store, component)
+            .await?;
+        let proc = Processor::new(&mut *store, &instance)?;
+        Ok((proc, instance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tangent_shared::runtime::CacheConfig;
+
+    #[tokio::test]
+    async fn timeouts_interrupt_a_looping_guest() {
+        let dir = std::env::temp_dir().join(format!("tangent-engine-{}", ulid::Ulid::new()));
+        let cfg = CacheConfig {
+            path: dir.join("cache.sqlite"),
+            ..CacheConfig::default()
+        };
+        let cache = Arc::new(CacheHandle::open(&cfg, &dir).unwrap());
+        let mut engine = WasmEngine::new_interruptible(cache, true).unwrap();
+
+        let name: Arc<str> = "spin".into();
+        let plugin: PluginConfig = serde_json::from_value(serde_json::json!({
+            "module_type": "rust",
+            "path": "spin",
+            "timeout_ms": 10,
+        }))
+        .unwrap();
+        engine
+            .config
+            .insert(Arc::clone(&name), PluginSettings::from_config(&plugin));
+
+        let component = Component::new(
+            engine.engine(),
+            r#"(component
+                (core module $m (func (export "spin") (loop $l (br $l))))
+                (core instance $i (instantiate $m))
+                (func (export "spin") (canon lift (core func $i "spin"))))"#,
+        )
+        .unwrap();
+        let mut store = engine.make_store(&name);
+        let instance = engine
+            .linker
+            .instantiate_async(&mut store, &component)
+            .await
+            .unwrap();
+        let spin = instance
+            .get_typed_func::<(), ()>(&mut store, "spin")
+            .unwrap();
+
+        let ticker = engine.engine().clone();
+        let ticks = tokio::spawn(async move {
+            let mut tick = tokio::time::interval(EPOCH_TICK);
+            loop {
+                tick.tick().await;
+                ticker.increment_epoch();
+            }
+        });
+        let limit = engine.call_timeout(&name).unwrap();
+        let res = tokio::time::timeout(limit, spin.call_async(&mut store, ())).await;
+        ticks.abort();
+        assert!(res.is_err(), "the looping guest should have timed out");
     }
 }
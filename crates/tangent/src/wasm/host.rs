@@ -1,3 +1,4 @@
+use std::future::Future;
 use std::sync::Arc;
 
 use ahash::HashMap;
@@ -6,6 +7,7 @@ use anyhow::Result;
 use bytes::{Bytes, BytesMut};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
+use prometheus::IntGauge;
 use reqwest::Client;
 use rusqlite::types::Value;
 use serde_json::Value as JSONValue;
@@ -13,12 +15,15 @@ use simd_json::base::ValueAsScalar;
 use simd_json::derived::{TypedArrayValue, TypedScalarValue};
 use simd_json::prelude::{ValueAsArray, ValueAsObject, ValueObjectAccess};
 use simd_json::{BorrowedValue, StaticNode};
+use tokio::sync::Semaphore;
 use wasmtime::component::{bindgen, HasData, Resource, ResourceTable};
 use wasmtime_wasi::{WasiCtx, WasiCtxView, WasiView};
 
-use crate::cache::CacheHandle;
+use crate::cache::{CacheHandle, CacheTx, RUNTIME_KEY_PREFIX};
+use crate::wasm::engine::MemoryLimit;
 use crate::wasm::host::tangent::logs::log;
 use crate::wasm::host::tangent::logs::remote;
+use crate::PLUGIN_REMOTE_CALLS_INFLIGHT;
 use log::Scalar;
 
 static LOCKS: Lazy<Mutex<HashMap<String, bool>>> = Lazy::new(|| Mutex::new(HashMap::new()));
@@ -32,6 +37,7 @@ bindgen!({
     },
     with: {
         "tangent:logs/log.logview": JsonLogView,
+        "tangent:logs/cache.cache-tx": CacheTx,
     }
 });
 
@@ -40,9 +46,20 @@ pub struct HostEngine {
     pub table: ResourceTable,
     http_client: Client,
     cache: Arc<CacheHandle>,
+    /// Recorded as the owner of cache entries this plugin writes.
+    plugin: Arc<str>,
     plugin_cfg: Arc<HashMap<String, JSONValue>>,
     /// If true, short-circuit remote calls with successful empty responses.
     pub disable_remote_calls: bool,
+    /// Caps in-flight requests from `call_batch`.
+    remote_limit: Arc<Semaphore>,
+    remote_inflight: IntGauge,
+    /// Linear memory cap from the plugin's `max_memory_mb`.
+    pub memory_limit: Option<MemoryLimit>,
+    /// Whether the plugin's `capabilities` include `remote_calls`.
+    pub remote_calls_allowed: bool,
+    /// Whether the plugin's `capabilities` include `cache`.
+    pub cache_allowed: bool,
 }
 
 impl HostEngine {
@@ -51,6 +68,8 @@ impl HostEngine {
         cache: Arc<CacheHandle>,
         config: Arc<HashMap<String, JSONValue>>,
         disable_remote_calls: bool,
+        plugin: Arc<str>,
+        remote_call_concurrency: usize,
     ) -> Self {
         Self {
             ctx,
@@ -59,9 +78,47 @@ impl HostEngine {
             cache,
             plugin_cfg: config,
             disable_remote_calls,
+            remote_limit: Arc::new(Semaphore::new(remote_call_concurrency.max(1))),
+            remote_inflight: PLUGIN_REMOTE_CALLS_INFLIGHT.with_label_values(&[&*plugin]),
+            plugin,
+            memory_limit: None,
+            remote_calls_allowed: true,
+            cache_allowed: true,
         }
     }
 
+    /// Error returned to the guest for a call its capabilities don't allow.
+    fn denied(&self, capability: &str) -> String {
+        format!(
+            "plugin {} was not granted the {capability} capability",
+            self.plugin
+        )
+    }
+
+    fn check_cache(&self) -> Result<(), String> {
+        if self.cache_allowed {
+            Ok(())
+        } else {
+            Err(self.denied("cache"))
+        }
+    }
+
+    /// Refuse keys in the runtime's own namespace, so a plugin can't clobber
+    /// a source's checkpoint.
+    fn check_key(key: &str) -> Result<(), String> {
+        if key.starts_with(RUNTIME_KEY_PREFIX) {
+            return Err(format!(
+                "cache keys starting with `{RUNTIME_KEY_PREFIX}` are reserved for the runtime"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Swap the map the guest reads through `config::get`.
+    pub fn set_plugin_cfg(&mut self, config: Arc<HashMap<String, JSONValue>>) {
+        self.plugin_cfg = config;
+    }
+
     async fn execute_single(client: Client, r: remote::Request) -> remote::Response {
         use remote::Method;
 
@@ -166,6 +223,9 @@ impl remote::Host for HostEngine {
         &mut self,
         reqs: Vec<remote::Request>,
     ) -> Result<Vec<remote::Response>, String> {
+        if !self.remote_calls_allowed {
+            return Err(self.denied("remote_calls"));
+        }
         if self.disable_remote_calls {
             // Short-circuit with successful empty responses.
             let out = reqs
@@ -181,18 +241,43 @@ impl remote::Host for HostEngine {
             return Ok(out);
         }
 
-        let mut out = Vec::with_capacity(reqs.len());
-        let client = self.http_client.clone();
-
-        for r in reqs {
-            let resp = Self::execute_single(client.clone(), r);
-            out.push(resp.await);
-        }
-
+        let client = &self.http_client;
+        let out = run_bounded(&self.remote_limit, &self.remote_inflight, reqs, |r| {
+            Self::execute_single(client.clone(), r)
+        })
+        .await;
         Ok(out)
     }
 }
 
+/// Run `f` over `items` concurrently with at most `limit` permits' worth in
+/// flight, returning results in input order.
+async fn run_bounded<T, R, F, Fut>(
+    limit: &Semaphore,
+    inflight: &IntGauge,
+    items: Vec<T>,
+    f: F,
+) -> Vec<R>
+where
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = R>,
+{
+    futures::future::join_all(items.into_iter().map(|item| {
+        let fut = f(item);
+        async move {
+            let _permit = limit
+                .acquire()
+                .await
+                .expect("remote call semaphore is never closed");
+            inflight.inc();
+            let out = fut.await;
+            inflight.dec();
+            out
+        }
+    }))
+    .await
+}
+
 impl tangent::logs::config::Host for HostEngine {
     fn get(&mut self, key: String) -> Option<String> {
         self.plugin_cfg.get(&key).map(|v| {
@@ -229,18 +314,73 @@ impl tangent::logs::lock::Host for HostEngine {
 
 impl tangent::logs::cache::Host for HostEngine {
     fn get(&mut self, key: String) -> Result<Option<Scalar>, String> {
+        self.check_cache()?;
+        Self::check_key(&key)?;
         self.cache.get(&key).map_err(|e| e.to_string())
     }
 
     fn set(&mut self, key: String, value: Scalar, ttl_ms: Option<u64>) -> Result<(), String> {
+        self.check_cache()?;
+        Self::check_key(&key)?;
         self.cache
-            .set(&key, &value, ttl_ms)
+            .set_as(&self.plugin, &key, &value, ttl_ms)
             .map_err(|e| e.to_string())
     }
 
     fn del(&mut self, key: String) -> Result<bool, String> {
+        self.check_cache()?;
+        Self::check_key(&key)?;
         self.cache.del(&key).map_err(|e| e.to_string())
     }
+
+    fn begin_transaction(&mut self) -> Result<Resource<CacheTx>, String> {
+        self.check_cache()?;
+        let tx = self.cache.begin().map_err(|e| e.to_string())?;
+        self.table.push(tx).map_err(|e| e.to_string())
+    }
+
+    fn commit(&mut self, tx: Resource<CacheTx>) -> Result<(), String> {
+        let tx = self.table.delete(tx).map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    fn rollback(&mut self, tx: Resource<CacheTx>) -> Result<(), String> {
+        let tx = self.table.delete(tx).map_err(|e| e.to_string())?;
+        tx.rollback().map_err(|e| e.to_string())
+    }
+}
+
+impl tangent::logs::cache::HostCacheTx for HostEngine {
+    fn get(&mut self, h: Resource<CacheTx>, key: String) -> Result<Option<Scalar>, String> {
+        Self::check_key(&key)?;
+        let tx = self.table.get(&h).map_err(|e| e.to_string())?;
+        tx.get(&key).map_err(|e| e.to_string())
+    }
+
+    fn set(
+        &mut self,
+        h: Resource<CacheTx>,
+        key: String,
+        value: Scalar,
+        ttl_ms: Option<u64>,
+    ) -> Result<(), String> {
+        Self::check_key(&key)?;
+        let tx = self.table.get(&h).map_err(|e| e.to_string())?;
+        tx.set_as(&self.plugin, &key, &value, ttl_ms)
+            .map_err(|e| e.to_string())
+    }
+
+    fn del(&mut self, h: Resource<CacheTx>, key: String) -> Result<bool, String> {
+        Self::check_key(&key)?;
+        let tx = self.table.get(&h).map_err(|e| e.to_string())?;
+        tx.del(&key).map_err(|e| e.to_string())
+    }
+
+    fn drop(&mut self, h: Resource<CacheTx>) -> wasmtime::Result<()> {
+        // CacheTx rolls back on drop if it was never committed.
+        let _ = self.table.delete(h)?;
+        Ok(())
+    }
 }
 
 struct JsonDoc {
@@ -390,3 +530,34 @@ impl HasData for HostEngine {
 }
 
 impl log::Host for HostEngine {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn run_bounded_caps_concurrency() {
+        let limit = Semaphore::new(2);
+        let gauge = PLUGIN_REMOTE_CALLS_INFLIGHT.with_label_values(&["bounded-test"]);
+        let current = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let out = run_bounded(&limit, &gauge, (0..10).collect(), |i: usize| {
+            let (current, peak) = (&current, &peak);
+            async move {
+                let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                current.fetch_sub(1, Ordering::SeqCst);
+                i * 2
+            }
+        })
+        .await;
+
+        assert_eq!(out, (0..10).map(|i| i * 2).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(gauge.get(), 0);
+    }
+}
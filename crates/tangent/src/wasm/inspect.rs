@@ -0,0 +1,266 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use tangent_shared::plugins::PluginConfig;
+use tangent_shared::Config;
+
+use crate::cache::CacheHandle;
+use crate::wasm::engine::WasmEngine;
+use crate::wasm::host::tangent::logs::log::Scalar;
+use crate::wasm::host::JsonLogView;
+use crate::wasm::mapper::probe_priorities;
+use crate::wasm::probe::{compile_selector, dispatch_targets, eval_selector, CompiledSelector};
+
+pub use crate::wasm::host::exports::tangent::logs::mapper::{Pred, Selector};
+
+/// What a compiled plugin reports about itself.
+pub struct PluginInfo {
+    pub name: String,
+    pub version: String,
+    pub selectors: Vec<Selector>,
+    /// Dispatch priority of each selector, in the same order.
+    pub priorities: Vec<Option<u8>>,
+}
+
+/// Load the precompiled component for plugin `name` from the config at
+/// `config_path` and call its `metadata()` and `probe()`. No pipeline is
+/// started and remote calls are disabled.
+pub async fn inspect(
+    config_path: &Path,
+    name: &str,
+    cache: Arc<CacheHandle>,
+) -> Result<PluginInfo> {
+    let cfg = Config::from_file(config_path)?;
+    let (name, plugin_cfg) = cfg
+        .plugins
+        .iter()
+        .find(|(n, _)| n.as_ref() == name)
+        .with_context(|| format!("plugin {name} not found in {}", config_path.display()))?;
+
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    let component_path = config_dir
+        .join(&cfg.runtime.plugins_path)
+        .join(format!("{name}.cwasm"));
+
+    let mut engine = WasmEngine::new(cache, true)?;
+    load(&mut engine, Arc::clone(name), &component_path, plugin_cfg).await
+}
+
+/// A plugin's selectors, kept alongside their compiled form so events can be
+/// dry-run against them.
+pub struct PluginProbe {
+    pub plugin: Arc<str>,
+    pub selectors: Vec<Selector>,
+    pub priorities: Vec<Option<u8>>,
+    compiled: Vec<CompiledSelector>,
+}
+
+impl PluginProbe {
+    /// Index of the first selector `event` matches, the one the runtime
+    /// would route it to the plugin by.
+    pub fn first_match(&self, event: &JsonLogView) -> Option<usize> {
+        self.compiled
+            .iter()
+            .position(|sel| eval_selector(sel, event))
+    }
+}
+
+/// Indexes into `plugins` of those the runtime would hand `event` to, once
+/// selector priorities have settled overlapping matches.
+pub fn receivers(plugins: &[PluginProbe], event: &JsonLogView) -> Vec<usize> {
+    let mut out = Vec::new();
+    dispatch_targets(
+        plugins.iter().map(|p| p.compiled.as_slice()),
+        event,
+        &mut out,
+    );
+    out
+}
+
+/// Load the precompiled component of every plugin in the config at
+/// `config_path`, in name order, and compile the selectors it reports.
+pub async fn probe_all(config_path: &Path, cache: Arc<CacheHandle>) -> Result<Vec<PluginProbe>> {
+    let cfg = Config::from_file(config_path)?;
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    let plugins_dir = config_dir.join(&cfg.runtime.plugins_path);
+
+    let mut engine = WasmEngine::new(cache, true)?;
+    let mut out = Vec::with_capacity(cfg.plugins.len());
+    for (name, plugin_cfg) in &cfg.plugins {
+        let component_path = plugins_dir.join(format!("{name}.cwasm"));
+        let info = load(&mut engine, Arc::clone(name), &component_path, plugin_cfg)
+            .await
+            .with_context(|| format!("plugin {name}"))?;
+        let compiled = info
+            .selectors
+            .iter()
+            .zip(&info.priorities)
+            .map(|(sel, priority)| compile_selector(sel, *priority))
+            .collect::<Result<_>>()
+            .with_context(|| format!("compiling selectors of plugin {name}"))?;
+        out.push(PluginProbe {
+            plugin: Arc::clone(name),
+            selectors: info.selectors,
+            priorities: info.priorities,
+            compiled,
+        });
+    }
+    Ok(out)
+}
+
+/// Every `.cwasm` in the plugins directory of the config at `config_path`,
+/// sorted by path, with what it reports about itself or why it failed to
+/// load. Components without a config entry are loaded with default settings.
+pub async fn list(
+    config_path: &Path,
+    cache: Arc<CacheHandle>,
+) -> Result<Vec<(PathBuf, Result<PluginInfo>)>> {
+    let cfg = Config::from_file(config_path)?;
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    let plugins_dir = config_dir.join(&cfg.runtime.plugins_path);
+
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(&plugins_dir)
+        .with_context(|| format!("reading {}", plugins_dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "cwasm") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut engine = WasmEngine::new(cache, true)?;
+    let default_cfg = PluginConfig::default();
+    let mut out = Vec::with_capacity(paths.len());
+    for path in paths {
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        let (name, plugin_cfg) = match cfg.plugins.get_key_value(stem) {
+            Some((name, plugin_cfg)) => (Arc::clone(name), plugin_cfg),
+            None => (Arc::from(stem), &default_cfg),
+        };
+        let info = load(&mut engine, name, &path, plugin_cfg).await;
+        out.push((path, info));
+    }
+    Ok(out)
+}
+
+async fn load(
+    engine: &mut WasmEngine,
+    name: Arc<str>,
+    component_path: &Path,
+    plugin_cfg: &PluginConfig,
+) -> Result<PluginInfo> {
+    let component = engine
+        .load_precompiled(Arc::clone(&name), component_path, plugin_cfg)
+        .with_context(|| format!("loading {}", component_path.display()))?;
+
+    let mut store = engine.make_store(&name);
+    let (proc, instance) = engine.make_processor(&mut store, &component).await?;
+    let guest = proc.tangent_logs_mapper();
+    let meta = guest.call_metadata(&mut store).await?;
+    let selectors = guest.call_probe(&mut store).await?;
+    let priorities = probe_priorities(
+        &mut store,
+        &instance,
+        selectors.len(),
+        engine.selector_priority(&name),
+    )
+    .await?;
+
+    Ok(PluginInfo {
+        name: meta.name,
+        version: meta.version,
+        selectors,
+        priorities,
+    })
+}
+
+/// Human-readable form of a selector predicate, e.g. `source.name == "payments"`.
+pub fn describe_pred(pred: &Pred) -> String {
+    match pred {
+        Pred::Has(path) => format!("has {path}"),
+        Pred::Eq((path, s)) => format!("{path} == {}", describe_scalar(s)),
+        Pred::Prefix((path, prefix)) => format!("{path} starts with {prefix:?}"),
+        Pred::In((path, list)) => {
+            let items: Vec<String> = list.iter().map(describe_scalar).collect();
+            format!("{path} in [{}]", items.join(", "))
+        }
+        Pred::Gt((path, rhs)) => format!("{path} > {rhs}"),
+        Pred::Regex((path, re)) => format!("{path} =~ /{re}/"),
+    }
+}
+
+/// Human-readable form of a whole selector: `all` predicates and'ed with
+/// `any(...)` and `!(...)` groups for `any` and `none`. `*` matches everything.
+pub fn describe_selector(sel: &Selector) -> String {
+    let join = |preds: &[Pred], sep: &str| {
+        preds
+            .iter()
+            .map(describe_pred)
+            .collect::<Vec<_>>()
+            .join(sep)
+    };
+    let mut parts = Vec::new();
+    if !sel.all.is_empty() {
+        parts.push(join(&sel.all, " && "));
+    }
+    if !sel.any.is_empty() {
+        parts.push(format!("({})", join(&sel.any, " || ")));
+    }
+    if !sel.none.is_empty() {
+        parts.push(format!("!({})", join(&sel.none, " || ")));
+    }
+    if parts.is_empty() {
+        return "*".to_string();
+    }
+    parts.join(" && ")
+}
+
+fn describe_scalar(s: &Scalar) -> String {
+    match s {
+        Scalar::Str(x) => format!("{x:?}"),
+        Scalar::Int(x) => x.to_string(),
+        Scalar::Float(x) => x.to_string(),
+        Scalar::Boolean(x) => x.to_string(),
+        Scalar::Bytes(bs) => {
+            let hex: String = bs.iter().map(|b| format!("{b:02x}")).collect();
+            format!("0x{hex}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preds_read_like_expressions() {
+        assert_eq!(
+            describe_pred(&Pred::Eq((
+                "source.name".into(),
+                Scalar::Str("payments".into())
+            ))),
+            r#"source.name == "payments""#
+        );
+        assert_eq!(
+            describe_pred(&Pred::In((
+                "level".into(),
+                vec![Scalar::Int(1), Scalar::Bytes(vec![0xab])]
+            ))),
+            "level in [1, 0xab]"
+        );
+        assert_eq!(
+            describe_selector(&Selector {
+                any: vec![Pred::Has("a".into()), Pred::Has("b".into())],
+                all: vec![Pred::Gt(("n".into(), 1.0))],
+                none: vec![Pred::Prefix(("msg".into(), "debug".into()))],
+            }),
+            r#"n > 1 && (has a || has b) && !(msg starts with "debug")"#
+        );
+    }
+}
@@ -1,14 +1,40 @@
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
-use wasmtime::component::Component;
+use ahash::HashMap;
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::Value;
+use tangent_shared::plugins::PluginEncoding;
+use wasmtime::component::{Component, ComponentType, Instance, Lift, Resource, TypedFunc};
 use wasmtime::Store;
 
+use crate::sinks::encoding;
 use crate::wasm::engine::WasmEngine;
 use crate::wasm::host::exports::tangent::logs::mapper::Selector;
-use crate::wasm::host::{HostEngine, Processor};
+use crate::wasm::host::{HostEngine, JsonLogView, Processor};
 
 use crate::wasm::probe::{compile_selector, CompiledSelector};
 
+const ROUTED_MAPPER: &str = "tangent:logs/routed-mapper@0.1.0";
+const PROCESS_LOGS_V3: &str = "process-logs-v3";
+const RANKED_MAPPER: &str = "tangent:logs/ranked-mapper@0.1.0";
+const PROBE_PRIORITIES: &str = "probe-priorities";
+
+/// `routed-mapper.output-event`. Spelled out by hand because `bindgen!` only
+/// covers the `processor` world, which doesn't export `routed-mapper`.
+#[derive(ComponentType, Lift)]
+#[component(record)]
+pub struct OutputEvent {
+    #[component(name = "key-prefix")]
+    pub key_prefix: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+pub type ProcessLogsV3 =
+    TypedFunc<(Vec<Resource<JsonLogView>>,), (Result<Vec<OutputEvent>, String>,)>;
+
 pub struct MapperCtx {
     pub cfg_name: Arc<str>,
     pub name: String,
@@ -16,6 +42,122 @@ pub struct MapperCtx {
     pub store: Store<HostEngine>,
     pub proc: Processor,
     pub selectors: Vec<CompiledSelector>,
+    /// Epoch ticks allowed per guest call when running with `--trace-wasm`.
+    /// Unset for plugins with a timeout, whose stores yield every tick.
+    pub epoch_deadline: Option<u64>,
+    /// Wall-clock limit for one guest call, from `timeout_ms`.
+    pub timeout: Option<Duration>,
+    /// Format of the guest's output payloads.
+    pub encoding: PluginEncoding,
+    component: Component,
+    /// Set when the plugin targets `routed-processor`; used instead of
+    /// `mapper.process-logs`.
+    pub process_logs_v3: Option<ProcessLogsV3>,
+}
+
+impl MapperCtx {
+    /// Instantiate `component` in a fresh store with plugin `name`'s
+    /// settings and read its metadata and selectors.
+    pub async fn load(
+        engine: &WasmEngine,
+        name: &Arc<str>,
+        component: &Component,
+    ) -> anyhow::Result<Self> {
+        let mut store = engine.make_store(name);
+
+        let (proc, instance) = engine.make_processor(&mut store, component).await?;
+        let process_logs_v3 = routed_export(&mut store, &instance)?;
+        let guest = proc.tangent_logs_mapper();
+
+        let meta = guest.call_metadata(&mut store).await?;
+        let sels: Vec<Selector> = guest.call_probe(&mut store).await?;
+        let priorities = probe_priorities(
+            &mut store,
+            &instance,
+            sels.len(),
+            engine.selector_priority(name),
+        )
+        .await?;
+
+        let selectors: Vec<CompiledSelector> = sels
+            .iter()
+            .zip(priorities)
+            .map(|(sel, priority)| compile_selector(sel, priority))
+            .collect::<anyhow::Result<_>>()?;
+        let timeout = engine.call_timeout(name);
+
+        Ok(MapperCtx {
+            cfg_name: Arc::clone(name),
+            name: meta.name,
+            version: meta.version,
+            store,
+            proc,
+            selectors,
+            epoch_deadline: engine.epoch_deadline().filter(|_| timeout.is_none()),
+            timeout,
+            encoding: engine.output_encoding(name),
+            component: component.clone(),
+            process_logs_v3,
+        })
+    }
+
+    /// Whether the last guest call trapped on the `max_memory_mb` cap.
+    pub fn memory_exceeded(&self) -> bool {
+        self.store
+            .data()
+            .memory_limit
+            .as_ref()
+            .is_some_and(|l| l.exceeded())
+    }
+
+    /// Run the guest over `input`, returning NDJSON payloads. Plugins without
+    /// `process-logs-v3` come back as a single event with no key prefix.
+    /// MessagePack or Arrow output that fails to decode counts as a guest
+    /// error.
+    pub async fn process_logs(
+        &mut self,
+        input: Vec<Resource<JsonLogView>>,
+    ) -> anyhow::Result<Result<Vec<OutputEvent>, String>> {
+        let res = self.call_guest(input).await?;
+        let (decode, format): (fn(&[u8]) -> anyhow::Result<Vec<u8>>, _) = match self.encoding {
+            PluginEncoding::Ndjson => return Ok(res),
+            PluginEncoding::Msgpack => (msgpack_records_to_ndjson, "msgpack"),
+            PluginEncoding::ArrowIpc => (encoding::arrow_ipc_to_ndjson, "arrow_ipc"),
+        };
+        Ok(res.and_then(|events| {
+            events
+                .into_iter()
+                .map(|mut ev| {
+                    ev.payload = decode(&ev.payload)
+                        .map_err(|e| format!("decoding {format} output: {e}"))?;
+                    Ok(ev)
+                })
+                .collect()
+        }))
+    }
+
+    async fn call_guest(
+        &mut self,
+        input: Vec<Resource<JsonLogView>>,
+    ) -> anyhow::Result<Result<Vec<OutputEvent>, String>> {
+        if let Some(f) = &self.process_logs_v3 {
+            let (res,) = f.call_async(&mut self.store, (input,)).await?;
+            f.post_return_async(&mut self.store).await?;
+            return Ok(res);
+        }
+
+        let res = self
+            .proc
+            .tangent_logs_mapper()
+            .call_process_logs(&mut self.store, &input)
+            .await?;
+        Ok(res.map(|payload| {
+            vec![OutputEvent {
+                key_prefix: None,
+                payload,
+            }]
+        }))
+    }
 }
 
 pub struct Mappers {
@@ -30,29 +172,135 @@ impl Mappers {
         let mut mappers = Vec::with_capacity(components.len());
 
         for (name, component) in components {
-            let mut store = engine.make_store(name);
-
-            let proc = engine.make_processor(&mut store, component).await?;
-            let guest = proc.tangent_logs_mapper();
-
-            let meta = guest.call_metadata(&mut store).await?;
-            let sels: Vec<Selector> = guest.call_probe(&mut store).await?;
-
-            let selectors: Vec<CompiledSelector> = sels
-                .iter()
-                .map(compile_selector)
-                .collect::<anyhow::Result<_>>()?;
-
-            mappers.push(MapperCtx {
-                cfg_name: Arc::clone(name),
-                name: meta.name,
-                version: meta.version,
-                store,
-                proc,
-                selectors,
-            });
+            mappers.push(MapperCtx::load(engine, name, component).await?);
         }
 
         Ok(Self { mappers })
     }
+
+    /// Swap the mapper for plugin `name` for a fresh instance of the component
+    /// at `loc`. The old instance stays in place if anything fails.
+    pub async fn reload(
+        &mut self,
+        engine: &WasmEngine,
+        name: &Arc<str>,
+        loc: &Path,
+    ) -> anyhow::Result<()> {
+        let slot = self
+            .mappers
+            .iter()
+            .position(|m| &m.cfg_name == name)
+            .with_context(|| format!("no mapper loaded for plugin {name}"))?;
+
+        let component = engine.reload(name, loc)?;
+        self.mappers[slot] = MapperCtx::load(engine, name, &component).await?;
+        Ok(())
+    }
+
+    /// Point every live instance of plugin `name` at `config`.
+    pub fn set_config(&mut self, name: &Arc<str>, config: &Arc<HashMap<String, Value>>) {
+        for m in self.mappers.iter_mut().filter(|m| &m.cfg_name == name) {
+            m.store.data_mut().set_plugin_cfg(Arc::clone(config));
+        }
+    }
+
+    /// Replace mapper `idx` with a fresh instance of the same component, for
+    /// instances left unusable by an interrupted or trapped call.
+    pub async fn restart(&mut self, engine: &WasmEngine, idx: usize) -> anyhow::Result<()> {
+        let m = &self.mappers[idx];
+        let (name, component) = (Arc::clone(&m.cfg_name), m.component.clone());
+        self.mappers[idx] = MapperCtx::load(engine, &name, &component).await?;
+        Ok(())
+    }
+}
+
+fn routed_export(
+    store: &mut Store<HostEngine>,
+    instance: &Instance,
+) -> anyhow::Result<Option<ProcessLogsV3>> {
+    let Some(iface) = instance.get_export_index(&mut *store, None, ROUTED_MAPPER) else {
+        return Ok(None);
+    };
+    let Some(func) = instance.get_export_index(&mut *store, Some(&iface), PROCESS_LOGS_V3) else {
+        return Ok(None);
+    };
+    let f = instance
+        .get_typed_func(&mut *store, func)
+        .with_context(|| {
+            format!("{ROUTED_MAPPER}#{PROCESS_LOGS_V3} has an unexpected signature")
+        })?;
+    Ok(Some(f))
+}
+
+/// Priority of each of a plugin's `count` selectors: what the guest's
+/// optional `ranked-mapper.probe-priorities` reports, with `default` (the
+/// plugin's `selector_priority`) for any it leaves unset.
+pub async fn probe_priorities(
+    store: &mut Store<HostEngine>,
+    instance: &Instance,
+    count: usize,
+    default: Option<u8>,
+) -> anyhow::Result<Vec<Option<u8>>> {
+    let mut out = vec![default; count];
+    let Some(iface) = instance.get_export_index(&mut *store, None, RANKED_MAPPER) else {
+        return Ok(out);
+    };
+    let Some(func) = instance.get_export_index(&mut *store, Some(&iface), PROBE_PRIORITIES) else {
+        return Ok(out);
+    };
+    let f: TypedFunc<(), (Vec<Option<u8>>,)> = instance
+        .get_typed_func(&mut *store, func)
+        .with_context(|| {
+            format!("{RANKED_MAPPER}#{PROBE_PRIORITIES} has an unexpected signature")
+        })?;
+    let (ranked,) = f.call_async(&mut *store, ()).await?;
+    f.post_return_async(&mut *store).await?;
+    if ranked.len() != count {
+        anyhow::bail!(
+            "{RANKED_MAPPER}#{PROBE_PRIORITIES} returned {} priorities for {count} selectors",
+            ranked.len()
+        );
+    }
+    for (p, r) in out.iter_mut().zip(ranked) {
+        if r.is_some() {
+            *p = r;
+        }
+    }
+    Ok(out)
+}
+
+/// Re-encode back-to-back MessagePack records as one NDJSON line each.
+fn msgpack_records_to_ndjson(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut rd = std::io::Cursor::new(data);
+    let mut out = Vec::with_capacity(data.len() * 2);
+    while (rd.position() as usize) < data.len() {
+        let val = serde_json::Value::deserialize(&mut rmp_serde::Deserializer::new(&mut rd))?;
+        serde_json::to_writer(&mut out, &val)?;
+        out.push(b'\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[test]
+    fn msgpack_records_become_ndjson_lines() {
+        #[derive(Serialize)]
+        struct Rec {
+            msg: &'static str,
+            n: i64,
+        }
+        let mut data = rmp_serde::to_vec_named(&Rec { msg: "a", n: 1 }).unwrap();
+        data.extend(rmp_serde::to_vec_named(&Rec { msg: "b", n: 2 }).unwrap());
+
+        assert_eq!(
+            msgpack_records_to_ndjson(&data).unwrap(),
+            b"{\"msg\":\"a\",\"n\":1}\n{\"msg\":\"b\",\"n\":2}\n"
+        );
+        assert!(msgpack_records_to_ndjson(&data[..data.len() - 1]).is_err());
+        assert!(msgpack_records_to_ndjson(&[]).unwrap().is_empty());
+    }
 }
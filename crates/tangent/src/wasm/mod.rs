@@ -1,4 +1,7 @@
+pub mod config_reload;
 pub mod engine;
 pub mod host;
+pub mod inspect;
 pub mod mapper;
 pub mod probe;
+pub mod watch;
@@ -1,4 +1,6 @@
+use ahash::AHashSet as HashSet;
 use regex::Regex;
+use tangent_shared::dag::EdgeFilter;
 
 use crate::wasm::{
     host::JsonLogView,
@@ -34,22 +36,111 @@ enum PredOp {
     Has { path: String },
     Eq { path: String, rhs: CmpScalar },
     Prefix { path: String, prefix: String },
-    In { path: String, set: Vec<CmpScalar> },
+    In { path: String, set: ScalarSet },
     Gt { path: String, rhs: f64 },
     Re { path: String, re: Regex },
+    Contains { path: String, needle: String },
+}
+
+/// Lists longer than this are hashed; shorter ones are scanned linearly.
+const IN_LINEAR_SCAN_MAX: usize = 8;
+
+enum ScalarSet {
+    Linear(Vec<CmpScalar>),
+    Hashed(HashedScalars),
+}
+
+#[derive(Default)]
+struct HashedScalars {
+    strs: HashSet<String>,
+    ints: HashSet<i64>,
+    bytes: HashSet<Vec<u8>>,
+    bools: [bool; 2],
+    // f64 isn't hashable and compares with an epsilon, so floats stay a list.
+    floats: Vec<f64>,
+}
+
+impl ScalarSet {
+    fn new(list: Vec<CmpScalar>) -> Self {
+        if list.len() <= IN_LINEAR_SCAN_MAX {
+            return Self::Linear(list);
+        }
+        let mut h = HashedScalars::default();
+        for s in list {
+            match s {
+                Str(x) => {
+                    h.strs.insert(x);
+                }
+                Int(x) => {
+                    h.ints.insert(x);
+                }
+                Bytes(x) => {
+                    h.bytes.insert(x);
+                }
+                Bool(x) => h.bools[x as usize] = true,
+                Float(x) => h.floats.push(x),
+            }
+        }
+        Self::Hashed(h)
+    }
+
+    fn contains(&self, v: &CmpScalar) -> bool {
+        match self {
+            Self::Linear(list) => list.iter().any(|rhs| scalar_eq(v, rhs)),
+            Self::Hashed(h) => match v {
+                Str(a) => h.strs.contains(a),
+                Bytes(a) => h.bytes.contains(a),
+                Bool(a) => h.bools[*a as usize],
+                Int(a) => {
+                    h.ints.contains(a) || h.floats.iter().any(|b| scalar_eq(&Int(*a), &Float(*b)))
+                }
+                Float(a) => {
+                    (a.fract() == 0.0 && h.ints.contains(&(*a as i64)))
+                        || h.floats.iter().any(|b| scalar_eq(&Float(*a), &Float(*b)))
+                }
+            },
+        }
+    }
+}
+
+fn scalar_eq(a: &CmpScalar, b: &CmpScalar) -> bool {
+    match (a, b) {
+        (Str(a), Str(b)) => a == b,
+        (Int(a), Int(b)) => a == b,
+        (Float(a), Float(b)) => (a - b).abs() < f64::EPSILON,
+        (Bool(a), Bool(b)) => a == b,
+        (Bytes(a), Bytes(b)) => a == b,
+        (Int(a), Float(b)) => (*a as f64 - b).abs() < f64::EPSILON,
+        (Float(a), Int(b)) => (a - *b as f64).abs() < f64::EPSILON,
+        _ => false,
+    }
 }
 
 pub struct CompiledSelector {
     any: Vec<PredOp>,
     all: Vec<PredOp>,
     none: Vec<PredOp>,
+    priority: Option<u8>,
+}
+
+impl CompiledSelector {
+    /// Sort key for `priority`: lower wins, unset loses to any set value.
+    fn rank(&self) -> u16 {
+        self.priority.map_or(u16::from(u8::MAX) + 1, u16::from)
+    }
 }
 
-pub fn compile_selector(sel: &mapper::Selector) -> anyhow::Result<CompiledSelector> {
+/// Compile `sel` to dispatch at `priority`, which the guest reports
+/// separately through `ranked-mapper`.
+pub fn compile_selector(
+    sel: &mapper::Selector,
+    priority: Option<u8>,
+) -> anyhow::Result<CompiledSelector> {
     let mut cs = CompiledSelector {
         any: vec![],
         all: vec![],
         none: vec![],
+        priority,
     };
 
     let conv = |p: &Pred| -> anyhow::Result<PredOp> {
@@ -65,7 +156,7 @@ pub fn compile_selector(sel: &mapper::Selector) -> anyhow::Result<CompiledSelect
             },
             Pred::In((path, list)) => PredOp::In {
                 path: path.clone(),
-                set: list.iter().cloned().map(Into::into).collect(),
+                set: ScalarSet::new(list.iter().cloned().map(Into::into).collect()),
             },
             Pred::Gt((path, rhs)) => PredOp::Gt {
                 path: path.clone(),
@@ -96,19 +187,7 @@ fn eval_pred(pred: &PredOp, view: &JsonLogView) -> bool {
 
         PredOp::Eq { path, rhs } => {
             let val = view.lookup(path).and_then(JsonLogView::to_scalar);
-            val.is_some_and(|s| {
-                let s: CmpScalar = s.into();
-                match (s, rhs) {
-                    (Str(a), Str(b)) => a == *b,
-                    (Int(a), Int(b)) => a == *b,
-                    (Float(a), Float(b)) => (a - b).abs() < f64::EPSILON,
-                    (Bool(a), Bool(b)) => a == *b,
-                    (Bytes(a), Bytes(b)) => a == *b,
-                    (Int(a), Float(b)) => (a as f64 - b).abs() < f64::EPSILON,
-                    (Float(a), Int(b)) => (a - *b as f64).abs() < f64::EPSILON,
-                    _ => false,
-                }
-            })
+            val.is_some_and(|s| scalar_eq(&s.into(), rhs))
         }
 
         PredOp::Prefix { path, prefix } => {
@@ -118,19 +197,7 @@ fn eval_pred(pred: &PredOp, view: &JsonLogView) -> bool {
 
         PredOp::In { path, set } => {
             let val = view.lookup(path).and_then(JsonLogView::to_scalar);
-            val.is_some_and(|val| {
-                let v: CmpScalar = val.into();
-                set.iter().any(|rhs| match (&v, rhs) {
-                    (Str(a), Str(b)) => a == b,
-                    (Int(a), Int(b)) => a == b,
-                    (Float(a), Float(b)) => (a - b).abs() < f64::EPSILON,
-                    (Bool(a), Bool(b)) => a == b,
-                    (Bytes(a), Bytes(b)) => a == b,
-                    (Int(a), Float(b)) => (*a as f64 - b).abs() < f64::EPSILON,
-                    (Float(a), Int(b)) => (a - *b as f64).abs() < f64::EPSILON,
-                    _ => false,
-                })
-            })
+            val.is_some_and(|val| set.contains(&val.into()))
         }
 
         PredOp::Gt { path, rhs } => {
@@ -146,9 +213,46 @@ fn eval_pred(pred: &PredOp, view: &JsonLogView) -> bool {
             let out = view.lookup(path).and_then(JsonLogView::to_scalar);
             matches!(out, Some(log::Scalar::Str(s)) if re.is_match(&s))
         }
+
+        PredOp::Contains { path, needle } => {
+            let val = view.lookup(path).and_then(JsonLogView::to_scalar);
+            matches!(val, Some(log::Scalar::Str(s)) if s.contains(needle.as_str()))
+        }
     }
 }
 
+/// An `Edge::filter` compiled for evaluation in the router.
+pub struct CompiledEdgeFilter(PredOp);
+
+pub fn compile_edge_filter(filter: &EdgeFilter) -> anyhow::Result<CompiledEdgeFilter> {
+    Ok(CompiledEdgeFilter(match filter {
+        EdgeFilter::Exists { path } => PredOp::Has { path: path.clone() },
+        EdgeFilter::Contains { path, value } => PredOp::Contains {
+            path: path.clone(),
+            needle: value.clone(),
+        },
+        EdgeFilter::Eq { path, value } => {
+            let rhs = match value {
+                serde_json::Value::String(s) => Str(s.clone()),
+                serde_json::Value::Bool(b) => Bool(*b),
+                serde_json::Value::Number(n) => match n.as_i64() {
+                    Some(i) => Int(i),
+                    None => Float(n.as_f64().unwrap_or(f64::NAN)),
+                },
+                other => anyhow::bail!("eq filter on {path} can't compare against {other}"),
+            };
+            PredOp::Eq {
+                path: path.clone(),
+                rhs,
+            }
+        }
+    }))
+}
+
+pub fn eval_edge_filter(filter: &CompiledEdgeFilter, v: &JsonLogView) -> bool {
+    eval_pred(&filter.0, v)
+}
+
 pub fn eval_selector(sel: &CompiledSelector, v: &JsonLogView) -> bool {
     // ANY
     if !sel.any.is_empty() {
@@ -177,3 +281,154 @@ pub fn eval_selector(sel: &CompiledSelector, v: &JsonLogView) -> bool {
     }
     true
 }
+
+/// Fill `out` with the indexes of the plugins `v` should go to, given each
+/// plugin's selectors in order. Of the plugins with a matching selector,
+/// only those whose best match has the lowest priority are kept.
+pub fn dispatch_targets<'a>(
+    plugins: impl IntoIterator<Item = &'a [CompiledSelector]>,
+    v: &JsonLogView,
+    out: &mut Vec<usize>,
+) {
+    out.clear();
+    let mut best = u16::MAX;
+    for (idx, selectors) in plugins.into_iter().enumerate() {
+        let Some(rank) = selectors
+            .iter()
+            .filter(|s| eval_selector(s, v))
+            .map(CompiledSelector::rank)
+            .min()
+        else {
+            continue;
+        };
+        if rank < best {
+            best = rank;
+            out.clear();
+        }
+        if rank == best {
+            out.push(idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    fn in_selector(path: &str, list: Vec<log::Scalar>) -> CompiledSelector {
+        compile_selector(
+            &mapper::Selector {
+                any: vec![],
+                all: vec![Pred::In((path.to_string(), list))],
+                none: vec![],
+            },
+            None,
+        )
+        .unwrap()
+    }
+
+    fn view(json: &str) -> JsonLogView {
+        JsonLogView::from_bytes(BytesMut::from(json)).unwrap()
+    }
+
+    fn services(n: usize) -> Vec<log::Scalar> {
+        (0..n)
+            .map(|i| log::Scalar::Str(format!("svc-{i}")))
+            .collect()
+    }
+
+    #[test]
+    fn in_small_list_scans_linearly() {
+        let sel = in_selector("source.name", services(5));
+        assert!(matches!(
+            sel.all[0],
+            PredOp::In {
+                set: ScalarSet::Linear(_),
+                ..
+            }
+        ));
+
+        assert!(eval_selector(&sel, &view(r#"{"source":{"name":"svc-0"}}"#)));
+        assert!(eval_selector(&sel, &view(r#"{"source":{"name":"svc-4"}}"#)));
+        assert!(!eval_selector(
+            &sel,
+            &view(r#"{"source":{"name":"svc-5"}}"#)
+        ));
+        assert!(!eval_selector(&sel, &view(r#"{"other":"svc-0"}"#)));
+    }
+
+    #[test]
+    fn in_large_list_uses_hash_set() {
+        let mut list = services(50);
+        list.push(log::Scalar::Int(200));
+        list.push(log::Scalar::Float(1.5));
+        let sel = in_selector("source.name", list.clone());
+        assert!(matches!(
+            sel.all[0],
+            PredOp::In {
+                set: ScalarSet::Hashed(_),
+                ..
+            }
+        ));
+
+        assert!(eval_selector(&sel, &view(r#"{"source":{"name":"svc-0"}}"#)));
+        assert!(eval_selector(
+            &sel,
+            &view(r#"{"source":{"name":"svc-49"}}"#)
+        ));
+        assert!(!eval_selector(
+            &sel,
+            &view(r#"{"source":{"name":"svc-50"}}"#)
+        ));
+
+        let sel = in_selector("status", list);
+        assert!(eval_selector(&sel, &view(r#"{"status":200}"#)));
+        assert!(eval_selector(&sel, &view(r#"{"status":200.0}"#)));
+        assert!(eval_selector(&sel, &view(r#"{"status":1.5}"#)));
+        assert!(!eval_selector(&sel, &view(r#"{"status":404}"#)));
+    }
+
+    #[test]
+    fn overlapping_selectors_dispatch_by_priority() {
+        let sel = |service: &str, priority| {
+            compile_selector(
+                &mapper::Selector {
+                    any: vec![],
+                    all: vec![Pred::Eq((
+                        "service".to_string(),
+                        log::Scalar::Str(service.to_string()),
+                    ))],
+                    none: vec![],
+                },
+                priority,
+            )
+            .unwrap()
+        };
+        let targets = |plugins: &[Vec<CompiledSelector>], json: &str| {
+            let mut out = Vec::new();
+            dispatch_targets(plugins.iter().map(Vec::as_slice), &view(json), &mut out);
+            out
+        };
+
+        // A generic plugin at priority 5 and a specific one at 1 both match
+        // checkout events; only the specific one gets them.
+        let plugins = vec![
+            vec![sel("checkout", Some(5)), sel("search", Some(5))],
+            vec![sel("checkout", Some(1))],
+            vec![sel("checkout", None)],
+        ];
+        assert_eq!(targets(&plugins, r#"{"service":"checkout"}"#), vec![1]);
+        assert_eq!(targets(&plugins, r#"{"service":"search"}"#), vec![0]);
+        assert!(targets(&plugins, r#"{"service":"auth"}"#).is_empty());
+
+        // Ties, including all-unset, go to every matching plugin.
+        let plugins = vec![
+            vec![sel("checkout", Some(2))],
+            vec![sel("checkout", Some(2))],
+        ];
+        assert_eq!(targets(&plugins, r#"{"service":"checkout"}"#), vec![0, 1]);
+        let plugins = vec![vec![sel("checkout", None)], vec![sel("checkout", None)]];
+        assert_eq!(targets(&plugins, r#"{"service":"checkout"}"#), vec![0, 1]);
+    }
+}
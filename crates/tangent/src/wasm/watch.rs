@@ -0,0 +1,74 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::timeout;
+
+/// Quiet period after the last change to a component before it is reloaded,
+/// so a file that is still being written isn't picked up half-way.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// A plugin whose compiled component changed on disk.
+#[derive(Debug, Clone)]
+pub struct PluginReload {
+    pub name: Arc<str>,
+    pub path: PathBuf,
+}
+
+/// Watch each plugin's component file and announce changes on `reloads`.
+/// Watching stops when the returned watcher is dropped.
+pub fn watch(
+    plugins: Vec<(Arc<str>, PathBuf)>,
+    reloads: broadcast::Sender<PluginReload>,
+) -> Result<RecommendedWatcher> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(ev) if matches!(ev.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+            for p in ev.paths {
+                let _ = tx.send(p);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("plugin watcher error: {e}"),
+    })
+    .context("starting plugin watcher")?;
+
+    // Watch the directories rather than the files: builds usually replace a
+    // component by renaming over it, which ends a watch on the old inode.
+    let dirs: BTreeSet<&Path> = plugins.iter().filter_map(|(_, p)| p.parent()).collect();
+    for dir in dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("watching {}", dir.display()))?;
+    }
+    tracing::info!("watching {} plugin(s) for changes", plugins.len());
+
+    let by_path: HashMap<PathBuf, Arc<str>> =
+        plugins.into_iter().map(|(name, p)| (p, name)).collect();
+
+    tokio::spawn(async move {
+        while let Some(first) = rx.recv().await {
+            let mut changed = BTreeSet::from([first]);
+            while let Ok(Some(p)) = timeout(RELOAD_DEBOUNCE, rx.recv()).await {
+                changed.insert(p);
+            }
+
+            for path in changed {
+                let Some(name) = by_path.get(&path) else {
+                    continue;
+                };
+                tracing::info!(plugin = %name, "component changed; reloading {}", path.display());
+                let _ = reloads.send(PluginReload {
+                    name: Arc::clone(name),
+                    path,
+                });
+            }
+        }
+    });
+
+    Ok(watcher)
+}
@@ -1,40 +1,106 @@
-use ahash::{HashMap, HashMapExt};
-use anyhow::Result;
+use ahash::{HashMap, HashMapExt, HashSet};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
+use simd_json::prelude::Writable;
+use simd_json::{BorrowedValue, StaticNode};
+use std::pin::Pin;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
 };
 use std::time::{Duration, Instant};
 use tangent_shared::dag::NodeRef;
-use tokio::sync::mpsc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc};
 use tokio::task::JoinHandle;
 use tokio::time::{self, Instant as TokioInstant};
 use wasmtime::component::{Component, Resource};
 
+use crate::wasm::config_reload::{ConfigPoller, PluginConfigSource};
 use crate::wasm::host::JsonLogView;
+use crate::wasm::watch::PluginReload;
 use crate::{
     router::Router,
-    wasm::{self, mapper::Mappers, probe::eval_selector},
+    wasm::{self, engine::WasmEngine, mapper::Mappers, probe::dispatch_targets},
 };
-use crate::{CONSUMER_BYTES_TOTAL, CONSUMER_OBJECTS_TOTAL, GUEST_BYTES_TOTAL, GUEST_LATENCY};
+use crate::{
+    CONSUMER_BYTES_TOTAL, CONSUMER_OBJECTS_TOTAL, GUEST_BYTES_TOTAL, GUEST_LATENCY,
+    PLUGIN_CONFIG_RELOAD_TOTAL, PLUGIN_RELOADS_TOTAL, PLUGIN_RELOAD_ERRORS_TOTAL,
+    PLUGIN_TIMEOUT_TOTAL, WORKER_BACKPRESSURE_TOTAL, WORKER_DROPS_TOTAL,
+};
+
+/// Fixed seeds, so a key maps to the same worker across restarts.
+static AFFINITY_HASH: ahash::RandomState = ahash::RandomState::with_seeds(
+    0x243f_6a88_85a3_08d3,
+    0x1319_8a2e_0370_7344,
+    0xa409_3822_299f_31d0,
+    0x082e_fa98_ec4e_6c89,
+);
 
 #[async_trait]
 pub trait Ack: Send + Sync {
     async fn ack(&self) -> Result<()>;
 }
 
-pub struct Record {
-    pub payload: BytesMut,
-    pub ack: Option<Arc<dyn Ack>>,
+pub enum Record {
+    Inline {
+        payload: BytesMut,
+        ack: Option<Arc<dyn Ack>>,
+    },
+    /// A large newline-delimited body that is split into `Inline` records by
+    /// `WorkerPool::dispatch` as it is read, so it is never fully buffered.
+    Streaming {
+        reader: Pin<Box<dyn AsyncRead + Send>>,
+        ack: Option<Arc<dyn Ack>>,
+        source_name: Arc<str>,
+    },
+}
+
+/// Acks the wrapped ack once the stream is exhausted and every line read
+/// from it has been acked. Starts with one reference held by the reader,
+/// released by acking the `StreamAck` itself.
+pub(crate) struct StreamAck {
+    remaining: AtomicUsize,
+    inner: Arc<dyn Ack>,
+}
+
+impl StreamAck {
+    pub(crate) fn new(inner: Arc<dyn Ack>) -> Arc<Self> {
+        Arc::new(Self {
+            remaining: AtomicUsize::new(1),
+            inner,
+        })
+    }
+
+    /// An ack for one more part read from the stream.
+    pub(crate) fn part(self: &Arc<Self>) -> Arc<dyn Ack> {
+        self.remaining.fetch_add(1, Ordering::AcqRel);
+        self.clone()
+    }
+}
+
+#[async_trait]
+impl Ack for StreamAck {
+    async fn ack(&self) -> Result<()> {
+        if self.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.ack().await?;
+        }
+        Ok(())
+    }
 }
 
 pub struct Worker {
     id: usize,
     rx: mpsc::Receiver<Record>,
+    engine: WasmEngine,
     mappers: Mappers,
+    /// Plugin reloads, applied between batches so an in-flight flush always
+    /// finishes on the component it started with.
+    reloads: Option<broadcast::Receiver<PluginReload>>,
+    /// Polls the `set-config` sidecar; changes are applied between batches.
+    config_poller: Option<ConfigPoller>,
     batch_max_size: usize,
     batch_max_age: Duration,
     router: Arc<Router>,
@@ -50,6 +116,8 @@ impl Worker {
         let sleeper = time::sleep_until(deadline);
         tokio::pin!(sleeper);
 
+        self.reload_plugin_config().await;
+
         loop {
             tokio::select! {
                 maybe_job = self.rx.recv() => {
@@ -60,13 +128,17 @@ impl Worker {
                             }
                             break;
                         }
-                        Some(rec) => {
+                        Some(Record::Streaming { source_name, .. }) => {
+                            // dispatch() splits streams before they reach a worker.
+                            tracing::error!(source = %source_name, "streaming record reached worker {}; dropping", self.id);
+                        }
+                        Some(Record::Inline { payload, ack }) => {
                             if batch.is_empty() {
                                 deadline = TokioInstant::now() + self.batch_max_age;
                                 sleeper.as_mut().reset(deadline);
                             }
 
-                            let payload_len = rec.payload.len();
+                            let payload_len = payload.len();
 
                             if total_size + payload_len > self.batch_max_size {
                                 self.flush_batch(&mut batch, &mut acks, &mut total_size).await?;
@@ -75,15 +147,15 @@ impl Worker {
                             }
 
                             if payload_len > self.batch_max_size && batch.is_empty() {
-                                let mut single = vec![rec.payload];
-                                let mut single_ack = rec.ack.as_slice().to_owned();
+                                let mut single = vec![payload];
+                                let mut single_ack = ack.as_slice().to_owned();
                                 self.flush_batch(&mut single, &mut single_ack, &mut total_size).await?;
                                 deadline = TokioInstant::now() + self.batch_max_age;
                                 sleeper.as_mut().reset(deadline);
                             } else {
                                 total_size += payload_len;
-                                batch.push(rec.payload);
-                                if let Some(a) = rec.ack { acks.push(a); }
+                                batch.push(payload);
+                                if let Some(a) = ack { acks.push(a); }
                             }
                         }
                     }
@@ -95,12 +167,65 @@ impl Worker {
                     deadline = TokioInstant::now() + self.batch_max_age;
                     sleeper.as_mut().reset(deadline);
                 }
+                reload = next_reload(&mut self.reloads) => {
+                    match reload {
+                        Ok(r) => self.reload_plugin(&r).await,
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            tracing::warn!("worker {} missed {n} plugin reloads", self.id);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => self.reloads = None,
+                    }
+                }
+                () = next_config_tick(&mut self.config_poller) => {
+                    self.reload_plugin_config().await;
+                }
             }
         }
 
         Ok(())
     }
 
+    async fn reload_plugin(&mut self, r: &PluginReload) {
+        match self.mappers.reload(&self.engine, &r.name, &r.path).await {
+            Ok(()) => {
+                PLUGIN_RELOADS_TOTAL.with_label_values(&[&r.name]).inc();
+                tracing::info!(plugin = %r.name, "worker {} reloaded plugin", self.id);
+            }
+            Err(e) => {
+                PLUGIN_RELOAD_ERRORS_TOTAL
+                    .with_label_values(&[&r.name])
+                    .inc();
+                tracing::error!(plugin = %r.name, "worker {} plugin reload failed; keeping previous build: {e:#}", self.id);
+            }
+        }
+    }
+
+    /// Apply plugin config that changed in the `set-config` sidecar. A
+    /// sidecar that can't be read or parsed leaves the current config alone.
+    async fn reload_plugin_config(&mut self) {
+        let Some(poller) = self.config_poller.as_mut() else {
+            return;
+        };
+        let configs = match poller.poll().await {
+            Ok(Some(configs)) => configs,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!("worker {} keeping current plugin config: {e:#}", self.id);
+                return;
+            }
+        };
+        for (name, config) in configs {
+            if self.engine.plugin_config(&name).map(|c| &**c) == Some(&config) {
+                continue;
+            }
+            let config = Arc::new(config);
+            self.engine.set_plugin_config(&name, Arc::clone(&config));
+            self.mappers.set_config(&name, &config);
+            PLUGIN_CONFIG_RELOAD_TOTAL.with_label_values(&[&name]).inc();
+            tracing::info!(plugin = %name, "worker {} applied updated plugin config", self.id);
+        }
+    }
+
     pub async fn flush_batch(
         &mut self,
         batch: &mut Vec<BytesMut>,
@@ -114,41 +239,56 @@ impl Worker {
 
         let mut groups: HashMap<usize, Vec<JsonLogView>> = HashMap::default();
         let mut sizes: HashMap<usize, usize> = HashMap::default();
+        // Parsing rewrites the buffer in place, so keep a copy of each event
+        // for dead-letter sinks.
+        let keep_raw = self.router.has_dead_letters();
+        let mut raws: HashMap<usize, Vec<Bytes>> = HashMap::default();
+        let mut targets = Vec::new();
         for b in batch.drain(..) {
             let sz = b.len();
+            let raw = keep_raw.then(|| Bytes::copy_from_slice(&b));
             let lv = JsonLogView::from_bytes(b)?;
-            let mut matched = false;
-            for (idx, m) in self.mappers.mappers.iter_mut().enumerate() {
-                if m.selectors.iter().any(|s| eval_selector(s, &lv)) {
-                    groups.entry(idx).or_default().push(lv.clone());
-                    *sizes.entry(idx).or_default() += sz;
-                    matched = true;
+            dispatch_targets(
+                self.mappers.mappers.iter().map(|m| m.selectors.as_slice()),
+                &lv,
+                &mut targets,
+            );
+            for &idx in &targets {
+                groups.entry(idx).or_default().push(lv.clone());
+                *sizes.entry(idx).or_default() += sz;
+                if let Some(raw) = &raw {
+                    raws.entry(idx).or_default().push(raw.clone());
                 }
             }
 
-            if !matched {
+            if targets.is_empty() {
                 tracing::debug!("log did not match any mappers");
             }
         }
 
-        let mut plugin_outputs: HashMap<Arc<str>, Vec<BytesMut>> =
+        let mut plugin_outputs: HashMap<Arc<str>, Vec<(Option<Arc<str>>, BytesMut)>> =
             HashMap::with_capacity(batch.len());
 
         for (idx, lvs) in groups {
             let m = &mut self.mappers.mappers[idx];
 
+            let batch_len = lvs.len();
             let mut owned: Vec<Resource<JsonLogView>> = Vec::new();
             for lv in lvs {
                 let h = m.store.data_mut().table.push(lv)?;
                 owned.push(h);
             }
 
+            if let Some(ticks) = m.epoch_deadline {
+                m.store.set_epoch_deadline(ticks);
+            }
+
             let start = Instant::now();
-            let res = m
-                .proc
-                .tangent_logs_mapper()
-                .call_process_logs(&mut m.store, &owned)
-                .await;
+            // `None` when the call ran past the plugin's timeout.
+            let res = match m.timeout {
+                Some(limit) => time::timeout(limit, m.process_logs(owned)).await.ok(),
+                None => Some(m.process_logs(owned).await),
+            };
 
             let secs = start.elapsed().as_secs_f64();
             GUEST_LATENCY
@@ -157,26 +297,70 @@ impl Worker {
             GUEST_BYTES_TOTAL.inc_by(*sizes.get(&idx).unwrap() as u64);
 
             let out = match res {
-                Err(host_err) => {
+                None => {
+                    let limit = m.timeout.unwrap_or_default();
+                    PLUGIN_TIMEOUT_TOTAL.with_label_values(&[&m.cfg_name]).inc();
+                    tracing::warn!(
+                        mapper = %m.name,
+                        batch_size = batch_len,
+                        "guest call exceeded timeout of {}ms; restarting plugin",
+                        limit.as_millis()
+                    );
+                    let error = format!("plugin timed out after {}ms", limit.as_millis());
+                    self.abandon_batch(idx, raws.remove(&idx).unwrap_or_default(), &error)
+                        .await?;
+                    continue;
+                }
+                Some(Err(host_err)) if m.memory_exceeded() => {
+                    tracing::warn!(
+                        mapper = %m.name,
+                        batch_size = batch_len,
+                        "guest exceeded its memory limit; restarting plugin: {host_err:#}"
+                    );
+                    let error = format!("{host_err:#}");
+                    self.abandon_batch(idx, raws.remove(&idx).unwrap_or_default(), &error)
+                        .await?;
+                    continue;
+                }
+                Some(Err(host_err)) => {
+                    if let Some(bt) = host_err.downcast_ref::<wasmtime::WasmBacktrace>() {
+                        tracing::error!(
+                            mapper = %m.name,
+                            batch_size = batch_len,
+                            trap = ?host_err.downcast_ref::<wasmtime::Trap>(),
+                            "guest call trapped after {secs:.3}s; backtrace:\n{bt}"
+                        );
+                    }
                     tracing::error!(error = ?host_err, mapper=%m.name, "host error in process_log");
                     return Err(host_err);
                 }
-                Ok(Ok(frames)) => frames,
-                Ok(Err(guest_err)) => {
+                Some(Ok(Ok(events))) => events,
+                Some(Ok(Err(guest_err))) => {
                     tracing::warn!(mapper=%m.name, error = ?guest_err, "guest error; skipping");
+                    let failed = raws.remove(&idx).unwrap_or_default();
+                    if let Err(e) = self
+                        .router
+                        .dead_letter(&m.cfg_name, failed, &guest_err)
+                        .await
+                    {
+                        tracing::warn!(mapper=%m.name, "dead-letter delivery failed: {e:#}");
+                    }
                     continue;
                 }
             };
 
-            if out.is_empty() {
+            if out.iter().all(|ev| ev.payload.is_empty()) {
                 tracing::warn!(mapper=%m.name, "mapper produced empty output");
                 continue;
             }
 
-            plugin_outputs
-                .entry(m.cfg_name.clone())
-                .or_default()
-                .push(Bytes::from(out).try_into_mut().unwrap())
+            let frames = plugin_outputs.entry(m.cfg_name.clone()).or_default();
+            for ev in out.into_iter().filter(|ev| !ev.payload.is_empty()) {
+                frames.push((
+                    ev.key_prefix.map(Arc::from),
+                    Bytes::from(ev.payload).try_into_mut().unwrap(),
+                ));
+            }
         }
 
         let upstream_acks = std::mem::take(acks);
@@ -184,7 +368,7 @@ impl Worker {
 
         for (plugin_name, frames) in plugin_outputs {
             self.router
-                .forward(
+                .forward_with_key_prefix(
                     &NodeRef::Plugin { name: plugin_name },
                     frames,
                     std::mem::take(&mut remaining),
@@ -196,15 +380,54 @@ impl Worker {
         *total_size = 0;
         Ok(())
     }
+
+    /// Dead-letter a batch whose guest call was cut short and swap in a fresh
+    /// instance of mapper `idx`, since the interrupted one can't be re-entered.
+    async fn abandon_batch(&mut self, idx: usize, failed: Vec<Bytes>, error: &str) -> Result<()> {
+        let plugin = Arc::clone(&self.mappers.mappers[idx].cfg_name);
+        if let Err(e) = self.router.dead_letter(&plugin, failed, error).await {
+            tracing::warn!(mapper = %plugin, "dead-letter delivery failed: {e:#}");
+        }
+        self.mappers
+            .restart(&self.engine, idx)
+            .await
+            .with_context(|| format!("restarting plugin {plugin}"))
+    }
+}
+
+/// Next reload for a worker, or never when it isn't watching plugins.
+async fn next_reload(
+    reloads: &mut Option<broadcast::Receiver<PluginReload>>,
+) -> std::result::Result<PluginReload, broadcast::error::RecvError> {
+    match reloads {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn next_config_tick(poller: &mut Option<ConfigPoller>) {
+    match poller {
+        Some(p) => p.tick().await,
+        None => std::future::pending().await,
+    }
 }
 
 pub struct WorkerPool {
     senders: Vec<mpsc::Sender<Record>>,
     rr: AtomicUsize,
+    /// Dotted path of `worker_affinity_key`; events for `affinity_plugins`
+    /// are sent to the worker its value hashes to instead of round-robin.
+    affinity_key: Option<String>,
+    /// Plugins that set `worker_affinity_key`. Events for any other plugin
+    /// go round-robin without being parsed.
+    affinity_plugins: HashSet<Arc<str>>,
+    /// `runtime.backpressure_timeout_ms`; `None` waits for a worker forever.
+    backpressure_timeout: Option<Duration>,
     handles: Vec<JoinHandle<()>>,
 }
 
 impl WorkerPool {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         size: usize,
         engines: Vec<wasm::engine::WasmEngine>,
@@ -212,16 +435,21 @@ impl WorkerPool {
         batch_max_size: usize,
         batch_max_age: Duration,
         router: Arc<Router>,
+        reloads: Option<&broadcast::Sender<PluginReload>>,
+        plugin_config: Option<&PluginConfigSource>,
+        affinity_key: Option<&str>,
+        affinity_plugins: HashSet<Arc<str>>,
+        backpressure_timeout: Option<Duration>,
     ) -> anyhow::Result<Self> {
         let mut senders = Vec::with_capacity(size);
         let mut handles = Vec::with_capacity(size);
 
         let ch_capacity = 4096;
-        for i in 0..size {
+        for (i, engine) in engines.into_iter().enumerate().take(size) {
             let (tx, rx) = mpsc::channel::<Record>(ch_capacity);
             senders.push(tx);
 
-            let mut mappers = Mappers::load_all(&engines[i], &components[i]).await?;
+            let mut mappers = Mappers::load_all(&engine, &components[i]).await?;
             if let Some(first) = mappers.mappers.first_mut() {
                 let start = Instant::now();
                 match first
@@ -246,7 +474,10 @@ impl WorkerPool {
             let worker = Worker {
                 id: i,
                 rx,
+                engine,
                 mappers,
+                reloads: reloads.map(broadcast::Sender::subscribe),
+                config_poller: plugin_config.map(PluginConfigSource::poller),
                 batch_max_size,
                 batch_max_age,
                 router: Arc::clone(&router),
@@ -262,19 +493,163 @@ impl WorkerPool {
         Ok(Self {
             senders,
             rr: AtomicUsize::new(0),
+            affinity_key: affinity_key.map(str::to_string),
+            affinity_plugins,
+            backpressure_timeout,
             handles: handles,
         })
     }
 
-    pub async fn dispatch(&self, mut job: Record) -> Result<()> {
+    /// Hand `job`, bound for `plugin`, to a worker.
+    pub async fn dispatch(&self, plugin: &str, job: Record) -> Result<()> {
+        let keyed = self.affinity_key.is_some() && self.affinity_plugins.contains(plugin);
+        match job {
+            Record::Inline { payload, ack } if keyed => self.dispatch_by_key(payload, ack).await,
+            Record::Inline { .. } => self.send(job).await,
+            Record::Streaming {
+                reader,
+                ack,
+                source_name,
+            } => self.dispatch_stream(reader, ack, source_name, keyed).await,
+        }
+    }
+
+    /// Read `reader` line by line, dispatching each non-empty line as its own
+    /// `Record::Inline`. `ack` fires once every line has been processed; on a
+    /// read error it never fires so the source can redeliver.
+    async fn dispatch_stream(
+        &self,
+        reader: Pin<Box<dyn AsyncRead + Send>>,
+        ack: Option<Arc<dyn Ack>>,
+        source_name: Arc<str>,
+        keyed: bool,
+    ) -> Result<()> {
+        let stream_ack = ack.map(StreamAck::new);
+
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let n = match reader.read_until(b'\n', &mut line).await {
+                Ok(n) => n,
+                Err(e) => {
+                    tracing::error!(source = %source_name, error = ?e, "reading streaming record failed");
+                    return Err(e.into());
+                }
+            };
+            if n == 0 {
+                break;
+            }
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            let job = Record::Inline {
+                payload: BytesMut::from(&line[..]),
+                ack: stream_ack.as_ref().map(StreamAck::part),
+            };
+            match keyed.then(|| self.worker_for(&line)).flatten() {
+                Some(idx) => self.send_to(idx, job).await?,
+                None => self.send(job).await?,
+            }
+        }
+
+        if let Some(a) = stream_ack {
+            a.ack().await?;
+        }
+        Ok(())
+    }
+
+    /// Split the NDJSON `payload` by the worker each line's affinity key
+    /// hashes to. Lines without the key go round-robin as one record; `ack`
+    /// fires once every part has been processed.
+    async fn dispatch_by_key(&self, payload: BytesMut, ack: Option<Arc<dyn Ack>>) -> Result<()> {
+        let mut parts: HashMap<Option<usize>, BytesMut> = HashMap::new();
+        for line in payload.split_inclusive(|&b| b == b'\n') {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            parts
+                .entry(self.worker_for(line))
+                .or_default()
+                .extend_from_slice(line);
+        }
+        if parts.len() <= 1 {
+            let job = Record::Inline { payload, ack };
+            return match parts.into_keys().next().flatten() {
+                Some(idx) => self.send_to(idx, job).await,
+                None => self.send(job).await,
+            };
+        }
+
+        let ack = ack.map(|inner| {
+            Arc::new(StreamAck {
+                remaining: AtomicUsize::new(parts.len()),
+                inner,
+            })
+        });
+        for (idx, payload) in parts {
+            let job = Record::Inline {
+                payload,
+                ack: ack.clone().map(|a| a as Arc<dyn Ack>),
+            };
+            match idx {
+                Some(idx) => self.send_to(idx, job).await?,
+                None => self.send(job).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Worker for one NDJSON line, from a hash of its affinity key's value.
+    /// `None` when there is no key, the line isn't JSON or lacks the field.
+    fn worker_for(&self, line: &[u8]) -> Option<usize> {
+        let key = self.affinity_key.as_deref()?;
+        let view = JsonLogView::from_bytes(BytesMut::from(line)).ok()?;
+        let hash = match view.lookup(key)? {
+            BorrowedValue::Static(StaticNode::Null) => return None,
+            BorrowedValue::String(s) => AFFINITY_HASH.hash_one(&**s),
+            other => AFFINITY_HASH.hash_one(other.encode()),
+        };
+        Some((hash % self.senders.len().max(1) as u64) as usize)
+    }
+
+    /// Send to worker `idx` only, waiting for room in its queue: an event
+    /// pinned by its affinity key can't be moved to an idler worker.
+    async fn send_to(&self, idx: usize, job: Record) -> Result<()> {
+        if let Record::Inline { payload, .. } = &job {
+            CONSUMER_BYTES_TOTAL.inc_by(payload.len() as u64);
+            CONSUMER_OBJECTS_TOTAL.inc();
+        }
+        let Some(tx) = self.senders.get(idx) else {
+            anyhow::bail!("worker pool is closed")
+        };
+        let job = match tx.try_send(job) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Full(j)) => {
+                WORKER_BACKPRESSURE_TOTAL.inc();
+                j
+            }
+            Err(TrySendError::Closed(j)) => j,
+        };
+        if self.send_within_timeout(tx, job).await.is_err() {
+            tracing::warn!("worker {idx} unavailable; dropping job");
+            anyhow::bail!("worker {idx} unavailable")
+        }
+        Ok(())
+    }
+
+    async fn send(&self, mut job: Record) -> Result<()> {
         let n = self.senders.len();
         if n == 0 {
             anyhow::bail!("worker pool is closed")
         }
         let start = self.rr.fetch_add(1, Ordering::Relaxed) % n;
 
-        CONSUMER_BYTES_TOTAL.inc_by(job.payload.len() as u64);
-        CONSUMER_OBJECTS_TOTAL.inc();
+        if let Record::Inline { payload, .. } = &job {
+            CONSUMER_BYTES_TOTAL.inc_by(payload.len() as u64);
+            CONSUMER_OBJECTS_TOTAL.inc();
+        }
 
         for i in 0..n {
             let idx = (start + i) % n;
@@ -285,9 +660,14 @@ impl WorkerPool {
                 }
             }
         }
+        WORKER_BACKPRESSURE_TOTAL.inc();
 
         let idx = start;
-        if let Err(_e) = self.senders[idx].send(job).await {
+        if self
+            .send_within_timeout(&self.senders[idx], job)
+            .await
+            .is_err()
+        {
             tracing::warn!("all workers unavailable; dropping job");
             anyhow::bail!("all workers unavailable")
         }
@@ -295,10 +675,37 @@ impl WorkerPool {
         Ok(())
     }
 
+    /// Wait for room in `tx`, giving up after `backpressure_timeout`. A job
+    /// that times out is dropped unacked and counts as `Ok`, so the source
+    /// keeps reading; `Err` means the worker has exited.
+    async fn send_within_timeout(
+        &self,
+        tx: &mpsc::Sender<Record>,
+        job: Record,
+    ) -> Result<(), mpsc::error::SendError<Record>> {
+        let Some(limit) = self.backpressure_timeout else {
+            return tx.send(job).await;
+        };
+        match time::timeout(limit, tx.send(job)).await {
+            Ok(res) => res,
+            Err(_) => {
+                WORKER_DROPS_TOTAL.inc();
+                tracing::warn!(
+                    "workers still full after {}ms; dropping batch",
+                    limit.as_millis()
+                );
+                Ok(())
+            }
+        }
+    }
+
     pub async fn join(self) {
         let WorkerPool {
             senders,
             rr: _,
+            affinity_key: _,
+            affinity_plugins: _,
+            backpressure_timeout: _,
             mut handles,
         } = self;
         drop(senders);
@@ -315,7 +722,181 @@ impl WorkerPool {
         Self {
             senders: Vec::new(),
             rr: AtomicUsize::new(0),
+            affinity_key: None,
+            affinity_plugins: HashSet::default(),
+            backpressure_timeout: None,
             handles: handles,
         }
     }
+
+    /// Pool that hands every dispatched record to `senders` instead of
+    /// running plugins.
+    pub(crate) fn with_senders_for_test(senders: Vec<mpsc::Sender<Record>>) -> Self {
+        Self {
+            senders,
+            rr: AtomicUsize::new(0),
+            affinity_key: None,
+            affinity_plugins: HashSet::default(),
+            backpressure_timeout: None,
+            handles: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct CountAck(AtomicUsize);
+
+    #[async_trait]
+    impl Ack for CountAck {
+        async fn ack(&self) -> Result<()> {
+            self.0.fetch_add(1, Ordering::AcqRel);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn streaming_record_is_split_into_lines() {
+        let (tx, mut rx) = mpsc::channel::<Record>(16);
+        let pool = WorkerPool {
+            senders: vec![tx],
+            rr: AtomicUsize::new(0),
+            affinity_key: None,
+            affinity_plugins: HashSet::default(),
+            backpressure_timeout: None,
+            handles: Vec::new(),
+        };
+
+        let body: &'static [u8] = b"{\"a\":1}\n\n{\"a\":2}\n{\"a\":3}";
+        let acked = Arc::new(CountAck::default());
+        pool.dispatch(
+            "mapper",
+            Record::Streaming {
+                reader: Box::pin(body),
+                ack: Some(acked.clone()),
+                source_name: Arc::from("sqs"),
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut lines = Vec::new();
+        while let Ok(Record::Inline { payload, ack }) = rx.try_recv() {
+            lines.push(payload);
+            assert_eq!(acked.0.load(Ordering::Acquire), 0);
+            ack.unwrap().ack().await.unwrap();
+        }
+        assert_eq!(lines, vec!["{\"a\":1}\n", "{\"a\":2}\n", "{\"a\":3}"]);
+        assert_eq!(acked.0.load(Ordering::Acquire), 1);
+    }
+
+    #[tokio::test]
+    async fn affinity_key_pins_values_to_workers() {
+        let (txs, mut rxs): (Vec<_>, Vec<_>) =
+            (0..4).map(|_| mpsc::channel::<Record>(1024)).unzip();
+        let pool = WorkerPool {
+            senders: txs,
+            rr: AtomicUsize::new(0),
+            affinity_key: Some("user.id".into()),
+            affinity_plugins: HashSet::from_iter([Arc::from("sticky")]),
+            backpressure_timeout: None,
+            handles: Vec::new(),
+        };
+
+        let mut payload = String::new();
+        for round in 0..2 {
+            for user in 0..400 {
+                payload.push_str(&format!(
+                    "{{\"user\":{{\"id\":\"u{user}\"}},\"n\":{round}}}\n"
+                ));
+            }
+        }
+        payload.push_str("{\"n\":\"no key\"}\n");
+        let acked = Arc::new(CountAck::default());
+        pool.dispatch(
+            "sticky",
+            Record::Inline {
+                payload: BytesMut::from(payload.as_str()),
+                ack: Some(acked.clone()),
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut owner: HashMap<String, usize> = HashMap::new();
+        let mut keyless = 0;
+        for (idx, rx) in rxs.iter_mut().enumerate() {
+            let mut users = 0;
+            while let Ok(Record::Inline { payload, ack }) = rx.try_recv() {
+                assert_eq!(acked.0.load(Ordering::Acquire), 0);
+                for line in payload[..].split(|&b| b == b'\n').filter(|l| !l.is_empty()) {
+                    let v: serde_json::Value = serde_json::from_slice(line).unwrap();
+                    let Some(user) = v.pointer("/user/id").and_then(|u| u.as_str()) else {
+                        keyless += 1;
+                        continue;
+                    };
+                    let prev = owner.insert(user.to_string(), idx);
+                    assert!(prev.is_none_or(|p| p == idx), "{user} went to two workers");
+                    users += 1;
+                }
+                ack.unwrap().ack().await.unwrap();
+            }
+            // 800 keyed lines over 4 workers: each should get a fair share.
+            assert!(
+                (100..=300).contains(&users),
+                "worker {idx} got {users} lines"
+            );
+        }
+        assert_eq!(owner.len(), 400);
+        assert_eq!(keyless, 1);
+        assert_eq!(acked.0.load(Ordering::Acquire), 1);
+
+        // Plugins without the key get the batch whole, round-robin.
+        pool.dispatch(
+            "other",
+            Record::Inline {
+                payload: BytesMut::from(payload.as_str()),
+                ack: None,
+            },
+        )
+        .await
+        .unwrap();
+        let batches: usize = rxs
+            .iter_mut()
+            .map(|rx| std::iter::from_fn(|| rx.try_recv().ok()).count())
+            .sum();
+        assert_eq!(batches, 1);
+    }
+
+    #[tokio::test]
+    async fn full_workers_drop_the_batch_after_the_backpressure_timeout() {
+        let (tx, mut rx) = mpsc::channel::<Record>(1);
+        let pool = WorkerPool {
+            senders: vec![tx],
+            rr: AtomicUsize::new(0),
+            affinity_key: None,
+            affinity_plugins: HashSet::default(),
+            backpressure_timeout: Some(Duration::from_millis(10)),
+            handles: Vec::new(),
+        };
+        let job = |n: &str| Record::Inline {
+            payload: BytesMut::from(n),
+            ack: None,
+        };
+
+        let (pressured, dropped) = (WORKER_BACKPRESSURE_TOTAL.get(), WORKER_DROPS_TOTAL.get());
+        pool.dispatch("mapper", job("1\n")).await.unwrap();
+        pool.dispatch("mapper", job("2\n")).await.unwrap();
+        assert!(WORKER_BACKPRESSURE_TOTAL.get() > pressured);
+        assert!(WORKER_DROPS_TOTAL.get() > dropped);
+
+        let Ok(Record::Inline { payload, .. }) = rx.try_recv() else {
+            panic!("first batch should be queued");
+        };
+        assert_eq!(&payload[..], b"1\n");
+        assert!(rx.try_recv().is_err());
+    }
 }
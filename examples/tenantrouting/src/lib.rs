@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+wit_bindgen::generate!({
+    path: ".tangent/wit",
+    world: "routed-processor",
+    generate_all,
+});
+
+use exports::tangent::logs::mapper::{Guest, Meta, Pred, Selector};
+use exports::tangent::logs::routed_mapper::{Guest as RoutedGuest, OutputEvent};
+use tangent::logs::log::{Logview, Scalar};
+
+struct Component;
+
+export!(Component);
+
+#[derive(Serialize)]
+struct TenantLog {
+    tenant_id: Option<String>,
+    message: String,
+    level: String,
+}
+
+fn string_at(lv: &Logview, path: &str) -> Option<String> {
+    match lv.get(path) {
+        Some(Scalar::Str(v)) => Some(v),
+        _ => None,
+    }
+}
+
+fn to_line(lv: &Logview) -> Result<(Option<String>, Vec<u8>), String> {
+    let out = TenantLog {
+        tenant_id: string_at(lv, "tenant_id"),
+        message: string_at(lv, "msg").unwrap_or_default(),
+        level: string_at(lv, "level").unwrap_or_default(),
+    };
+    let mut line = serde_json::to_vec(&out).map_err(|e| e.to_string())?;
+    line.push(b'\n');
+    Ok((out.tenant_id, line))
+}
+
+impl Guest for Component {
+    fn metadata() -> Meta {
+        Meta {
+            name: "tenantrouting".to_string(),
+            version: "0.1.0".to_string(),
+        }
+    }
+
+    fn probe() -> Vec<Selector> {
+        vec![Selector {
+            any: Vec::new(),
+            all: vec![Pred::Has("msg".to_string())],
+            none: Vec::new(),
+        }]
+    }
+
+    // Used by runtimes that don't know about `routed-mapper`; everything goes
+    // to the edge's key_prefix.
+    fn process_logs(input: Vec<Logview>) -> Result<Vec<u8>, String> {
+        let mut buf = Vec::new();
+        for lv in &input {
+            buf.extend(to_line(lv)?.1);
+        }
+        Ok(buf)
+    }
+}
+
+impl RoutedGuest for Component {
+    // One output event per tenant so each tenant's logs land under
+    // `<tenant_id>/logs/` in the bucket.
+    fn process_logs_v3(input: Vec<Logview>) -> Result<Vec<OutputEvent>, String> {
+        let mut by_tenant: BTreeMap<Option<String>, Vec<u8>> = BTreeMap::new();
+        for lv in &input {
+            let (tenant, line) = to_line(lv)?;
+            by_tenant.entry(tenant).or_default().extend(line);
+        }
+
+        Ok(by_tenant
+            .into_iter()
+            .map(|(tenant, payload)| OutputEvent {
+                key_prefix: tenant.map(|t| format!("{t}/logs")),
+                payload,
+            })
+            .collect())
+    }
+}